@@ -35,7 +35,7 @@ pub fn group_node_ids_by_type(tree: &Tree, node_ids: &[NodeId]) -> BTreeMap<Stri
     for &id in node_ids {
         if let Ok(node) = tree.get_node(id) {
             grouped
-                .entry(node.node_type.clone())
+                .entry(node.node_type.to_string())
                 .or_default()
                 .push(id);
         }
@@ -0,0 +1,25 @@
+//! Cancellation signals polled by long parses
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A flag a parser checks every so often, stopping once it is set
+///
+/// An `AtomicBool` suits Rust hosts, which set it from another thread.
+/// From JavaScript, pass an `Int32Array` over a `SharedArrayBuffer`: the
+/// parse running in a worker stops once another thread stores a non-zero
+/// first element with `Atomics.store`.
+pub trait CancelSignal {
+    fn is_cancelled(&self) -> bool;
+}
+
+impl CancelSignal for AtomicBool {
+    fn is_cancelled(&self) -> bool {
+        self.load(Ordering::Relaxed)
+    }
+}
+
+impl CancelSignal for js_sys::Int32Array {
+    fn is_cancelled(&self) -> bool {
+        js_sys::Atomics::load(self, 0).is_ok_and(|value| value != 0)
+    }
+}
@@ -12,6 +12,12 @@ pub enum SynthError {
     #[error("Invalid node ID: {0}")]
     InvalidNodeId(u32),
 
+    #[error("Child index {index} out of range for node {parent} with {len} children")]
+    ChildIndexOutOfRange { parent: u32, index: usize, len: usize },
+
+    #[error("Cycle detected: node {node} is {parent} or one of its ancestors")]
+    CycleDetected { node: u32, parent: u32 },
+
     #[error("Tree structure error: {0}")]
     TreeStructureError(String),
 
@@ -19,11 +25,49 @@ pub enum SynthError {
     SerializationError(String),
 }
 
+impl SynthError {
+    /// Variant name, e.g. `"InvalidNodeId"`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SynthError::ParseError(_) => "ParseError",
+            SynthError::InvalidNodeId(_) => "InvalidNodeId",
+            SynthError::ChildIndexOutOfRange { .. } => "ChildIndexOutOfRange",
+            SynthError::CycleDetected { .. } => "CycleDetected",
+            SynthError::TreeStructureError(_) => "TreeStructureError",
+            SynthError::SerializationError(_) => "SerializationError",
+        }
+    }
+
+    /// `{ kind, message, ...context }`, the shape errors take in JavaScript
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut value = match *self {
+            SynthError::InvalidNodeId(id) => serde_json::json!({ "id": id }),
+            SynthError::ChildIndexOutOfRange { parent, index, len } => {
+                serde_json::json!({ "parent": parent, "index": index, "len": len })
+            }
+            SynthError::CycleDetected { node, parent } => {
+                serde_json::json!({ "node": node, "parent": parent })
+            }
+            _ => serde_json::json!({}),
+        };
+        value["kind"] = self.kind().into();
+        value["message"] = self.to_string().into();
+        value
+    }
+}
+
+/// Errors reach JavaScript as `{ kind, message, ...context }` objects
 impl From<SynthError> for JsValue {
     fn from(err: SynthError) -> Self {
-        JsValue::from_str(&err.to_string())
+        json_to_js(&err.to_json(), &err)
     }
 }
 
+/// `json` (an error's `to_json`, possibly with more context) as a
+/// JavaScript object, or the error's message if that fails
+pub(crate) fn json_to_js(json: &serde_json::Value, err: &SynthError) -> JsValue {
+    js_sys::JSON::parse(&json.to_string()).unwrap_or_else(|_| JsValue::from_str(&err.to_string()))
+}
+
 /// Result type for Synth WASM operations
 pub type SynthResult<T> = Result<T, SynthError>;
@@ -4,6 +4,9 @@
 //! Provides Tree structure compatible with the TypeScript @sylphx/synth package.
 
 mod tree;
+mod node_type;
+mod line_ending;
+mod cancel;
 mod query;
 mod error;
 mod position;
@@ -120,6 +123,9 @@ mod linter_engine;
 mod metrics_analyzer_engine;
 
 pub use tree::*;
+pub use node_type::NodeType;
+pub use line_ending::{detect_line_ending, LineEnding, LineEndingInfo};
+pub use cancel::CancelSignal;
 pub use query::{depth, descendants, find_by_type};
 pub use error::*;
 pub use position::*;
@@ -0,0 +1,121 @@
+//! Line-ending detection for round-trip preservation.
+
+use serde::{Deserialize, Serialize};
+
+/// Line-ending convention of a source text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    /// `\n`
+    #[default]
+    Lf,
+    /// `\r\n`
+    Crlf,
+    /// `\r`
+    Cr,
+}
+
+impl LineEnding {
+    /// The line terminator itself
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /// Short name (`"lf"`, `"crlf"`, `"cr"`)
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "lf",
+            LineEnding::Crlf => "crlf",
+            LineEnding::Cr => "cr",
+        }
+    }
+}
+
+/// Result of scanning a source for line endings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LineEndingInfo {
+    /// Most frequent line ending (`Lf` when the source has none; ties
+    /// prefer `Lf`, then `Crlf`)
+    pub dominant: LineEnding,
+    /// More than one style occurs
+    pub mixed: bool,
+}
+
+/// Count line endings and report the dominant style.
+#[must_use]
+pub fn detect_line_ending(source: &str) -> LineEndingInfo {
+    let bytes = source.as_bytes();
+    let (mut lf, mut crlf, mut cr) = (0usize, 0usize, 0usize);
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\r' if bytes.get(i + 1) == Some(&b'\n') => {
+                crlf += 1;
+                i += 1;
+            }
+            b'\r' => cr += 1,
+            b'\n' => lf += 1,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let dominant = if lf >= crlf && lf >= cr {
+        LineEnding::Lf
+    } else if crlf >= cr {
+        LineEnding::Crlf
+    } else {
+        LineEnding::Cr
+    };
+    let styles = [lf, crlf, cr].iter().filter(|&&n| n > 0).count();
+
+    LineEndingInfo {
+        dominant,
+        mixed: styles > 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_pure_styles() {
+        assert_eq!(
+            detect_line_ending("a\nb\n"),
+            LineEndingInfo { dominant: LineEnding::Lf, mixed: false }
+        );
+        assert_eq!(
+            detect_line_ending("a\r\nb\r\n"),
+            LineEndingInfo { dominant: LineEnding::Crlf, mixed: false }
+        );
+        assert_eq!(
+            detect_line_ending("a\rb\r"),
+            LineEndingInfo { dominant: LineEnding::Cr, mixed: false }
+        );
+        assert_eq!(detect_line_ending("no newline"), LineEndingInfo::default());
+    }
+
+    #[test]
+    fn mixed_reports_dominant() {
+        let info = detect_line_ending("a\r\nb\r\nc\nd\r\n");
+        assert_eq!(info.dominant, LineEnding::Crlf);
+        assert!(info.mixed);
+
+        let info = detect_line_ending("a\nb\r\n");
+        assert_eq!(info.dominant, LineEnding::Lf);
+        assert!(info.mixed);
+    }
+
+    #[test]
+    fn serializes_lowercase() {
+        assert_eq!(serde_json::to_string(&LineEnding::Crlf).unwrap(), "\"crlf\"");
+        assert_eq!(LineEnding::Crlf.as_str(), "\r\n");
+    }
+}
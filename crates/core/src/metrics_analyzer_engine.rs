@@ -381,7 +381,7 @@ impl MetricsAnalyzer {
             return;
         };
         if is_operator_node_type(&node.node_type) {
-            operators.insert(node.node_type.clone());
+            operators.insert(node.node_type.to_string());
             *total_operators = total_operators.saturating_add(1);
         } else if is_operand_node_type(&node.node_type) {
             let (value, name) = extract_operand_fields(node);
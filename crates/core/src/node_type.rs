@@ -0,0 +1,256 @@
+//! Typed node kinds shared by all Synth parsers
+//!
+//! Known markdown (mdast) and JavaScript (ESTree) node types are enum
+//! variants; anything else (plugins, custom nodes) goes through
+//! `NodeType::Other`. Serializes to the same strings as before, so JSON
+//! output is unchanged.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+macro_rules! node_types {
+    ($($variant:ident => $name:literal,)*) => {
+        /// Node type of a tree node
+        #[derive(Debug, Clone)]
+        pub enum NodeType {
+            $($variant,)*
+            /// Custom/plugin node type
+            Other(String),
+        }
+
+        impl NodeType {
+            /// The node type's string name (e.g. `"heading"`, `"Identifier"`)
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $(NodeType::$variant => $name,)*
+                    NodeType::Other(name) => name,
+                }
+            }
+
+            /// Look up a known node type by name
+            fn known(name: &str) -> Option<Self> {
+                match name {
+                    $($name => Some(NodeType::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+node_types! {
+    Root => "root",
+
+    // Markdown (mdast)
+    Heading => "heading",
+    Paragraph => "paragraph",
+    Text => "text",
+    Code => "code",
+    ThematicBreak => "thematicBreak",
+    Blockquote => "blockquote",
+    List => "list",
+    ListItem => "listItem",
+    Table => "table",
+    TableRow => "tableRow",
+    TableCell => "tableCell",
+    InlineCode => "inlineCode",
+    Emphasis => "emphasis",
+    Strong => "strong",
+    Delete => "delete",
+    Link => "link",
+    Image => "image",
+    Html => "html",
+    Break => "break",
+    Definition => "definition",
+    FootnoteReference => "footnoteReference",
+    FootnoteDefinition => "footnoteDefinition",
+    Yaml => "yaml",
+    Toml => "toml",
+    Json => "json",
+
+    // JavaScript (ESTree)
+    Program => "Program",
+    VariableDeclaration => "VariableDeclaration",
+    VariableDeclarator => "VariableDeclarator",
+    FunctionDeclaration => "FunctionDeclaration",
+    ClassDeclaration => "ClassDeclaration",
+    ImportDeclaration => "ImportDeclaration",
+    ExportNamedDeclaration => "ExportNamedDeclaration",
+    ExportDefaultDeclaration => "ExportDefaultDeclaration",
+    ExportAllDeclaration => "ExportAllDeclaration",
+    BlockStatement => "BlockStatement",
+    ExpressionStatement => "ExpressionStatement",
+    IfStatement => "IfStatement",
+    ForStatement => "ForStatement",
+    ForInStatement => "ForInStatement",
+    ForOfStatement => "ForOfStatement",
+    WhileStatement => "WhileStatement",
+    DoWhileStatement => "DoWhileStatement",
+    SwitchStatement => "SwitchStatement",
+    SwitchCase => "SwitchCase",
+    ReturnStatement => "ReturnStatement",
+    ThrowStatement => "ThrowStatement",
+    TryStatement => "TryStatement",
+    CatchClause => "CatchClause",
+    BreakStatement => "BreakStatement",
+    ContinueStatement => "ContinueStatement",
+    LabeledStatement => "LabeledStatement",
+    EmptyStatement => "EmptyStatement",
+    DebuggerStatement => "DebuggerStatement",
+    Identifier => "Identifier",
+    PrivateIdentifier => "PrivateIdentifier",
+    Literal => "Literal",
+    ArrayExpression => "ArrayExpression",
+    ObjectExpression => "ObjectExpression",
+    Property => "Property",
+    FunctionExpression => "FunctionExpression",
+    ArrowFunctionExpression => "ArrowFunctionExpression",
+    ClassExpression => "ClassExpression",
+    CallExpression => "CallExpression",
+    NewExpression => "NewExpression",
+    MemberExpression => "MemberExpression",
+    ChainExpression => "ChainExpression",
+    BinaryExpression => "BinaryExpression",
+    UnaryExpression => "UnaryExpression",
+    UpdateExpression => "UpdateExpression",
+    AssignmentExpression => "AssignmentExpression",
+    LogicalExpression => "LogicalExpression",
+    ConditionalExpression => "ConditionalExpression",
+    SequenceExpression => "SequenceExpression",
+    SpreadElement => "SpreadElement",
+    TemplateLiteral => "TemplateLiteral",
+    TemplateElement => "TemplateElement",
+    TaggedTemplateExpression => "TaggedTemplateExpression",
+    ThisExpression => "ThisExpression",
+    Super => "Super",
+    AwaitExpression => "AwaitExpression",
+    YieldExpression => "YieldExpression",
+    ImportExpression => "ImportExpression",
+    MetaProperty => "MetaProperty",
+    ArrayPattern => "ArrayPattern",
+    ObjectPattern => "ObjectPattern",
+    AssignmentPattern => "AssignmentPattern",
+    RestElement => "RestElement",
+    ImportSpecifier => "ImportSpecifier",
+    ImportDefaultSpecifier => "ImportDefaultSpecifier",
+    ImportNamespaceSpecifier => "ImportNamespaceSpecifier",
+    ExportSpecifier => "ExportSpecifier",
+    ClassBody => "ClassBody",
+    MethodDefinition => "MethodDefinition",
+    PropertyDefinition => "PropertyDefinition",
+    StaticBlock => "StaticBlock",
+    Comment => "Comment",
+}
+
+impl From<&str> for NodeType {
+    fn from(name: &str) -> Self {
+        NodeType::known(name).unwrap_or_else(|| NodeType::Other(name.to_string()))
+    }
+}
+
+impl From<String> for NodeType {
+    fn from(name: String) -> Self {
+        NodeType::known(&name).unwrap_or(NodeType::Other(name))
+    }
+}
+
+impl From<&String> for NodeType {
+    fn from(name: &String) -> Self {
+        NodeType::from(name.as_str())
+    }
+}
+
+impl Deref for NodeType {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for NodeType {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for NodeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Equality and hashing go through the name, so `Other("heading")`
+// is the same type as `Heading`.
+impl PartialEq for NodeType {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for NodeType {}
+
+impl Hash for NodeType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl PartialEq<str> for NodeType {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for NodeType {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Serialize for NodeType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for NodeType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(NodeType::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_names_map_to_variants() {
+        assert_eq!(NodeType::from("thematicBreak"), NodeType::ThematicBreak);
+        assert!(matches!(NodeType::from("Identifier"), NodeType::Identifier));
+        assert!(matches!(NodeType::from("myPluginNode"), NodeType::Other(_)));
+        assert_eq!(NodeType::ListItem.as_str(), "listItem");
+        assert_eq!(NodeType::Other("heading".into()), NodeType::Heading);
+    }
+
+    #[test]
+    fn compares_with_strings() {
+        let t = NodeType::from("paragraph");
+        assert_eq!(t, "paragraph");
+        assert!(t != "thematic_break");
+        assert!(t.starts_with("para"));
+        assert_eq!(t.to_string(), "paragraph");
+    }
+
+    #[test]
+    fn serde_roundtrip_uses_plain_strings() {
+        for t in [NodeType::Heading, NodeType::Program, NodeType::Other("custom".into())] {
+            let json = serde_json::to_string(&t).unwrap();
+            assert_eq!(json, format!("\"{}\"", t.as_str()));
+            let back: NodeType = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, t);
+        }
+    }
+}
@@ -9,7 +9,7 @@ use wasm_bindgen::prelude::*;
 pub struct Position {
     /// Line number (1-indexed)
     pub line: u32,
-    /// Column number (0-indexed)
+    /// Column number (0-indexed), in the parser's `ColumnUnit`
     pub column: u32,
     /// Byte offset from start of source
     pub offset: u32,
@@ -23,6 +23,104 @@ impl Position {
     }
 }
 
+/// Unit a `Position.column` is counted in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ColumnUnit {
+    /// UTF-8 bytes
+    Byte,
+    /// UTF-16 code units, as LSP and VS Code count them
+    #[default]
+    Utf16,
+    /// Unicode scalar values
+    Char,
+}
+
+impl ColumnUnit {
+    /// Column just past `line_prefix`, the UTF-8 text from the start of
+    /// a line
+    ///
+    /// Works on bytes so an offset inside a character never panics; a
+    /// partial character counts once its lead byte is in.
+    pub fn column(self, line_prefix: &[u8]) -> u32 {
+        let units = match self {
+            ColumnUnit::Byte => line_prefix.len(),
+            _ if line_prefix.is_ascii() => line_prefix.len(),
+            // Continuation bytes don't start a character
+            ColumnUnit::Char => line_prefix.iter().filter(|&&b| b & 0xC0 != 0x80).count(),
+            // Four-byte characters are surrogate pairs
+            ColumnUnit::Utf16 => line_prefix
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0xBF => 0,
+                    0xF0.. => 2,
+                    _ => 1,
+                })
+                .sum(),
+        };
+        units as u32
+    }
+}
+
+/// Start offsets of a source's lines, for mapping byte offsets to lines
+///
+/// Lines end at `\n`, `\r\n` or a lone `\r`; the terminator is not part
+/// of the line text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    starts: Vec<u32>,
+    len: u32,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let bytes = source.as_bytes();
+        let starts = std::iter::once(0)
+            .chain(
+                bytes
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, &b)| b == b'\n' || (b == b'\r' && bytes.get(i + 1) != Some(&b'\n')))
+                    .map(|(i, _)| i as u32 + 1),
+            )
+            .collect();
+        Self { starts, len: source.len() as u32 }
+    }
+
+    /// Number of lines (a trailing newline starts an empty last line)
+    pub fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Line (1-indexed) containing `offset`; offsets past the end fall on
+    /// the last line
+    pub fn line(&self, offset: u32) -> u32 {
+        self.starts.partition_point(|&start| start <= offset) as u32
+    }
+
+    /// Byte offset where `line` (1-indexed) starts
+    pub fn line_start(&self, line: u32) -> u32 {
+        self.starts[line as usize - 1]
+    }
+
+    /// Text of `line` (1-indexed) without its terminator
+    pub fn line_text<'s>(&self, source: &'s str, line: u32) -> &'s str {
+        let start = self.line_start(line) as usize;
+        let end = self.starts.get(line as usize).map_or(self.len as usize, |&next| next as usize - 1);
+        let text = &source[start..end];
+        text.strip_suffix('\r').unwrap_or(text)
+    }
+
+    /// Position of `offset`, with the column counted in `unit`; offsets
+    /// past the end are clamped to it
+    pub fn position(&self, source: &str, offset: u32, unit: ColumnUnit) -> Position {
+        let offset = offset.min(self.len);
+        let line = self.line(offset);
+        let prefix = &source.as_bytes()[self.line_start(line) as usize..offset as usize];
+        Position::new(line, unit.column(prefix), offset)
+    }
+}
+
 /// A span representing a range in the source text
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Span {
@@ -79,6 +177,55 @@ mod tests {
         assert_eq!(s2.end.column, 10);
     }
 
+    #[test]
+    fn test_column_units_with_emoji() {
+        let line = "a😀é=";
+        let column = |unit: ColumnUnit, before: &str| unit.column(&line.as_bytes()[..line.find(before).unwrap()]);
+        assert_eq!(column(ColumnUnit::Byte, "="), 7);
+        assert_eq!(column(ColumnUnit::Utf16, "="), 4);
+        assert_eq!(column(ColumnUnit::Char, "="), 3);
+        assert_eq!(column(ColumnUnit::Utf16, "é"), 3);
+        assert_eq!(ColumnUnit::default(), ColumnUnit::Utf16);
+        assert_eq!(serde_json::from_str::<ColumnUnit>("\"utf16\"").unwrap(), ColumnUnit::Utf16);
+        // Inside the emoji
+        assert_eq!(ColumnUnit::Char.column(&line.as_bytes()[..3]), 2);
+    }
+
+    #[test]
+    fn test_line_index() {
+        let source = "first\r\nsecond é line\n\nlast";
+        let index = LineIndex::new(source);
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line_text(source, 1), "first");
+        assert_eq!(index.line_text(source, 2), "second é line");
+        assert_eq!(index.line_text(source, 3), "");
+        assert_eq!(index.line_text(source, 4), "last");
+
+        let offset = source.find("line").unwrap() as u32;
+        assert_eq!(index.line(offset), 2);
+        assert_eq!(index.position(source, offset, ColumnUnit::Utf16), Position::new(2, 9, offset));
+        assert_eq!(index.position(source, offset, ColumnUnit::Byte).column, 10);
+        // The newline itself ends its line
+        assert_eq!(index.line(5), 1);
+        assert_eq!(index.line(7), 2);
+        assert_eq!(index.line(source.len() as u32), 4);
+
+        let end = source.len() as u32;
+        assert_eq!(index.position(source, end + 5, ColumnUnit::Utf16), Position::new(4, 4, end));
+    }
+
+    #[test]
+    fn test_line_index_lone_cr() {
+        let source = "one\rtwo\r\nthree\r";
+        let index = LineIndex::new(source);
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line_text(source, 1), "one");
+        assert_eq!(index.line_text(source, 2), "two");
+        assert_eq!(index.line_text(source, 3), "three");
+        assert_eq!(index.line_text(source, 4), "");
+        assert_eq!(index.position(source, 5, ColumnUnit::Byte), Position::new(2, 1, 5));
+    }
+
     #[test]
     fn portfolio_web_media_wave4_span_serde_roundtrip() {
         let s = Span::from_coords(3, 1, 10, 3, 8, 17);
@@ -6,8 +6,10 @@ use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 use std::collections::HashMap;
 
+use crate::line_ending::{detect_line_ending, LineEnding};
+use crate::node_type::NodeType;
 use crate::position::Span;
-use crate::error::{SynthError, SynthResult};
+use crate::error::{json_to_js, SynthError, SynthResult};
 
 /// Get current timestamp (works in both WASM and native)
 fn now() -> u64 {
@@ -35,7 +37,7 @@ pub struct Node {
     pub id: NodeId,
     /// Node type (e.g., "heading", "paragraph", "text")
     #[serde(rename = "type")]
-    pub node_type: String,
+    pub node_type: NodeType,
     /// Parent node ID (None for root)
     pub parent: Option<NodeId>,
     /// Child node IDs
@@ -43,14 +45,25 @@ pub struct Node {
     /// Source span
     #[serde(skip_serializing_if = "Option::is_none")]
     pub span: Option<Span>,
-    /// Node-specific data
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Node-specific data, serialized with sorted keys so the same tree
+    /// always produces the same JSON
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_sorted")]
     pub data: Option<HashMap<String, serde_json::Value>>,
 }
 
+/// Serialize `data` in key order rather than hash order
+fn serialize_sorted<S: serde::Serializer>(
+    data: &Option<HashMap<String, serde_json::Value>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    data.as_ref()
+        .map(|data| data.iter().collect::<std::collections::BTreeMap<_, _>>())
+        .serialize(serializer)
+}
+
 impl Node {
     /// Create a new node
-    pub fn new(id: NodeId, node_type: impl Into<String>) -> Self {
+    pub fn new(id: NodeId, node_type: impl Into<NodeType>) -> Self {
         Self {
             id,
             node_type: node_type.into(),
@@ -91,6 +104,21 @@ pub struct TreeMetadata {
     pub created: u64,
     /// Last modified timestamp (ms since epoch)
     pub modified: u64,
+    /// Dominant line ending of the source
+    #[serde(default)]
+    pub line_ending: LineEnding,
+    /// Source mixes more than one line-ending style
+    #[serde(default)]
+    pub mixed_line_endings: bool,
+}
+
+/// What `Tree::prune` does with the children of a removed node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PruneMode {
+    /// Re-check the children and promote kept ones to the nearest kept ancestor
+    PromoteChildren,
+    /// Remove the whole subtree
+    DropSubtree,
 }
 
 /// AST Tree structure
@@ -116,8 +144,9 @@ impl Tree {
     #[wasm_bindgen(constructor)]
     pub fn new(language: &str, source: &str) -> Self {
         let timestamp = now();
+        let line_endings = detect_line_ending(source);
 
-        let root = Node::new(0, "root");
+        let root = Node::new(0, NodeType::Root);
 
         Self {
             meta: TreeMetadata {
@@ -125,6 +154,8 @@ impl Tree {
                 source: source.to_string(),
                 created: timestamp,
                 modified: timestamp,
+                line_ending: line_endings.dominant,
+                mixed_line_endings: line_endings.mixed,
             },
             root: 0,
             nodes: vec![root],
@@ -155,6 +186,18 @@ impl Tree {
         self.meta.source.clone()
     }
 
+    /// Get the dominant line ending (`"lf"`, `"crlf"` or `"cr"`)
+    #[wasm_bindgen(getter, js_name = lineEnding)]
+    pub fn line_ending(&self) -> String {
+        self.meta.line_ending.name().to_string()
+    }
+
+    /// Whether the source mixes line-ending styles
+    #[wasm_bindgen(getter, js_name = mixedLineEndings)]
+    pub fn mixed_line_endings(&self) -> bool {
+        self.meta.mixed_line_endings
+    }
+
     /// Serialize tree to JSON
     ///
     /// Note: Uses serde_json::to_string instead of serde_wasm_bindgen::to_value
@@ -167,6 +210,56 @@ impl Tree {
             .map_err(|e| JsValue::from_str(&format!("JSON parse error: {:?}", e)))
     }
 
+    /// Node counts per type, as a `{ type: count }` object
+    #[wasm_bindgen(js_name = typeHistogram)]
+    pub fn type_histogram_js(&self) -> Result<JsValue, JsValue> {
+        let json_string = serde_json::to_string(&self.type_histogram())
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        js_sys::JSON::parse(&json_string)
+            .map_err(|e| JsValue::from_str(&format!("JSON parse error: {:?}", e)))
+    }
+
+    /// Content-addressed node IDs, indexed by node ID (see `content_ids`);
+    /// `null` for detached nodes
+    #[wasm_bindgen(js_name = contentIds)]
+    pub fn content_ids_js(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.content_ids()).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Whether any node has type `type` (see `contains_type`)
+    #[wasm_bindgen(js_name = hasType)]
+    pub fn has_type_js(&self, node_type: &str) -> bool {
+        self.contains_type(node_type)
+    }
+
+    /// Detach a node and its subtree; throws `{ kind, message, ... }` on
+    /// an invalid ID (with the tree's `nodeCount`) or the root
+    #[wasm_bindgen(js_name = removeNode)]
+    pub fn remove_node_js(&mut self, id: NodeId) -> Result<(), JsValue> {
+        self.remove_node(id).map_err(|err| self.js_error(err))
+    }
+
+    /// Copy a node and its descendants into a new Tree rooted at it;
+    /// throws `{ kind, message, ... }` on an invalid ID
+    #[wasm_bindgen(js_name = subtree)]
+    pub fn subtree_js(&self, id: NodeId) -> Result<Tree, JsValue> {
+        self.clone_subtree(id).map_err(|err| self.js_error(err))
+    }
+
+    /// IDs of a node's ancestors, parent first and root last; throws
+    /// `{ kind, message, ... }` on an invalid ID
+    #[wasm_bindgen(js_name = ancestorsOf)]
+    pub fn ancestors_of_js(&self, id: NodeId) -> Result<Vec<NodeId>, JsValue> {
+        self.get_node(id).map_err(|err| self.js_error(err))?;
+        Ok(self.ancestors(id).map(|node| node.id).collect())
+    }
+
+    /// Indented JSON with sorted keys (see `to_json_pretty`)
+    #[wasm_bindgen(js_name = toJSONPretty)]
+    pub fn to_json_pretty_js(&self) -> Result<String, JsValue> {
+        Ok(self.to_json_pretty()?)
+    }
+
     /// Deserialize tree from JSON
     #[wasm_bindgen(js_name = fromJSON)]
     pub fn from_json(json: JsValue) -> Result<Tree, JsValue> {
@@ -191,6 +284,44 @@ impl Tree {
             .ok_or(SynthError::InvalidNodeId(id))
     }
 
+    /// `err.to_json()`, plus this tree's `nodeCount` for an invalid ID
+    pub fn error_json(&self, err: &SynthError) -> serde_json::Value {
+        let mut json = err.to_json();
+        if let SynthError::InvalidNodeId(_) = err {
+            json["nodeCount"] = self.nodes.len().into();
+        }
+        json
+    }
+
+    fn js_error(&self, err: SynthError) -> JsValue {
+        json_to_js(&self.error_json(&err), &err)
+    }
+
+    /// Get the root node
+    pub fn root(&self) -> &Node {
+        &self.nodes[self.root as usize]
+    }
+
+    /// Get the child IDs of a node (empty if the ID is invalid)
+    pub fn children_of(&self, id: NodeId) -> &[NodeId] {
+        self.nodes
+            .get(id as usize)
+            .map(|node| node.children.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// A node's ancestors, from its parent up to the root (or the top of
+    /// a detached subtree); empty for the root or an invalid ID
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = &Node> {
+        let parent_of = |node: &Node| node.parent.and_then(|parent| self.nodes.get(parent as usize));
+        std::iter::successors(self.nodes.get(id as usize).and_then(parent_of), move |node| parent_of(node))
+    }
+
+    /// Number of ancestors of a node: 0 for the root, 1 for its children
+    pub fn depth(&self, id: NodeId) -> usize {
+        self.ancestors(id).count()
+    }
+
     /// Get a mutable node by ID
     pub fn get_node_mut(&mut self, id: NodeId) -> SynthResult<&mut Node> {
         self.nodes.get_mut(id as usize)
@@ -206,10 +337,316 @@ impl Tree {
         Ok(())
     }
 
+    /// Move `child` to position `index` among `parent`'s children
+    ///
+    /// `child` is detached from its current parent first. Fails without
+    /// changing the tree if either ID is invalid, `index` is past the end
+    /// of the children, or `child` is `parent` or one of its ancestors.
+    pub fn insert_child(&mut self, parent_id: NodeId, index: usize, child_id: NodeId) -> SynthResult<()> {
+        self.get_node(child_id)?;
+        let mut ancestor = Some(parent_id);
+        while let Some(id) = ancestor {
+            if id == child_id {
+                return Err(SynthError::CycleDetected { node: child_id, parent: parent_id });
+            }
+            ancestor = self.get_node(id)?.parent;
+        }
+
+        let old_parent = self.get_node(child_id)?.parent;
+        let len = self.get_node(parent_id)?.children.iter().filter(|&&c| c != child_id).count();
+        if index > len {
+            return Err(SynthError::ChildIndexOutOfRange { parent: parent_id, index, len });
+        }
+
+        if let Some(old) = old_parent {
+            self.get_node_mut(old)?.children.retain(|&c| c != child_id);
+        }
+        self.get_node_mut(parent_id)?.children.insert(index, child_id);
+        self.get_node_mut(child_id)?.parent = Some(parent_id);
+        self.meta.modified = now();
+        Ok(())
+    }
+
+    /// Detach a node (and its subtree) from the tree
+    ///
+    /// The nodes stay in the arena, unreachable, until `compact` drops them.
+    pub fn remove_node(&mut self, id: NodeId) -> SynthResult<()> {
+        if id == self.root {
+            return Err(SynthError::TreeStructureError(format!("cannot remove the root node {id}")));
+        }
+        let node = self.get_node_mut(id)?;
+        let Some(parent) = node.parent.take() else {
+            return Err(SynthError::TreeStructureError(format!("node {id} is already detached")));
+        };
+        self.get_node_mut(parent)?.children.retain(|&c| c != id);
+        self.meta.modified = now();
+        Ok(())
+    }
+
+    /// Serialize to indented JSON with every object's keys sorted, for
+    /// snapshots and bug reports
+    ///
+    /// The creation and modification timestamps are left out, so the same
+    /// tree always gives the same text.
+    pub fn to_json_pretty(&self) -> SynthResult<String> {
+        let mut value = serde_json::to_value(self).map_err(|e| SynthError::SerializationError(e.to_string()))?;
+        if let Some(meta) = value.get_mut("meta").and_then(|meta| meta.as_object_mut()) {
+            meta.remove("created");
+            meta.remove("modified");
+        }
+        serde_json::to_string_pretty(&value).map_err(|e| SynthError::SerializationError(e.to_string()))
+    }
+
     /// Get all nodes (for iteration)
     pub fn nodes(&self) -> &[Node] {
         &self.nodes
     }
+
+    /// Count nodes per type (the root included)
+    pub fn type_histogram(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for node in &self.nodes {
+            *counts.entry(node.node_type.to_string()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Whether any node has type `node_type`, stopping at the first one
+    ///
+    /// Scans the arena, so detached nodes count until `compact`.
+    pub fn contains_type(&self, node_type: &str) -> bool {
+        self.nodes.iter().any(|node| node.node_type == node_type)
+    }
+
+    /// Remove every node `keep` rejects (the root is always kept)
+    ///
+    /// With `PruneMode::PromoteChildren` a removed node's kept descendants
+    /// move up to its nearest kept ancestor, in document order; with
+    /// `PruneMode::DropSubtree` they go with it. The arena is rebuilt, so
+    /// node IDs are renumbered in pre-order.
+    pub fn prune(&mut self, keep: impl Fn(&Node) -> bool, mode: PruneMode) {
+        fn visit(
+            old: &[Node],
+            id: NodeId,
+            parent: NodeId,
+            keep: &dyn Fn(&Node) -> bool,
+            mode: PruneMode,
+            out: &mut Vec<Node>,
+        ) {
+            let Some(node) = old.get(id as usize) else {
+                return;
+            };
+            let parent = if keep(node) {
+                let new_id = out.len() as NodeId;
+                out.push(Node {
+                    id: new_id,
+                    parent: Some(parent),
+                    children: Vec::new(),
+                    ..node.clone()
+                });
+                out[parent as usize].children.push(new_id);
+                new_id
+            } else if mode == PruneMode::PromoteChildren {
+                parent
+            } else {
+                return;
+            };
+            for &child in &node.children {
+                visit(old, child, parent, keep, mode, out);
+            }
+        }
+
+        let old = std::mem::take(&mut self.nodes);
+        let root = &old[self.root as usize];
+        let mut nodes = vec![Node {
+            id: 0,
+            children: Vec::new(),
+            ..root.clone()
+        }];
+        for &child in &root.children {
+            visit(&old, child, 0, &keep, mode, &mut nodes);
+        }
+
+        self.nodes = nodes;
+        self.root = 0;
+        self.meta.modified = now();
+    }
+
+    /// Copy `id` and its descendants into a standalone Tree with `id` as
+    /// the root
+    ///
+    /// IDs are renumbered in pre-order. The metadata (including the full
+    /// source) is kept, so spans still index into `source`.
+    pub fn clone_subtree(&self, id: NodeId) -> SynthResult<Tree> {
+        let root = self.get_node(id)?;
+        let mut nodes = vec![Node {
+            id: 0,
+            parent: None,
+            children: Vec::new(),
+            ..root.clone()
+        }];
+        let mut stack: Vec<(NodeId, NodeId)> = root.children.iter().rev().map(|&c| (c, 0)).collect();
+        while let Some((old_id, parent)) = stack.pop() {
+            let node = self.get_node(old_id)?;
+            let new_id = nodes.len() as NodeId;
+            nodes.push(Node {
+                id: new_id,
+                parent: Some(parent),
+                children: Vec::new(),
+                ..node.clone()
+            });
+            nodes[parent as usize].children.push(new_id);
+            stack.extend(node.children.iter().rev().map(|&c| (c, new_id)));
+        }
+
+        let timestamp = now();
+        Ok(Tree {
+            meta: TreeMetadata {
+                created: timestamp,
+                modified: timestamp,
+                ..self.meta.clone()
+            },
+            root: 0,
+            nodes,
+        })
+    }
+
+    /// Drop nodes no longer reachable from the root and close the gaps
+    ///
+    /// Kept nodes stay in arena order and get contiguous IDs; `parent` and
+    /// `children` are rewritten to match. Returns the old→new ID map,
+    /// indexed by old ID (`None` for dropped nodes).
+    pub fn compact(&mut self) -> Vec<Option<NodeId>> {
+        let mut reachable = vec![false; self.nodes.len()];
+        let mut stack = vec![self.root];
+        while let Some(id) = stack.pop() {
+            match reachable.get_mut(id as usize) {
+                Some(seen) if !*seen => *seen = true,
+                _ => continue,
+            }
+            stack.extend(&self.nodes[id as usize].children);
+        }
+
+        let mut ids = 0..;
+        let map: Vec<Option<NodeId>> = reachable
+            .iter()
+            .map(|&kept| if kept { ids.next() } else { None })
+            .collect();
+
+        let old = std::mem::take(&mut self.nodes);
+        self.nodes = old
+            .into_iter()
+            .zip(&map)
+            .filter_map(|(node, &new_id)| {
+                Some(Node {
+                    id: new_id?,
+                    parent: node.parent.and_then(|p| map.get(p as usize).copied().flatten()),
+                    children: node
+                        .children
+                        .iter()
+                        .filter_map(|&c| map.get(c as usize).copied().flatten())
+                        .collect(),
+                    ..node
+                })
+            })
+            .collect();
+        self.root = map[self.root as usize].unwrap_or(0);
+        self.meta.modified = now();
+        map
+    }
+
+    /// Apply `f` to the span of every node that has one
+    pub fn map_spans(&mut self, mut f: impl FnMut(&mut Span)) {
+        for span in self.nodes.iter_mut().filter_map(|node| node.span.as_mut()) {
+            f(span);
+        }
+        self.meta.modified = now();
+    }
+
+    /// Move every span by `offset_delta` bytes and `line_delta` lines
+    /// (clamped to the `u32` range), e.g. to place a separately parsed
+    /// fragment where it sits in a larger document
+    ///
+    /// Columns are kept, so they match when the fragment starts at the
+    /// start of a line. Lines of 0 (spans without line information) stay 0.
+    pub fn shift_spans(&mut self, offset_delta: i64, line_delta: i64) {
+        let shift = |value: u32, delta: i64| (i64::from(value) + delta).clamp(0, i64::from(u32::MAX)) as u32;
+        self.map_spans(|span| {
+            for position in [&mut span.start, &mut span.end] {
+                position.offset = shift(position.offset, offset_delta);
+                if position.line > 0 {
+                    position.line = shift(position.line, line_delta);
+                }
+            }
+        });
+    }
+
+    /// A hash of each reachable node's type, data and children, as 16 hex
+    /// digits, indexed by node ID (`None` for detached nodes)
+    ///
+    /// Spans and arena positions don't count, so the same content gets
+    /// the same ID in another parse (or elsewhere in the document). Within
+    /// a tree, the nth repeat (in pre-order) of a hash is rehashed with
+    /// `n`, so IDs are unique; repeats of a node shift when an identical
+    /// one is added before them.
+    pub fn content_ids(&self) -> Vec<Option<String>> {
+        let mut order = Vec::new();
+        let mut seen = vec![false; self.nodes.len()];
+        let mut stack = vec![self.root];
+        while let Some(id) = stack.pop() {
+            match seen.get_mut(id as usize) {
+                Some(seen) if !*seen => *seen = true,
+                _ => continue,
+            }
+            order.push(id);
+            stack.extend(self.nodes[id as usize].children.iter().rev());
+        }
+
+        // Children come after their parent in pre-order
+        let mut hashes = vec![0u64; self.nodes.len()];
+        for &id in order.iter().rev() {
+            let node = &self.nodes[id as usize];
+            let mut hash = fnv1a(FNV_OFFSET, node.node_type.as_str().as_bytes());
+            if let Some(data) = &node.data {
+                let sorted: std::collections::BTreeMap<_, _> = data.iter().collect();
+                let json = serde_json::to_string(&sorted).unwrap_or_default();
+                hash = fnv1a(fnv1a(hash, &[0]), json.as_bytes());
+            }
+            for &child in &node.children {
+                hash = fnv1a(hash, &hashes[child as usize].to_le_bytes());
+            }
+            hashes[id as usize] = hash;
+        }
+
+        let mut repeats: HashMap<u64, u64> = HashMap::new();
+        let mut ids = vec![None; self.nodes.len()];
+        for &id in &order {
+            let hash = hashes[id as usize];
+            let repeat = repeats.entry(hash).or_insert(0);
+            let unique = if *repeat == 0 { hash } else { fnv1a(hash, &repeat.to_le_bytes()) };
+            *repeat += 1;
+            ids[id as usize] = Some(format!("{unique:016x}"));
+        }
+        ids
+    }
+
+    /// The `n` most common node types, by count then name
+    pub fn most_common_types(&self, n: usize) -> Vec<(String, usize)> {
+        let mut counts: Vec<_> = self.type_histogram().into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(n);
+        counts
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// 64-bit FNV-1a, continuing from `hash`; stable across builds and
+/// platforms, unlike `DefaultHasher`
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(hash, |hash, &b| (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3))
 }
 
 #[cfg(test)]
@@ -223,6 +660,200 @@ mod tests {
         assert_eq!(tree.language(), "markdown");
     }
 
+    #[test]
+    fn test_line_ending_metadata() {
+        let tree = Tree::new("markdown", "a\nb\n");
+        assert_eq!(tree.line_ending(), "lf");
+        assert!(!tree.mixed_line_endings());
+
+        let tree = Tree::new("markdown", "a\r\nb\r\n");
+        assert_eq!(tree.line_ending(), "crlf");
+        assert!(!tree.mixed_line_endings());
+
+        let tree = Tree::new("markdown", "a\r\nb\nc\r\n");
+        assert_eq!(tree.line_ending(), "crlf");
+        assert!(tree.mixed_line_endings());
+    }
+
+    /// root → [heading → [text], paragraph → [text, strong → [heading]], heading]
+    fn outline_tree() -> Tree {
+        let mut tree = Tree::new("markdown", "");
+        let add = |tree: &mut Tree, parent: NodeId, node_type: &str| {
+            let id = tree.add_node(Node::new(0, node_type));
+            tree.add_child(parent, id).unwrap();
+            id
+        };
+        let h1 = add(&mut tree, 0, "heading");
+        add(&mut tree, h1, "text");
+        let para = add(&mut tree, 0, "paragraph");
+        add(&mut tree, para, "text");
+        let strong = add(&mut tree, para, "strong");
+        add(&mut tree, strong, "heading");
+        add(&mut tree, 0, "heading");
+        tree
+    }
+
+    fn types(tree: &Tree, ids: &[NodeId]) -> Vec<String> {
+        ids.iter()
+            .map(|&id| tree.get_node(id).unwrap().node_type.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_ancestors_and_depth() {
+        let tree = outline_tree();
+        let ancestors: Vec<_> = tree.ancestors(6).map(|node| node.id).collect();
+        assert_eq!(ancestors, [5, 3, 0]);
+        assert_eq!(types(&tree, &ancestors), ["strong", "paragraph", "root"]);
+        assert_eq!(tree.depth(6), 3);
+        assert_eq!(tree.depth(1), 1);
+
+        assert_eq!(tree.depth(tree.root_id()), 0);
+        assert_eq!(tree.ancestors(tree.root_id()).count(), 0);
+        assert_eq!(tree.ancestors(99).count(), 0);
+
+        let mut tree = tree;
+        tree.remove_node(3).unwrap();
+        assert_eq!(tree.ancestors(6).map(|node| node.id).collect::<Vec<_>>(), [5, 3]);
+    }
+
+    #[test]
+    fn test_prune_to_flat_headings() {
+        let mut tree = outline_tree();
+        tree.prune(|n| n.node_type == NodeType::Heading, PruneMode::PromoteChildren);
+
+        assert_eq!(tree.node_count(), 4);
+        let children = tree.children_of(tree.root_id()).to_vec();
+        assert_eq!(types(&tree, &children), ["heading", "heading", "heading"]);
+        for (i, node) in tree.nodes().iter().enumerate() {
+            assert_eq!(node.id as usize, i);
+            assert!(node.children.is_empty() || i == 0);
+        }
+        assert!(children.iter().all(|&id| tree.get_node(id).unwrap().parent == Some(0)));
+    }
+
+    #[test]
+    fn test_prune_drop_subtree() {
+        let mut tree = outline_tree();
+        tree.prune(|n| n.node_type == NodeType::Heading, PruneMode::DropSubtree);
+
+        // The heading inside the paragraph goes with it
+        let children = tree.children_of(tree.root_id()).to_vec();
+        assert_eq!(types(&tree, &children), ["heading", "heading"]);
+        assert_eq!(tree.node_count(), 3);
+
+        let mut tree = outline_tree();
+        tree.prune(|n| n.node_type != NodeType::Text, PruneMode::DropSubtree);
+        assert_eq!(tree.type_histogram().get("text"), None);
+        assert_eq!(tree.node_count(), 6);
+    }
+
+    #[test]
+    fn test_compact_after_removing_middle_node() {
+        let mut tree = outline_tree();
+        // Detach the paragraph (3); it and its subtree (4–6) become orphans
+        tree.get_node_mut(0).unwrap().children.retain(|&id| id != 3);
+
+        let map = tree.compact();
+        assert_eq!(map, [Some(0), Some(1), Some(2), None, None, None, None, Some(3)]);
+        assert_eq!(tree.node_count(), 4);
+        for (i, node) in tree.nodes().iter().enumerate() {
+            assert_eq!(node.id as usize, i);
+            for &child in &node.children {
+                assert_eq!(tree.get_node(child).unwrap().parent, Some(node.id));
+            }
+        }
+        assert_eq!(tree.children_of(0), [1, 3]);
+        assert_eq!(tree.children_of(1), [2]);
+        assert_eq!(types(&tree, &[1, 2, 3]), ["heading", "text", "heading"]);
+
+        // Nothing left to drop
+        assert_eq!(tree.compact(), [Some(0), Some(1), Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn test_shift_spans() {
+        let mut tree = outline_tree();
+        tree.get_node_mut(1).unwrap().span = Some(Span::from_coords(1, 2, 2, 2, 4, 10));
+        tree.get_node_mut(2).unwrap().span = Some(Span::from_coords(0, 0, 3, 0, 0, 5));
+
+        tree.shift_spans(100, 4);
+        assert_eq!(tree.get_node(1).unwrap().span, Some(Span::from_coords(5, 2, 102, 6, 4, 110)));
+        // No line information to shift; nodes without spans stay so
+        assert_eq!(tree.get_node(2).unwrap().span, Some(Span::from_coords(0, 0, 103, 0, 0, 105)));
+        assert_eq!(tree.get_node(3).unwrap().span, None);
+
+        tree.shift_spans(-105, -5);
+        assert_eq!(tree.get_node(1).unwrap().span, Some(Span::from_coords(0, 2, 0, 1, 4, 5)));
+
+        tree.map_spans(|span| span.end.column += 1);
+        assert_eq!(tree.get_node(1).unwrap().span.unwrap().end.column, 5);
+    }
+
+    #[test]
+    fn test_clone_subtree() {
+        let mut tree = outline_tree();
+        tree.get_node_mut(5).unwrap().span = Some(Span::from_coords(1, 2, 2, 1, 4, 4));
+
+        // The paragraph (3) with its text (4) and strong (5) > heading (6)
+        let sub = tree.clone_subtree(3).unwrap();
+        assert_eq!(sub.node_count(), 4);
+        assert_eq!(sub.root().parent, None);
+        for (i, node) in sub.nodes().iter().enumerate() {
+            assert_eq!(node.id as usize, i);
+            for &child in &node.children {
+                assert_eq!(sub.get_node(child).unwrap().parent, Some(node.id));
+            }
+        }
+        assert_eq!(types(&sub, &[0, 1, 2, 3]), ["paragraph", "text", "strong", "heading"]);
+        assert_eq!(sub.children_of(0), [1, 2]);
+        assert_eq!(sub.get_node(2).unwrap().span, tree.get_node(5).unwrap().span);
+        assert_eq!(sub.language(), tree.language());
+
+        // The original is untouched
+        assert_eq!(tree.node_count(), 8);
+        assert_eq!(tree.clone_subtree(7).unwrap().node_count(), 1);
+        assert!(matches!(tree.clone_subtree(8), Err(SynthError::InvalidNodeId(8))));
+    }
+
+    #[test]
+    fn test_remove_node_errors() {
+        let mut tree = outline_tree();
+
+        let err = tree.remove_node(99).unwrap_err();
+        assert!(matches!(err, SynthError::InvalidNodeId(99)));
+        assert_eq!(err.to_string(), "Invalid node ID: 99");
+        let json = tree.error_json(&err);
+        assert_eq!((json["kind"].as_str(), json["id"].as_u64()), (Some("InvalidNodeId"), Some(99)));
+        assert_eq!(json["nodeCount"], 8);
+        assert!(err.to_json().get("nodeCount").is_none());
+
+        assert!(matches!(tree.remove_node(0), Err(SynthError::TreeStructureError(_))));
+
+        tree.remove_node(3).unwrap();
+        assert_eq!(tree.children_of(0), [1, 7]);
+        // Removing it again is an error, not a silent no-op
+        let err = tree.remove_node(3).unwrap_err();
+        assert_eq!(err.to_string(), "Tree structure error: node 3 is already detached");
+    }
+
+    #[test]
+    fn test_insert_child() {
+        let mut tree = outline_tree();
+        // Move the last heading (7) to the front
+        tree.insert_child(0, 0, 7).unwrap();
+        assert_eq!(tree.children_of(0), [7, 1, 3]);
+
+        let err = tree.insert_child(0, 4, 1).unwrap_err();
+        assert!(matches!(err, SynthError::ChildIndexOutOfRange { parent: 0, index: 4, len: 2 }));
+
+        // The paragraph (3) can't go under its own descendant (strong, 5)
+        let err = tree.insert_child(5, 0, 3).unwrap_err();
+        assert!(matches!(err, SynthError::CycleDetected { node: 3, parent: 5 }));
+        assert_eq!(err.to_json()["kind"], "CycleDetected");
+        assert_eq!(tree.get_node(3).unwrap().parent, Some(0));
+    }
+
     #[test]
     fn test_add_node() {
         let mut tree = Tree::new("markdown", "# Hello");
@@ -233,4 +864,181 @@ mod tests {
         assert_eq!(id, 1);
         assert_eq!(tree.node_count(), 2);
     }
+
+    #[test]
+    fn test_node_json_roundtrip_keeps_type_strings() {
+        let mut tree = Tree::new("markdown", "---");
+        let hr = tree.add_node(Node::new(0, "thematicBreak"));
+        let custom = tree.add_node(Node::new(0, "myDirective"));
+        tree.add_child(tree.root_id(), hr).unwrap();
+        tree.add_child(tree.root_id(), custom).unwrap();
+
+        let json = serde_json::to_value(&tree).unwrap();
+        let types: Vec<&str> = json["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|n| n["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(types, ["root", "thematicBreak", "myDirective"]);
+
+        let back: Tree = serde_json::from_value(json).unwrap();
+        assert_eq!(back.nodes()[1].node_type, NodeType::ThematicBreak);
+        assert_eq!(back.nodes()[2].node_type, NodeType::Other("myDirective".into()));
+    }
+
+    #[test]
+    fn test_content_ids() {
+        let with_text = |tree: &mut Tree, value: &str| {
+            let para = tree.add_node(Node::new(0, "paragraph"));
+            tree.add_child(0, para).unwrap();
+            let mut text = Node::new(0, "text");
+            text.data = Some(HashMap::from([
+                ("value".to_string(), serde_json::json!(value)),
+                ("lang".to_string(), serde_json::json!(null)),
+            ]));
+            let text = tree.add_node(text);
+            tree.add_child(para, text).unwrap();
+            para
+        };
+        let mut tree = Tree::new("markdown", "");
+        let a = with_text(&mut tree, "a");
+        let b = with_text(&mut tree, "b");
+        let a2 = with_text(&mut tree, "a");
+        let ids = tree.content_ids();
+        assert!(ids.iter().all(|id| id.as_ref().is_some_and(|id| id.len() == 16)));
+        let unique: std::collections::HashSet<_> = ids.iter().collect();
+        assert_eq!(unique.len(), ids.len());
+
+        // Same content, other arena positions and spans: same IDs
+        let mut other = Tree::new("markdown", "");
+        let b_other = with_text(&mut other, "b");
+        other.get_node_mut(b_other).unwrap().span = Some(Span::from_coords(1, 0, 0, 1, 1, 1));
+        let a_other = with_text(&mut other, "a");
+        assert_eq!(other.content_ids()[b_other as usize], ids[b as usize]);
+        assert_eq!(other.content_ids()[a_other as usize], ids[a as usize]);
+        assert_ne!(ids[a2 as usize], ids[a as usize]);
+
+        tree.remove_node(b).unwrap();
+        let ids_after = tree.content_ids();
+        assert_eq!(ids_after[b as usize], None);
+        assert_eq!(ids_after[a as usize], ids[a as usize]);
+        assert_ne!(ids_after[0], ids[0]);
+    }
+
+    #[test]
+    fn test_data_serializes_with_sorted_keys() {
+        let keys: Vec<String> = (0..32).map(|i| format!("k{i:02}")).collect();
+        let node_with = |keys: &mut dyn Iterator<Item = &String>| {
+            let mut node = Node::new(1, "text");
+            node.data = Some(keys.map(|k| (k.clone(), serde_json::json!(k))).collect());
+            node
+        };
+        let mut tree = Tree::new("markdown", "");
+        let id = tree.add_node(node_with(&mut keys.iter()));
+        tree.add_child(0, id).unwrap();
+
+        let json = serde_json::to_string(&tree).unwrap();
+        assert_eq!(serde_json::to_string(&tree).unwrap(), json);
+        assert_eq!(serde_json::to_string(&tree.clone()).unwrap(), json);
+
+        // Separately built maps hash differently but serialize the same
+        let forward = serde_json::to_string(&node_with(&mut keys.iter())).unwrap();
+        let reverse = serde_json::to_string(&node_with(&mut keys.iter().rev())).unwrap();
+        assert_eq!(forward, reverse);
+        let positions: Vec<_> = keys.iter().map(|k| forward.find(&format!("\"{k}\":")).unwrap()).collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_to_json_pretty_is_stable() {
+        let build = || {
+            let mut tree = Tree::new("markdown", "# Hi");
+            let mut heading = Node::new(0, "heading").with_span(Span::from_coords(1, 0, 0, 1, 4, 4));
+            heading.data = Some(HashMap::from([
+                ("depth".to_string(), serde_json::json!(1)),
+                ("id".to_string(), serde_json::json!("hi")),
+                ("attrs".to_string(), serde_json::json!({ "z": 1, "a": 2 })),
+            ]));
+            let heading = tree.add_node(heading);
+            tree.add_child(0, heading).unwrap();
+            tree
+        };
+        let first = build();
+        let mut second = build();
+        second.meta.created += 1000;
+        second.meta.modified += 1000;
+
+        let json = first.to_json_pretty().unwrap();
+        assert_eq!(second.to_json_pretty().unwrap(), json);
+        assert!(json.contains("\n  \"meta\": {\n"));
+        assert!(!json.contains("created"));
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["nodes"][1]["data"]["attrs"]["z"], 1);
+        let keys: Vec<_> = value["nodes"][1].as_object().unwrap().keys().cloned().collect();
+        assert_eq!(keys, ["children", "data", "id", "parent", "span", "type"]);
+        let attrs = json.find("\"attrs\"").unwrap();
+        assert!(json[attrs..].find("\"a\"").unwrap() < json[attrs..].find("\"z\"").unwrap());
+    }
+
+    #[test]
+    fn test_contains_type() {
+        let mut tree = Tree::new("markdown", "");
+        for t in ["heading", "paragraph", "text"] {
+            let id = tree.add_node(Node::new(0, t));
+            tree.add_child(tree.root_id(), id).unwrap();
+        }
+        assert!(tree.contains_type("paragraph"));
+        assert!(tree.contains_type("root"));
+        assert!(!tree.contains_type("code"));
+        assert!(!tree.contains_type("Code"));
+
+        let code = tree.add_node(Node::new(0, "code"));
+        tree.add_child(tree.root_id(), code).unwrap();
+        assert!(tree.contains_type("code"));
+
+        tree.remove_node(code).unwrap();
+        assert!(tree.contains_type("code"));
+        tree.compact();
+        assert!(!tree.contains_type("code"));
+    }
+
+    #[test]
+    fn test_type_histogram() {
+        let mut tree = Tree::new("markdown", "");
+        for t in ["heading", "paragraph", "code", "paragraph", "heading", "paragraph"] {
+            let id = tree.add_node(Node::new(0, t));
+            tree.add_child(tree.root_id(), id).unwrap();
+        }
+
+        let histogram = tree.type_histogram();
+        assert_eq!(histogram["root"], 1);
+        assert_eq!(histogram["heading"], 2);
+        assert_eq!(histogram["paragraph"], 3);
+        assert_eq!(histogram["code"], 1);
+        assert_eq!(histogram.len(), 4);
+
+        assert_eq!(
+            tree.most_common_types(2),
+            vec![("paragraph".to_string(), 3), ("heading".to_string(), 2)]
+        );
+        assert_eq!(tree.most_common_types(10).len(), 4);
+    }
+
+    #[test]
+    fn test_root_and_children_of() {
+        let mut tree = Tree::new("markdown", "# Hello");
+        assert_eq!(tree.root().node_type, "root");
+        assert!(tree.children_of(tree.root_id()).is_empty());
+
+        let a = tree.add_node(Node::new(0, "heading"));
+        let b = tree.add_node(Node::new(0, "paragraph"));
+        tree.add_child(tree.root_id(), a).unwrap();
+        tree.add_child(tree.root_id(), b).unwrap();
+
+        assert_eq!(tree.children_of(tree.root_id()), &[a, b]);
+        assert_eq!(tree.root().children, vec![a, b]);
+        assert!(tree.children_of(99).is_empty());
+    }
 }
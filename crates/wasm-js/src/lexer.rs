@@ -5,6 +5,42 @@
 
 use memchr::memchr;
 
+/// Define `TokenKind` along with its name table, so the two can't drift
+macro_rules! token_kinds {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $($variant:ident $(= $value:literal)?,)*
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($variant $(= $value)?,)*
+        }
+
+        impl $name {
+            /// Every kind, in discriminant order
+            pub const ALL: &'static [$name] = &[$($name::$variant,)*];
+
+            /// Variant name, e.g. `"Identifier"` or `"QuestionDot"`
+            pub fn name(self) -> &'static str {
+                match self {
+                    $($name::$variant => stringify!($variant),)*
+                }
+            }
+
+            /// Kind for a variant name (the inverse of `name`)
+            pub fn from_name(name: &str) -> Option<$name> {
+                match name {
+                    $(stringify!($variant) => Some($name::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+token_kinds! {
 /// Token types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -14,7 +50,6 @@ pub enum TokenKind {
     Number,
     String,
     Template,
-    #[allow(dead_code)]
     Regex,
     BigInt,
 
@@ -151,19 +186,100 @@ pub enum TokenKind {
     Eof,
     Invalid,
 }
+}
 
 /// A token with position info
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Token {
     pub kind: TokenKind,
     pub start: u32,
     pub end: u32,
 }
 
+impl TokenKind {
+    /// Kind for a numeric discriminant
+    pub fn from_u8(kind: u8) -> Option<TokenKind> {
+        TokenKind::ALL.iter().copied().find(|&k| k as u8 == kind)
+    }
+
+    /// Whether this is an identifier or a (possibly contextual) keyword,
+    /// i.e. something that can name a binding or property
+    pub fn is_word(self) -> bool {
+        // Keywords are declared contiguously, from `Await` to `Module`
+        self == TokenKind::Identifier
+            || (TokenKind::Await as u8..=TokenKind::Module as u8).contains(&(self as u8))
+    }
+
+    /// Whether this is a reserved word in module code: the keywords from
+    /// `Await` to `Yield`, not the contextual ones after them
+    pub fn is_reserved(self) -> bool {
+        (TokenKind::Await as u8..=TokenKind::Yield as u8).contains(&(self as u8))
+    }
+
+    /// Whether a `/` following this token starts a regex rather than a division
+    ///
+    /// A `/` after an operand (identifier, literal, `)`, `]`, `}`) divides;
+    /// after operators, punctuation and keywords like `return`/`typeof`
+    /// it starts a regex literal.
+    fn allows_regex(self) -> bool {
+        !matches!(
+            self,
+            TokenKind::Identifier
+                | TokenKind::Number
+                | TokenKind::String
+                | TokenKind::Template
+                | TokenKind::Regex
+                | TokenKind::BigInt
+                | TokenKind::RParen
+                | TokenKind::RBracket
+                | TokenKind::RBrace
+                | TokenKind::PlusPlus
+                | TokenKind::MinusMinus
+                | TokenKind::This
+                | TokenKind::Super
+                | TokenKind::True
+                | TokenKind::False
+                | TokenKind::Null
+                // Contextual keywords are usually plain identifiers
+                | TokenKind::As
+                | TokenKind::Async
+                | TokenKind::From
+                | TokenKind::Get
+                | TokenKind::Set
+                | TokenKind::Static
+                | TokenKind::Type
+                | TokenKind::Interface
+                | TokenKind::Implements
+                | TokenKind::Private
+                | TokenKind::Protected
+                | TokenKind::Public
+                | TokenKind::Readonly
+                | TokenKind::Declare
+                | TokenKind::Abstract
+                | TokenKind::Namespace
+                | TokenKind::Module
+        )
+    }
+}
+
+/// Where a lexer stands between two tokens
+///
+/// Saved with `Lexer::state` and restored with `Lexer::resume`, so a
+/// caller that keeps states along a file can lex from near any offset
+/// instead of from the start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LexerState {
+    pub pos: u32,
+    pub prev: TokenKind,
+}
+
 /// JavaScript Lexer
 pub struct Lexer<'a> {
     src: &'a [u8],
     pos: usize,
+    /// Last significant token (comments and newlines excluded), used to
+    /// tell a regex from a division
+    prev: TokenKind,
 }
 
 impl<'a> Lexer<'a> {
@@ -171,9 +287,27 @@ impl<'a> Lexer<'a> {
         Self {
             src: source.as_bytes(),
             pos: 0,
+            // Start of input: `/` begins a regex
+            prev: TokenKind::Semicolon,
+        }
+    }
+
+    /// Lexer for `source` continuing from a saved `state`
+    ///
+    /// `state` must come from `state()` on a lexer over the same source.
+    pub fn resume(source: &'a str, state: LexerState) -> Self {
+        Self {
+            src: source.as_bytes(),
+            pos: (state.pos as usize).min(source.len()),
+            prev: state.prev,
         }
     }
 
+    /// Current position and regex context, for `resume`
+    pub fn state(&self) -> LexerState {
+        LexerState { pos: self.pos as u32, prev: self.prev }
+    }
+
     #[inline]
     fn byte(&self, pos: usize) -> Option<u8> {
         self.src.get(pos).copied()
@@ -261,6 +395,13 @@ impl<'a> Lexer<'a> {
             }
         };
 
+        if !matches!(
+            kind,
+            TokenKind::Newline | TokenKind::LineComment | TokenKind::BlockComment
+        ) {
+            self.prev = kind;
+        }
+
         Token {
             kind,
             start,
@@ -388,8 +529,9 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        // Decimal part
-        if self.current() == Some(b'.') && self.peek().map(|b| b.is_ascii_digit()).unwrap_or(false) {
+        // Decimal part; a trailing `.` belongs to the number too, so
+        // `1..toString()` is `1.` then `.toString`
+        if self.current() == Some(b'.') {
             self.pos += 1;
             while let Some(b) = self.current() {
                 match b {
@@ -432,7 +574,8 @@ impl<'a> Lexer<'a> {
         while let Some(b) = self.current() {
             match b {
                 b'\\' => {
-                    self.pos += 2; // skip escape
+                    // skip escape, but not past a trailing backslash
+                    self.pos = (self.pos + 2).min(self.src.len());
                 }
                 b'\n' => break, // unterminated
                 _ if b == quote => {
@@ -451,7 +594,7 @@ impl<'a> Lexer<'a> {
 
         while let Some(b) = self.current() {
             match b {
-                b'\\' => self.pos += 2,
+                b'\\' => self.pos = (self.pos + 2).min(self.src.len()),
                 b'`' => {
                     self.pos += 1;
                     return TokenKind::Template;
@@ -564,11 +707,50 @@ impl<'a> Lexer<'a> {
                 self.pos = self.src.len();
                 TokenKind::BlockComment
             }
+            _ if self.prev.allows_regex() && self.scan_regex() => TokenKind::Regex,
             Some(b'=') => { self.pos += 1; TokenKind::SlashEq }
             _ => TokenKind::Slash,
         }
     }
 
+    /// Scan a regex body and flags after the opening `/`
+    ///
+    /// Returns false (position unchanged) if the line ends before the
+    /// closing `/`, so the caller falls back to a division token.
+    fn scan_regex(&mut self) -> bool {
+        let start = self.pos;
+        let mut in_class = false;
+        loop {
+            match self.current() {
+                None | Some(b'\n') => {
+                    self.pos = start;
+                    return false;
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    if matches!(self.current(), None | Some(b'\n')) {
+                        self.pos = start;
+                        return false;
+                    }
+                }
+                Some(b'[') => in_class = true,
+                Some(b']') => in_class = false,
+                Some(b'/') if !in_class => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+
+        // Flags
+        while let Some(b'a'..=b'z' | b'A'..=b'Z') = self.current() {
+            self.pos += 1;
+        }
+        true
+    }
+
     fn scan_percent(&mut self) -> TokenKind {
         self.pos += 1;
         if self.current() == Some(b'=') {
@@ -710,6 +892,27 @@ impl<'a> Lexer<'a> {
         }
         tokens
     }
+
+    /// Tokens overlapping `start..end`, lexing from the current state
+    ///
+    /// Tokens ending at or before `start` are skipped without being kept
+    /// and lexing stops at the first token past `end`, so resuming from a
+    /// state saved near `start` only lexes the range itself. An empty
+    /// range returns the token containing `start`, if any. `Eof` is never
+    /// included.
+    pub fn tokens_in_range(&mut self, start: u32, end: u32) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next_token();
+            if token.kind == TokenKind::Eof || token.start >= end.max(start + 1) {
+                break;
+            }
+            if token.end > start {
+                tokens.push(token);
+            }
+        }
+        tokens
+    }
 }
 
 #[cfg(test)]
@@ -726,6 +929,44 @@ mod tests {
         assert_eq!(lexer.next_token().kind, TokenKind::Class);
     }
 
+    #[test]
+    fn test_keyword_prefixed_identifiers() {
+        for source in ["instanceofx", "instanceofFoo", "inner", "in_"] {
+            let tokens = Lexer::new(source).tokenize();
+            assert_eq!(tokens.len(), 2, "{source}");
+            assert_eq!(tokens[0].kind, TokenKind::Identifier, "{source}");
+            assert_eq!(tokens[0].end as usize, source.len());
+        }
+        let kinds: Vec<_> = Lexer::new("a instanceof B in c")
+            .tokenize()
+            .iter()
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::Identifier,
+                TokenKind::Instanceof,
+                TokenKind::Identifier,
+                TokenKind::In,
+                TokenKind::Identifier,
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_kind_names_round_trip() {
+        assert_eq!(TokenKind::ALL.len(), TokenKind::Invalid as usize);
+        for &kind in TokenKind::ALL {
+            assert_eq!(TokenKind::from_name(kind.name()), Some(kind));
+            assert_eq!(TokenKind::from_u8(kind as u8), Some(kind));
+        }
+        assert_eq!(TokenKind::QuestionDot.name(), "QuestionDot");
+        assert_eq!(TokenKind::from_name("questionDot"), None);
+        assert_eq!(TokenKind::from_u8(0), None);
+    }
+
     #[test]
     fn test_operators() {
         let mut lexer = Lexer::new("=== !== ?? ?. => ...");
@@ -748,6 +989,21 @@ mod tests {
         assert_eq!(lexer.next_token().kind, TokenKind::Number);
     }
 
+    #[test]
+    fn test_trailing_decimal_point() {
+        let source = "1..toString 1.5.toString 1.e2 0x1.a";
+        let mut lexer = Lexer::new(source);
+        let mut texts = Vec::new();
+        loop {
+            let token = lexer.next_token();
+            if token.kind == TokenKind::Eof {
+                break;
+            }
+            texts.push(&source[token.start as usize..token.end as usize]);
+        }
+        assert_eq!(texts, ["1.", ".", "toString", "1.5", ".", "toString", "1.e2", "0x1", ".", "a"]);
+    }
+
     #[test]
     fn test_strings() {
         let mut lexer = Lexer::new(r#""hello" 'world' `template`"#);
@@ -756,6 +1012,46 @@ mod tests {
         assert_eq!(lexer.next_token().kind, TokenKind::Template);
     }
 
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        Lexer::new(source)
+            .tokenize()
+            .into_iter()
+            .map(|t| t.kind)
+            .filter(|k| *k != TokenKind::Eof)
+            .collect()
+    }
+
+    #[test]
+    fn test_regex_after_assignment() {
+        use TokenKind::*;
+        assert_eq!(kinds("const r = /a/g"), vec![Const, Identifier, Eq, Regex]);
+        assert_eq!(kinds("x = /[/]\\//"), vec![Identifier, Eq, Regex]);
+    }
+
+    #[test]
+    fn test_regex_after_arrow_and_in_arrays() {
+        use TokenKind::*;
+        assert_eq!(
+            kinds("arr.map(x => /x/.test(x))"),
+            vec![
+                Identifier, Dot, Identifier, LParen, Identifier, Arrow, Regex, Dot, Identifier,
+                LParen, Identifier, RParen, RParen,
+            ]
+        );
+        assert_eq!(kinds("[/a/, /b/]"), vec![LBracket, Regex, Comma, Regex, RBracket]);
+        assert_eq!(kinds("return /a/"), vec![Return, Regex]);
+        assert_eq!(kinds("typeof /a/"), vec![Typeof, Regex]);
+    }
+
+    #[test]
+    fn test_slash_after_operand_is_division() {
+        use TokenKind::*;
+        assert_eq!(kinds("a / b / c"), vec![Identifier, Slash, Identifier, Slash, Identifier]);
+        assert_eq!(kinds("(a) / 2"), vec![LParen, Identifier, RParen, Slash, Number]);
+        assert_eq!(kinds("x /= 2"), vec![Identifier, SlashEq, Number]);
+        assert_eq!(kinds("a\n/ b"), vec![Identifier, Newline, Slash, Identifier]);
+    }
+
     #[test]
     fn test_comments() {
         let mut lexer = Lexer::new("// line\n/* block */");
@@ -763,4 +1059,37 @@ mod tests {
         assert_eq!(lexer.next_token().kind, TokenKind::Newline);
         assert_eq!(lexer.next_token().kind, TokenKind::BlockComment);
     }
+
+    #[test]
+    fn test_tokens_in_range_match_full_tokenization() {
+        let source = "const re = /a\\/b/g;\n// note\nlet x = a / b + `t ${y}`;\nfoo(/* c */ 1);\n";
+        let full = Lexer::new(source).tokenize();
+        let overlapping = |start: u32, end: u32| -> Vec<Token> {
+            full.iter()
+                .copied()
+                .filter(|t| t.kind != TokenKind::Eof && t.end > start && t.start < end)
+                .collect()
+        };
+
+        let len = source.len() as u32;
+        for (start, end) in [(0, len), (0, 5), (8, 14), (12, 40), (27, 29), (45, 60), (len - 3, len)] {
+            let from_start = Lexer::new(source).tokens_in_range(start, end);
+            assert_eq!(from_start, overlapping(start, end), "{start}..{end}");
+            assert!(!from_start.is_empty());
+
+            // Resuming from a state saved before the range gives the same tokens
+            let mut lexer = Lexer::new(source);
+            let mut state = lexer.state();
+            while lexer.next_token().end <= start {
+                state = lexer.state();
+            }
+            let resumed = Lexer::resume(source, state).tokens_in_range(start, end);
+            assert_eq!(resumed, from_start, "{start}..{end}");
+        }
+
+        // An empty range gives the token containing it
+        let inside_regex = Lexer::new(source).tokens_in_range(13, 13);
+        assert_eq!(inside_regex.len(), 1);
+        assert_eq!(inside_regex[0].kind, TokenKind::Regex);
+    }
 }
@@ -3,11 +3,15 @@
 //! High-performance JS/TS parser compiled to WebAssembly.
 //! Supports ES2024 syntax.
 
+#![forbid(unsafe_code)]
+
 mod lexer;
 mod parser;
 
-use lexer::Lexer;
-use parser::Parser;
+use lexer::{Lexer, LexerState, Token, TokenKind};
+use parser::{Diagnostic, JsonMode, Parser, Symbol};
+use std::collections::HashMap;
+use synth_wasm_core::{ColumnUnit, LineIndex, Node, NodeId, Span, SynthResult, Tree};
 use wasm_bindgen::prelude::*;
 
 /// Count tokens (for benchmarking)
@@ -18,20 +22,456 @@ pub fn tokenize(source: &str) -> usize {
     tokens.len()
 }
 
+/// Tokenize and return `[{ kind, start, end }, ...]` as a JSON string
+///
+/// `kind` is the token kind's name (see `tokenKindName`); `Eof` is omitted.
+#[wasm_bindgen(js_name = tokenizeToJson)]
+pub fn tokenize_to_json(source: &str) -> String {
+    tokens_to_json(Lexer::new(source).tokenize())
+}
+
+/// Tokens overlapping `start..end`, in the same format as `tokenizeToJson`
+///
+/// Tokens before the range are lexed but not kept, and lexing stops past
+/// `end`. An empty range gives the token containing `start`, if any.
+/// With a `checkpoint` from `lexerCheckpoint`, lexing resumes there
+/// instead of at the start of the source; the source before the
+/// checkpoint must not have changed since it was taken.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { lexerCheckpoint, tokensInRange } from '@sylphx/synth-wasm-js';
+///
+/// const checkpoint = lexerCheckpoint(source, viewportStart);
+/// // Later, after edits below the viewport start
+/// const tokens = JSON.parse(tokensInRange(source, viewportStart, viewportEnd, checkpoint));
+/// ```
+#[wasm_bindgen(js_name = tokensInRange)]
+pub fn tokens_in_range(source: &str, start: u32, end: u32, checkpoint: Option<Vec<u32>>) -> Result<String, JsValue> {
+    let mut lexer = match checkpoint.as_deref() {
+        None => Lexer::new(source),
+        Some(&[pos, prev]) => {
+            let prev = u8::try_from(prev).ok().and_then(TokenKind::from_u8);
+            let Some(prev) = prev.filter(|_| pos <= start) else {
+                return Err(JsValue::from_str("invalid lexer checkpoint"));
+            };
+            Lexer::resume(source, LexerState { pos, prev })
+        }
+        Some(_) => return Err(JsValue::from_str("invalid lexer checkpoint")),
+    };
+    Ok(tokens_to_json(lexer.tokens_in_range(start, end)))
+}
+
+/// Lexer state after the last token ending at or before `offset`, as
+/// `[pos, prevKind]`, for resuming `tokensInRange` there
+#[wasm_bindgen(js_name = lexerCheckpoint)]
+pub fn lexer_checkpoint(source: &str, offset: u32) -> Vec<u32> {
+    let mut lexer = Lexer::new(source);
+    let mut state = lexer.state();
+    loop {
+        let token = lexer.next_token();
+        if token.kind == TokenKind::Eof || token.end > offset {
+            break;
+        }
+        state = lexer.state();
+    }
+    vec![state.pos, state.prev as u32]
+}
+
+/// Tokens from flat `[kind, start, end, ...]` triples over `source`
+fn tokens_from_triples(source: &str, triples: &[u32]) -> Result<Vec<Token>, String> {
+    let (triples, rest) = triples.as_chunks::<3>();
+    if !rest.is_empty() {
+        return Err("tokens must be [kind, start, end] triples".to_string());
+    }
+    triples
+        .iter()
+        .map(|&[kind, start, end]| {
+            let kind = u8::try_from(kind)
+                .ok()
+                .and_then(TokenKind::from_u8)
+                .ok_or_else(|| format!("unknown token kind {kind}"))?;
+            if start > end || end as usize > source.len() {
+                return Err(format!("token span {start}..{end} is outside the source"));
+            }
+            Ok(Token { kind, start, end })
+        })
+        .collect()
+}
+
+/// `[{ kind, start, end }, ...]` JSON for `tokens`, without `Eof`
+fn tokens_to_json(tokens: Vec<Token>) -> String {
+    let tokens: Vec<_> = tokens
+        .into_iter()
+        .filter(|t| t.kind != TokenKind::Eof)
+        .map(|t| serde_json::json!({ "kind": t.kind.name(), "start": t.start, "end": t.end }))
+        .collect();
+    serde_json::Value::Array(tokens).to_string()
+}
+
+/// Name of a numeric token kind (as in `Parser.tokens()`), e.g. `"Identifier"`
+#[wasm_bindgen(js_name = tokenKindName)]
+pub fn token_kind_name(kind: u8) -> Option<String> {
+    TokenKind::from_u8(kind).map(|k| k.name().to_string())
+}
+
+/// Numeric token kind for a name (the inverse of `tokenKindName`)
+#[wasm_bindgen(js_name = tokenKindFromName)]
+pub fn token_kind_from_name(name: &str) -> Option<u8> {
+    TokenKind::from_name(name).map(|k| k as u8)
+}
+
 /// Parse JavaScript and return node count (for benchmarking)
+///
+/// The count includes the `Program` root, like the markdown `parseCount`
+/// includes its root.
 #[wasm_bindgen(js_name = parseCount)]
 pub fn parse_count(source: &str) -> usize {
     let mut parser = Parser::new(source);
     parser.parse_count()
 }
 
+/// Parse JavaScript and return node count without the `Program` root
+#[wasm_bindgen(js_name = parseCountExcludingRoot)]
+pub fn parse_count_excluding_root(source: &str) -> usize {
+    let mut parser = Parser::new(source);
+    parser.parse_count_excluding_root()
+}
+
 /// Parse JavaScript and return binary AST
+///
+/// With `maxNodes`, parsing stops after the top-level statement that
+/// takes the node count past it, and the `Program` ends there; bounds the
+/// work done on untrusted input.
+///
+/// `cancel` is an optional `Int32Array` over a `SharedArrayBuffer`,
+/// checked every few top-level statements: once another thread stores a
+/// non-zero first element, parsing stops the same way.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { parseBinary } from '@sylphx/synth-wasm-js';
+///
+/// // In a worker; the page calls `Atomics.store(cancel, 0, 1)` to stop it
+/// const cancel = new Int32Array(sharedBuffer);
+/// const buffer = parseBinary(hugeSource, 1_000_000, cancel);
+/// ```
 #[wasm_bindgen(js_name = parseBinary)]
-pub fn parse_binary(source: &str) -> Vec<u8> {
-    let mut parser = Parser::new(source);
+pub fn parse_binary(source: &str, max_nodes: Option<usize>, cancel: Option<js_sys::Int32Array>) -> Vec<u8> {
+    let mut parser = configure(Parser::new(source), max_nodes, cancel.as_ref());
     parser.parse_binary()
 }
 
+/// Parse TypeScript and return binary AST
+///
+/// Same format, `maxNodes` and `cancel` as `parseBinary`, with TS-only
+/// declarations (enums, namespaces, `declare`, `export =`,
+/// `import x = require()`) recognized.
+#[wasm_bindgen(js_name = parseBinaryTs)]
+pub fn parse_binary_ts(source: &str, max_nodes: Option<usize>, cancel: Option<js_sys::Int32Array>) -> Vec<u8> {
+    let mut parser = configure(Parser::new(source).with_typescript(true), max_nodes, cancel.as_ref());
+    parser.parse_binary()
+}
+
+/// Apply the `maxNodes` and `cancel` export arguments to `parser`
+fn configure<'a>(parser: Parser<'a>, max_nodes: Option<usize>, cancel: Option<&'a js_sys::Int32Array>) -> Parser<'a> {
+    let parser = parser.with_max_nodes(max_nodes);
+    match cancel {
+        Some(cancel) => parser.with_cancel_flag(cancel),
+        None => parser,
+    }
+}
+
+/// Parse in strict mode and return binary AST
+///
+/// Same format as `parseBinary` (as TypeScript when `typescript` is true),
+/// but throws on the first construct the parser would otherwise skip over.
+#[wasm_bindgen(js_name = parseBinaryStrict)]
+pub fn parse_binary_strict(source: &str, typescript: Option<bool>) -> Result<Vec<u8>, JsValue> {
+    let mut parser = Parser::new(source)
+        .with_typescript(typescript.unwrap_or(false))
+        .with_strict(true);
+    parser.parse()?;
+    Ok(parser.encode_binary())
+}
+
+/// Parse strict JSON and return binary AST
+///
+/// Same format as `parseBinary`. Throws on anything outside RFC 8259
+/// JSON (comments, trailing commas, single quotes, bare keys).
+#[wasm_bindgen(js_name = parseJson)]
+pub fn parse_json(source: &str) -> Result<Vec<u8>, JsValue> {
+    parse_json_binary(source, JsonMode::Strict)
+}
+
+/// Parse JSON5 and return binary AST
+#[wasm_bindgen(js_name = parseJson5)]
+pub fn parse_json5(source: &str) -> Result<Vec<u8>, JsValue> {
+    parse_json_binary(source, JsonMode::Json5)
+}
+
+fn parse_json_binary(source: &str, mode: JsonMode) -> Result<Vec<u8>, JsValue> {
+    let mut parser = Parser::new(source);
+    parser.parse_json(mode);
+    if let Some(diagnostic) = parser.diagnostics().first() {
+        return Err(JsValue::from_str(&format!(
+            "{} at offset {}",
+            diagnostic.message, diagnostic.start
+        )));
+    }
+    Ok(parser.encode_binary())
+}
+
+/// A parsed source, for several queries over a single parse
+///
+/// Tokenizes and parses once on construction; `nodeCount()`, `binary()`,
+/// `tokens()`, `diagnostics()` and `symbols()` all read the cached result.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { Parser } from '@sylphx/synth-wasm-js';
+///
+/// const parsed = new Parser(source, false);
+/// const count = parsed.nodeCount();
+/// const buffer = parsed.binary();
+///
+/// // Reparse from tokens produced (or edited) elsewhere
+/// const again = Parser.fromTokens(source, parsed.tokens(), false);
+///
+/// // Diagnostics with `line`, `lineText` and `caret` for error frames
+/// const [first] = new Parser(source, false, true).diagnostics();
+/// ```
+#[wasm_bindgen(js_name = Parser)]
+pub struct ParsedSource {
+    tokens: Vec<Token>,
+    nodes: Vec<parser::Node>,
+    diagnostics: Vec<Diagnostic>,
+    symbols: Vec<Symbol>,
+}
+
+#[wasm_bindgen(js_class = Parser)]
+impl ParsedSource {
+    /// Parse `source`, as TypeScript when `typescript` is true; with
+    /// `sourceLines`, diagnostics carry the text of their line
+    ///
+    /// With `maxNodes`, or once `cancel` is set (see `parseBinary`),
+    /// parsing stops after the current top-level statement with a
+    /// truncation or cancellation diagnostic.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        source: &str,
+        typescript: Option<bool>,
+        source_lines: Option<bool>,
+        max_nodes: Option<usize>,
+        cancel: Option<js_sys::Int32Array>,
+    ) -> ParsedSource {
+        let tokens = Lexer::new(source).tokenize();
+        Self::parse(source, tokens, typescript, source_lines, max_nodes, cancel)
+    }
+
+    /// Parse `source` from pre-tokenized input instead of lexing it
+    ///
+    /// `tokens` are flat `[kind, start, end, ...]` triples, as returned by
+    /// `tokens()`, with spans indexing into `source`; a trailing `Eof` is
+    /// optional. Throws if a kind is unknown or a span is out of bounds.
+    /// Other arguments are as for the constructor.
+    #[wasm_bindgen(js_name = fromTokens)]
+    pub fn from_tokens(
+        source: &str,
+        tokens: Vec<u32>,
+        typescript: Option<bool>,
+        source_lines: Option<bool>,
+        max_nodes: Option<usize>,
+        cancel: Option<js_sys::Int32Array>,
+    ) -> Result<ParsedSource, JsValue> {
+        let tokens = tokens_from_triples(source, &tokens).map_err(|e| JsValue::from_str(&e))?;
+        Ok(Self::parse(source, tokens, typescript, source_lines, max_nodes, cancel))
+    }
+
+    fn parse(
+        source: &str,
+        tokens: Vec<Token>,
+        typescript: Option<bool>,
+        source_lines: Option<bool>,
+        max_nodes: Option<usize>,
+        cancel: Option<js_sys::Int32Array>,
+    ) -> ParsedSource {
+        let parser = Parser::from_tokens(source, tokens.clone())
+            .with_typescript(typescript.unwrap_or(false))
+            .with_source_lines(source_lines.unwrap_or(false))
+            .with_symbols(true);
+        let mut parser = configure(parser, max_nodes, cancel.as_ref());
+        parser.parse_count();
+        ParsedSource {
+            tokens,
+            nodes: parser.nodes().to_vec(),
+            diagnostics: parser.diagnostics().to_vec(),
+            symbols: parser.symbols().to_vec(),
+        }
+    }
+
+    /// Node count, `Program` root included (same as `parseCount`)
+    #[wasm_bindgen(js_name = nodeCount)]
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Binary AST (same format as `parseBinary`)
+    pub fn binary(&self) -> Vec<u8> {
+        parser::encode_binary(&self.nodes)
+    }
+
+    /// Tokens as flat `[kind, start, end, ...]` triples, without `Eof`
+    pub fn tokens(&self) -> Vec<u32> {
+        self.tokens
+            .iter()
+            .filter(|t| t.kind != TokenKind::Eof)
+            .flat_map(|t| [t.kind as u32, t.start, t.end])
+            .collect()
+    }
+
+    /// Recoverable problems as `{ message, start, end }[]`, plus `line`,
+    /// `lineText` and `caret` (UTF-16 column) when `sourceLines` was set
+    pub fn diagnostics(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.diagnostics)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Declared bindings as `{ name, kind, start, end, scope }[]`
+    ///
+    /// `kind` is `var`, `let`, `const`, `function`, `class` or `import`;
+    /// `scope` is 0 for the program, then blocks and function bodies
+    /// numbered in the order they open.
+    pub fn symbols(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.symbols)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+}
+
+/// Parse into a Tree, the same structure the markdown parser returns
+///
+/// The root is the `Program`; nodes use ESTree type names and keep
+/// non-zero `flags`/`extra` (see `parseBinary`) in `data`. Span columns
+/// are UTF-16 code units, as in the markdown parser's default. With
+/// `maxNodes` or `cancel`, parsing stops as in `parseBinary` and the root
+/// gets `data.truncated` or `data.cancelled`. Throws `{ kind, message,
+/// ... }` if the tree can't be built.
+#[wasm_bindgen(js_name = parseTree)]
+pub fn parse_tree_js(
+    source: &str,
+    typescript: Option<bool>,
+    max_nodes: Option<usize>,
+    cancel: Option<js_sys::Int32Array>,
+) -> Result<Tree, JsValue> {
+    let parser = Parser::new(source).with_typescript(typescript.unwrap_or(false));
+    Ok(parse_tree_with(source, configure(parser, max_nodes, cancel.as_ref()))?)
+}
+
+/// Parse into a Tree (see `parseTree`)
+pub fn parse_tree(source: &str, typescript: bool) -> SynthResult<Tree> {
+    parse_tree_with(source, Parser::new(source).with_typescript(typescript))
+}
+
+/// Run `parser` over `source` into a Tree, noting on the root why it
+/// stopped early
+fn parse_tree_with(source: &str, mut parser: Parser<'_>) -> SynthResult<Tree> {
+    parser.parse_count();
+    let mut tree = build_tree(source, parser.nodes())?;
+    if let Some(reason) = parser.stopped() {
+        let root = tree.root_id();
+        tree.get_node_mut(root)?
+            .data
+            .get_or_insert_with(HashMap::new)
+            .insert(reason.to_string(), serde_json::json!(true));
+    }
+    Ok(tree)
+}
+
+/// Parse statements up to the first line that is `sentinel` into a Tree
+///
+/// Parsing stops where a statement would start with `sentinel` as the rest
+/// of its line (trailing whitespace ignored), such as the closing fence of
+/// a code block. The `Program`'s `data.consumed` is the byte offset where
+/// it stopped, the source length when nothing matched. Otherwise the same
+/// as `parseTree`.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { parseUntil } from '@sylphx/synth-wasm-js';
+///
+/// const { nodes } = parseUntil(afterFence, '```').toJSON();
+/// const consumed = nodes[0].data.consumed;
+/// ```
+#[wasm_bindgen(js_name = parseUntil)]
+pub fn parse_until_js(source: &str, sentinel: &str, typescript: Option<bool>) -> Result<Tree, JsValue> {
+    Ok(parse_until(source, sentinel, typescript.unwrap_or(false))?)
+}
+
+/// Parse up to the first `sentinel` line into a Tree (see `parseUntil`)
+pub fn parse_until(source: &str, sentinel: &str, typescript: bool) -> SynthResult<Tree> {
+    let mut parser = Parser::new(source).with_typescript(typescript);
+    parser.parse_until(|line| line.trim_end() == sentinel);
+    let mut tree = build_tree(source, parser.nodes())?;
+    let root = tree.root_id();
+    tree.get_node_mut(root)?
+        .data
+        .get_or_insert_with(HashMap::new)
+        .insert("consumed".to_string(), serde_json::json!(parser.consumed_len()));
+    Ok(tree)
+}
+
+/// Tree of the parser's `nodes`, a flat post-order list, so a node's
+/// children are the subtrees finished just before it that lie within its
+/// span. Leaf kinds take no children, so sibling leaves with equal spans
+/// (an import specifier's imported and local names) stay siblings.
+fn build_tree(source: &str, nodes: &[parser::Node]) -> SynthResult<Tree> {
+    let lines = LineIndex::new(source);
+    // Recovery on unterminated input can leave offsets past the end
+    let position = |offset: u32| lines.position(source, offset.min(source.len() as u32), ColumnUnit::Utf16);
+    let span = |node: &parser::Node| Span::new(position(node.start), position(node.end));
+    let tree_node = |node: &parser::Node| {
+        let mut tree_node = Node::new(0, node.kind.name()).with_span(span(node));
+        let mut data = HashMap::new();
+        if node.flags != 0 {
+            data.insert("flags".to_string(), serde_json::json!(node.flags));
+        }
+        if node.extra != 0 {
+            data.insert("extra".to_string(), serde_json::json!(node.extra));
+        }
+        if !data.is_empty() {
+            tree_node.data = Some(data);
+        }
+        tree_node
+    };
+
+    let mut tree = Tree::new("javascript", source);
+    let root = tree.root_id();
+    if let Some(program) = nodes.first() {
+        *tree.get_node_mut(root)? = tree_node(program);
+    }
+
+    // Finished subtrees not yet attached: (id, start, end)
+    let mut open: Vec<(NodeId, u32, u32)> = Vec::new();
+    for node in nodes.iter().skip(1) {
+        let id = tree.add_node(tree_node(node));
+        let first = if node.kind.is_leaf() {
+            open.len()
+        } else {
+            open.iter()
+                .rposition(|&(_, start, end)| start < node.start || end > node.end)
+                .map_or(0, |i| i + 1)
+        };
+        for (child, _, _) in open.drain(first..) {
+            tree.add_child(id, child)?;
+        }
+        open.push((id, node.start, node.end));
+    }
+    for (child, _, _) in open {
+        tree.add_child(root, child)?;
+    }
+    Ok(tree)
+}
+
 /// Get the version of the JavaScript parser
 #[wasm_bindgen]
 pub fn version() -> String {
@@ -41,7 +481,6 @@ pub fn version() -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use parser::NodeKind;
     use std::collections::HashMap;
     use std::fs;
     use std::path::PathBuf;
@@ -51,78 +490,11 @@ mod tests {
             .join("../../test/fixtures/javascript-parity/golden.json")
     }
 
-    fn node_kind_name(kind: NodeKind) -> &'static str {
-        match kind {
-            NodeKind::Program => "Program",
-            NodeKind::VariableDeclaration => "VariableDeclaration",
-            NodeKind::VariableDeclarator => "VariableDeclarator",
-            NodeKind::FunctionDeclaration => "FunctionDeclaration",
-            NodeKind::ClassDeclaration => "ClassDeclaration",
-            NodeKind::ImportDeclaration => "ImportDeclaration",
-            NodeKind::ExportDeclaration => "ExportDeclaration",
-            NodeKind::BlockStatement => "BlockStatement",
-            NodeKind::ExpressionStatement => "ExpressionStatement",
-            NodeKind::IfStatement => "IfStatement",
-            NodeKind::ForStatement => "ForStatement",
-            NodeKind::ForInStatement => "ForInStatement",
-            NodeKind::ForOfStatement => "ForOfStatement",
-            NodeKind::WhileStatement => "WhileStatement",
-            NodeKind::DoWhileStatement => "DoWhileStatement",
-            NodeKind::SwitchStatement => "SwitchStatement",
-            NodeKind::SwitchCase => "SwitchCase",
-            NodeKind::ReturnStatement => "ReturnStatement",
-            NodeKind::ThrowStatement => "ThrowStatement",
-            NodeKind::TryStatement => "TryStatement",
-            NodeKind::CatchClause => "CatchClause",
-            NodeKind::BreakStatement => "BreakStatement",
-            NodeKind::ContinueStatement => "ContinueStatement",
-            NodeKind::EmptyStatement => "EmptyStatement",
-            NodeKind::Identifier => "Identifier",
-            NodeKind::Literal => "Literal",
-            NodeKind::ArrayExpression => "ArrayExpression",
-            NodeKind::ObjectExpression => "ObjectExpression",
-            NodeKind::Property => "Property",
-            NodeKind::FunctionExpression => "FunctionExpression",
-            NodeKind::ArrowFunctionExpression => "ArrowFunctionExpression",
-            NodeKind::ClassExpression => "ClassExpression",
-            NodeKind::CallExpression => "CallExpression",
-            NodeKind::NewExpression => "NewExpression",
-            NodeKind::MemberExpression => "MemberExpression",
-            NodeKind::BinaryExpression => "BinaryExpression",
-            NodeKind::UnaryExpression => "UnaryExpression",
-            NodeKind::UpdateExpression => "UpdateExpression",
-            NodeKind::AssignmentExpression => "AssignmentExpression",
-            NodeKind::LogicalExpression => "LogicalExpression",
-            NodeKind::ConditionalExpression => "ConditionalExpression",
-            NodeKind::SequenceExpression => "SequenceExpression",
-            NodeKind::SpreadElement => "SpreadElement",
-            NodeKind::TemplateLiteral => "TemplateLiteral",
-            NodeKind::TaggedTemplateExpression => "TaggedTemplateExpression",
-            NodeKind::ThisExpression => "ThisExpression",
-            NodeKind::Super => "Super",
-            NodeKind::AwaitExpression => "AwaitExpression",
-            NodeKind::YieldExpression => "YieldExpression",
-            NodeKind::ArrayPattern => "ArrayPattern",
-            NodeKind::ObjectPattern => "ObjectPattern",
-            NodeKind::AssignmentPattern => "AssignmentPattern",
-            NodeKind::RestElement => "RestElement",
-            NodeKind::ImportSpecifier => "ImportSpecifier",
-            NodeKind::ImportDefaultSpecifier => "ImportDefaultSpecifier",
-            NodeKind::ImportNamespaceSpecifier => "ImportNamespaceSpecifier",
-            NodeKind::ExportSpecifier => "ExportSpecifier",
-            NodeKind::MethodDefinition => "MethodDefinition",
-            NodeKind::PropertyDefinition => "PropertyDefinition",
-            NodeKind::Comment => "Comment",
-            NodeKind::ClassBody => "ClassBody",
-            NodeKind::TemplateElement => "TemplateElement",
-        }
-    }
-
     fn wasm_kind_counts(nodes: &[parser::Node]) -> HashMap<String, u32> {
         let mut counts = HashMap::new();
         for node in nodes {
             *counts
-                .entry(node_kind_name(node.kind).to_string())
+                .entry(node.kind.name().to_string())
                 .or_insert(0) += 1;
         }
         counts
@@ -158,6 +530,10 @@ mod tests {
         assert!(!fixtures.is_empty(), "golden fixtures must not be empty");
 
         for (id, fixture) in &fixtures {
+            // Valid code parses the same way in strict mode
+            if let Err(e) = Parser::new(&fixture.source).with_strict(true).parse() {
+                panic!("fixture {id}: strict parse failed: {e}");
+            }
             let mut parser = Parser::new(&fixture.source);
             parser.parse_count();
             let got = normalize_wasm_counts_for_ts_parity(wasm_kind_counts(parser.nodes()));
@@ -174,17 +550,200 @@ mod tests {
         assert!(count > 0);
     }
 
+    #[test]
+    fn test_tokenize_to_json() {
+        let json: serde_json::Value = serde_json::from_str(&tokenize_to_json("a?.b")).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                { "kind": "Identifier", "start": 0, "end": 1 },
+                { "kind": "QuestionDot", "start": 1, "end": 3 },
+                { "kind": "Identifier", "start": 3, "end": 4 },
+            ])
+        );
+        let kind = token_kind_from_name("QuestionDot").unwrap();
+        assert_eq!(token_kind_name(kind).as_deref(), Some("QuestionDot"));
+        assert_eq!(token_kind_name(255), None);
+    }
+
+    #[test]
+    fn test_tokens_in_range() {
+        let source = "let a = 1;\nx = a / 2 / 3;\n";
+        let full: Vec<serde_json::Value> = serde_json::from_str(&tokenize_to_json(source)).unwrap();
+        let (start, end) = (14, 22);
+        let expected: Vec<_> = full
+            .iter()
+            .filter(|t| t["end"].as_u64().unwrap() > start && t["start"].as_u64().unwrap() < end)
+            .cloned()
+            .collect();
+        let range = |checkpoint| -> Vec<serde_json::Value> {
+            serde_json::from_str(&tokens_in_range(source, start as u32, end as u32, checkpoint).unwrap()).unwrap()
+        };
+        assert_eq!(range(None), expected);
+        assert_eq!(expected.len(), 4);
+
+        // Resuming after `x =` still reads `/` as division
+        let checkpoint = lexer_checkpoint(source, start as u32);
+        assert_eq!(checkpoint[0], 14);
+        assert_eq!(checkpoint[1], TokenKind::Eq as u32);
+        assert_eq!(range(Some(checkpoint)), expected);
+    }
+
     #[test]
     fn test_parse_count() {
         let count = parse_count("const x = 1;");
         assert!(count >= 3); // Program, VariableDeclaration, VariableDeclarator, etc.
     }
 
+    #[test]
+    fn test_parser_handle_is_consistent() {
+        let source = "function foo() { return 42; }\nfoo();";
+        let parsed = ParsedSource::new(source, None, None, None, None);
+
+        let buf = parsed.binary();
+        let node_count = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+        assert_eq!(parsed.node_count(), node_count);
+        assert_eq!(parsed.node_count(), parse_count(source));
+        assert_eq!(buf, parse_binary(source, None, None));
+        // Queries read the cached parse
+        assert_eq!(parsed.binary(), buf);
+
+        let tokens = parsed.tokens();
+        assert_eq!(tokens.len() % 3, 0);
+        assert_eq!(tokens.len() / 3, tokenize(source) - 1);
+        assert_eq!(&source[tokens[1] as usize..tokens[2] as usize], "function");
+    }
+
+    #[test]
+    fn test_parser_handle_from_tokens() {
+        let source = "function foo() { return 42; }\nfoo();";
+        let parsed = ParsedSource::new(source, None, None, None, None);
+        let again = ParsedSource::from_tokens(source, parsed.tokens(), None, None, None, None).unwrap();
+        assert_eq!(again.binary(), parsed.binary());
+        assert_eq!(again.tokens(), parsed.tokens());
+
+        assert!(tokens_from_triples(source, &[255, 0, 1]).unwrap_err().contains("unknown token kind"));
+        let kind = TokenKind::Identifier as u32;
+        assert!(tokens_from_triples(source, &[kind, 0, 99]).unwrap_err().contains("outside the source"));
+        assert!(tokens_from_triples(source, &[kind, 0]).is_err());
+    }
+
+    #[test]
+    fn test_parser_handle_typescript() {
+        let source = "enum E { A }";
+        let ts = ParsedSource::new(source, Some(true), None, None, None);
+        assert_eq!(ts.binary(), parse_binary_ts(source, None, None));
+        assert_ne!(ts.node_count(), ParsedSource::new(source, None, None, None, None).node_count());
+    }
+
     #[test]
     fn test_parse_binary() {
-        let buf = parse_binary("function foo() { return 42; }");
+        let buf = parse_binary("function foo() { return 42; }", None, None);
         assert!(buf.len() > 4); // At least header + some nodes
         let node_count = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
         assert!(node_count >= 4);
     }
+
+    #[test]
+    fn test_max_nodes_exports() {
+        let source = "x = 1;\n".repeat(100);
+        let buf = parse_binary(&source, Some(20), None);
+        let node_count = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+        assert!(node_count > 20 && node_count <= 25, "{node_count}");
+        assert_eq!(parse_binary_ts(&source, Some(20), None), buf);
+
+        let parsed = ParsedSource::new(&source, None, None, Some(20), None);
+        assert_eq!(parsed.node_count(), node_count);
+        assert!(parsed.diagnostics[0].message.contains("truncated"));
+
+        let tree = parse_tree_js(&source, None, Some(20), None).unwrap();
+        assert_eq!(tree.node_count(), node_count);
+        assert_eq!(tree.root().data.as_ref().unwrap()["truncated"], true);
+        let tree = parse_tree_js(&source, None, None, None).unwrap();
+        assert!(!tree.root().data.as_ref().unwrap().contains_key("truncated"));
+    }
+
+    fn outline(tree: &Tree, id: NodeId, out: &mut String) {
+        let node = tree.get_node(id).unwrap();
+        out.push_str(node.node_type.as_str());
+        if !node.children.is_empty() {
+            out.push('(');
+            for (i, &child) in node.children.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                outline(tree, child, out);
+            }
+            out.push(')');
+        }
+    }
+
+    #[test]
+    fn test_parse_tree_nests_by_span() {
+        let source = "let x = f(1, [, a]);\nx;";
+        let tree = parse_tree(source, false).unwrap();
+        assert_eq!(tree.node_count(), parse_count(source));
+
+        let mut out = String::new();
+        outline(&tree, tree.root_id(), &mut out);
+        assert_eq!(
+            out,
+            "Program(VariableDeclaration(VariableDeclarator(Identifier \
+             CallExpression(Identifier Literal ArrayExpression(ArrayHole Identifier)))) \
+             ExpressionStatement(Identifier))"
+        );
+
+        let statement = tree.get_node(tree.children_of(tree.root_id())[1]).unwrap();
+        let span = statement.span.unwrap();
+        assert_eq!((span.start.line, span.start.column, span.start.offset), (2, 0, 21));
+        let declaration = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        assert_eq!(declaration.data.as_ref().unwrap()["flags"], parser::flags::LET);
+    }
+
+    #[test]
+    fn test_parse_tree_equal_span_siblings() {
+        for (source, expected) in [
+            (
+                "export { default } from \"./m\";",
+                "Program(ExportDeclaration(ExportSpecifier(Identifier) Literal))",
+            ),
+            ("import { a } from \"m\";", "Program(ImportDeclaration(ImportSpecifier(Identifier Identifier) Literal))"),
+            // Wrappers with the same span as their child still nest
+            ("a?.b", "Program(ExpressionStatement(ChainExpression(MemberExpression(Identifier Identifier))))"),
+        ] {
+            let tree = parse_tree(source, false).unwrap();
+            let mut out = String::new();
+            outline(&tree, tree.root_id(), &mut out);
+            assert_eq!(out, expected, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_tree_unterminated_input() {
+        for source in ["`unterminated ${x", "`a\\", "'unterminated", "\"a\\", "f(`${", "/* open"] {
+            let tree = parse_tree(source, false).unwrap();
+            for node in tree.nodes() {
+                let span = node.span.unwrap();
+                assert!(span.start.offset <= span.end.offset, "{source:?}");
+                assert!(span.end.offset as usize <= source.len(), "{source:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_until() {
+        let source = "let a = 1;\nf(a);\n```  \nnot js {\n";
+        let tree = parse_until(source, "```", false).unwrap();
+        let consumed = source.find("```").unwrap();
+        assert_eq!(tree.root().data.as_ref().unwrap()["consumed"], consumed);
+        assert_eq!(tree.root().span.unwrap().end.offset as usize, consumed);
+        let types: Vec<_> = tree.children_of(tree.root_id())
+            .iter()
+            .map(|&id| tree.get_node(id).unwrap().node_type.as_str().to_string())
+            .collect();
+        assert_eq!(types, ["VariableDeclaration", "ExpressionStatement"]);
+
+        let tree = parse_until("let x;\n", "END", false).unwrap();
+        assert_eq!(tree.root().data.as_ref().unwrap()["consumed"], 7);
+    }
 }
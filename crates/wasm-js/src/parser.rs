@@ -4,6 +4,7 @@
 //! Produces a compact AST suitable for WASM output.
 
 use crate::lexer::{Lexer, Token, TokenKind};
+use synth_wasm_core::{CancelSignal, ColumnUnit, LineIndex, SynthError, SynthResult};
 
 /// AST Node types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -91,6 +92,118 @@ pub enum NodeKind {
     // ESTree containers required for TS golden parity (append-only — preserve prior discriminants)
     ClassBody,
     TemplateElement,
+
+    // TypeScript declarations (only produced in TS mode)
+    TSEnumDeclaration,
+    TSEnumMember,
+    TSModuleDeclaration,
+    TSModuleBlock,
+
+    Decorator,
+
+    TSExportAssignment,
+    TSImportEquals,
+
+    /// An elided array element (`[1, , 3]`), zero-width at its comma
+    ArrayHole,
+
+    /// An optional chain (`a?.b.c()`), wrapping its outermost member or
+    /// call; `extra` is that node's index
+    ChainExpression,
+}
+
+impl NodeKind {
+    /// ESTree-style type name (e.g. `"Identifier"`)
+    pub fn name(self) -> &'static str {
+        match self {
+            NodeKind::Program => "Program",
+            NodeKind::VariableDeclaration => "VariableDeclaration",
+            NodeKind::VariableDeclarator => "VariableDeclarator",
+            NodeKind::FunctionDeclaration => "FunctionDeclaration",
+            NodeKind::ClassDeclaration => "ClassDeclaration",
+            NodeKind::ImportDeclaration => "ImportDeclaration",
+            NodeKind::ExportDeclaration => "ExportDeclaration",
+            NodeKind::BlockStatement => "BlockStatement",
+            NodeKind::ExpressionStatement => "ExpressionStatement",
+            NodeKind::IfStatement => "IfStatement",
+            NodeKind::ForStatement => "ForStatement",
+            NodeKind::ForInStatement => "ForInStatement",
+            NodeKind::ForOfStatement => "ForOfStatement",
+            NodeKind::WhileStatement => "WhileStatement",
+            NodeKind::DoWhileStatement => "DoWhileStatement",
+            NodeKind::SwitchStatement => "SwitchStatement",
+            NodeKind::SwitchCase => "SwitchCase",
+            NodeKind::ReturnStatement => "ReturnStatement",
+            NodeKind::ThrowStatement => "ThrowStatement",
+            NodeKind::TryStatement => "TryStatement",
+            NodeKind::CatchClause => "CatchClause",
+            NodeKind::BreakStatement => "BreakStatement",
+            NodeKind::ContinueStatement => "ContinueStatement",
+            NodeKind::EmptyStatement => "EmptyStatement",
+            NodeKind::Identifier => "Identifier",
+            NodeKind::Literal => "Literal",
+            NodeKind::ArrayExpression => "ArrayExpression",
+            NodeKind::ObjectExpression => "ObjectExpression",
+            NodeKind::Property => "Property",
+            NodeKind::FunctionExpression => "FunctionExpression",
+            NodeKind::ArrowFunctionExpression => "ArrowFunctionExpression",
+            NodeKind::ClassExpression => "ClassExpression",
+            NodeKind::CallExpression => "CallExpression",
+            NodeKind::NewExpression => "NewExpression",
+            NodeKind::MemberExpression => "MemberExpression",
+            NodeKind::BinaryExpression => "BinaryExpression",
+            NodeKind::UnaryExpression => "UnaryExpression",
+            NodeKind::UpdateExpression => "UpdateExpression",
+            NodeKind::AssignmentExpression => "AssignmentExpression",
+            NodeKind::LogicalExpression => "LogicalExpression",
+            NodeKind::ConditionalExpression => "ConditionalExpression",
+            NodeKind::SequenceExpression => "SequenceExpression",
+            NodeKind::SpreadElement => "SpreadElement",
+            NodeKind::TemplateLiteral => "TemplateLiteral",
+            NodeKind::TaggedTemplateExpression => "TaggedTemplateExpression",
+            NodeKind::ThisExpression => "ThisExpression",
+            NodeKind::Super => "Super",
+            NodeKind::AwaitExpression => "AwaitExpression",
+            NodeKind::YieldExpression => "YieldExpression",
+            NodeKind::ArrayPattern => "ArrayPattern",
+            NodeKind::ObjectPattern => "ObjectPattern",
+            NodeKind::AssignmentPattern => "AssignmentPattern",
+            NodeKind::RestElement => "RestElement",
+            NodeKind::ImportSpecifier => "ImportSpecifier",
+            NodeKind::ImportDefaultSpecifier => "ImportDefaultSpecifier",
+            NodeKind::ImportNamespaceSpecifier => "ImportNamespaceSpecifier",
+            NodeKind::ExportSpecifier => "ExportSpecifier",
+            NodeKind::MethodDefinition => "MethodDefinition",
+            NodeKind::PropertyDefinition => "PropertyDefinition",
+            NodeKind::Comment => "Comment",
+            NodeKind::ClassBody => "ClassBody",
+            NodeKind::TemplateElement => "TemplateElement",
+            NodeKind::TSEnumDeclaration => "TSEnumDeclaration",
+            NodeKind::TSEnumMember => "TSEnumMember",
+            NodeKind::TSModuleDeclaration => "TSModuleDeclaration",
+            NodeKind::TSModuleBlock => "TSModuleBlock",
+            NodeKind::Decorator => "Decorator",
+            NodeKind::TSExportAssignment => "TSExportAssignment",
+            NodeKind::TSImportEquals => "TSImportEquals",
+            NodeKind::ArrayHole => "ArrayHole",
+            NodeKind::ChainExpression => "ChainExpression",
+        }
+    }
+
+    /// Whether nodes of this kind never have children
+    pub fn is_leaf(self) -> bool {
+        matches!(
+            self,
+            NodeKind::Identifier
+                | NodeKind::Literal
+                | NodeKind::ThisExpression
+                | NodeKind::Super
+                | NodeKind::EmptyStatement
+                | NodeKind::TemplateElement
+                | NodeKind::ArrayHole
+                | NodeKind::Comment
+        )
+    }
 }
 
 /// Compact AST node - 16 bytes
@@ -98,26 +211,26 @@ pub enum NodeKind {
 #[repr(C)]
 pub struct Node {
     pub kind: NodeKind,
-    pub flags: u8,       // Various flags (e.g., async, generator, computed)
-    pub _pad: [u8; 2],
+    pub _pad: u8,
+    pub flags: u16,      // Various flags (e.g., async, generator, computed)
     pub start: u32,
     pub end: u32,
-    pub extra: u32,      // Extra data (e.g., operator type, child count)
+    pub extra: u32,      // Extra data (e.g., operator TokenKind, child count)
 }
 
 impl Node {
     pub fn new(kind: NodeKind, start: u32, end: u32) -> Self {
         Self {
             kind,
+            _pad: 0,
             flags: 0,
-            _pad: [0; 2],
             start,
             end,
             extra: 0,
         }
     }
 
-    pub fn with_flags(mut self, flags: u8) -> Self {
+    pub fn with_flags(mut self, flags: u16) -> Self {
         self.flags = flags;
         self
     }
@@ -126,78 +239,311 @@ impl Node {
         self.extra = extra;
         self
     }
+
+    /// Record an operator in `extra` as its `TokenKind` discriminant
+    pub fn with_operator(self, operator: TokenKind) -> Self {
+        self.with_extra(operator as u32)
+    }
 }
 
 /// Flags for nodes
 pub mod flags {
-    pub const CONST: u8 = 1 << 0;
-    pub const LET: u8 = 1 << 1;
-    pub const ASYNC: u8 = 1 << 2;
-    pub const GENERATOR: u8 = 1 << 3;
-    pub const COMPUTED: u8 = 1 << 4;
-    pub const SHORTHAND: u8 = 1 << 5;
-    pub const STATIC: u8 = 1 << 6;
-    pub const EXPORT_DEFAULT: u8 = 1 << 7;
+    pub const CONST: u16 = 1 << 0;
+    pub const LET: u16 = 1 << 1;
+    pub const ASYNC: u16 = 1 << 2;
+    pub const GENERATOR: u16 = 1 << 3;
+    pub const COMPUTED: u16 = 1 << 4;
+    pub const SHORTHAND: u16 = 1 << 5;
+    pub const STATIC: u16 = 1 << 6;
+    pub const EXPORT_DEFAULT: u16 = 1 << 7;
+    /// `declare` modifier (TS)
+    pub const DECLARE: u16 = 1 << 8;
+    /// Directive (`"use strict";`) in a prologue. Shares its bit with CONST,
+    /// which is only ever set on VariableDeclaration.
+    pub const DIRECTIVE: u16 = 1 << 0;
+    /// Program or function whose code is strict. Shares its bit with LET,
+    /// which is only ever set on VariableDeclaration.
+    pub const STRICT: u16 = 1 << 1;
+    /// Member or call after `?.` (`a?.b`, `a?.[b]`, `a?.()`). Shares its
+    /// bit with CONST, which is only ever set on VariableDeclaration.
+    pub const OPTIONAL: u16 = 1 << 0;
+}
+
+/// A recoverable problem found while parsing
+///
+/// With `Parser::with_source_lines`, also carries the line `start` is on
+/// so an error frame can be rendered without the source.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+    pub message: String,
+    pub start: u32,
+    pub end: u32,
+    /// Line number (1-indexed) of `start`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// Text of that line, without its terminator
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_text: Option<String>,
+    /// Column of `start` in `line_text`, in UTF-16 code units
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caret: Option<u32>,
+}
+
+/// What a declared binding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+    Var,
+    Let,
+    Const,
+    Function,
+    Class,
+    Import,
+}
+
+/// A binding declared in the source, collected under `Parser::with_symbols`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Span of the declared identifier
+    pub start: u32,
+    pub end: u32,
+    /// Scope the binding belongs to: 0 for the program, then blocks and
+    /// function bodies numbered in the order they open. `var` goes to the
+    /// enclosing function body.
+    pub scope: u32,
+}
+
+/// Top-level statements parsed between checks of the cancel flag
+const CANCEL_CHECK_INTERVAL: usize = 64;
+
+/// JSON dialect accepted by `Parser::parse_json`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonMode {
+    /// RFC 8259 JSON: no comments, trailing commas, single quotes or bare keys
+    Strict,
+    /// JSON5: comments, trailing commas, single quotes, identifier keys,
+    /// `+` signs, `Infinity`/`NaN` and hex numbers allowed
+    Json5,
+}
+
+/// A function's parameter list, as read by `Parser::parse_function_params`
+struct Params {
+    count: usize,
+    /// The last parameter is a rest element
+    rest: bool,
+    /// Plain identifiers only: no defaults, patterns or rest element
+    simple: bool,
+    /// First binding of a name already bound by an earlier parameter
+    duplicate: Option<Token>,
+}
+
+/// Where the parser pulls its tokens from
+enum TokenSource<'a> {
+    /// Tokens are scanned on demand
+    Lexer(Lexer<'a>),
+    /// Externally produced tokens, consumed in order
+    Tokens { tokens: Vec<Token>, index: usize },
+}
+
+impl TokenSource<'_> {
+    fn next_token(&mut self) -> Token {
+        match self {
+            TokenSource::Lexer(lexer) => lexer.next_token(),
+            TokenSource::Tokens { tokens, index } => {
+                let token = tokens.get(*index).copied().unwrap_or_else(|| {
+                    // Past the end: synthesize EOF after the last token
+                    let end = tokens.last().map_or(0, |t| t.end);
+                    Token { kind: TokenKind::Eof, start: end, end }
+                });
+                *index += 1;
+                token
+            }
+        }
+    }
 }
 
 /// JavaScript Parser
 pub struct Parser<'a> {
     source: &'a str,
-    lexer: Lexer<'a>,
+    tokens: TokenSource<'a>,
     current: Token,
     peek: Token,
     nodes: Vec<Node>,
+    diagnostics: Vec<Diagnostic>,
+    typescript: bool,
+    /// Where the last parse stopped
+    consumed: usize,
+    /// Why the last parse stopped early: `"truncated"` or `"cancelled"`
+    stopped: Option<&'static str>,
+    /// Stop parsing once more nodes than this have been produced
+    max_nodes: Option<usize>,
+    cancel: Option<&'a dyn CancelSignal>,
+    /// Report every recovery and stop at the first one
+    strict: bool,
+    /// Inside code made strict by a `"use strict"` directive
+    strict_code: bool,
+    /// `ASYNC`/`GENERATOR` flags of the enclosing functions, innermost
+    /// last; decides whether `await` and `yield` are keywords
+    functions: Vec<u16>,
+    /// Attach the source line to diagnostics
+    source_lines: bool,
+    /// Built on the first diagnostic when `source_lines` is set
+    line_index: Option<LineIndex>,
+    /// Declared bindings, when collecting symbols
+    symbols: Option<Vec<Symbol>>,
+    /// Kind of binding the pattern being parsed declares
+    binding: Option<SymbolKind>,
+    /// Names bound by the parameter list being parsed
+    params: Option<Vec<Token>>,
+    /// Open scopes (id, whether a function body), innermost last
+    scopes: Vec<(u32, bool)>,
+    /// Scopes opened so far
+    scope_count: u32,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(source: &'a str) -> Self {
-        let mut lexer = Lexer::new(source);
-        let current = lexer.next_token();
-        let peek = lexer.next_token();
+        Self::with_token_source(source, TokenSource::Lexer(Lexer::new(source)))
+    }
+
+    /// Create a parser over a pre-tokenized stream
+    ///
+    /// `tokens` must come from `source` (spans index into it). A trailing
+    /// `Eof` token is optional.
+    pub fn from_tokens(source: &'a str, tokens: Vec<Token>) -> Self {
+        Self::with_token_source(source, TokenSource::Tokens { tokens, index: 0 })
+    }
+
+    fn with_token_source(source: &'a str, mut tokens: TokenSource<'a>) -> Self {
+        let current = tokens.next_token();
+        let peek = tokens.next_token();
         Self {
             source,
-            lexer,
+            tokens,
             current,
             peek,
             nodes: Vec::with_capacity(source.len() / 8),
+            diagnostics: Vec::new(),
+            typescript: false,
+            consumed: 0,
+            stopped: None,
+            max_nodes: None,
+            cancel: None,
+            strict: false,
+            strict_code: false,
+            functions: Vec::new(),
+            source_lines: false,
+            line_index: None,
+            symbols: None,
+            binding: None,
+            params: None,
+            scopes: Vec::new(),
+            scope_count: 0,
+        }
+    }
+
+    /// Cap the node count (`None` for no cap); parsing stops after the
+    /// top-level statement that exceeds it, with a truncation diagnostic
+    pub fn with_max_nodes(mut self, max_nodes: Option<usize>) -> Self {
+        self.max_nodes = max_nodes;
+        self
+    }
+
+    /// Stop parsing once `flag` is set (checked every few top-level
+    /// statements), with a cancellation diagnostic
+    ///
+    /// The flag can be set from another thread.
+    pub fn with_cancel_flag(mut self, flag: &'a dyn CancelSignal) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    /// Enable strict mode
+    ///
+    /// Constructs the parser normally skips over (a missing token, a
+    /// non-identifier where a binding is expected, an unexpected token)
+    /// are reported as diagnostics, parsing stops after the statement
+    /// containing the first one, and `parse` returns it as an error.
+    pub fn with_strict(mut self, enabled: bool) -> Self {
+        self.strict = enabled;
+        self
+    }
+
+    /// Enable TypeScript mode (enums, namespaces, `declare` statements)
+    pub fn with_typescript(mut self, enabled: bool) -> Self {
+        self.typescript = enabled;
+        self
+    }
+
+    /// Fill in each diagnostic's `line`, `line_text` and `caret`
+    pub fn with_source_lines(mut self, enabled: bool) -> Self {
+        self.source_lines = enabled;
+        self
+    }
+
+    /// Collect declared bindings (variables, functions, classes, imports)
+    /// into `symbols`
+    pub fn with_symbols(mut self, enabled: bool) -> Self {
+        self.symbols = enabled.then(Vec::new);
+        self
+    }
+
+    /// Parse and return the node count, failing in strict mode on the
+    /// first reported problem
+    pub fn parse(&mut self) -> SynthResult<usize> {
+        let count = self.parse_count();
+        match self.diagnostics.first() {
+            Some(diagnostic) if self.strict => Err(SynthError::ParseError(format!(
+                "{} at offset {}",
+                diagnostic.message, diagnostic.start
+            ))),
+            _ => Ok(count),
         }
     }
 
     /// Parse and return node count
+    ///
+    /// Counting convention (shared with the markdown parser): the count
+    /// includes the root, here the `Program` node. Use
+    /// `parse_count_excluding_root` to count only the nodes under it.
     pub fn parse_count(&mut self) -> usize {
         self.parse_program();
         self.nodes.len()
     }
 
+    /// Parse and return node count, not counting the `Program` node
+    pub fn parse_count_excluding_root(&mut self) -> usize {
+        self.parse_count() - 1
+    }
+
     /// Parsed AST nodes (for golden parity tests / external consumers).
     #[allow(dead_code)]
     pub fn nodes(&self) -> &[Node] {
         &self.nodes
     }
 
+    /// Recoverable problems found while parsing
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Declared bindings in source order (empty unless `with_symbols`)
+    pub fn symbols(&self) -> &[Symbol] {
+        self.symbols.as_deref().unwrap_or_default()
+    }
+
     /// Parse and return binary output
     pub fn parse_binary(&mut self) -> Vec<u8> {
         self.parse_program();
+        self.encode_binary()
+    }
 
-        let node_count = self.nodes.len();
-        let node_size = std::mem::size_of::<Node>();
-        let mut buf = vec![0u8; 4 + node_count * node_size];
-
-        // Header
-        buf[0..4].copy_from_slice(&(node_count as u32).to_le_bytes());
-
-        // Nodes
-        for (i, node) in self.nodes.iter().enumerate() {
-            let offset = 4 + i * node_size;
-            buf[offset] = node.kind as u8;
-            buf[offset + 1] = node.flags;
-            // skip padding [2..4]
-            buf[offset + 4..offset + 8].copy_from_slice(&node.start.to_le_bytes());
-            buf[offset + 8..offset + 12].copy_from_slice(&node.end.to_le_bytes());
-            buf[offset + 12..offset + 16].copy_from_slice(&node.extra.to_le_bytes());
-        }
-
-        buf
+    /// Encode the parsed nodes in the `parseBinary` format
+    pub fn encode_binary(&self) -> Vec<u8> {
+        encode_binary(&self.nodes)
     }
 
     // ========================================
@@ -206,7 +552,7 @@ impl<'a> Parser<'a> {
 
     fn advance(&mut self) {
         self.current = self.peek;
-        self.peek = self.lexer.next_token();
+        self.peek = self.tokens.next_token();
     }
 
     #[allow(dead_code)]
@@ -237,10 +583,80 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Output index of the most recently pushed node
+    ///
+    /// `Program` is inserted at index 0 once parsing finishes, so a node
+    /// at `nodes[i]` during parsing ends up at `i + 1`.
+    fn last_node_index(&self) -> u32 {
+        self.nodes.len() as u32
+    }
+
+    fn report(&mut self, message: impl Into<String>, start: u32, end: u32) {
+        let mut diagnostic = Diagnostic {
+            message: message.into(),
+            start,
+            end,
+            line: None,
+            line_text: None,
+            caret: None,
+        };
+        if self.source_lines {
+            let source = self.source;
+            let index = self.line_index.get_or_insert_with(|| LineIndex::new(source));
+            let position = index.position(source, start, ColumnUnit::Utf16);
+            diagnostic.line = Some(position.line);
+            diagnostic.line_text = Some(index.line_text(source, position.line).to_string());
+            diagnostic.caret = Some(position.column);
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Record `token` as a name bound by the pattern being parsed
+    fn bind(&mut self, token: Token) {
+        if let Some(kind) = self.binding {
+            self.declare(kind, token);
+        }
+        if let Some(params) = &mut self.params
+            && token.kind.is_word()
+        {
+            params.push(token);
+        }
+    }
+
+    /// Record `token` as a binding of `kind`, when collecting symbols
+    fn declare(&mut self, kind: SymbolKind, token: Token) {
+        if self.symbols.is_none() || !token.kind.is_word() {
+            return;
+        }
+        let scope = if kind == SymbolKind::Var {
+            self.scopes.iter().rev().find(|&&(_, function)| function)
+        } else {
+            self.scopes.last()
+        };
+        let symbol = Symbol {
+            name: self.token_text(token).to_string(),
+            kind,
+            start: token.start,
+            end: token.end,
+            scope: scope.map_or(0, |&(id, _)| id),
+        };
+        if let Some(symbols) = &mut self.symbols {
+            symbols.push(symbol);
+        }
+    }
+
     fn expect(&mut self, kind: TokenKind) {
-        if !self.eat(kind) {
-            // In production, would return error
-            // For now, just skip
+        if !self.eat(kind) && self.strict {
+            let token = self.current;
+            self.report(format!("Expected {}", kind.name()), token.start, token.end);
+        }
+    }
+
+    /// In strict mode, report the current token when it can't be skipped over
+    fn report_unexpected(&mut self) {
+        if self.strict {
+            let token = self.current;
+            self.report(format!("Unexpected token {}", token.kind.name()), token.start, token.end);
         }
     }
 
@@ -248,33 +664,102 @@ impl<'a> Parser<'a> {
     // Parsing
     // ========================================
 
+    /// Parse top-level statements until `stop` matches, returning the node count
+    ///
+    /// `stop` sees the rest of the line at each point a statement could
+    /// start. Parsing ends before the matching text, and `consumed_len()`
+    /// reports its offset (the source length when nothing matched), so a
+    /// host can parse a sub-region of a larger document.
+    pub fn parse_until(&mut self, stop: impl Fn(&str) -> bool) -> usize {
+        self.parse_program_until(stop);
+        self.nodes.len()
+    }
+
+    /// Bytes consumed by the last parse
+    pub fn consumed_len(&self) -> usize {
+        self.consumed
+    }
+
+    /// Why the last parse stopped before the end: `"truncated"` by
+    /// `max_nodes` or `"cancelled"`
+    pub fn stopped(&self) -> Option<&'static str> {
+        self.stopped
+    }
+
     fn parse_program(&mut self) {
+        self.parse_program_until(|_| false);
+    }
+
+    fn parse_program_until(&mut self, stop: impl Fn(&str) -> bool) {
         let start = self.current.start;
 
         self.skip_comments_and_newlines();
 
+        self.consumed = self.source.len();
+        self.stopped = None;
+        self.strict_code = false;
+        let mut statements = 0usize;
+        let mut prologue = true;
         while !self.at(TokenKind::Eof) {
+            let rest = &self.source[self.current.start as usize..];
+            if stop(rest.split('\n').next().unwrap_or(rest)) {
+                self.consumed = self.current.start as usize;
+                break;
+            }
+            let (token, before) = (self.current, self.nodes.len());
             self.parse_statement_or_declaration();
+            prologue = prologue && self.mark_directive(token, before);
             self.skip_comments_and_newlines();
+            statements += 1;
+
+            if self.at(TokenKind::Eof) || (self.strict && !self.diagnostics.is_empty()) {
+                break;
+            }
+            let (start, end) = (self.current.start, self.source.len() as u32);
+            if let Some(max) = self.max_nodes
+                && self.nodes.len() > max
+            {
+                self.report(format!("Program truncated: more than {max} nodes"), start, end);
+                self.consumed = start as usize;
+                self.stopped = Some("truncated");
+                break;
+            }
+            if statements.is_multiple_of(CANCEL_CHECK_INTERVAL)
+                && self.cancel.is_some_and(|flag| flag.is_cancelled())
+            {
+                self.report("Parsing cancelled", start, end);
+                self.consumed = start as usize;
+                self.stopped = Some("cancelled");
+                break;
+            }
         }
 
-        let end = self.current.end;
+        let end = if self.consumed < self.source.len() {
+            self.consumed as u32
+        } else {
+            self.current.end
+        };
+        let flags = if self.strict_code { flags::STRICT } else { 0 };
         self.nodes.insert(0, Node::new(NodeKind::Program, start, end)
+            .with_flags(flags)
             .with_extra(self.nodes.len() as u32));
     }
 
     fn parse_statement_or_declaration(&mut self) {
         match self.current.kind {
+            // TypeScript declarations (checked first: `const enum` vs `const`)
+            _ if self.typescript && self.at_ts_declaration() => self.parse_ts_declaration(),
+
             // Declarations
             TokenKind::Const | TokenKind::Let | TokenKind::Var => self.parse_variable_declaration(),
             TokenKind::Function => self.parse_function_declaration(),
             TokenKind::Class => self.parse_class_declaration(),
             TokenKind::Import => self.parse_import_declaration(),
-            TokenKind::Export => self.parse_export_declaration(),
+            TokenKind::Export => self.parse_export_declaration(false),
+            TokenKind::At => self.parse_decorated_declaration(),
             TokenKind::Async if self.peek.kind == TokenKind::Function => {
                 self.parse_function_declaration()
             }
-
             // Statements
             TokenKind::LBrace => self.parse_block_statement(),
             TokenKind::If => self.parse_if_statement(),
@@ -296,10 +781,10 @@ impl<'a> Parser<'a> {
 
     fn parse_variable_declaration(&mut self) {
         let start = self.current.start;
-        let flags = match self.current.kind {
-            TokenKind::Const => flags::CONST,
-            TokenKind::Let => flags::LET,
-            _ => 0,
+        let (flags, kind) = match self.current.kind {
+            TokenKind::Const => (flags::CONST, SymbolKind::Const),
+            TokenKind::Let => (flags::LET, SymbolKind::Let),
+            _ => (0, SymbolKind::Var),
         };
         self.advance(); // skip const/let/var
 
@@ -307,7 +792,7 @@ impl<'a> Parser<'a> {
 
         let mut count = 0;
         loop {
-            self.parse_variable_declarator();
+            self.parse_variable_declarator(kind);
             count += 1;
 
             self.skip_comments_and_newlines();
@@ -325,11 +810,13 @@ impl<'a> Parser<'a> {
             .with_extra(count));
     }
 
-    fn parse_variable_declarator(&mut self) {
+    fn parse_variable_declarator(&mut self, kind: SymbolKind) {
         let start = self.current.start;
 
         // Parse binding pattern or identifier
+        let outer = self.binding.replace(kind);
         self.parse_binding_pattern();
+        self.binding = outer;
 
         self.skip_comments_and_newlines();
 
@@ -347,7 +834,10 @@ impl<'a> Parser<'a> {
         match self.current.kind {
             TokenKind::LBracket => self.parse_array_pattern(),
             TokenKind::LBrace => self.parse_object_pattern(),
-            _ => self.parse_identifier(),
+            _ => {
+                self.bind(self.current);
+                self.parse_identifier();
+            }
         }
     }
 
@@ -418,6 +908,7 @@ impl<'a> Parser<'a> {
         let start = self.current.start;
 
         // Property key
+        let key = self.current;
         self.parse_identifier();
 
         self.skip_comments_and_newlines();
@@ -426,11 +917,19 @@ impl<'a> Parser<'a> {
         if self.eat(TokenKind::Colon) {
             self.skip_comments_and_newlines();
             self.parse_binding_pattern();
+        } else {
+            // Shorthand: the key is the binding
+            self.bind(key);
         }
 
         if self.eat(TokenKind::Eq) {
             self.skip_comments_and_newlines();
+            // Bindings in the default value aren't the pattern's
+            let binding = self.binding.take();
+            let params = self.params.take();
             self.parse_expression();
+            self.binding = binding;
+            self.params = params;
         }
 
         let end = self.current.start;
@@ -464,35 +963,54 @@ impl<'a> Parser<'a> {
 
         // Function name (optional for expressions)
         if self.at(TokenKind::Identifier) {
+            self.declare(SymbolKind::Function, self.current);
             self.parse_identifier();
         }
 
         self.skip_comments_and_newlines();
-        self.parse_function_params();
+        let params = self.parse_function_params();
         self.skip_comments_and_newlines();
-        self.parse_block_statement();
+        if self.typescript && !self.at(TokenKind::LBrace) {
+            // TS overload / ambient signature without a body
+            self.eat(TokenKind::Semicolon);
+        } else if self.parse_function_body(flags) {
+            flags |= flags::STRICT;
+        }
+        self.check_duplicate_params(&params, flags & flags::STRICT != 0);
 
         let end = self.current.start;
         self.nodes.push(Node::new(NodeKind::FunctionDeclaration, start, end)
             .with_flags(flags));
     }
 
-    fn parse_function_params(&mut self) {
+    /// Parse `(params)`
+    fn parse_function_params(&mut self) -> Params {
         self.expect(TokenKind::LParen);
         self.skip_comments_and_newlines();
 
+        let outer = self.params.replace(Vec::new());
+        let mut count = 0;
+        let mut rest = false;
+        let mut simple = true;
         while !self.at(TokenKind::RParen) && !self.at(TokenKind::Eof) {
+            count += 1;
             if self.at(TokenKind::DotDotDot) {
                 self.parse_rest_element();
+                rest = true;
+                simple = false;
                 break;
             }
+            simple &= self.at(TokenKind::Identifier);
             self.parse_binding_pattern();
 
             // Default value
             self.skip_comments_and_newlines();
             if self.eat(TokenKind::Eq) {
+                simple = false;
                 self.skip_comments_and_newlines();
+                let params = self.params.take();
                 self.parse_expression();
+                self.params = params;
             }
 
             self.skip_comments_and_newlines();
@@ -503,6 +1021,36 @@ impl<'a> Parser<'a> {
         }
 
         self.expect(TokenKind::RParen);
+        let names = std::mem::replace(&mut self.params, outer).unwrap_or_default();
+        let duplicate = names.iter().enumerate().find_map(|(i, &name)| {
+            let text = self.token_text(name);
+            names[..i].iter().any(|&earlier| self.token_text(earlier) == text).then_some(name)
+        });
+        Params { count, rest, simple, duplicate }
+    }
+
+    /// Report a getter with parameters or a setter without exactly one
+    /// plain parameter (early errors), at the accessor's key
+    fn check_accessor_params(&mut self, accessor: TokenKind, params: &Params, key: Token) {
+        let message = match accessor {
+            TokenKind::Get if params.count > 0 => "Getter must not have any formal parameters",
+            TokenKind::Set if params.count != 1 => "Setter must have exactly one formal parameter",
+            TokenKind::Set if params.rest => "Setter function argument must not be a rest parameter",
+            _ => return,
+        };
+        self.report(message, key.start, key.end);
+    }
+
+    /// Report a parameter name bound twice (an early error), unless the
+    /// function is sloppy-mode code with a simple parameter list
+    ///
+    /// Methods pass `strict`, as their parameters must always be unique.
+    fn check_duplicate_params(&mut self, params: &Params, strict: bool) {
+        if let Some(name) = params.duplicate
+            && (strict || !params.simple)
+        {
+            self.report("Duplicate parameter name not allowed in this context", name.start, name.end);
+        }
     }
 
     fn parse_class_declaration(&mut self) {
@@ -513,6 +1061,7 @@ impl<'a> Parser<'a> {
 
         // Class name
         if self.at(TokenKind::Identifier) {
+            self.declare(SymbolKind::Class, self.current);
             self.parse_identifier();
         }
 
@@ -552,6 +1101,10 @@ impl<'a> Parser<'a> {
         let start = self.current.start;
         let mut flags = 0;
 
+        if self.at(TokenKind::At) {
+            self.parse_decorators();
+        }
+
         // Static
         if self.eat(TokenKind::Static) {
             flags |= flags::STATIC;
@@ -570,15 +1123,20 @@ impl<'a> Parser<'a> {
             self.skip_comments_and_newlines();
         }
 
-        // Get/Set
-        let is_getter = self.at(TokenKind::Get);
-        let is_setter = self.at(TokenKind::Set);
-        if is_getter || is_setter {
+        // Get/Set, unless it's the member's name (`get() {}`, `set = 1`)
+        let accessor = self.current.kind;
+        let is_accessor = matches!(accessor, TokenKind::Get | TokenKind::Set)
+            && !matches!(
+                self.peek.kind,
+                TokenKind::LParen | TokenKind::Eq | TokenKind::Semicolon | TokenKind::RBrace
+            );
+        if is_accessor {
             self.advance();
             self.skip_comments_and_newlines();
         }
 
         // Property name
+        let key = self.current;
         if self.eat(TokenKind::LBracket) {
             flags |= flags::COMPUTED;
             self.parse_expression();
@@ -593,12 +1151,16 @@ impl<'a> Parser<'a> {
         if self.at(TokenKind::LParen) {
             // ESTree MethodDefinition.value is a FunctionExpression
             let fn_start = self.current.start;
-            self.parse_function_params();
+            let params = self.parse_function_params();
+            if is_accessor {
+                self.check_accessor_params(accessor, &params, key);
+            }
             self.skip_comments_and_newlines();
-            self.parse_block_statement();
+            let strict = if self.parse_function_body(flags) { flags::STRICT } else { 0 };
+            self.check_duplicate_params(&params, true);
             let end = self.current.start;
             self.nodes.push(
-                Node::new(NodeKind::FunctionExpression, fn_start, end).with_flags(flags),
+                Node::new(NodeKind::FunctionExpression, fn_start, end).with_flags(flags | strict),
             );
             self.nodes.push(
                 Node::new(NodeKind::MethodDefinition, start, end).with_flags(flags),
@@ -634,6 +1196,12 @@ impl<'a> Parser<'a> {
             return;
         }
 
+        // TS: import x = require("m") / import x = A.B
+        if self.typescript && self.at(TokenKind::Identifier) && self.peek.kind == TokenKind::Eq {
+            self.parse_ts_import_equals(start);
+            return;
+        }
+
         // import defaultExport from "module"
         // import * as name from "module"
         // import { named } from "module"
@@ -642,6 +1210,7 @@ impl<'a> Parser<'a> {
         if self.at(TokenKind::Identifier) {
             // Default import
             let spec_start = self.current.start;
+            self.declare(SymbolKind::Import, self.current);
             self.parse_identifier();
             let spec_end = self.current.start;
             self.nodes.push(Node::new(NodeKind::ImportDefaultSpecifier, spec_start, spec_end));
@@ -658,6 +1227,7 @@ impl<'a> Parser<'a> {
             self.skip_comments_and_newlines();
             self.expect(TokenKind::As);
             self.skip_comments_and_newlines();
+            self.declare(SymbolKind::Import, self.current);
             self.parse_identifier();
             let spec_end = self.current.start;
             self.nodes.push(Node::new(NodeKind::ImportNamespaceSpecifier, spec_start, spec_end));
@@ -669,11 +1239,21 @@ impl<'a> Parser<'a> {
             while !self.at(TokenKind::RBrace) && !self.at(TokenKind::Eof) {
                 let spec_start = self.current.start;
                 // ESTree ImportSpecifier has both imported + local Identifier nodes.
+                // `default` may be imported by name, but only bound under another.
+                let mut local = self.current;
                 self.parse_identifier();
                 self.skip_comments_and_newlines();
 
-                if self.eat(TokenKind::As) {
+                let renamed = self.eat(TokenKind::As);
+                if renamed {
                     self.skip_comments_and_newlines();
+                    local = self.current;
+                }
+                if local.kind == TokenKind::Default {
+                    self.report("`default` cannot be used as an import binding", local.start, local.end);
+                }
+                self.declare(SymbolKind::Import, local);
+                if renamed {
                     self.parse_identifier();
                 } else {
                     // No rename: emit local Identifier twin (same span) to match ESTree.
@@ -715,18 +1295,75 @@ impl<'a> Parser<'a> {
         self.nodes.push(Node::new(NodeKind::ImportDeclaration, start, end));
     }
 
-    fn parse_export_declaration(&mut self) {
+    /// TS `import x = require("m")` or `import x = A.B`
+    ///
+    /// Flag bit 0 marks an external `require(...)` reference.
+    fn parse_ts_import_equals(&mut self, start: u32) {
+        self.declare(SymbolKind::Import, self.current);
+        self.parse_identifier();
+        self.skip_comments_and_newlines();
+        self.expect(TokenKind::Eq);
+        self.skip_comments_and_newlines();
+
+        let external = self.at(TokenKind::Identifier)
+            && self.peek.kind == TokenKind::LParen
+            && self.token_text(self.current) == "require";
+        // require("m") parses as a call, A.B as a member chain
+        self.parse_call_expression();
+
+        self.eat(TokenKind::Semicolon);
+        let end = self.current.start;
+        self.nodes.push(Node::new(NodeKind::TSImportEquals, start, end)
+            .with_flags(external as u16));
+    }
+
+    fn token_text(&self, token: Token) -> &'a str {
+        &self.source[token.start as usize..token.end as usize]
+    }
+
+    /// `decorated` is set when decorators already preceded `export`
+    fn parse_export_declaration(&mut self, decorated: bool) {
         let start = self.current.start;
         self.advance(); // skip export
 
         self.skip_comments_and_newlines();
 
+        // TS: export = expr
+        if self.typescript && self.eat(TokenKind::Eq) {
+            self.skip_comments_and_newlines();
+            self.parse_assignment_expression();
+            self.eat(TokenKind::Semicolon);
+            let end = self.current.start;
+            self.nodes.push(Node::new(NodeKind::TSExportAssignment, start, end));
+            return;
+        }
+
         let mut flags = 0;
 
-        if self.eat(TokenKind::Default) {
+        let default = self.eat(TokenKind::Default);
+        if default {
             flags |= flags::EXPORT_DEFAULT;
             self.skip_comments_and_newlines();
+        }
 
+        // export @dec class / export default @dec class
+        if self.at(TokenKind::At) {
+            if decorated {
+                let at = self.current;
+                self.report(
+                    "Decorators cannot appear both before and after `export`",
+                    at.start,
+                    at.end,
+                );
+            }
+            self.parse_decorators();
+            if !self.at(TokenKind::Class) {
+                let stray = self.current;
+                self.report("Decorators must precede a class", stray.start, stray.end);
+            }
+        }
+
+        if default {
             // export default expression
             match self.current.kind {
                 TokenKind::Function | TokenKind::Async => self.parse_function_declaration(),
@@ -741,8 +1378,17 @@ impl<'a> Parser<'a> {
             self.advance();
             self.skip_comments_and_newlines();
 
+            // Reserved local names, only valid when re-exporting
+            let mut keyword_locals = Vec::new();
+
             while !self.at(TokenKind::RBrace) && !self.at(TokenKind::Eof) {
                 let spec_start = self.current.start;
+                // Local Identifier, then the exported one only when renamed
+                // (`a as b`). `default` is a valid name on either side.
+                let local = self.current;
+                if local.kind.is_reserved() {
+                    keyword_locals.push(local);
+                }
                 self.parse_identifier();
                 self.skip_comments_and_newlines();
 
@@ -767,7 +1413,18 @@ impl<'a> Parser<'a> {
             self.skip_comments_and_newlines();
             if self.eat(TokenKind::From) {
                 self.skip_comments_and_newlines();
-                self.advance(); // skip string
+                if self.at(TokenKind::String) {
+                    let lit_start = self.current.start;
+                    self.advance();
+                    self.nodes
+                        .push(Node::new(NodeKind::Literal, lit_start, self.current.start));
+                } else {
+                    self.advance(); // skip unexpected token fail-soft
+                }
+            } else {
+                for local in keyword_locals {
+                    self.report("Only a re-export can export a reserved word by name", local.start, local.end);
+                }
             }
 
             self.eat(TokenKind::Semicolon);
@@ -788,6 +1445,7 @@ impl<'a> Parser<'a> {
         } else {
             // export declaration
             match self.current.kind {
+                _ if self.typescript && self.at_ts_declaration() => self.parse_ts_declaration(),
                 TokenKind::Const | TokenKind::Let | TokenKind::Var => self.parse_variable_declaration(),
                 TokenKind::Function | TokenKind::Async => self.parse_function_declaration(),
                 TokenKind::Class => self.parse_class_declaration(),
@@ -800,63 +1458,503 @@ impl<'a> Parser<'a> {
             .with_flags(flags));
     }
 
-    fn parse_block_statement(&mut self) {
+    // ========================================
+    // TypeScript declarations
+    // ========================================
+
+    /// Whether the current token starts a TS-only declaration.
+    /// Contextual keywords must be followed by their operand on the same line:
+    /// newlines are tokens, so an operand on the next line is never `peek`.
+    fn at_ts_declaration(&self) -> bool {
+        match self.current.kind {
+            TokenKind::Enum => true,
+            TokenKind::Const => self.peek.kind == TokenKind::Enum,
+            TokenKind::Namespace => self.peek.kind == TokenKind::Identifier,
+            TokenKind::Module => {
+                matches!(self.peek.kind, TokenKind::Identifier | TokenKind::String)
+            }
+            TokenKind::Abstract => self.peek.kind == TokenKind::Class,
+            TokenKind::Declare => matches!(
+                self.peek.kind,
+                TokenKind::Const
+                    | TokenKind::Let
+                    | TokenKind::Var
+                    | TokenKind::Function
+                    | TokenKind::Async
+                    | TokenKind::Class
+                    | TokenKind::Abstract
+                    | TokenKind::Enum
+                    | TokenKind::Namespace
+                    | TokenKind::Module
+            ),
+            _ => false,
+        }
+    }
+
+    fn parse_ts_declaration(&mut self) {
+        match self.current.kind {
+            TokenKind::Enum | TokenKind::Const => self.parse_ts_enum_declaration(),
+            TokenKind::Namespace | TokenKind::Module => self.parse_ts_module_declaration(),
+            TokenKind::Abstract => {
+                self.advance(); // skip abstract
+                self.skip_comments_and_newlines();
+                self.parse_class_declaration();
+            }
+            TokenKind::Declare => self.parse_ts_declare(),
+            _ => self.parse_expression_statement(),
+        }
+    }
+
+    fn parse_ts_declare(&mut self) {
         let start = self.current.start;
-        self.expect(TokenKind::LBrace);
+        self.advance(); // skip declare
+        self.skip_comments_and_newlines();
+
+        match self.current.kind {
+            TokenKind::Const if self.peek.kind == TokenKind::Enum => {
+                self.parse_ts_enum_declaration()
+            }
+            TokenKind::Const | TokenKind::Let | TokenKind::Var => {
+                self.parse_variable_declaration()
+            }
+            TokenKind::Function | TokenKind::Async => self.parse_function_declaration(),
+            TokenKind::Class => self.parse_class_declaration(),
+            _ => self.parse_ts_declaration(),
+        }
+
+        // Nodes are pushed post-order: the declaration is the last node
+        if let Some(decl) = self.nodes.last_mut() {
+            decl.start = start;
+            decl.flags |= flags::DECLARE;
+        }
+    }
+
+    fn parse_ts_enum_declaration(&mut self) {
+        let start = self.current.start;
+        let mut flags = 0;
+
+        if self.eat(TokenKind::Const) {
+            flags |= flags::CONST;
+            self.skip_comments_and_newlines();
+        }
+
+        self.expect(TokenKind::Enum);
+        self.skip_comments_and_newlines();
+        self.parse_identifier();
+        self.skip_comments_and_newlines();
 
+        self.expect(TokenKind::LBrace);
         self.skip_comments_and_newlines();
 
         let mut count = 0;
         while !self.at(TokenKind::RBrace) && !self.at(TokenKind::Eof) {
-            self.parse_statement_or_declaration();
+            self.parse_ts_enum_member();
             count += 1;
+
+            self.skip_comments_and_newlines();
+            if !self.eat(TokenKind::Comma) {
+                break;
+            }
             self.skip_comments_and_newlines();
         }
 
         self.expect(TokenKind::RBrace);
         let end = self.current.start;
 
-        self.nodes.push(Node::new(NodeKind::BlockStatement, start, end)
+        self.nodes.push(Node::new(NodeKind::TSEnumDeclaration, start, end)
+            .with_flags(flags)
             .with_extra(count));
     }
 
-    fn parse_if_statement(&mut self) {
+    fn parse_ts_enum_member(&mut self) {
         let start = self.current.start;
-        self.advance(); // skip if
 
-        self.skip_comments_and_newlines();
-        self.expect(TokenKind::LParen);
-        self.skip_comments_and_newlines();
-        self.parse_expression();
-        self.skip_comments_and_newlines();
-        self.expect(TokenKind::RParen);
+        // Member name: identifier or string literal
+        if self.at(TokenKind::String) {
+            self.advance();
+            self.nodes.push(Node::new(NodeKind::Literal, start, self.current.start));
+        } else {
+            self.parse_identifier();
+        }
 
         self.skip_comments_and_newlines();
-        self.parse_statement_or_declaration();
 
-        // else
-        self.skip_comments_and_newlines();
-        let has_else = self.eat(TokenKind::Else);
-        if has_else {
+        // Optional initializer
+        let has_init = self.eat(TokenKind::Eq);
+        if has_init {
             self.skip_comments_and_newlines();
-            self.parse_statement_or_declaration();
+            self.parse_assignment_expression();
         }
 
         let end = self.current.start;
-        self.nodes.push(Node::new(NodeKind::IfStatement, start, end)
-            .with_flags(if has_else { 1 } else { 0 }));
+        self.nodes.push(Node::new(NodeKind::TSEnumMember, start, end)
+            .with_flags(if has_init { 1 } else { 0 }));
     }
 
-    fn parse_for_statement(&mut self) {
+    fn parse_ts_module_declaration(&mut self) {
         let start = self.current.start;
-        self.advance(); // skip for
+        self.advance(); // skip namespace/module
 
         self.skip_comments_and_newlines();
 
-        // Check for await
-        let is_await = self.eat(TokenKind::Await);
-        self.skip_comments_and_newlines();
-
+        // Name: dotted identifier path or (ambient module) string literal
+        if self.at(TokenKind::String) {
+            let lit_start = self.current.start;
+            self.advance();
+            self.nodes
+                .push(Node::new(NodeKind::Literal, lit_start, self.current.start));
+        } else {
+            self.parse_identifier();
+            while self.eat(TokenKind::Dot) {
+                self.parse_identifier();
+            }
+        }
+
+        self.skip_comments_and_newlines();
+
+        // Body is optional for shorthand ambient modules: `declare module "x";`
+        if self.at(TokenKind::LBrace) {
+            let body_start = self.current.start;
+            self.advance();
+            self.skip_comments_and_newlines();
+
+            let mut count = 0;
+            while !self.at(TokenKind::RBrace) && !self.at(TokenKind::Eof) {
+                self.parse_statement_or_declaration();
+                count += 1;
+                self.skip_comments_and_newlines();
+            }
+
+            self.expect(TokenKind::RBrace);
+            let body_end = self.current.start;
+            self.nodes.push(Node::new(NodeKind::TSModuleBlock, body_start, body_end)
+                .with_extra(count));
+        } else {
+            self.eat(TokenKind::Semicolon);
+        }
+
+        let end = self.current.start;
+        self.nodes.push(Node::new(NodeKind::TSModuleDeclaration, start, end));
+    }
+
+    // ========================================
+    // JSON mode
+    // ========================================
+
+    /// Parse the source as a single JSON value and return the node count
+    ///
+    /// Produces the same node kinds as the expression parser (`Program`
+    /// root, `ObjectExpression`/`Property`, `ArrayExpression`, `Literal`,
+    /// `UnaryExpression` for signs). Syntax the dialect rejects is
+    /// reported in `diagnostics()`.
+    pub fn parse_json(&mut self, mode: JsonMode) -> usize {
+        let start = self.current.start;
+        self.skip_json_trivia(mode);
+
+        if self.at(TokenKind::Eof) {
+            self.report("Expected a JSON value", start, start);
+        } else {
+            self.parse_json_value(mode);
+            self.skip_json_trivia(mode);
+            if !self.at(TokenKind::Eof) {
+                let extra = self.current;
+                self.report("Unexpected content after JSON value", extra.start, extra.end);
+            }
+        }
+
+        let end = self.current.end;
+        self.nodes.insert(0, Node::new(NodeKind::Program, start, end)
+            .with_extra(self.nodes.len() as u32));
+        self.nodes.len()
+    }
+
+    /// Skip newlines and comments, rejecting comments in strict JSON
+    fn skip_json_trivia(&mut self, mode: JsonMode) {
+        loop {
+            match self.current.kind {
+                TokenKind::Newline => self.advance(),
+                TokenKind::LineComment | TokenKind::BlockComment => {
+                    if mode == JsonMode::Strict {
+                        let comment = self.current;
+                        self.report("Comments are not allowed in JSON", comment.start, comment.end);
+                    }
+                    self.advance();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn parse_json_value(&mut self, mode: JsonMode) {
+        let token = self.current;
+        let json5 = mode == JsonMode::Json5;
+
+        match token.kind {
+            TokenKind::LBrace => self.parse_json_container(mode, TokenKind::RBrace),
+            TokenKind::LBracket => self.parse_json_container(mode, TokenKind::RBracket),
+            TokenKind::String => {
+                if !json5 && self.token_text(token).starts_with('\'') {
+                    self.report("JSON strings must use double quotes", token.start, token.end);
+                }
+                self.advance();
+                self.nodes.push(Node::new(NodeKind::Literal, token.start, self.current.start));
+            }
+            TokenKind::Number => {
+                if !json5 && !is_json_number(self.token_text(token)) {
+                    self.report("Invalid JSON number", token.start, token.end);
+                }
+                self.advance();
+                self.nodes.push(Node::new(NodeKind::Literal, token.start, self.current.start));
+            }
+            TokenKind::True | TokenKind::False | TokenKind::Null => {
+                self.advance();
+                self.nodes.push(Node::new(NodeKind::Literal, token.start, self.current.start));
+            }
+            TokenKind::Identifier
+                if json5 && matches!(self.token_text(token), "Infinity" | "NaN") =>
+            {
+                self.parse_identifier();
+            }
+            TokenKind::Minus | TokenKind::Plus => {
+                if token.kind == TokenKind::Plus && !json5 {
+                    self.report("Leading `+` is not allowed in JSON", token.start, token.end);
+                }
+                self.advance();
+                let operand = self.current;
+                let numeric = operand.kind == TokenKind::Number
+                    || (json5
+                        && operand.kind == TokenKind::Identifier
+                        && matches!(self.token_text(operand), "Infinity" | "NaN"));
+                if numeric {
+                    self.parse_json_value(mode);
+                } else {
+                    self.report("Expected a number after sign", operand.start, operand.end);
+                }
+                let end = self.current.start;
+                self.nodes.push(Node::new(NodeKind::UnaryExpression, token.start, end)
+                    .with_operator(token.kind));
+            }
+            _ => {
+                self.report("Unexpected token in JSON", token.start, token.end);
+                self.advance();
+            }
+        }
+    }
+
+    /// Object (`close` = `}`) or array (`close` = `]`) with comma-separated members
+    fn parse_json_container(&mut self, mode: JsonMode, close: TokenKind) {
+        let start = self.current.start;
+        self.advance(); // skip { or [
+        self.skip_json_trivia(mode);
+
+        let mut count = 0;
+        while !self.at(close) && !self.at(TokenKind::Eof) {
+            if close == TokenKind::RBrace {
+                self.parse_json_property(mode);
+            } else {
+                self.parse_json_value(mode);
+            }
+            count += 1;
+            self.skip_json_trivia(mode);
+
+            let comma = self.current;
+            if !self.eat(TokenKind::Comma) {
+                break;
+            }
+            self.skip_json_trivia(mode);
+            if self.at(close) && mode == JsonMode::Strict {
+                self.report("Trailing commas are not allowed in JSON", comma.start, comma.end);
+            }
+        }
+
+        if !self.at(close) {
+            let unexpected = self.current;
+            self.report("Unterminated JSON object or array", unexpected.start, unexpected.end);
+        }
+        self.expect(close);
+        let end = self.current.start;
+        let kind = if close == TokenKind::RBrace {
+            NodeKind::ObjectExpression
+        } else {
+            NodeKind::ArrayExpression
+        };
+        self.nodes.push(Node::new(kind, start, end).with_extra(count));
+    }
+
+    fn parse_json_property(&mut self, mode: JsonMode) {
+        let key = self.current;
+        match key.kind {
+            TokenKind::String => {
+                if mode == JsonMode::Strict && self.token_text(key).starts_with('\'') {
+                    self.report("JSON strings must use double quotes", key.start, key.end);
+                }
+                self.advance();
+                self.nodes.push(Node::new(NodeKind::Literal, key.start, self.current.start));
+            }
+            // JSON5 identifier keys (keywords like `default` included)
+            _ if mode == JsonMode::Json5 && self.token_text(key).starts_with(is_identifier_start) => {
+                self.parse_identifier();
+            }
+            _ => {
+                self.report("JSON object keys must be strings", key.start, key.end);
+                self.advance();
+            }
+        }
+
+        self.skip_json_trivia(mode);
+        self.expect(TokenKind::Colon);
+        self.skip_json_trivia(mode);
+        self.parse_json_value(mode);
+
+        let end = self.current.start;
+        self.nodes.push(Node::new(NodeKind::Property, key.start, end));
+    }
+
+    // ========================================
+    // Decorators
+    // ========================================
+
+    /// Parse one or more `@expr` decorators
+    fn parse_decorators(&mut self) {
+        while self.at(TokenKind::At) {
+            let start = self.current.start;
+            self.advance(); // skip @
+            // @foo, @foo.bar, @foo(args), @(expr)
+            self.parse_call_expression();
+            let end = self.current.start;
+            self.nodes.push(Node::new(NodeKind::Decorator, start, end));
+            self.skip_comments_and_newlines();
+        }
+    }
+
+    /// Statement-level `@dec class` / `@dec export class`
+    fn parse_decorated_declaration(&mut self) {
+        let start = self.current.start;
+        self.parse_decorators();
+
+        match self.current.kind {
+            TokenKind::Class => self.parse_class_declaration(),
+            TokenKind::Export => self.parse_export_declaration(true),
+            TokenKind::Abstract if self.typescript => self.parse_ts_declaration(),
+            _ => {
+                let end = self.current.start;
+                self.report("Decorators are only valid on classes and class members", start, end);
+            }
+        }
+    }
+
+    fn parse_block_statement(&mut self) {
+        self.parse_block(false);
+    }
+
+    /// Parse the body of a function with `flags`, returning whether the
+    /// function is strict
+    fn parse_function_body(&mut self, flags: u16) -> bool {
+        self.functions.push(flags & (flags::ASYNC | flags::GENERATOR));
+        let strict = self.parse_block(true);
+        self.functions.pop();
+        strict
+    }
+
+    /// `yield` is a keyword only directly inside a generator
+    fn yield_is_keyword(&self) -> bool {
+        self.functions.last().is_some_and(|&f| f & flags::GENERATOR != 0)
+    }
+
+    /// `await` is a keyword inside async functions and at the top level
+    /// (top-level await in modules)
+    fn await_is_keyword(&self) -> bool {
+        self.functions.last().is_none_or(|&f| f & flags::ASYNC != 0)
+    }
+
+    fn parse_block(&mut self, function_body: bool) -> bool {
+        let start = self.current.start;
+        self.expect(TokenKind::LBrace);
+        if self.symbols.is_some() {
+            self.scope_count += 1;
+            self.scopes.push((self.scope_count, function_body));
+        }
+
+        self.skip_comments_and_newlines();
+
+        let outer = self.strict_code;
+        let mut prologue = function_body;
+        let mut count = 0;
+        while !self.at(TokenKind::RBrace) && !self.at(TokenKind::Eof) {
+            let (token, before) = (self.current, self.nodes.len());
+            self.parse_statement_or_declaration();
+            prologue = prologue && self.mark_directive(token, before);
+            count += 1;
+            self.skip_comments_and_newlines();
+        }
+
+        self.expect(TokenKind::RBrace);
+        let end = self.current.start;
+
+        self.nodes.push(Node::new(NodeKind::BlockStatement, start, end)
+            .with_extra(count));
+        self.scopes.pop();
+
+        let strict = self.strict_code;
+        self.strict_code = outer;
+        strict
+    }
+
+    /// Flag the statement parsed from `token` (its nodes starting at
+    /// `before`) as a directive if it is a lone string literal, returning
+    /// whether the directive prologue continues
+    fn mark_directive(&mut self, token: Token, before: usize) -> bool {
+        let directive = token.kind == TokenKind::String
+            && self.nodes.len() == before + 2
+            && self.nodes[before].kind == NodeKind::Literal
+            && self.nodes[before + 1].kind == NodeKind::ExpressionStatement;
+        if !directive {
+            return false;
+        }
+        self.nodes[before + 1].flags |= flags::DIRECTIVE;
+        if matches!(self.token_text(token), "\"use strict\"" | "'use strict'") {
+            self.strict_code = true;
+        }
+        true
+    }
+
+    fn parse_if_statement(&mut self) {
+        let start = self.current.start;
+        self.advance(); // skip if
+
+        self.skip_comments_and_newlines();
+        self.expect(TokenKind::LParen);
+        self.skip_comments_and_newlines();
+        self.parse_expression();
+        self.skip_comments_and_newlines();
+        self.expect(TokenKind::RParen);
+
+        self.skip_comments_and_newlines();
+        self.parse_statement_or_declaration();
+
+        // else
+        self.skip_comments_and_newlines();
+        let has_else = self.eat(TokenKind::Else);
+        if has_else {
+            self.skip_comments_and_newlines();
+            self.parse_statement_or_declaration();
+        }
+
+        let end = self.current.start;
+        self.nodes.push(Node::new(NodeKind::IfStatement, start, end)
+            .with_flags(if has_else { 1 } else { 0 }));
+    }
+
+    fn parse_for_statement(&mut self) {
+        let start = self.current.start;
+        self.advance(); // skip for
+
+        self.skip_comments_and_newlines();
+
+        // Check for await
+        let is_await = self.eat(TokenKind::Await);
+        self.skip_comments_and_newlines();
+
         self.expect(TokenKind::LParen);
         self.skip_comments_and_newlines();
 
@@ -1142,9 +2240,11 @@ impl<'a> Parser<'a> {
             self.parse_identifier();
             self.advance(); // skip =>
             self.skip_comments_and_newlines();
-            self.parse_arrow_body();
+            let flags = if self.parse_arrow_body() { flags::STRICT } else { 0 };
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::ArrowFunctionExpression, start, end));
+            self.nodes.push(
+                Node::new(NodeKind::ArrowFunctionExpression, start, end).with_flags(flags),
+            );
             return;
         }
 
@@ -1159,19 +2259,25 @@ impl<'a> Parser<'a> {
             TokenKind::CaretEq | TokenKind::AmpAmpEq | TokenKind::PipePipeEq |
             TokenKind::QuestionQuestionEq
         ) {
+            let operator = self.current.kind;
             self.advance();
             self.skip_comments_and_newlines();
             self.parse_assignment_expression();
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::AssignmentExpression, start, end));
+            self.nodes.push(Node::new(NodeKind::AssignmentExpression, start, end)
+                .with_operator(operator));
         }
     }
 
-    fn parse_arrow_body(&mut self) {
+    /// Parse an arrow body, returning whether the function is strict
+    fn parse_arrow_body(&mut self) -> bool {
         if self.at(TokenKind::LBrace) {
-            self.parse_block_statement();
+            self.parse_function_body(0)
         } else {
+            self.functions.push(0);
             self.parse_assignment_expression();
+            self.functions.pop();
+            self.strict_code
         }
     }
 
@@ -1195,11 +2301,13 @@ impl<'a> Parser<'a> {
         let start = self.current.start;
         self.parse_logical_and_expression();
 
-        while self.eat(TokenKind::PipePipe) || self.eat(TokenKind::QuestionQuestion) {
+        while matches!(self.current.kind, TokenKind::PipePipe | TokenKind::QuestionQuestion) {
+            let operator = self.current.kind;
+            self.advance();
             self.skip_comments_and_newlines();
             self.parse_logical_and_expression();
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::LogicalExpression, start, end));
+            self.nodes.push(Node::new(NodeKind::LogicalExpression, start, end).with_operator(operator));
         }
     }
 
@@ -1207,11 +2315,13 @@ impl<'a> Parser<'a> {
         let start = self.current.start;
         self.parse_bitwise_or_expression();
 
-        while self.eat(TokenKind::AmpAmp) {
+        while self.at(TokenKind::AmpAmp) {
+            let operator = self.current.kind;
+            self.advance();
             self.skip_comments_and_newlines();
             self.parse_bitwise_or_expression();
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::LogicalExpression, start, end));
+            self.nodes.push(Node::new(NodeKind::LogicalExpression, start, end).with_operator(operator));
         }
     }
 
@@ -1219,11 +2329,13 @@ impl<'a> Parser<'a> {
         let start = self.current.start;
         self.parse_bitwise_xor_expression();
 
-        while self.eat(TokenKind::Pipe) {
+        while self.at(TokenKind::Pipe) {
+            let operator = self.current.kind;
+            self.advance();
             self.skip_comments_and_newlines();
             self.parse_bitwise_xor_expression();
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end));
+            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end).with_operator(operator));
         }
     }
 
@@ -1231,11 +2343,13 @@ impl<'a> Parser<'a> {
         let start = self.current.start;
         self.parse_bitwise_and_expression();
 
-        while self.eat(TokenKind::Caret) {
+        while self.at(TokenKind::Caret) {
+            let operator = self.current.kind;
+            self.advance();
             self.skip_comments_and_newlines();
             self.parse_bitwise_and_expression();
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end));
+            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end).with_operator(operator));
         }
     }
 
@@ -1243,11 +2357,13 @@ impl<'a> Parser<'a> {
         let start = self.current.start;
         self.parse_equality_expression();
 
-        while self.eat(TokenKind::Amp) {
+        while self.at(TokenKind::Amp) {
+            let operator = self.current.kind;
+            self.advance();
             self.skip_comments_and_newlines();
             self.parse_equality_expression();
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end));
+            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end).with_operator(operator));
         }
     }
 
@@ -1256,11 +2372,12 @@ impl<'a> Parser<'a> {
         self.parse_relational_expression();
 
         while matches!(self.current.kind, TokenKind::EqEq | TokenKind::BangEq | TokenKind::EqEqEq | TokenKind::BangEqEq) {
+            let operator = self.current.kind;
             self.advance();
             self.skip_comments_and_newlines();
             self.parse_relational_expression();
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end));
+            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end).with_operator(operator));
         }
     }
 
@@ -1268,12 +2385,21 @@ impl<'a> Parser<'a> {
         let start = self.current.start;
         self.parse_shift_expression();
 
-        while matches!(self.current.kind, TokenKind::Lt | TokenKind::Gt | TokenKind::LtEq | TokenKind::GtEq | TokenKind::Instanceof | TokenKind::In) {
+        loop {
+            // `in`/`instanceof` can't start a statement, so a line break before
+            // them never ends the expression
+            if self.at(TokenKind::Newline) && matches!(self.peek.kind, TokenKind::Instanceof | TokenKind::In) {
+                self.advance();
+            }
+            if !matches!(self.current.kind, TokenKind::Lt | TokenKind::Gt | TokenKind::LtEq | TokenKind::GtEq | TokenKind::Instanceof | TokenKind::In) {
+                break;
+            }
+            let operator = self.current.kind;
             self.advance();
             self.skip_comments_and_newlines();
             self.parse_shift_expression();
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end));
+            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end).with_operator(operator));
         }
     }
 
@@ -1282,11 +2408,12 @@ impl<'a> Parser<'a> {
         self.parse_additive_expression();
 
         while matches!(self.current.kind, TokenKind::LtLt | TokenKind::GtGt | TokenKind::GtGtGt) {
+            let operator = self.current.kind;
             self.advance();
             self.skip_comments_and_newlines();
             self.parse_additive_expression();
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end));
+            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end).with_operator(operator));
         }
     }
 
@@ -1295,11 +2422,12 @@ impl<'a> Parser<'a> {
         self.parse_multiplicative_expression();
 
         while matches!(self.current.kind, TokenKind::Plus | TokenKind::Minus) {
+            let operator = self.current.kind;
             self.advance();
             self.skip_comments_and_newlines();
             self.parse_multiplicative_expression();
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end));
+            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end).with_operator(operator));
         }
     }
 
@@ -1308,11 +2436,12 @@ impl<'a> Parser<'a> {
         self.parse_exponentiation_expression();
 
         while matches!(self.current.kind, TokenKind::Star | TokenKind::Slash | TokenKind::Percent) {
+            let operator = self.current.kind;
             self.advance();
             self.skip_comments_and_newlines();
             self.parse_exponentiation_expression();
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end));
+            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end).with_operator(operator));
         }
     }
 
@@ -1320,11 +2449,13 @@ impl<'a> Parser<'a> {
         let start = self.current.start;
         self.parse_unary_expression();
 
-        if self.eat(TokenKind::StarStar) {
+        if self.at(TokenKind::StarStar) {
+            let operator = self.current.kind;
+            self.advance();
             self.skip_comments_and_newlines();
             self.parse_exponentiation_expression(); // right-associative
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end));
+            self.nodes.push(Node::new(NodeKind::BinaryExpression, start, end).with_operator(operator));
         }
     }
 
@@ -1347,7 +2478,7 @@ impl<'a> Parser<'a> {
                 let end = self.current.start;
                 self.nodes.push(Node::new(NodeKind::UpdateExpression, start, end));
             }
-            TokenKind::Await => {
+            TokenKind::Await if self.await_is_keyword() => {
                 self.advance();
                 self.skip_comments_and_newlines();
                 self.parse_unary_expression();
@@ -1370,45 +2501,60 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Member/call chains are left-nested: each `MemberExpression`,
+    /// `CallExpression`, `NewExpression` and `TaggedTemplateExpression`
+    /// stores the output index of its object/callee/tag node in `extra`,
+    /// so `a.b.c()` reconstructs as `((a.b).c)()`.
+    ///
+    /// A chain with a `?.` link is wrapped in a `ChainExpression`, the
+    /// extent `?.` short-circuits: all of `a?.b.c()`, while in
+    /// `(a?.b).c` only the parenthesized part.
     fn parse_call_expression(&mut self) {
         let start = self.current.start;
         self.parse_member_expression();
+        let mut optional_chain = false;
 
         loop {
+            let object = self.last_node_index();
             match self.current.kind {
                 TokenKind::LParen => {
                     self.parse_arguments();
                     let end = self.current.start;
-                    self.nodes.push(Node::new(NodeKind::CallExpression, start, end));
+                    self.nodes.push(Node::new(NodeKind::CallExpression, start, end)
+                        .with_extra(object));
                 }
-                TokenKind::LBracket => {
+                TokenKind::QuestionDot if self.peek.kind == TokenKind::LParen => {
+                    optional_chain = true;
                     self.advance();
-                    self.skip_comments_and_newlines();
-                    self.parse_expression();
-                    self.skip_comments_and_newlines();
-                    self.expect(TokenKind::RBracket);
+                    self.parse_arguments();
                     let end = self.current.start;
-                    self.nodes.push(Node::new(NodeKind::MemberExpression, start, end)
-                        .with_flags(flags::COMPUTED));
+                    self.nodes.push(Node::new(NodeKind::CallExpression, start, end)
+                        .with_flags(flags::OPTIONAL)
+                        .with_extra(object));
                 }
-                TokenKind::Dot | TokenKind::QuestionDot => {
-                    let optional = self.current.kind == TokenKind::QuestionDot;
-                    self.advance();
-                    self.skip_comments_and_newlines();
-                    self.parse_identifier();
-                    let end = self.current.start;
-                    self.nodes.push(Node::new(NodeKind::MemberExpression, start, end)
-                        .with_flags(if optional { 1 } else { 0 }));
+                TokenKind::LBracket | TokenKind::Dot | TokenKind::QuestionDot => {
+                    optional_chain |= self.current.kind == TokenKind::QuestionDot;
+                    self.parse_member_access(start, object);
                 }
                 TokenKind::Template => {
-                    // Tagged template
+                    // Tagged template: the tag is everything parsed so far
                     self.parse_template_literal();
                     let end = self.current.start;
-                    self.nodes.push(Node::new(NodeKind::TaggedTemplateExpression, start, end));
+                    self.nodes.push(Node::new(NodeKind::TaggedTemplateExpression, start, end)
+                        .with_extra(object));
                 }
+                // No ASI before a template: it still tags the line above
+                TokenKind::Newline if self.peek.kind == TokenKind::Template => self.advance(),
                 _ => break,
             }
         }
+
+        if optional_chain {
+            let expression = self.last_node_index();
+            let end = self.current.start;
+            self.nodes.push(Node::new(NodeKind::ChainExpression, start, end)
+                .with_extra(expression));
+        }
     }
 
     fn parse_arguments(&mut self) {
@@ -1440,19 +2586,69 @@ impl<'a> Parser<'a> {
         self.nodes.push(Node::new(NodeKind::SpreadElement, start, end));
     }
 
+    /// `.name`, `?.name`, `[expr]` or `?.[expr]` applied to the node at
+    /// `object`, which starts at `start`
+    fn parse_member_access(&mut self, start: u32, object: u32) {
+        let optional = self.current.kind == TokenKind::QuestionDot;
+        if optional && self.peek.kind == TokenKind::LBracket {
+            self.advance();
+        }
+        let optional_flag = if optional { flags::OPTIONAL } else { 0 };
+
+        if self.eat(TokenKind::LBracket) {
+            self.skip_comments_and_newlines();
+            self.parse_expression();
+            self.skip_comments_and_newlines();
+            self.expect(TokenKind::RBracket);
+            let end = self.current.start;
+            self.nodes.push(Node::new(NodeKind::MemberExpression, start, end)
+                .with_flags(flags::COMPUTED | optional_flag)
+                .with_extra(object));
+            return;
+        }
+
+        self.advance();
+        self.skip_comments_and_newlines();
+        self.parse_identifier();
+        let end = self.current.start;
+        self.nodes.push(Node::new(NodeKind::MemberExpression, start, end)
+            .with_flags(optional_flag)
+            .with_extra(object));
+    }
+
+    /// Primary expression, or `new` with its callee and arguments
+    ///
+    /// The callee is a whole member chain without calls, so `new a.b.C(1)`
+    /// constructs `a.b.C` and the first argument list belongs to `new`.
+    /// `new X(...)` is itself a member expression the chain continues
+    /// from, which nests `new new Y()()` as `new (new Y())()`. Without
+    /// arguments (`new X`) the chain ends.
     fn parse_member_expression(&mut self) {
         let start = self.current.start;
 
         if self.eat(TokenKind::New) {
             self.skip_comments_and_newlines();
+            let callee_start = self.current.start;
             self.parse_member_expression();
+            while matches!(self.current.kind, TokenKind::Dot | TokenKind::LBracket) {
+                let object = self.last_node_index();
+                self.parse_member_access(callee_start, object);
+            }
+            let callee = self.last_node_index();
 
-            if self.at(TokenKind::LParen) {
+            let has_arguments = self.at(TokenKind::LParen);
+            if has_arguments {
                 self.parse_arguments();
             }
 
             let end = self.current.start;
-            self.nodes.push(Node::new(NodeKind::NewExpression, start, end));
+            self.nodes.push(Node::new(NodeKind::NewExpression, start, end)
+                .with_extra(callee));
+
+            while has_arguments && matches!(self.current.kind, TokenKind::Dot | TokenKind::LBracket) {
+                let object = self.last_node_index();
+                self.parse_member_access(start, object);
+            }
         } else {
             self.parse_primary_expression();
         }
@@ -1463,8 +2659,17 @@ impl<'a> Parser<'a> {
 
         match self.current.kind {
             TokenKind::Identifier => self.parse_identifier(),
-            TokenKind::Number | TokenKind::BigInt | TokenKind::String |
-            TokenKind::True | TokenKind::False | TokenKind::Null => {
+            TokenKind::Number | TokenKind::BigInt => {
+                let end = self.current.end;
+                // `1.toString()`: the `.` went to the number, a syntax
+                // error in every mode
+                if self.source[end as usize..].starts_with(is_identifier_start) {
+                    self.report("Identifier directly after number", start, end + 1);
+                }
+                self.advance();
+                self.nodes.push(Node::new(NodeKind::Literal, start, self.current.start));
+            }
+            TokenKind::String | TokenKind::Regex | TokenKind::True | TokenKind::False | TokenKind::Null => {
                 self.advance();
                 self.nodes.push(Node::new(NodeKind::Literal, start, self.current.start));
             }
@@ -1499,7 +2704,16 @@ impl<'a> Parser<'a> {
                 }
             }
             TokenKind::Class => self.parse_class_expression(),
-            TokenKind::Yield => {
+            TokenKind::At => {
+                self.parse_decorators();
+                if self.at(TokenKind::Class) {
+                    self.parse_class_expression();
+                } else {
+                    let end = self.current.start;
+                    self.report("Decorators are only valid on classes and class members", start, end);
+                }
+            }
+            TokenKind::Yield if self.yield_is_keyword() => {
                 self.advance();
                 self.skip_comments_and_newlines();
                 if !matches!(self.current.kind, TokenKind::Semicolon | TokenKind::RBrace | TokenKind::RParen | TokenKind::RBracket | TokenKind::Comma | TokenKind::Colon) {
@@ -1508,8 +2722,11 @@ impl<'a> Parser<'a> {
                 let end = self.current.start;
                 self.nodes.push(Node::new(NodeKind::YieldExpression, start, end));
             }
+            // Plain identifiers outside generators / async functions
+            TokenKind::Yield | TokenKind::Await => self.parse_identifier(),
             _ => {
                 // Skip unknown token
+                self.report_unexpected();
                 self.advance();
             }
         }
@@ -1517,6 +2734,9 @@ impl<'a> Parser<'a> {
 
     fn parse_identifier(&mut self) {
         let start = self.current.start;
+        if !self.current.kind.is_word() {
+            self.report_unexpected();
+        }
         self.advance();
         self.nodes.push(Node::new(NodeKind::Identifier, start, self.current.start));
     }
@@ -1532,11 +2752,10 @@ impl<'a> Parser<'a> {
 
         if end_exclusive > start_usize && end_exclusive <= self.source.len() {
             let raw = &self.source[start_usize..end_exclusive];
-            // raw includes surrounding backticks when lexer kept them in span
-            let inner = raw
-                .strip_prefix('`')
-                .and_then(|s| s.strip_suffix('`'))
-                .unwrap_or(raw);
+            // raw includes surrounding backticks when lexer kept them in span;
+            // an unterminated template has only the opening one
+            let body = raw.strip_prefix('`').unwrap_or(raw);
+            let inner = body.strip_suffix('`').unwrap_or(body);
             let mut i = 0;
             let bytes = inner.as_bytes();
             let mut quasi_count = 0u32;
@@ -1547,7 +2766,7 @@ impl<'a> Parser<'a> {
                     + i;
                 while i < bytes.len() {
                     if bytes[i] == b'\\' {
-                        i += 2;
+                        i = (i + 2).min(bytes.len());
                         continue;
                     }
                     if bytes[i] == b'$' && i + 1 < bytes.len() && bytes[i + 1] == b'{' {
@@ -1622,17 +2841,22 @@ impl<'a> Parser<'a> {
 
         self.skip_comments_and_newlines();
 
+        // `count` is the array's length: elements plus holes, not a
+        // trailing comma
         let mut count = 0;
         while !self.at(TokenKind::RBracket) && !self.at(TokenKind::Eof) {
+            count += 1;
             if self.at(TokenKind::Comma) {
+                let at = self.current.start;
+                self.nodes.push(Node::new(NodeKind::ArrayHole, at, at));
                 self.advance();
-                count += 1;
-            } else if self.at(TokenKind::DotDotDot) {
+                self.skip_comments_and_newlines();
+                continue;
+            }
+            if self.at(TokenKind::DotDotDot) {
                 self.parse_spread_element();
-                count += 1;
             } else {
                 self.parse_assignment_expression();
-                count += 1;
             }
 
             self.skip_comments_and_newlines();
@@ -1656,11 +2880,12 @@ impl<'a> Parser<'a> {
         self.skip_comments_and_newlines();
 
         let mut count = 0;
+        let mut proto_keys = Vec::new();
         while !self.at(TokenKind::RBrace) && !self.at(TokenKind::Eof) {
             if self.at(TokenKind::DotDotDot) {
                 self.parse_spread_element();
-            } else {
-                self.parse_object_property();
+            } else if let Some(key) = self.parse_object_property() {
+                proto_keys.push(key);
             }
             count += 1;
 
@@ -1674,18 +2899,36 @@ impl<'a> Parser<'a> {
         self.expect(TokenKind::RBrace);
         let end = self.current.start;
 
+        // A repeated `__proto__: value` is an early error, except when the
+        // object is really a destructuring pattern (`({ ... } = x)`)
+        if !self.at(TokenKind::Eq) {
+            for key in proto_keys.iter().skip(1) {
+                self.report(
+                    "Duplicate __proto__ fields are not allowed in object literals",
+                    key.start,
+                    key.end,
+                );
+            }
+        }
+
         self.nodes.push(Node::new(NodeKind::ObjectExpression, start, end)
             .with_extra(count));
     }
 
-    fn parse_object_property(&mut self) {
+    /// Returns the key token of a `__proto__: value` property (the form
+    /// that sets the prototype; shorthand, computed and method keys don't)
+    fn parse_object_property(&mut self) -> Option<Token> {
         let start = self.current.start;
         let mut flags = 0;
 
-        // Getter/setter
-        let is_getter = self.at(TokenKind::Get);
-        let is_setter = self.at(TokenKind::Set);
-        if (is_getter || is_setter) && self.peek.kind != TokenKind::Colon && self.peek.kind != TokenKind::LParen {
+        // Getter/setter, unless it's the key (`get: 1`, `get() {}`, `{ get }`)
+        let accessor = self.current.kind;
+        let is_accessor = matches!(accessor, TokenKind::Get | TokenKind::Set)
+            && !matches!(
+                self.peek.kind,
+                TokenKind::Colon | TokenKind::LParen | TokenKind::Comma | TokenKind::RBrace
+            );
+        if is_accessor {
             self.advance();
             self.skip_comments_and_newlines();
         }
@@ -1704,24 +2947,37 @@ impl<'a> Parser<'a> {
         }
 
         // Computed property
+        let name = self.current;
         if self.eat(TokenKind::LBracket) {
             flags |= flags::COMPUTED;
             self.parse_expression();
             self.expect(TokenKind::RBracket);
-        } else {
+        }
+        let key = self.current;
+        if flags & flags::COMPUTED == 0 {
             // Regular key
             self.parse_identifier();
         }
 
         self.skip_comments_and_newlines();
 
+        let mut proto_key = None;
         // Method shorthand
         if self.at(TokenKind::LParen) {
-            self.parse_function_params();
+            let params = self.parse_function_params();
+            if is_accessor {
+                self.check_accessor_params(accessor, &params, name);
+            }
             self.skip_comments_and_newlines();
-            self.parse_block_statement();
+            self.parse_function_body(flags);
+            self.check_duplicate_params(&params, true);
         } else if self.eat(TokenKind::Colon) {
             // Regular property
+            if flags == 0
+                && matches!(self.token_text(key), "__proto__" | "\"__proto__\"" | "'__proto__'")
+            {
+                proto_key = Some(key);
+            }
             self.skip_comments_and_newlines();
             self.parse_assignment_expression();
         } else {
@@ -1732,6 +2988,7 @@ impl<'a> Parser<'a> {
         let end = self.current.start;
         self.nodes.push(Node::new(NodeKind::Property, start, end)
             .with_flags(flags));
+        proto_key
     }
 
     fn parse_function_expression(&mut self) {
@@ -1757,9 +3014,12 @@ impl<'a> Parser<'a> {
         }
 
         self.skip_comments_and_newlines();
-        self.parse_function_params();
+        let params = self.parse_function_params();
         self.skip_comments_and_newlines();
-        self.parse_block_statement();
+        if self.parse_function_body(flags) {
+            flags |= flags::STRICT;
+        }
+        self.check_duplicate_params(&params, flags & flags::STRICT != 0);
 
         let end = self.current.start;
         self.nodes.push(Node::new(NodeKind::FunctionExpression, start, end)
@@ -1793,9 +3053,69 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// Encode nodes in the `parseBinary` format
+///
+/// Header: node count (u32 LE), then 16 bytes per node:
+/// kind, flags (u16 LE), 1 byte padding, start, end, extra (u32 LE each).
+/// Flags sit at byte 1, so readers of the low byte alone still see the
+/// first eight flags.
+pub fn encode_binary(nodes: &[Node]) -> Vec<u8> {
+    let node_count = nodes.len();
+    let node_size = std::mem::size_of::<Node>();
+    let mut buf = vec![0u8; 4 + std::mem::size_of_val(nodes)];
+
+    // Header
+    buf[0..4].copy_from_slice(&(node_count as u32).to_le_bytes());
+
+    // Nodes
+    for (i, node) in nodes.iter().enumerate() {
+        let offset = 4 + i * node_size;
+        buf[offset] = node.kind as u8;
+        buf[offset + 1..offset + 3].copy_from_slice(&node.flags.to_le_bytes());
+        // skip padding [3..4]
+        buf[offset + 4..offset + 8].copy_from_slice(&node.start.to_le_bytes());
+        buf[offset + 8..offset + 12].copy_from_slice(&node.end.to_le_bytes());
+        buf[offset + 12..offset + 16].copy_from_slice(&node.extra.to_le_bytes());
+    }
+
+    buf
+}
+
+/// Whether `text` matches the strict JSON number grammar (sign excluded)
+fn is_json_number(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    let int_len = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    if int_len == 0 || (int_len > 1 && bytes[0] == b'0') {
+        return false;
+    }
+
+    let mut rest = &bytes[int_len..];
+    if let Some(fraction) = rest.strip_prefix(b".") {
+        let digits = fraction.iter().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            return false;
+        }
+        rest = &fraction[digits..];
+    }
+    if let Some(exponent) = rest.strip_prefix(b"e").or_else(|| rest.strip_prefix(b"E")) {
+        let exponent = exponent
+            .strip_prefix(b"+")
+            .or_else(|| exponent.strip_prefix(b"-"))
+            .unwrap_or(exponent);
+        return !exponent.is_empty() && exponent.iter().all(u8::is_ascii_digit);
+    }
+    rest.is_empty()
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '$'
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::Cell;
+    use std::sync::atomic::AtomicBool;
 
     #[test]
     fn test_variable_declaration() {
@@ -1856,6 +3176,43 @@ mod tests {
         assert!(lit_count >= 1, "module path Literal required");
     }
 
+    #[test]
+    fn test_default_as_specifier_name() {
+        fn parse(source: &str) -> (bool, Vec<(NodeKind, &str)>) {
+            let mut parser = Parser::new(source).with_strict(true);
+            let ok = parser.parse().is_ok();
+            let nodes: Vec<_> = parser
+                .nodes()
+                .iter()
+                .map(|n| (n.kind, source[n.start as usize..n.end as usize].trim_end()))
+                .collect();
+            (ok, nodes)
+        }
+
+        let (ok, nodes) = parse("export { default as Foo } from './foo';");
+        assert!(ok);
+        assert_eq!(nodes[1..5], [
+            (NodeKind::Identifier, "default"),
+            (NodeKind::Identifier, "Foo"),
+            (NodeKind::ExportSpecifier, "default as Foo"),
+            (NodeKind::Literal, "'./foo'"),
+        ]);
+
+        // Mixed forms; a bare specifier has only its local Identifier
+        let (ok, nodes) = parse("export { a, default, b as default } from 'm';");
+        assert!(ok);
+        let specifiers: Vec<_> = nodes.iter().filter(|n| n.0 == NodeKind::ExportSpecifier).collect();
+        assert_eq!(specifiers.len(), 3);
+        assert_eq!(nodes.iter().filter(|n| n.0 == NodeKind::Identifier).count(), 4);
+
+        for source in ["export { x as default };", "import { default as X } from 'm';", "export { from, type };"] {
+            assert!(parse(source).0, "{source}");
+        }
+        for source in ["export { default };", "import { default } from 'm';", "import { x as default } from 'm';"] {
+            assert!(!parse(source).0, "{source}");
+        }
+    }
+
     #[test]
     fn test_template_elements_for_interpolation() {
         let mut parser = Parser::new("const s = `hello ${name}`;");
@@ -1869,6 +3226,887 @@ mod tests {
     }
 
 
+    fn ts_kinds(source: &str) -> Vec<NodeKind> {
+        let mut parser = Parser::new(source).with_typescript(true);
+        parser.parse_count();
+        parser.nodes().iter().map(|n| n.kind).collect()
+    }
+
+    #[test]
+    fn test_ts_enum_with_values() {
+        let mut parser =
+            Parser::new("const enum E { A = 1, B = \"b\", C }\nlet x = 1;").with_typescript(true);
+        parser.parse_count();
+        let nodes = parser.nodes();
+
+        let decl = nodes
+            .iter()
+            .find(|n| n.kind == NodeKind::TSEnumDeclaration)
+            .expect("enum declaration");
+        assert_eq!(decl.extra, 3);
+        assert_eq!(decl.flags & flags::CONST, flags::CONST);
+
+        let members: Vec<_> = nodes.iter().filter(|n| n.kind == NodeKind::TSEnumMember).collect();
+        assert_eq!(members.len(), 3);
+        assert_eq!(members.iter().filter(|m| m.flags == 1).count(), 2);
+
+        // Parsing resumes after the enum body
+        assert!(nodes.iter().any(|n| n.kind == NodeKind::VariableDeclaration));
+    }
+
+    #[test]
+    fn test_ts_namespace_with_exported_function() {
+        let kinds = ts_kinds("namespace N.Inner { export function f() { return 1; } }");
+        assert!(kinds.contains(&NodeKind::TSModuleDeclaration));
+        assert!(kinds.contains(&NodeKind::TSModuleBlock));
+        assert!(kinds.contains(&NodeKind::ExportDeclaration));
+        assert!(kinds.contains(&NodeKind::FunctionDeclaration));
+    }
+
+    #[test]
+    fn test_ts_declare_statements() {
+        let mut parser = Parser::new(
+            "declare function f();\ndeclare module \"m\";\ndeclare enum D { X }\nconst y = 2;",
+        )
+        .with_typescript(true);
+        parser.parse_count();
+        let nodes = parser.nodes();
+
+        let func = nodes.iter().find(|n| n.kind == NodeKind::FunctionDeclaration).unwrap();
+        assert_eq!(func.flags & flags::DECLARE, flags::DECLARE);
+        assert_eq!(func.start, 0);
+        let module = nodes.iter().find(|n| n.kind == NodeKind::TSModuleDeclaration).unwrap();
+        assert_eq!(module.flags & flags::DECLARE, flags::DECLARE);
+        let decl = nodes.iter().find(|n| n.kind == NodeKind::TSEnumDeclaration).unwrap();
+        assert_eq!(decl.flags & flags::DECLARE, flags::DECLARE);
+        assert!(nodes.iter().any(|n| n.kind == NodeKind::VariableDeclaration));
+    }
+
+    #[test]
+    fn test_ts_declare_class() {
+        let mut parser = Parser::new("declare class Foo {}\nexport default 1;").with_typescript(true);
+        parser.parse_count();
+        let nodes = parser.nodes();
+
+        assert!(!nodes.iter().any(|n| n.kind == NodeKind::ClassExpression));
+        let class = nodes.iter().find(|n| n.kind == NodeKind::ClassDeclaration).unwrap();
+        assert_eq!(class.flags & flags::DECLARE, flags::DECLARE);
+        assert_eq!(class.start, 0);
+        let export = nodes.iter().find(|n| n.kind == NodeKind::ExportDeclaration).unwrap();
+        assert_eq!(export.flags, flags::EXPORT_DEFAULT);
+
+        // `declare` then a newline is an identifier statement
+        let mut parser = Parser::new("declare\nclass Foo {}").with_typescript(true);
+        parser.parse_count();
+        let class = parser.nodes().iter().find(|n| n.kind == NodeKind::ClassDeclaration).unwrap();
+        assert_eq!((class.start, class.flags & flags::DECLARE), (8, 0));
+    }
+
+    #[test]
+    fn test_ts_import_equals() {
+        let mut parser =
+            Parser::new("import fs = require('fs');
+import B = A.B;").with_typescript(true);
+        parser.parse_count();
+        let imports: Vec<_> = parser
+            .nodes()
+            .iter()
+            .filter(|n| n.kind == NodeKind::TSImportEquals)
+            .collect();
+        assert_eq!(imports.len(), 2);
+        assert_eq!(imports[0].flags, 1);
+        assert_eq!(imports[1].flags, 0);
+        assert!(!parser.nodes().iter().any(|n| n.kind == NodeKind::ImportDeclaration));
+    }
+
+    #[test]
+    fn test_ts_export_assignment() {
+        let kinds = ts_kinds("class MyClass {}
+export = MyClass;");
+        assert!(kinds.contains(&NodeKind::TSExportAssignment));
+        assert!(!kinds.contains(&NodeKind::ExportDeclaration));
+    }
+
+    #[test]
+    fn test_ts_declarations_ignored_without_ts_mode() {
+        let mut parser = Parser::new("namespace N { }");
+        parser.parse_count();
+        assert!(!parser.nodes().iter().any(|n| n.kind == NodeKind::TSModuleDeclaration));
+    }
+
+    #[test]
+    fn test_parse_from_tokens_matches_source() {
+        let source = "import { a } from 'm';\nclass C { m() { return a?.b(1, `t${x}`); } }\nfor (const x of y) {}";
+        let tokens = Lexer::new(source).tokenize();
+
+        let mut from_source = Parser::new(source);
+        let mut from_tokens = Parser::from_tokens(source, tokens.clone());
+        assert_eq!(from_tokens.parse_count(), from_source.parse_count());
+        let kinds = |p: &Parser| p.nodes().iter().map(|n| (n.kind, n.start, n.end)).collect::<Vec<_>>();
+        assert_eq!(kinds(&from_tokens), kinds(&from_source));
+
+        // Trailing EOF is optional
+        let mut without_eof = Parser::from_tokens(source, tokens[..tokens.len() - 1].to_vec());
+        assert_eq!(without_eof.parse_count(), from_source.nodes().len());
+    }
+
+    #[test]
+    fn test_parse_from_filtered_tokens() {
+        // Dropping comment tokens up front must not change the tree
+        let source = "let x = /* c */ 1; // tail";
+        let tokens: Vec<Token> = Lexer::new(source)
+            .tokenize()
+            .into_iter()
+            .filter(|t| !matches!(t.kind, TokenKind::LineComment | TokenKind::BlockComment))
+            .collect();
+        let mut parser = Parser::from_tokens(source, tokens);
+        assert_eq!(parser.parse_count(), Parser::new(source).parse_count());
+    }
+
+    fn decorator_parse(source: &str) -> (Vec<NodeKind>, Vec<Diagnostic>) {
+        let mut parser = Parser::new(source);
+        parser.parse_count();
+        let kinds = parser.nodes().iter().map(|n| n.kind).collect();
+        (kinds, parser.diagnostics().to_vec())
+    }
+
+    #[test]
+    fn test_decorator_export_orderings() {
+        for source in [
+            "@dec export class A {}",
+            "export @dec class A {}",
+            "export default @dec() class {}",
+        ] {
+            let (kinds, diagnostics) = decorator_parse(source);
+            assert!(diagnostics.is_empty(), "{source}: {diagnostics:?}");
+            assert!(kinds.contains(&NodeKind::Decorator), "{source}");
+            assert!(kinds.contains(&NodeKind::ExportDeclaration), "{source}");
+            assert!(kinds.contains(&NodeKind::ClassDeclaration), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_decorators_on_members_and_expressions() {
+        let (kinds, diagnostics) =
+            decorator_parse("const C = @a.b class { @log m() {} @observable x = 1; }");
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        assert_eq!(kinds.iter().filter(|&&k| k == NodeKind::Decorator).count(), 3);
+        assert!(kinds.contains(&NodeKind::ClassExpression));
+    }
+
+    #[test]
+    fn test_misplaced_decorators_report_diagnostics() {
+        let (_, diagnostics) = decorator_parse("@dec function f() {}");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].start, 0);
+
+        let (_, diagnostics) = decorator_parse("x = @y;");
+        assert_eq!(diagnostics.len(), 1);
+
+        let (_, diagnostics) = decorator_parse("@a export @b class A {}");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("both before and after"));
+    }
+
+    #[test]
+    fn test_member_call_chain_nesting() {
+        let mut parser = Parser::new("a.b.c();");
+        parser.parse_count();
+        let nodes = parser.nodes();
+        let kinds: Vec<NodeKind> = nodes.iter().map(|n| n.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                NodeKind::Program,
+                NodeKind::Identifier,       // 1: a
+                NodeKind::Identifier,       // 2: b
+                NodeKind::MemberExpression, // 3: a.b
+                NodeKind::Identifier,       // 4: c
+                NodeKind::MemberExpression, // 5: (a.b).c
+                NodeKind::CallExpression,   // 6: ((a.b).c)()
+                NodeKind::ExpressionStatement,
+            ]
+        );
+        assert_eq!(nodes[3].extra, 1);
+        assert_eq!(nodes[5].extra, 3);
+        assert_eq!(nodes[6].extra, 5);
+        assert_eq!((nodes[3].start, nodes[3].end), (0, 3));
+        assert_eq!((nodes[5].start, nodes[5].end), (0, 5));
+        assert_eq!((nodes[6].start, nodes[6].end), (0, 7));
+    }
+
+    #[test]
+    fn test_optional_chain_expression() {
+        let source = "a?.b.c();";
+        let mut parser = Parser::new(source);
+        parser.parse_count();
+        let nodes = parser.nodes();
+        let kinds: Vec<NodeKind> = nodes.iter().map(|n| n.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                NodeKind::Program,
+                NodeKind::Identifier,       // 1: a
+                NodeKind::Identifier,       // 2: b
+                NodeKind::MemberExpression, // 3: a?.b
+                NodeKind::Identifier,       // 4: c
+                NodeKind::MemberExpression, // 5: (a?.b).c
+                NodeKind::CallExpression,   // 6: ((a?.b).c)()
+                NodeKind::ChainExpression,  // 7: the whole chain
+                NodeKind::ExpressionStatement,
+            ]
+        );
+        assert_eq!(nodes[3].flags, flags::OPTIONAL);
+        assert_eq!((nodes[5].flags, nodes[6].flags), (0, 0));
+        assert_eq!(nodes[7].extra, 6);
+        assert_eq!((nodes[7].start, nodes[7].end), (0, 8));
+
+        // (source of each chain, flags of each member/call)
+        fn chains(source: &str) -> (Vec<&str>, Vec<u16>) {
+            let mut parser = Parser::new(source);
+            parser.parse_count();
+            assert!(parser.diagnostics().is_empty(), "{source}: {:?}", parser.diagnostics());
+            let nodes = parser.nodes();
+            let chains = nodes
+                .iter()
+                .filter(|n| n.kind == NodeKind::ChainExpression)
+                .map(|n| &source[n.start as usize..n.end as usize])
+                .collect();
+            let flags = nodes
+                .iter()
+                .filter(|n| matches!(n.kind, NodeKind::MemberExpression | NodeKind::CallExpression))
+                .map(|n| n.flags)
+                .collect();
+            (chains, flags)
+        }
+        assert_eq!(chains("a?.[0]?.(x);"), (vec!["a?.[0]?.(x)"], vec![flags::COMPUTED | flags::OPTIONAL, flags::OPTIONAL]));
+        // Parentheses end the short-circuit
+        assert_eq!(chains("(a?.b).c;"), (vec!["a?.b"], vec![flags::OPTIONAL, 0]));
+        assert_eq!(chains("a.b.c;").0, Vec::<&str>::new());
+        assert_eq!(chains("x = y ? .5 : 1;").0, Vec::<&str>::new());
+
+        // In the tree, the chain is the parent of its outermost call
+        let tree = crate::parse_tree(source, false).unwrap();
+        let chain = tree.nodes().iter().find(|n| n.node_type == "ChainExpression").unwrap();
+        let [call] = chain.children[..] else { panic!() };
+        assert_eq!(tree.get_node(call).unwrap().node_type, "CallExpression");
+    }
+
+    #[test]
+    fn test_new_expression_nesting() {
+        // (kind, source, source of the `extra` node) for each member/new node
+        fn chain(source: &str) -> Vec<(NodeKind, &str, &str)> {
+            let mut parser = Parser::new(source).with_strict(true);
+            parser.parse().unwrap();
+            let nodes = parser.nodes();
+            let text = |n: &Node| source[n.start as usize..n.end as usize].trim_end();
+            nodes
+                .iter()
+                .filter(|n| matches!(n.kind, NodeKind::NewExpression | NodeKind::MemberExpression | NodeKind::CallExpression))
+                .map(|n| (n.kind, text(n), text(&nodes[n.extra as usize])))
+                .collect()
+        }
+
+        assert_eq!(chain("new a.b.C(1);"), [
+            (NodeKind::MemberExpression, "a.b", "a"),
+            (NodeKind::MemberExpression, "a.b.C", "a.b"),
+            (NodeKind::NewExpression, "new a.b.C(1)", "a.b.C"),
+        ]);
+        assert_eq!(chain("new X;"), [(NodeKind::NewExpression, "new X", "X")]);
+        assert_eq!(chain("new new Y()();"), [
+            (NodeKind::NewExpression, "new Y()", "Y"),
+            (NodeKind::NewExpression, "new new Y()()", "new Y()"),
+        ]);
+        // Arguments end the callee; later accesses and calls apply to the result
+        assert_eq!(chain("new a.b().c();"), [
+            (NodeKind::MemberExpression, "a.b", "a"),
+            (NodeKind::NewExpression, "new a.b()", "a.b"),
+            (NodeKind::MemberExpression, "new a.b().c", "new a.b()"),
+            (NodeKind::CallExpression, "new a.b().c()", "new a.b().c"),
+        ]);
+        assert_eq!(chain("new new Y().z();"), [
+            (NodeKind::NewExpression, "new Y()", "Y"),
+            (NodeKind::MemberExpression, "new Y().z", "new Y()"),
+            (NodeKind::NewExpression, "new new Y().z()", "new Y().z"),
+        ]);
+    }
+
+    #[test]
+    fn test_computed_member_records_object() {
+        let mut parser = Parser::new("x.y[z](1)`t`;");
+        parser.parse_count();
+        let nodes = parser.nodes();
+        let computed = nodes
+            .iter()
+            .position(|n| n.kind == NodeKind::MemberExpression && n.flags & flags::COMPUTED != 0)
+            .unwrap();
+        assert_eq!(nodes[nodes[computed].extra as usize].kind, NodeKind::MemberExpression);
+        let call = nodes.iter().position(|n| n.kind == NodeKind::CallExpression).unwrap();
+        assert_eq!(nodes[call].extra as usize, computed);
+        let tagged = nodes
+            .iter()
+            .position(|n| n.kind == NodeKind::TaggedTemplateExpression)
+            .unwrap();
+        assert_eq!(nodes[tagged].extra as usize, call);
+    }
+
+    /// (tag kind, tag source, template source) of each tagged template
+    fn tagged_templates(source: &str) -> Vec<(NodeKind, &str, &str)> {
+        let mut parser = Parser::new(source);
+        parser.parse_count();
+        let nodes = parser.nodes();
+        nodes
+            .iter()
+            .filter(|n| n.kind == NodeKind::TaggedTemplateExpression)
+            .map(|tagged| {
+                let tag = nodes[tagged.extra as usize];
+                (
+                    tag.kind,
+                    &source[tag.start as usize..tag.end as usize],
+                    &source[tag.end as usize..tagged.end as usize],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tagged_template_member_and_call_tags() {
+        assert_eq!(
+            tagged_templates("String.raw`a${b}c`;"),
+            [(NodeKind::MemberExpression, "String.raw", "`a${b}c`")]
+        );
+        assert_eq!(
+            tagged_templates("const Box = styled.div`color:red`;"),
+            [(NodeKind::MemberExpression, "styled.div", "`color:red`")]
+        );
+        assert_eq!(
+            tagged_templates("styled(Button)`color:red`;"),
+            [(NodeKind::CallExpression, "styled(Button)", "`color:red`")]
+        );
+        assert_eq!(
+            tagged_templates("i18n.t`hi``there`;"),
+            [
+                (NodeKind::MemberExpression, "i18n.t", "`hi`"),
+                (NodeKind::TaggedTemplateExpression, "i18n.t`hi`", "`there`"),
+            ]
+        );
+        // A template on the next line still tags the expression above
+        assert_eq!(
+            tagged_templates("css.global\n`body{}`;"),
+            [(NodeKind::MemberExpression, "css.global", "\n`body{}`")]
+        );
+    }
+
+    #[test]
+    fn test_assignment_operator_recorded() {
+        let mut parser = Parser::new("a = b; a ??= b; a &&= b; a ||= b; a += b;");
+        parser.parse_count();
+        let operators: Vec<u32> = parser
+            .nodes()
+            .iter()
+            .filter(|n| n.kind == NodeKind::AssignmentExpression)
+            .map(|n| n.extra)
+            .collect();
+        assert_eq!(
+            operators,
+            vec![
+                TokenKind::Eq as u32,
+                TokenKind::QuestionQuestionEq as u32,
+                TokenKind::AmpAmpEq as u32,
+                TokenKind::PipePipeEq as u32,
+                TokenKind::PlusEq as u32,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_array_holes_and_trailing_commas() {
+        let parse = |source: &str| {
+            let mut parser = Parser::new(source).with_strict(true);
+            parser.parse().unwrap();
+            parser.nodes().to_vec()
+        };
+        let length = |source: &str| {
+            let nodes = parse(source);
+            nodes.iter().find(|n| n.kind == NodeKind::ArrayExpression).unwrap().extra
+        };
+
+        let nodes = parse("[1, , 3,];");
+        let kinds: Vec<_> = nodes[1..5].iter().map(|n| n.kind).collect();
+        assert_eq!(
+            kinds,
+            [NodeKind::Literal, NodeKind::ArrayHole, NodeKind::Literal, NodeKind::ArrayExpression]
+        );
+        assert_eq!(nodes[4].extra, 3);
+        assert_eq!((nodes[2].start, nodes[2].end), (4, 4));
+
+        assert_eq!(length("[];"), 0);
+        assert_eq!(length("[,];"), 1);
+        assert_eq!(length("[, ,];"), 2);
+        assert_eq!(length("[1,];"), 1);
+        assert_eq!(length("[...a, , b];"), 3);
+
+        // No phantom property for a trailing comma
+        let nodes = parse("({ a: 1, b, });");
+        let object = nodes.iter().find(|n| n.kind == NodeKind::ObjectExpression).unwrap();
+        assert_eq!(object.extra, 2);
+        assert_eq!(nodes.iter().filter(|n| n.kind == NodeKind::Property).count(), 2);
+    }
+
+    #[test]
+    fn test_binary_operator_recorded() {
+        let binaries = |source: &str| -> Vec<(TokenKind, String)> {
+            let mut parser = Parser::new(source);
+            parser.parse_count();
+            parser
+                .nodes()
+                .iter()
+                .filter(|n| matches!(n.kind, NodeKind::BinaryExpression | NodeKind::LogicalExpression))
+                .map(|n| {
+                    let operator = TokenKind::from_u8(n.extra as u8).unwrap();
+                    (operator, source[n.start as usize..n.end as usize].trim_end().to_string())
+                })
+                .collect()
+        };
+
+        assert_eq!(binaries("a instanceof B;"), [(TokenKind::Instanceof, "a instanceof B".into())]);
+        assert_eq!(binaries("'x' in obj;"), [(TokenKind::In, "'x' in obj".into())]);
+        assert_eq!(
+            binaries("a\ninstanceof\nB;"),
+            [(TokenKind::Instanceof, "a\ninstanceof\nB".into())]
+        );
+        assert_eq!(
+            binaries("a + b * c ?? d;"),
+            [
+                (TokenKind::Star, "b * c".into()),
+                (TokenKind::Plus, "a + b * c".into()),
+                (TokenKind::QuestionQuestion, "a + b * c ?? d".into()),
+            ]
+        );
+        // Keyword-prefixed identifiers are not operators
+        assert_eq!(binaries("instanceofFoo;"), []);
+        assert_eq!(binaries("inx;"), []);
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        // The default parse recovers
+        let mut parser = Parser::new("const = 1;");
+        assert!(parser.parse().is_ok());
+        assert!(parser.diagnostics().is_empty());
+
+        let mut parser = Parser::new("const = 1;\nlet x = 2;").with_strict(true);
+        let err = parser.parse().unwrap_err().to_string();
+        assert!(err.contains("Unexpected token Eq at offset 6"), "{err}");
+        // Parsing stopped after the failing statement
+        assert_eq!(parser.diagnostics().len(), 1);
+        assert!(!parser.nodes().iter().any(|n| n.start >= 11));
+
+        let err = Parser::new("if (a { b }").with_strict(true).parse().unwrap_err();
+        assert!(err.to_string().contains("Expected RParen"), "{err}");
+
+        let source = "const { a, b: [c] } = obj; class A { get default() { return this.in; } }";
+        assert!(Parser::new(source).with_strict(true).parse().is_ok());
+    }
+
+    #[test]
+    fn test_contextual_yield_and_await() {
+        let count = |source: &str, kind: NodeKind| {
+            let mut parser = Parser::new(source).with_strict(true);
+            assert!(parser.parse().is_ok(), "{source}: {:?}", parser.diagnostics());
+            parser.nodes().iter().filter(|n| n.kind == kind).count()
+        };
+
+        let source = "function f() { var yield = 1; return yield; }";
+        assert_eq!(count(source, NodeKind::YieldExpression), 0);
+        assert_eq!(count(source, NodeKind::Identifier), 3);
+
+        assert_eq!(count("function* g() { yield 1; yield; }", NodeKind::YieldExpression), 2);
+        // A nested function or arrow has its own context
+        let source = "function* g() { function h() { return yield + 1; } yield h; const k = x => yield; }";
+        assert_eq!(count(source, NodeKind::YieldExpression), 1);
+        assert_eq!(count("const o = { *m() { yield 1; } };", NodeKind::YieldExpression), 1);
+
+        assert_eq!(count("async function a() { await x; }", NodeKind::AwaitExpression), 1);
+        assert_eq!(count("function b() { var await = 1; return await; }", NodeKind::AwaitExpression), 0);
+        assert_eq!(count("class C { async m() { await x; } n() { return await; } }", NodeKind::AwaitExpression), 1);
+        // Top-level await (modules)
+        assert_eq!(count("await load();", NodeKind::AwaitExpression), 1);
+    }
+
+    #[test]
+    fn test_member_access_on_numbers() {
+        for source in ["1..toString();", "1.5.toString();", "(1).toString();", "1.e3.toFixed();", "0x10.toString();"] {
+            let mut parser = Parser::new(source).with_strict(true);
+            assert!(parser.parse().is_ok(), "{source}: {:?}", parser.diagnostics());
+            let kinds: Vec<_> = parser.nodes().iter().map(|n| n.kind).collect();
+            assert_eq!(kinds.iter().filter(|&&k| k == NodeKind::MemberExpression).count(), 1, "{source}");
+            let literal = parser.nodes().iter().find(|n| n.kind == NodeKind::Literal).unwrap();
+            let text = &source[literal.start as usize..literal.end as usize];
+            assert!(["1.", "1.5", "1", "1.e3", "0x10"].contains(&text), "{source}: {text}");
+        }
+
+        let err = Parser::new("1.toString();").with_strict(true).parse().unwrap_err();
+        assert!(err.to_string().contains("Identifier directly after number"), "{err}");
+        // Reported, then recovered from, outside strict mode too
+        let mut parser = Parser::new("1.toString();");
+        assert!(parser.parse().is_ok());
+        assert_eq!(parser.diagnostics()[0].message, "Identifier directly after number");
+        assert_eq!((parser.diagnostics()[0].start, parser.diagnostics()[0].end), (0, 3));
+    }
+
+    #[test]
+    fn test_directive_prologue() {
+        let strict = |source: &str| {
+            let mut parser = Parser::new(source);
+            parser.parse_count();
+            parser.nodes()[0].flags & flags::STRICT != 0
+        };
+        assert!(strict("\"use strict\";\nx;"));
+        assert!(strict("'use asm'\n'use strict'\nx;"));
+        assert!(!strict("x;\n\"use strict\";"));
+        assert!(!strict("\"use strict\" + x;"));
+        assert!(!strict("'use\\x20strict';"));
+
+        let mut parser = Parser::new("\"use strict\";\nx;");
+        parser.parse_count();
+        let statements: Vec<_> = parser.nodes().iter()
+            .filter(|n| n.kind == NodeKind::ExpressionStatement)
+            .map(|n| n.flags & flags::DIRECTIVE != 0)
+            .collect();
+        assert_eq!(statements, [true, false]);
+
+        // Function prologues make only that function (and what it contains) strict
+        let mut parser = Parser::new("function f() { 'use strict'; return x => {}; }\nfunction g() {}");
+        parser.parse_count();
+        let functions: Vec<_> = parser.nodes().iter()
+            .filter(|n| matches!(n.kind,
+                NodeKind::FunctionDeclaration | NodeKind::ArrowFunctionExpression))
+            .map(|n| (n.kind, n.flags & flags::STRICT != 0))
+            .collect();
+        assert_eq!(functions, [
+            (NodeKind::ArrowFunctionExpression, true),
+            (NodeKind::FunctionDeclaration, true),
+            (NodeKind::FunctionDeclaration, false),
+        ]);
+        assert_eq!(parser.nodes()[0].flags & flags::STRICT, 0);
+    }
+
+    #[test]
+    fn test_parse_count_convention() {
+        // Program, VariableDeclaration, VariableDeclarator, Identifier, Literal
+        assert_eq!(Parser::new("const x = 1;").parse_count(), 5);
+        assert_eq!(Parser::new("const x = 1;").parse_count_excluding_root(), 4);
+        assert_eq!(Parser::new("").parse_count(), 1);
+        assert_eq!(Parser::new("").parse_count_excluding_root(), 0);
+    }
+
+    fn json_parse(source: &str, mode: JsonMode) -> (Vec<NodeKind>, Vec<Diagnostic>) {
+        let mut parser = Parser::new(source);
+        parser.parse_json(mode);
+        let kinds = parser.nodes().iter().map(|n| n.kind).collect();
+        (kinds, parser.diagnostics().to_vec())
+    }
+
+    #[test]
+    fn test_json_object() {
+        let source = "{\n  \"name\": \"synth\",\n  \"tags\": [1, -2.5e3, true, null],\n  \"nested\": {}\n}";
+        let (kinds, diagnostics) = json_parse(source, JsonMode::Strict);
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        assert_eq!(kinds[0], NodeKind::Program);
+        assert_eq!(kinds.iter().filter(|&&k| k == NodeKind::Property).count(), 3);
+        assert_eq!(kinds.iter().filter(|&&k| k == NodeKind::ObjectExpression).count(), 2);
+        assert!(kinds.contains(&NodeKind::ArrayExpression));
+        assert!(kinds.contains(&NodeKind::UnaryExpression));
+    }
+
+    #[test]
+    fn test_json_strict_rejects_json5_syntax() {
+        for source in [
+            "[1, 2,]",
+            "{\"a\": 1,}",
+            "// c\n{}",
+            "{'a': 1}",
+            "{a: 1}",
+            "+1",
+            "0x10",
+            "01",
+            "{} {}",
+        ] {
+            let (_, diagnostics) = json_parse(source, JsonMode::Strict);
+            assert!(!diagnostics.is_empty(), "{source} should be rejected");
+        }
+
+        let (_, diagnostics) = json_parse("[1, 2,]", JsonMode::Strict);
+        assert!(diagnostics[0].message.contains("Trailing comma"));
+        assert_eq!(diagnostics[0].start, 5);
+    }
+
+    #[test]
+    fn test_json5_allows_relaxed_syntax() {
+        let source = "// config\n{unquoted: 'single', trailing: [1, 2,], hex: 0x10, inf: +Infinity, /* c */}";
+        let (kinds, diagnostics) = json_parse(source, JsonMode::Json5);
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        assert_eq!(kinds.iter().filter(|&&k| k == NodeKind::Property).count(), 4);
+    }
+
+    #[test]
+    fn test_parse_until_sentinel() {
+        let source = "const a = 1;\nfoo(a);\n```\nnot js {\n";
+        let mut parser = Parser::new(source);
+        parser.parse_until(|line| line.starts_with("```"));
+        assert_eq!(parser.consumed_len(), source.find("```").unwrap());
+
+        let nodes = parser.nodes();
+        assert_eq!(nodes[0].kind, NodeKind::Program);
+        assert_eq!(nodes[0].end as usize, parser.consumed_len());
+        assert_eq!(
+            nodes.iter().filter(|n| n.kind == NodeKind::ExpressionStatement).count(),
+            1
+        );
+
+        // No sentinel: consumes everything
+        let mut parser = Parser::new("let x;\n");
+        parser.parse_until(|line| line == "END");
+        assert_eq!(parser.consumed_len(), 7);
+    }
+
+    #[test]
+    fn test_duplicate_proto_diagnostic() {
+        let mut parser = Parser::new("const o = {__proto__: a, __proto__: b};");
+        parser.parse_count();
+        assert!(parser.nodes().iter().any(|n| n.kind == NodeKind::ObjectExpression));
+        assert_eq!(parser.diagnostics().len(), 1);
+        assert!(parser.diagnostics()[0].message.contains("__proto__"));
+        assert_eq!(parser.diagnostics()[0].start, 25);
+
+        // Quoted keys set the prototype too
+        let mut parser = Parser::new("o = {'__proto__': a, \"__proto__\": b};");
+        parser.parse_count();
+        assert_eq!(parser.diagnostics().len(), 1);
+
+        for source in [
+            "o = {__proto__: a};",
+            // Shorthand, computed and method keys are ordinary properties
+            "o = {__proto__: a, __proto__};",
+            "o = {__proto__: a, ['__proto__']: b};",
+            "o = {__proto__: a, __proto__() {}};",
+            // Destructuring pattern
+            "({__proto__: a, __proto__: b} = x);",
+        ] {
+            let mut parser = Parser::new(source);
+            parser.parse_count();
+            assert!(parser.diagnostics().is_empty(), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_symbols() {
+        fn symbols(source: &str) -> Vec<(String, SymbolKind, u32)> {
+            let mut parser = Parser::new(source).with_symbols(true);
+            parser.parse_count();
+            parser.symbols().iter().map(|s| (s.name.clone(), s.kind, s.scope)).collect()
+        }
+        let names = |symbols: &[(String, SymbolKind, u32)]| -> Vec<String> {
+            symbols.iter().map(|(name, _, _)| name.clone()).collect()
+        };
+
+        assert_eq!(
+            symbols("const a = 1; function b(){} import c from 'd'"),
+            [
+                ("a".to_string(), SymbolKind::Const, 0),
+                ("b".to_string(), SymbolKind::Function, 0),
+                ("c".to_string(), SymbolKind::Import, 0),
+            ]
+        );
+
+        let source = "var v; function f(a) { let x; if (a) { var y; const {p, q: r, s = () => { let t }} = o; } }\n\
+                      class C {} import * as ns from 'm'; import { k as l, m } from 'n';";
+        let found = symbols(source);
+        assert_eq!(names(&found), ["v", "f", "x", "y", "p", "r", "s", "t", "C", "ns", "l", "m"]);
+        // `var` belongs to the function body, `const` to the block
+        let scopes: Vec<u32> = found.iter().map(|&(_, _, scope)| scope).collect();
+        assert_eq!(scopes, [0, 0, 1, 1, 2, 2, 2, 3, 0, 0, 0, 0]);
+        assert_eq!(found[3].1, SymbolKind::Var);
+
+        // Span of the identifier; off by default
+        let mut parser = Parser::new("let [first, ...rest] = xs;").with_symbols(true);
+        parser.parse_count();
+        let rest = &parser.symbols()[1];
+        assert_eq!((rest.name.as_str(), rest.start, rest.end), ("rest", 15, 19));
+        let mut parser = Parser::new("let a;");
+        parser.parse_count();
+        assert!(parser.symbols().is_empty());
+    }
+
+    #[test]
+    fn test_diagnostic_source_lines() {
+        let source = "const a = 1;\r\nclass Émile { get name(x) {} }\nlet b;";
+        let mut parser = Parser::new(source).with_source_lines(true);
+        parser.parse_count();
+        let diagnostic = &parser.diagnostics()[0];
+        assert_eq!(diagnostic.line, Some(2));
+        assert_eq!(diagnostic.line_text.as_deref(), Some("class Émile { get name(x) {} }"));
+        assert_eq!(diagnostic.caret, Some(18));
+        assert_eq!(&source[diagnostic.start as usize..diagnostic.end as usize], "name");
+
+        let json = serde_json::to_value(diagnostic).unwrap();
+        assert_eq!(json["lineText"], "class Émile { get name(x) {} }");
+        assert_eq!(json["caret"], 18);
+
+        // Off by default, and left out of the JSON
+        let mut parser = Parser::new(source);
+        parser.parse_count();
+        assert_eq!(parser.diagnostics()[0].line_text, None);
+        assert!(serde_json::to_value(&parser.diagnostics()[0]).unwrap().get("lineText").is_none());
+    }
+
+    #[test]
+    fn test_accessor_arity_diagnostics() {
+        fn diagnostics(source: &str) -> Vec<(String, &str)> {
+            let mut parser = Parser::new(source);
+            parser.parse_count();
+            parser
+                .diagnostics()
+                .iter()
+                .map(|d| (d.message.clone(), &source[d.start as usize..d.end as usize]))
+                .collect()
+        }
+
+        assert_eq!(
+            diagnostics("class A { get x(a) {} }"),
+            [("Getter must not have any formal parameters".to_string(), "x")]
+        );
+        assert_eq!(
+            diagnostics("o = { set y() {} };"),
+            [("Setter must have exactly one formal parameter".to_string(), "y")]
+        );
+        assert_eq!(diagnostics("class A { static set [k](a, b) {} }")[0].1, "[");
+        assert_eq!(
+            diagnostics("o = { set z(...v) {} };"),
+            [("Setter function argument must not be a rest parameter".to_string(), "z")]
+        );
+
+        for source in [
+            "class A { get x() {} set x(v) {} static get y() {} }",
+            "o = { get x() { return 1; }, set x({ a, b } = {}) {} };",
+            // `get`/`set` as names
+            "class A { get(a, b) {} set = 1; static set() {} }",
+            "o = { get(a, b) {}, set: 1, get };",
+        ] {
+            assert_eq!(diagnostics(source), [], "{source}");
+        }
+    }
+
+    #[test]
+    fn test_duplicate_parameters() {
+        fn duplicates(source: &str) -> Vec<(u32, &str)> {
+            let mut parser = Parser::new(source);
+            parser.parse_count();
+            parser
+                .diagnostics()
+                .iter()
+                .map(|d| (d.start, &source[d.start as usize..d.end as usize]))
+                .collect()
+        }
+
+        // Allowed in sloppy code with a simple parameter list
+        assert_eq!(duplicates("function f(a, a) {}"), []);
+        assert_eq!(duplicates("x = function (a, b, a) {};"), []);
+
+        let message = "Duplicate parameter name not allowed in this context";
+        let mut parser = Parser::new("'use strict'; function f(a, a) {}");
+        parser.parse_count();
+        assert_eq!(parser.diagnostics().len(), 1);
+        assert_eq!(parser.diagnostics()[0].message, message);
+        assert_eq!(duplicates("'use strict'; function f(a, a) {}"), [(28, "a")]);
+        assert_eq!(duplicates("function f(a, a) { 'use strict'; }"), [(14, "a")]);
+        assert_eq!(duplicates("x = function (b, a, b) { 'use strict'; };"), [(20, "b")]);
+
+        // Non-simple lists, and methods, never allow them
+        for source in [
+            "function f(a, a = 1) {}",
+            "function f(a, [a]) {}",
+            "function f({ a }, a) {}",
+            "function f(a, ...a) {}",
+            "function f({ x: a, y: a }) {}",
+            "class A { m(a, a) {} }",
+            "o = { m(a, a) {} };",
+        ] {
+            assert_eq!(duplicates(source).len(), 1, "{source}");
+        }
+
+        // Names in default values and nested functions don't count
+        for source in [
+            "function f(a, b = (a) => a, { c = function (a) { var a; } }) {}",
+            "'use strict'; function f(a, b) { function g(a, b) {} }",
+            "function f(a, b) { 'use strict'; var a; }",
+        ] {
+            assert_eq!(duplicates(source), [], "{source}");
+        }
+    }
+
+    #[test]
+    fn test_max_nodes_truncates() {
+        let source = "x = 1;\n".repeat(100);
+        let mut parser = Parser::new(&source).with_max_nodes(Some(20));
+        let count = parser.parse_count();
+
+        // Each statement adds four nodes; stops right after crossing the cap
+        assert!(count > 20 && count <= 25, "{count}");
+        assert_eq!(parser.diagnostics().len(), 1);
+        assert!(parser.diagnostics()[0].message.contains("truncated"));
+        assert_eq!(parser.consumed_len(), parser.diagnostics()[0].start as usize);
+        assert_eq!(parser.nodes()[0].end as usize, parser.consumed_len());
+        assert_eq!(parser.stopped(), Some("truncated"));
+
+        let mut parser = Parser::new(&source).with_max_nodes(Some(1000));
+        assert_eq!(parser.parse_count(), 401);
+        assert!(parser.diagnostics().is_empty());
+        assert_eq!(parser.stopped(), None);
+    }
+
+    #[test]
+    fn test_cancel_flag_stops_parse() {
+        let source = "x = 1;\n".repeat(1000);
+        let cancel = AtomicBool::new(false);
+        let mut parser = Parser::new(&source).with_cancel_flag(&cancel);
+        assert_eq!(parser.parse_count(), 4001);
+        assert!(parser.diagnostics().is_empty());
+
+        // Set partway: stops at the next check, keeping what was parsed
+        let cancel = SetOnCheck(Cell::new(3));
+        let mut parser = Parser::new(&source).with_cancel_flag(&cancel);
+        parser.parse_count();
+        assert_eq!(parser.diagnostics()[0].message, "Parsing cancelled");
+        assert_eq!(parser.nodes()[0].end as usize, parser.consumed_len());
+        assert_eq!(parser.stopped(), Some("cancelled"));
+        assert_eq!(
+            parser.nodes().iter().filter(|n| n.kind == NodeKind::ExpressionStatement).count(),
+            3 * CANCEL_CHECK_INTERVAL
+        );
+
+        // Already set: stops at the first check
+        let cancel = AtomicBool::new(true);
+        let mut parser = Parser::new(&source).with_cancel_flag(&cancel);
+        parser.parse_count();
+        assert_eq!(
+            parser.nodes().iter().filter(|n| n.kind == NodeKind::ExpressionStatement).count(),
+            CANCEL_CHECK_INTERVAL
+        );
+    }
+
+    /// A flag that becomes set on its `n`th check, as if a host set it
+    /// partway through the parse
+    struct SetOnCheck(Cell<usize>);
+
+    impl CancelSignal for SetOnCheck {
+        fn is_cancelled(&self) -> bool {
+            self.0.set(self.0.get().saturating_sub(1));
+            self.0.get() == 0
+        }
+    }
+
     #[test]
     fn test_arrow_function() {
         let mut parser = Parser::new("const fn = x => x * 2;");
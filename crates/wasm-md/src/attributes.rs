@@ -0,0 +1,101 @@
+//! Heading attribute lists (kramdown/pandoc `{#id .class key=value}`)
+//!
+//! Under the `attributes` option a heading ending in an attribute list
+//! (`## Install {#setup .wide}`) keeps only the text before it; the list
+//! goes into the heading's `data` as `id`, `classes` and `attributes`
+//! (the key-value pairs). A list that doesn't parse stays heading text.
+
+use std::collections::BTreeMap;
+
+/// A parsed attribute list
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Attributes {
+    pub id: Option<String>,
+    pub classes: Vec<String>,
+    pub pairs: BTreeMap<String, String>,
+}
+
+/// Attribute list ending `text`: where the text before it ends (trailing
+/// whitespace removed) and the parsed list
+pub(crate) fn trailing(text: &str) -> Option<(usize, Attributes)> {
+    let body = text.strip_suffix('}')?;
+    let open = body.rfind('{')?;
+    let before = &text[..open];
+    if !before.is_empty() && !before.ends_with([' ', '\t']) {
+        return None;
+    }
+    let attributes = parse(&body[open + 1..])?;
+    Some((before.trim_end_matches([' ', '\t']).len(), attributes))
+}
+
+/// Parse the inside of `{...}`: whitespace-separated `#id`, `.class` and
+/// `key=value` (value optionally quoted)
+fn parse(mut list: &str) -> Option<Attributes> {
+    let mut attributes = Attributes::default();
+    loop {
+        list = list.trim_start_matches([' ', '\t']);
+        if list.is_empty() {
+            break;
+        }
+        if let Some(rest) = list.strip_prefix('#') {
+            let len = name_len(rest)?;
+            attributes.id = Some(rest[..len].to_string());
+            list = &rest[len..];
+        } else if let Some(rest) = list.strip_prefix('.') {
+            let len = name_len(rest)?;
+            attributes.classes.push(rest[..len].to_string());
+            list = &rest[len..];
+        } else {
+            let len = name_len(list)?;
+            let key = &list[..len];
+            let rest = list[len..].strip_prefix('=')?;
+            let (value, after) = match rest.as_bytes().first() {
+                Some(&quote @ (b'"' | b'\'')) => {
+                    let close = 1 + rest[1..].find(quote as char)?;
+                    (&rest[1..close], &rest[close + 1..])
+                }
+                _ => {
+                    let len = rest.find([' ', '\t']).unwrap_or(rest.len());
+                    (&rest[..len], &rest[len..])
+                }
+            };
+            attributes.pairs.insert(key.to_string(), value.to_string());
+            list = after;
+        }
+        // Entries must be separated by whitespace
+        if !list.is_empty() && !list.starts_with([' ', '\t']) {
+            return None;
+        }
+    }
+    (attributes != Attributes::default()).then_some(attributes)
+}
+
+/// Length of the identifier at the start of `text`: letters, digits, `-`,
+/// `_` and `:`
+fn name_len(text: &str) -> Option<usize> {
+    let len = text
+        .bytes()
+        .take_while(|&b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b':'))
+        .count();
+    (len > 0).then_some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trailing_attributes() {
+        let (end, attributes) = trailing("Title {#intro .a .b data-x=\"1 2\" k=v}").unwrap();
+        assert_eq!(end, 5);
+        assert_eq!(attributes.id.as_deref(), Some("intro"));
+        assert_eq!(attributes.classes, ["a", "b"]);
+        assert_eq!(attributes.pairs["data-x"], "1 2");
+        assert_eq!(attributes.pairs["k"], "v");
+        assert_eq!(trailing("{.only}").map(|(end, _)| end), Some(0));
+
+        for text in ["Title", "Title{#x}", "Title {}", "Title {#}", "Set {a, b}", "T {#x.y}", "T {k=\"open}"] {
+            assert_eq!(trailing(text), None, "{text}");
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Phase timings for the markdown parser
+//!
+//! Each phase is timed on its own pass over the source, and each one
+//! includes the work of the one before it. Phase times are reported as the
+//! difference to the previous phase, so they add up to roughly `totalMs`
+//! (a full `parse`). Differences are clamped at zero against timer noise.
+
+use std::hint::black_box;
+
+use serde::Serialize;
+
+use crate::parser_v2::MarkdownParserV2;
+
+/// Timings in milliseconds, plus the node count of the full tree
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseTimings {
+    /// Splitting the source into lines
+    pub tokenize_ms: f64,
+    /// Recognizing blocks on top of the line scan, without building nodes
+    pub block_scan_ms: f64,
+    /// Building the Tree (nodes, inline content) on top of the block scan
+    pub build_ms: f64,
+    /// A full `parse`
+    pub total_ms: f64,
+    pub node_count: usize,
+}
+
+/// Time the tokenize, block scan and tree build phases of `source`
+pub fn benchmark(source: &str) -> PhaseTimings {
+    let (_, lines) = time(|| black_box(memchr::memchr_iter(b'\n', source.as_bytes()).count()));
+    let (_, scan) = time(|| black_box(MarkdownParserV2::new(source).block_count()));
+    let (tree, total) = time(|| MarkdownParserV2::new(source).parse());
+
+    PhaseTimings {
+        tokenize_ms: lines,
+        block_scan_ms: (scan - lines).max(0.0),
+        build_ms: (total - scan).max(0.0),
+        total_ms: total,
+        node_count: tree.map_or(0, |tree| tree.node_count()),
+    }
+}
+
+/// Run `f`, returning its result and the elapsed milliseconds
+fn time<T>(f: impl FnOnce() -> T) -> (T, f64) {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let start = now();
+        let value = f();
+        (value, now() - start)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let start = std::time::Instant::now();
+        let value = f();
+        (value, start.elapsed().as_secs_f64() * 1000.0)
+    }
+}
+
+/// `performance.now()` where the host has it, `Date.now()` otherwise
+#[cfg(target_arch = "wasm32")]
+fn now() -> f64 {
+    use wasm_bindgen::{JsCast, JsValue};
+
+    let global = js_sys::global();
+    if let Ok(performance) = js_sys::Reflect::get(&global, &JsValue::from_str("performance"))
+        && let Ok(now) = js_sys::Reflect::get(&performance, &JsValue::from_str("now"))
+        && let Some(now) = now.dyn_ref::<js_sys::Function>()
+        && let Some(ms) = now.call0(&performance).ok().and_then(|ms| ms.as_f64())
+    {
+        return ms;
+    }
+    js_sys::Date::now()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_benchmark_phases() {
+        let block = "# Title\n\nSome *emphasis* and `code` with a [link](https://example.com).\n\n\
+                     - one\n- two\n\n```rust\nfn main() {}\n```\n\n> quote\n\n";
+        let source = block.repeat(500);
+        let timings = benchmark(&source);
+
+        assert_eq!(timings.node_count, MarkdownParserV2::new(&source).parse().unwrap().node_count());
+        assert!(timings.node_count > 500 * 5);
+        assert!(timings.tokenize_ms > 0.0);
+        assert!(timings.total_ms > 0.0);
+        assert!(timings.build_ms > 0.0);
+        let sum = timings.tokenize_ms + timings.block_scan_ms + timings.build_ms;
+        assert!(sum >= timings.total_ms * 0.99, "{timings:?}");
+        assert!(sum <= timings.total_ms * 1.5, "{timings:?}");
+    }
+}
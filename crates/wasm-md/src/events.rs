@@ -0,0 +1,177 @@
+//! SAX-style parse events
+//!
+//! `MarkdownParserV2::parse_events` reports the document as a pre-order
+//! stream of events instead of returning a Tree: `EnterBlock`/`ExitBlock`
+//! around every node that isn't `text` (inline containers such as
+//! `emphasis` included), and `Text` for text nodes. Walking the Tree from
+//! `parse` gives the same sequence. Under `references: "eager"`, a
+//! `LateReferences` event before the root's `ExitBlock` lists references
+//! that were reported as text before their definition was reached.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use synth_wasm_core::{NodeId, NodeType, Span, Tree};
+
+use crate::reference::LateReference;
+
+/// A parse event; borrowed data is only valid during the callback
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum Event<'n> {
+    #[serde(rename_all = "camelCase")]
+    EnterBlock {
+        #[serde(rename = "type")]
+        node_type: &'n NodeType,
+        span: Option<Span>,
+        data: Option<&'n HashMap<String, serde_json::Value>>,
+    },
+    Text {
+        value: &'n str,
+        span: Option<Span>,
+    },
+    ExitBlock {
+        #[serde(rename = "type")]
+        node_type: &'n NodeType,
+    },
+    LateReferences {
+        references: &'n [LateReference],
+    },
+}
+
+/// Report `id` and its descendants in pre-order
+pub(crate) fn walk(tree: &Tree, id: NodeId, on_event: &mut impl FnMut(Event<'_>)) {
+    let Ok(node) = tree.get_node(id) else {
+        return;
+    };
+    if node.node_type == NodeType::Text {
+        let value = node.data.as_ref().and_then(|d| d.get("value")).and_then(|v| v.as_str());
+        on_event(Event::Text { value: value.unwrap_or_default(), span: node.span });
+        return;
+    }
+
+    on_event(Event::EnterBlock {
+        node_type: &node.node_type,
+        span: node.span,
+        data: node.data.as_ref(),
+    });
+    for &child in &node.children {
+        walk(tree, child, on_event);
+    }
+    on_event(Event::ExitBlock { node_type: &node.node_type });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser_v2::{MarkdownParserV2, ParseOptions, References};
+
+    fn record(events: &mut Vec<serde_json::Value>) -> impl FnMut(Event<'_>) + '_ {
+        |event| events.push(serde_json::to_value(event).unwrap())
+    }
+
+    #[test]
+    fn test_events_match_tree_walk() {
+        let src = "# Title *em*\n\nText with [a link](/u).\n\n- one\n- two\n  - nested\n\n> quote^[note]\n\n---\n";
+        let options = || ParseOptions { footnotes: true, inline_footnotes: true, ..Default::default() };
+
+        let tree = MarkdownParserV2::new(src).with_options(options()).parse().unwrap();
+        let mut expected = Vec::new();
+        walk(&tree, tree.root_id(), &mut record(&mut expected));
+
+        let mut events = Vec::new();
+        MarkdownParserV2::new(src)
+            .with_options(options())
+            .parse_events(record(&mut events))
+            .unwrap();
+
+        assert_eq!(events, expected);
+        assert!(events.len() > 20);
+        assert_eq!(events[0]["event"], "enterBlock");
+        assert_eq!(events[0]["type"], "root");
+        assert!(events.iter().any(|e| e["type"] == "footnoteDefinition"));
+        assert!(events.iter().any(|e| e["event"] == "text" && e["value"] == "nested"));
+    }
+
+    #[test]
+    fn test_reference_before_definition() {
+        let src = "See [the docs] and [guide][g].\n\n[the docs]: /docs \"Docs\"\n[G]: /guide\n\nAgain [the docs].\n";
+        let events = |references| {
+            let mut events = Vec::new();
+            MarkdownParserV2::new(src)
+                .with_options(ParseOptions { references, ..Default::default() })
+                .parse_events(record(&mut events))
+                .unwrap();
+            events
+        };
+        let links = |events: &[serde_json::Value]| {
+            events
+                .iter()
+                .filter(|e| e["event"] == "enterBlock" && e["type"] == "link")
+                .map(|e| e["data"]["url"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // Prescan: resolved in place, as `parse` does
+        let prescan = events(References::Prescan);
+        assert_eq!(links(&prescan), ["/docs", "/guide", "/docs"]);
+        assert!(prescan.iter().all(|e| e["event"] != "lateReferences"));
+
+        // Eager: text until the definitions, then fixed up at the end
+        let eager = events(References::Eager);
+        assert_eq!(links(&eager), ["/docs"]);
+        let [.., late, exit] = &eager[..] else { panic!() };
+        assert_eq!(exit["event"], "exitBlock");
+        assert_eq!(late["event"], "lateReferences");
+        let fixups: Vec<_> = late["references"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| {
+                let span = &r["span"];
+                let (start, end) = (span["start"]["offset"].as_u64().unwrap(), span["end"]["offset"].as_u64().unwrap());
+                (&src[start as usize..end as usize], r["url"].clone(), r["title"].clone())
+            })
+            .collect();
+        assert_eq!(fixups, [
+            ("[the docs]", serde_json::json!("/docs"), serde_json::json!("Docs")),
+            ("[guide][g]", serde_json::json!("/guide"), serde_json::Value::Null),
+        ]);
+
+        // The tree gets the same list on the root
+        let mut parser = MarkdownParserV2::new(src)
+            .with_options(ParseOptions { references: References::Eager, ..Default::default() });
+        let tree = parser.parse().unwrap();
+        assert_eq!(parser.late_references().len(), 2);
+        assert_eq!(tree.root().data.as_ref().unwrap()["lateReferences"], late["references"]);
+    }
+
+    #[test]
+    fn test_eager_references_still_report_undefined_labels() {
+        let src = "[a][missing] [shortcut] [b][x]\n\n[x]: /x\n";
+        let mut parser = MarkdownParserV2::new(src)
+            .with_options(ParseOptions { references: References::Eager, ..Default::default() });
+        let tree = parser.parse().unwrap();
+        let labels: Vec<_> = parser.unresolved_references().iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(labels, ["missing"]);
+        let late: Vec<_> = parser.late_references().iter().map(|r| r.label.as_str()).collect();
+        assert_eq!(late, ["x"]);
+        assert_eq!(tree.root().data.as_ref().unwrap()["unresolvedReferences"][0]["label"], "missing");
+
+        // A definition inside a paragraph is text, as with the prescan
+        let mut parser = MarkdownParserV2::new("text\n[x]: /x\n\n[x]\n")
+            .with_options(ParseOptions { references: References::Eager, ..Default::default() });
+        let tree = parser.parse().unwrap();
+        assert!(!tree.nodes().iter().any(|n| n.node_type == NodeType::Link || n.node_type == NodeType::Definition));
+        assert!(parser.late_references().is_empty());
+    }
+
+    #[test]
+    fn test_events_for_empty_document() {
+        let mut events = Vec::new();
+        MarkdownParserV2::new("  \n").parse_events(record(&mut events)).unwrap();
+        assert_eq!(events, [
+            serde_json::json!({ "event": "enterBlock", "type": "root", "span": null, "data": null }),
+            serde_json::json!({ "event": "exitBlock", "type": "root" }),
+        ]);
+    }
+}
@@ -0,0 +1,97 @@
+//! Frontmatter at the start of a document
+//!
+//! A document whose first line is a fence of `---` (YAML), `+++` (TOML)
+//! or `;;;` (JSON) starts with frontmatter running to the next line
+//! holding the same fence. It becomes a `yaml`, `toml` or `json` node
+//! with the raw text between the fences in `data.value`; JSON that
+//! parses is also in `data.parsed`. An unclosed fence is not frontmatter,
+//! so `---` falls back to a thematic break. Fragments never have
+//! frontmatter.
+
+use synth_wasm_core::NodeType;
+
+/// Format of a frontmatter block, from its fence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl Format {
+    fn from_fence(line: &str) -> Option<Self> {
+        match line.trim_end_matches([' ', '\t', '\r']) {
+            "---" => Some(Format::Yaml),
+            "+++" => Some(Format::Toml),
+            ";;;" => Some(Format::Json),
+            _ => None,
+        }
+    }
+
+    pub fn node_type(self) -> NodeType {
+        match self {
+            Format::Yaml => NodeType::Yaml,
+            Format::Toml => NodeType::Toml,
+            Format::Json => NodeType::Json,
+        }
+    }
+
+    /// Code stored in the binary format's `flags`
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+}
+
+/// A frontmatter block at the start of a source
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Frontmatter<'a> {
+    pub format: Format,
+    /// Text between the fence lines, without the newline before the
+    /// closing fence
+    pub value: &'a str,
+    /// Offset where `value` starts
+    pub value_start: usize,
+    /// End of the closing fence line, its newline excluded
+    pub end: usize,
+}
+
+/// The frontmatter `source` starts with, if any
+pub(crate) fn detect(source: &str) -> Option<Frontmatter<'_>> {
+    let first_end = source.find('\n')?;
+    let format = Format::from_fence(&source[..first_end])?;
+    let value_start = first_end + 1;
+
+    let mut line_start = value_start;
+    while line_start <= source.len() {
+        let line_end = source[line_start..].find('\n').map_or(source.len(), |i| line_start + i);
+        if Format::from_fence(&source[line_start..line_end]) == Some(format) {
+            let value = &source[value_start..line_start];
+            let value = value.strip_suffix('\n').unwrap_or(value);
+            let value = value.strip_suffix('\r').unwrap_or(value);
+            return Some(Frontmatter { format, value, value_start, end: line_end });
+        }
+        line_start = line_end + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let yaml = detect("---\ntitle: Hi\ntags: [a]\n---\n# Body\n").unwrap();
+        assert_eq!((yaml.format, yaml.value, yaml.value_start, yaml.end), (Format::Yaml, "title: Hi\ntags: [a]", 4, 27));
+
+        let toml = detect("+++\ntitle = \"Hi\"\n+++").unwrap();
+        assert_eq!((toml.format, toml.value), (Format::Toml, "title = \"Hi\""));
+        assert_eq!(detect(";;;\n{}\n;;;\n").unwrap().format, Format::Json);
+        assert_eq!(detect("---\n---\n").unwrap().value, "");
+
+        // Unclosed, mismatched or not on the first line
+        for source in ["---\ntitle: Hi\n", "+++\nx\n---\n", "\n---\nx\n---\n", "----\nx\n----\n", "---"] {
+            assert_eq!(detect(source), None, "{source:?}");
+        }
+    }
+}
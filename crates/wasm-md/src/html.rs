@@ -0,0 +1,289 @@
+//! HTML block start and end conditions (CommonMark 4.6)
+//!
+//! An HTML block is kept as raw `html`; where it ends decides what is
+//! parsed as markdown again. Types 1–5 (`<script>`, comments, `<?...?>`,
+//! declarations, CDATA) run until the line containing their terminator,
+//! so blank lines inside them stay raw. Types 6 and 7 (block-level and
+//! other complete tags) end at the first blank line, which lets markdown
+//! separated from the tags by blank lines parse as usual:
+//! `<div>\n\n# Heading\n\n</div>` is html, heading, html.
+//!
+//! Comments (`<!-- prettier-ignore -->`) are often directives for
+//! tooling, so `html` nodes that are exactly one comment, block or
+//! inline, also carry its trimmed text in `data.comment`.
+//!
+//! Inside paragraphs, open and closing tags are inline `html` nodes.
+//! Under the `inlineHtml` option a few common text-level tags
+//! (`INLINE_TAGS`) with a matching close tag become `htmlElement` nodes
+//! instead, with `tagName`, `attributes` and the inline-parsed content
+//! as children.
+
+use std::collections::BTreeMap;
+
+/// Tags whose content is raw up to the matching close tag (type 1)
+const RAW_TAGS: [&str; 4] = ["pre", "script", "style", "textarea"];
+
+/// Block-level tag names (type 6)
+const BLOCK_TAGS: [&str; 62] = [
+    "address", "article", "aside", "base", "basefont", "blockquote", "body", "caption", "center",
+    "col", "colgroup", "dd", "details", "dialog", "dir", "div", "dl", "dt", "fieldset",
+    "figcaption", "figure", "footer", "form", "frame", "frameset", "h1", "h2", "h3", "h4", "h5",
+    "h6", "head", "header", "hr", "html", "iframe", "legend", "li", "link", "main", "menu",
+    "menuitem", "nav", "noframes", "ol", "optgroup", "option", "p", "param", "search", "section",
+    "summary", "table", "tbody", "td", "tfoot", "th", "thead", "title", "tr", "track", "ul",
+];
+
+/// Text-level tags parsed into `htmlElement` nodes under `inlineHtml`
+pub(crate) const INLINE_TAGS: [&str; 5] = ["kbd", "sub", "sup", "mark", "abbr"];
+
+/// An open or closing tag
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Tag<'a> {
+    pub name: &'a str,
+    pub closing: bool,
+    /// `<br/>`
+    pub self_closing: bool,
+    /// Attribute values with quotes removed; a bare attribute is `""`
+    pub attributes: BTreeMap<String, String>,
+    /// Length of the tag in the source
+    pub len: usize,
+}
+
+/// Type (1–7) of the HTML block `line` starts, if any
+///
+/// `line` starts at the `<`, after any indentation. Type 7 can't
+/// interrupt a paragraph; callers check that with `interrupts_paragraph`.
+pub(crate) fn block_start(line: &str) -> Option<u8> {
+    let rest = line.strip_prefix('<')?;
+
+    if let Some(name) = tag_name(rest)
+        && RAW_TAGS.iter().any(|tag| tag.eq_ignore_ascii_case(name))
+        && matches!(rest.as_bytes().get(name.len()), None | Some(b' ' | b'\t' | b'>'))
+    {
+        return Some(1);
+    }
+    if rest.starts_with("!--") {
+        return Some(2);
+    }
+    if rest.starts_with('?') {
+        return Some(3);
+    }
+    if rest.starts_with('!') && rest.as_bytes().get(1).is_some_and(u8::is_ascii_alphabetic) {
+        return Some(4);
+    }
+    if rest.starts_with("![CDATA[") {
+        return Some(5);
+    }
+
+    let closing = rest.strip_prefix('/');
+    if let Some(name) = tag_name(closing.unwrap_or(rest))
+        && BLOCK_TAGS.iter().any(|tag| tag.eq_ignore_ascii_case(name))
+    {
+        let after = &closing.unwrap_or(rest)[name.len()..];
+        if after.is_empty() || after.starts_with([' ', '\t', '>']) || after.starts_with("/>") {
+            return Some(6);
+        }
+    }
+
+    tag(line).is_some_and(|tag| line[tag.len..].trim().is_empty()).then_some(7)
+}
+
+/// Whether a block of `kind` may interrupt a paragraph
+pub(crate) fn interrupts_paragraph(kind: u8) -> bool {
+    kind != 7
+}
+
+/// Whether `line` holds the terminator of a block of `kind` (types 1–5)
+///
+/// Types 6 and 7 end at a blank line instead and never match.
+pub(crate) fn block_end(kind: u8, line: &str) -> bool {
+    match kind {
+        1 => {
+            let lower = line.to_ascii_lowercase();
+            RAW_TAGS.iter().any(|tag| lower.contains(&format!("</{tag}>")))
+        }
+        2 => line.contains("-->"),
+        3 => line.contains("?>"),
+        4 => line.contains('>'),
+        5 => line.contains("]]>"),
+        _ => false,
+    }
+}
+
+/// Length of the comment (`<!-- ... -->`) at the start of `text` and its
+/// trimmed content
+///
+/// The comment may span lines. `<!-->` and `<!--->` are empty comments.
+pub(crate) fn comment(text: &str) -> Option<(usize, &str)> {
+    let rest = text.strip_prefix("<!--")?;
+    // Search from the `<!--` dashes so `<!-->` and `<!--->` close
+    let close = 2 + text[2..].find("-->")?;
+    let content = rest.get(..close.saturating_sub(4)).unwrap_or_default();
+    Some((close + 3, content.trim()))
+}
+
+/// Tag name at the start of `text`: an ASCII letter followed by letters,
+/// digits and `-`
+fn tag_name(text: &str) -> Option<&str> {
+    let bytes = text.as_bytes();
+    if !bytes.first()?.is_ascii_alphabetic() {
+        return None;
+    }
+    let len = bytes
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || **b == b'-')
+        .count();
+    Some(&text[..len])
+}
+
+/// The open (`<a href="x">`, `<br/>`) or closing (`</a>`) tag at the
+/// start of `text`
+pub(crate) fn tag(text: &str) -> Option<Tag<'_>> {
+    let rest = text.strip_prefix('<')?;
+    if let Some(rest) = rest.strip_prefix('/') {
+        let name = tag_name(rest)?;
+        let after = &rest[name.len()..];
+        let trimmed = after.trim_start_matches([' ', '\t', '\n']);
+        return trimmed.starts_with('>').then(|| Tag {
+            name,
+            closing: true,
+            self_closing: false,
+            attributes: BTreeMap::new(),
+            len: text.len() - trimmed.len() + 1,
+        });
+    }
+
+    let name = tag_name(rest)?;
+    let mut rest = &rest[name.len()..];
+    let mut attributes = BTreeMap::new();
+    loop {
+        let trimmed = rest.trim_start_matches([' ', '\t', '\n']);
+        let self_closing = trimmed.starts_with("/>");
+        if let Some(after) = trimmed.strip_prefix("/>").or_else(|| trimmed.strip_prefix('>')) {
+            return Some(Tag {
+                name,
+                closing: false,
+                self_closing,
+                attributes,
+                len: text.len() - after.len(),
+            });
+        }
+        // Attributes must be separated by whitespace
+        if trimmed.len() == rest.len() {
+            return None;
+        }
+        let (key, value, after) = attribute(trimmed)?;
+        attributes.insert(key.to_string(), value.to_string());
+        rest = after;
+    }
+}
+
+/// Attribute (`name`, `name=value`, `name="value"`, `name='value'`) at
+/// the start of `text`: its name, value and the text after it
+fn attribute(text: &str) -> Option<(&str, &str, &str)> {
+    let bytes = text.as_bytes();
+    let first = *bytes.first()?;
+    if !(first.is_ascii_alphabetic() || first == b'_' || first == b':') {
+        return None;
+    }
+    let name_len = bytes
+        .iter()
+        .take_while(|&&b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.' | b':' | b'-'))
+        .count();
+    let (name, rest) = text.split_at(name_len);
+
+    let Some(value) = rest.trim_start_matches([' ', '\t']).strip_prefix('=') else {
+        return Some((name, "", rest));
+    };
+    let value = value.trim_start_matches([' ', '\t']);
+    match value.as_bytes().first()? {
+        &quote @ (b'"' | b'\'') => {
+            let close = value[1..].find(quote as char)?;
+            Some((name, &value[1..close + 1], &value[close + 2..]))
+        }
+        _ => {
+            let len = value
+                .bytes()
+                .take_while(|b| !b.is_ascii_whitespace() && !b"\"'=<>`".contains(b))
+                .count();
+            (len > 0).then(|| (name, &value[..len], &value[len..]))
+        }
+    }
+}
+
+/// Start and end of the `</name>` closing an element whose content
+/// starts `text`; nested `name` elements must balance
+pub(crate) fn closing_tag(text: &str, name: &str) -> Option<(usize, usize)> {
+    let mut depth = 0usize;
+    let mut from = 0;
+    while let Some(i) = text[from..].find('<').map(|i| from + i) {
+        let Some(tag) = tag(&text[i..]).filter(|tag| tag.name.eq_ignore_ascii_case(name)) else {
+            from = i + 1;
+            continue;
+        };
+        if !tag.closing {
+            depth += usize::from(!tag.self_closing);
+        } else if depth == 0 {
+            return Some((i, i + tag.len));
+        } else {
+            depth -= 1;
+        }
+        from = i + tag.len;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_start() {
+        assert_eq!(block_start("<script type=\"module\">"), Some(1));
+        assert_eq!(block_start("<PRE>"), Some(1));
+        assert_eq!(block_start("<!-- note"), Some(2));
+        assert_eq!(block_start("<?php"), Some(3));
+        assert_eq!(block_start("<!DOCTYPE html>"), Some(4));
+        assert_eq!(block_start("<![CDATA["), Some(5));
+        assert_eq!(block_start("<div class=\"x\">"), Some(6));
+        assert_eq!(block_start("</table>"), Some(6));
+        assert_eq!(block_start("<hr/>"), Some(6));
+        assert_eq!(block_start("<span id=\"a\">"), Some(7));
+        assert_eq!(block_start("</em>  "), Some(7));
+
+        for line in ["<span>text", "<scripting> x", "<a href=\"x>", "< div>", "<>", "<3 cats"] {
+            assert_eq!(block_start(line), None, "{line}");
+        }
+    }
+
+    #[test]
+    fn test_comment() {
+        assert_eq!(comment("<!-- toc --> after"), Some((12, "toc")));
+        assert_eq!(comment("<!--\n  multi\n  line\n-->"), Some((23, "multi\n  line")));
+        assert_eq!(comment("<!-->"), Some((5, "")));
+        assert_eq!(comment("<!--->"), Some((6, "")));
+        assert_eq!(comment("<!-- open"), None);
+        assert_eq!(comment("<div>"), None);
+    }
+
+    #[test]
+    fn test_tag() {
+        let tag = tag("<abbr title=\"Hyper Text\" lang=en hidden>HTML</abbr>").unwrap();
+        assert_eq!((tag.name, tag.closing, tag.self_closing, tag.len), ("abbr", false, false, 40));
+        let attributes: Vec<_> = tag.attributes.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        assert_eq!(attributes, [("hidden", ""), ("lang", "en"), ("title", "Hyper Text")]);
+
+        assert!(super::tag("<br/>").unwrap().self_closing);
+        assert!(super::tag("</kbd >").unwrap().closing);
+        assert_eq!(closing_tag("a <sub>b</sub> c</sub>d", "sub"), Some((16, 22)));
+        assert_eq!(closing_tag("a </kbd", "kbd"), None);
+    }
+
+    #[test]
+    fn test_block_end() {
+        assert!(block_end(1, "x = 1;</SCRIPT>"));
+        assert!(!block_end(1, "</div>"));
+        assert!(block_end(2, "end -->"));
+        assert!(!block_end(6, "</div>"));
+    }
+}
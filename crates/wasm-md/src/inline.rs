@@ -0,0 +1,1734 @@
+//! Inline scanning
+//!
+//! Splits the text of an inline container (paragraph, heading, list item,
+//! table cell) into `text` and inline nodes, attached as the container's
+//! children. Offsets are byte offsets into the original
+//! source, so inline spans line up with block spans.
+//!
+//! Emphasis follows CommonMark's delimiter-run algorithm: `*`/`_` runs are
+//! classified as left/right-flanking from the Unicode class (whitespace,
+//! punctuation, other) of the characters around them, then matched
+//! innermost-first with the "rule of 3". GFM strikethrough `~~text~~`
+//! goes through the same matching, with runs of exactly two `~`.
+//!
+//! Code spans bind tighter than everything else: a backtick run opens one
+//! only if a run of the same length closes it later in the container, and
+//! nothing inside is parsed. A run with no partner is literal text.
+
+use crate::html;
+use crate::parser_v2::{ParseOptions, References, SoftBreak, SpanMode, escape_html};
+use crate::reference::{Definition, UndefinedReferences, normalize_label, parse_destination, parse_link_title};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use synth_wasm_core::{Node, NodeId, NodeType, Span, SynthResult, Tree};
+
+/// A custom inline syntax (mentions, issue refs, ...), tried before the
+/// built-in ones
+pub trait InlineMatcher {
+    /// Bytes a match can start with; other positions are never offered
+    fn triggers(&self) -> &[u8];
+
+    /// Claim the start of `rest` (the container's text from a trigger
+    /// byte on), given the character before it: the bytes consumed and
+    /// the node to emit
+    ///
+    /// The scanner sets the node's span and attaches it; children are
+    /// ignored. A claim reaching past `rest` or ending inside a character
+    /// is dropped.
+    fn match_inline(&self, rest: &str, before: Option<char>) -> Option<(usize, Node)>;
+}
+
+/// Lets a sub-parser (a blockquote's content) share its parent's matchers
+impl<M: InlineMatcher + ?Sized> InlineMatcher for &M {
+    fn triggers(&self) -> &[u8] {
+        (**self).triggers()
+    }
+
+    fn match_inline(&self, rest: &str, before: Option<char>) -> Option<(usize, Node)> {
+        (**self).match_inline(rest, before)
+    }
+}
+
+/// A scanned inline element
+#[derive(Debug)]
+enum Inline {
+    Text(Range<usize>),
+    /// Node claimed by an `InlineMatcher`
+    Custom {
+        range: Range<usize>,
+        node: Node,
+    },
+    /// Autolink, inline `[text](dest)` or reference link
+    Link {
+        range: Range<usize>,
+        url: String,
+        title: Option<String>,
+        /// Label of a reference link (`[text][label]`, `[label]`)
+        label: Option<Range<usize>>,
+        children: Vec<Inline>,
+    },
+    /// Image `![alt](src)` or reference image `![alt][label]`; the alt
+    /// text is flattened to a string
+    Image {
+        range: Range<usize>,
+        url: String,
+        title: Option<String>,
+        label: Option<Range<usize>>,
+        alt: String,
+    },
+    Emphasis {
+        range: Range<usize>,
+        children: Vec<Inline>,
+    },
+    Strong {
+        range: Range<usize>,
+        children: Vec<Inline>,
+    },
+    /// Strikethrough `~~text~~`
+    Delete {
+        range: Range<usize>,
+        children: Vec<Inline>,
+    },
+    /// Raw HTML: a comment `<!-- ... -->` or an open or closing tag
+    Html(Range<usize>),
+    /// `INLINE_TAGS` element under `inlineHtml`, from its open tag to the
+    /// matching close tag
+    Element {
+        range: Range<usize>,
+        name: String,
+        attributes: BTreeMap<String, String>,
+        children: Vec<Inline>,
+    },
+    /// Code span; `content` is between the backtick runs
+    Code {
+        range: Range<usize>,
+        content: Range<usize>,
+    },
+    /// Inline footnote `^[content]`
+    Footnote {
+        range: Range<usize>,
+        content: Range<usize>,
+        children: Vec<Inline>,
+    },
+}
+
+/// A `*`, `_` or `~` delimiter run waiting to be matched
+#[derive(Debug)]
+struct Delimiter {
+    marker: u8,
+    /// Unmatched part of the run; matching consumes it from the inside out
+    remaining: Range<usize>,
+    original_len: usize,
+    can_open: bool,
+    can_close: bool,
+}
+
+/// Inline element or pending delimiter run, before emphasis is resolved
+#[derive(Debug)]
+enum Item {
+    Inline(Inline),
+    Delimiter(Delimiter),
+}
+
+/// Character class used by the flanking rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Whitespace,
+    Punctuation,
+    Other,
+}
+
+/// An undefined reference's range, its label's range and whether it is
+/// a shortcut
+type UnresolvedRange = (Range<usize>, Range<usize>, bool);
+
+/// Inline scanner over one container's text
+pub(crate) struct InlineScanner<'a, 'o> {
+    src: &'a str,
+    options: &'o ParseOptions,
+    /// Link reference definitions by normalized label
+    definitions: &'o HashMap<String, Definition>,
+    matchers: &'o [Box<dyn InlineMatcher + 'a>],
+    /// References whose label isn't defined
+    unresolved: RefCell<Vec<UnresolvedRange>>,
+    /// Scanning a link's text, where autolinks would nest links
+    in_link: Cell<bool>,
+}
+
+impl<'a, 'o> InlineScanner<'a, 'o> {
+    pub(crate) fn new(
+        src: &'a str,
+        options: &'o ParseOptions,
+        definitions: &'o HashMap<String, Definition>,
+        matchers: &'o [Box<dyn InlineMatcher + 'a>],
+    ) -> Self {
+        Self {
+            src,
+            options,
+            definitions,
+            matchers,
+            unresolved: RefCell::new(Vec::new()),
+            in_link: Cell::new(false),
+        }
+    }
+
+    /// Scan `range` of the source (starting on `line`) and attach the
+    /// result under `parent`
+    ///
+    /// Inline footnotes leave a `footnoteReference` in place; their
+    /// `footnoteDefinition`s are created detached and pushed to
+    /// `footnotes`, numbered after the ones already there. References to
+    /// undefined labels are pushed to `unresolved`.
+    pub(crate) fn attach(
+        &self,
+        tree: &mut Tree,
+        parent: NodeId,
+        range: Range<usize>,
+        line: u32,
+        footnotes: &mut Vec<NodeId>,
+        unresolved: &mut UndefinedReferences,
+    ) -> SynthResult<()> {
+        let origin = (range.start, line);
+        let inlines = self.scan(range);
+        for (reference, label, shortcut) in self.unresolved.take() {
+            let list = if shortcut { &mut unresolved.shortcut } else { &mut unresolved.full };
+            list.push((self.src[label].to_string(), self.span(reference, origin)));
+        }
+        self.emit(tree, parent, &inlines, origin, footnotes)
+    }
+
+    fn scan(&self, range: Range<usize>) -> Vec<Inline> {
+        let bytes = self.src.as_bytes();
+        let mut items = Vec::new();
+        let mut text_start = range.start;
+        let mut pos = range.start;
+
+        while pos < range.end {
+            let b = bytes[pos];
+
+            // A backslash-escaped punctuation character is plain text
+            if b == b'\\' && bytes.get(pos + 1).is_some_and(u8::is_ascii_punctuation) {
+                pos += 2;
+                continue;
+            }
+
+            if b == b'`' {
+                let run = bytes[pos..range.end].iter().take_while(|&&c| c == b'`').count();
+                let Some(close) = closing_backticks(bytes, pos + run, range.end, run) else {
+                    pos += run;
+                    continue;
+                };
+                if text_start < pos {
+                    items.push(Item::Inline(Inline::Text(text_start..pos)));
+                }
+                items.push(Item::Inline(Inline::Code {
+                    range: pos..close + run,
+                    content: pos + run..close,
+                }));
+                pos = close + run;
+                text_start = pos;
+                continue;
+            }
+
+            if !self.matchers.is_empty()
+                && let Some((end, node)) = self.scan_custom(pos, range.clone())
+            {
+                if text_start < pos {
+                    items.push(Item::Inline(Inline::Text(text_start..pos)));
+                }
+                items.push(Item::Inline(Inline::Custom { range: pos..end, node }));
+                pos = end;
+                text_start = end;
+                continue;
+            }
+
+            if matches!(b, b'*' | b'_' | b'~') {
+                if text_start < pos {
+                    items.push(Item::Inline(Inline::Text(text_start..pos)));
+                }
+                let end = pos + bytes[pos..range.end].iter().take_while(|&&c| c == b).count();
+                items.push(Item::Delimiter(self.delimiter(pos..end, &range)));
+                pos = end;
+                text_start = end;
+                continue;
+            }
+
+            if b == b'^'
+                && self.options.footnotes
+                && self.options.inline_footnotes
+                && bytes.get(pos + 1) == Some(&b'[')
+                && let Some(close) = closing_bracket(bytes, pos + 1, range.end)
+            {
+                if text_start < pos {
+                    items.push(Item::Inline(Inline::Text(text_start..pos)));
+                }
+                let content = pos + 2..close;
+                items.push(Item::Inline(Inline::Footnote {
+                    range: pos..close + 1,
+                    children: self.scan(content.clone()),
+                    content,
+                }));
+                pos = close + 1;
+                text_start = pos;
+                continue;
+            }
+
+            if b == b'<'
+                && let Some((len, _)) = html::comment(&self.src[pos..range.end])
+            {
+                if text_start < pos {
+                    items.push(Item::Inline(Inline::Text(text_start..pos)));
+                }
+                items.push(Item::Inline(Inline::Html(pos..pos + len)));
+                pos += len;
+                text_start = pos;
+                continue;
+            }
+
+            if b == b'<'
+                && let Some(tag) = html::tag(&self.src[pos..range.end])
+            {
+                if text_start < pos {
+                    items.push(Item::Inline(Inline::Text(text_start..pos)));
+                }
+                let open_end = pos + tag.len;
+                match self.element_close(&tag, open_end..range.end) {
+                    Some((close_start, close_end)) => {
+                        items.push(Item::Inline(Inline::Element {
+                            range: pos..close_end,
+                            name: tag.name.to_ascii_lowercase(),
+                            attributes: tag.attributes,
+                            children: self.scan(open_end..close_start),
+                        }));
+                        pos = close_end;
+                    }
+                    None => {
+                        items.push(Item::Inline(Inline::Html(pos..open_end)));
+                        pos = open_end;
+                    }
+                }
+                text_start = pos;
+                continue;
+            }
+
+            if b == b'!'
+                && bytes.get(pos + 1) == Some(&b'[')
+                && let Some((end, Inline::Link { url, title, label, children, .. })) =
+                    self.scan_link(pos + 1, range.end)
+            {
+                if text_start < pos {
+                    items.push(Item::Inline(Inline::Text(text_start..pos)));
+                }
+                let mut alt = String::new();
+                self.plain_text(&children, &mut alt);
+                items.push(Item::Inline(Inline::Image { range: pos..end, url, title, label, alt }));
+                pos = end;
+                text_start = end;
+                continue;
+            }
+
+            if b == b'['
+                && let Some((end, link)) = self.scan_link(pos, range.end)
+            {
+                if text_start < pos {
+                    items.push(Item::Inline(Inline::Text(text_start..pos)));
+                }
+                items.push(Item::Inline(link));
+                pos = end;
+                text_start = end;
+                continue;
+            }
+
+            if b.is_ascii_alphanumeric()
+                && let Some((end, url)) = self.autolink_at(pos, &range)
+            {
+                if text_start < pos {
+                    items.push(Item::Inline(Inline::Text(text_start..pos)));
+                }
+                items.push(Item::Inline(Inline::Link {
+                    range: pos..end,
+                    url,
+                    title: None,
+                    label: None,
+                    children: vec![Inline::Text(pos..end)],
+                }));
+                pos = end;
+                text_start = end;
+                continue;
+            }
+            pos += 1;
+        }
+
+        if text_start < range.end {
+            items.push(Item::Inline(Inline::Text(text_start..range.end)));
+        }
+
+        resolve_emphasis(&mut items);
+        finish(items)
+    }
+
+    /// Classify the delimiter run at `run` within the container `range`
+    fn delimiter(&self, run: Range<usize>, range: &Range<usize>) -> Delimiter {
+        // The container's edges count as whitespace
+        let before = self.src[range.start..run.start]
+            .chars()
+            .next_back()
+            .map_or(CharClass::Whitespace, char_class);
+        let after = self.src[run.end..range.end]
+            .chars()
+            .next()
+            .map_or(CharClass::Whitespace, char_class);
+
+        let left_flanking = after != CharClass::Whitespace
+            && (after != CharClass::Punctuation || before != CharClass::Other);
+        let right_flanking = before != CharClass::Whitespace
+            && (before != CharClass::Punctuation || after != CharClass::Other);
+
+        let marker = self.src.as_bytes()[run.start];
+        let (can_open, can_close) = if marker == b'~' {
+            // Any other number of tildes is literal
+            let pair = run.len() == 2;
+            (pair && left_flanking, pair && right_flanking)
+        } else if marker == b'_' {
+            // `_` never opens or closes inside a word
+            (
+                left_flanking && (!right_flanking || before == CharClass::Punctuation),
+                right_flanking && (!left_flanking || after == CharClass::Punctuation),
+            )
+        } else {
+            (left_flanking, right_flanking)
+        };
+
+        Delimiter {
+            marker,
+            original_len: run.len(),
+            remaining: run,
+            can_open,
+            can_close,
+        }
+    }
+
+    /// Node of the first matcher claiming the text at `pos`, and where it
+    /// ends
+    fn scan_custom(&self, pos: usize, range: Range<usize>) -> Option<(usize, Node)> {
+        let b = self.src.as_bytes()[pos];
+        let mut matchers = self.matchers.iter().filter(|matcher| matcher.triggers().contains(&b)).peekable();
+        if matchers.peek().is_none() || !self.src.is_char_boundary(pos) {
+            return None;
+        }
+        let rest = &self.src[pos..range.end];
+        let before = self.src[range.start..pos].chars().next_back();
+        matchers
+            .find_map(|matcher| matcher.match_inline(rest, before))
+            .filter(|&(len, _)| len > 0 && rest.is_char_boundary(len))
+            .map(|(len, node)| (pos + len, node))
+    }
+
+    /// Link at the `[` at `pos`, and where it ends
+    ///
+    /// An inline link `[text](dest)` takes precedence over a reference.
+    /// Anything malformed is left as text.
+    fn scan_link(&self, pos: usize, limit: usize) -> Option<(usize, Inline)> {
+        let close = closing_bracket(self.src.as_bytes(), pos, limit)?;
+        if let Some(link) = self.scan_inline_link(pos, close, limit) {
+            return Some(link);
+        }
+        self.scan_reference(pos, close, limit)
+    }
+
+    /// Inline link `[text](dest "title")` whose text closes at `close`
+    ///
+    /// The title is optional and must be separated from the destination
+    /// by whitespace.
+    fn scan_inline_link(&self, pos: usize, close: usize, limit: usize) -> Option<(usize, Inline)> {
+        let bytes = &self.src.as_bytes()[..limit];
+        if bytes.get(close + 1) != Some(&b'(') {
+            return None;
+        }
+        let skip_space = |mut i: usize| {
+            while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+                i += 1;
+            }
+            i
+        };
+
+        let dest = skip_space(close + 2);
+        let (url, len) = if bytes.get(dest) == Some(&b')') {
+            (String::new(), 0)
+        } else {
+            parse_destination(&self.src[dest..limit])?
+        };
+        let mut end = skip_space(dest + len);
+        let mut title = None;
+        if end > dest + len
+            && let Some((text, len)) = parse_link_title(&self.src[end..limit])
+        {
+            title = Some(text);
+            end = skip_space(end + len);
+        }
+        if bytes.get(end) != Some(&b')') {
+            return None;
+        }
+
+        let link = Inline::Link {
+            range: pos..end + 1,
+            url,
+            title,
+            label: None,
+            children: self.scan_link_text(pos + 1..close),
+        };
+        Some((end + 1, link))
+    }
+
+    /// Reference link at the `[` at `pos` whose first bracket closes at
+    /// `close`: full `[text][label]`, collapsed `[label][]` or shortcut
+    /// `[label]`, when its label is defined
+    ///
+    /// An undefined full or collapsed reference is recorded as unresolved;
+    /// an undefined shortcut is usually just bracketed text, recorded only
+    /// under `references: "eager"`, where its definition may come later,
+    /// and not as the label of an undefined full reference.
+    fn scan_reference(&self, pos: usize, close: usize, limit: usize) -> Option<(usize, Inline)> {
+        let bytes = self.src.as_bytes();
+        let text = pos + 1..close;
+
+        let (label, end, shortcut) = if bytes.get(close + 1) == Some(&b'[') && close + 1 < limit {
+            let label_close = closing_bracket(bytes, close + 1, limit)?;
+            if label_close == close + 2 {
+                (text.clone(), label_close + 1, false)
+            } else {
+                (close + 2..label_close, label_close + 1, false)
+            }
+        } else {
+            (text.clone(), close + 1, true)
+        };
+
+        let Some(definition) = self.definitions.get(&normalize_label(&self.src[label.clone()])) else {
+            let mut unresolved = self.unresolved.borrow_mut();
+            let recorded = !shortcut
+                || self.options.references == References::Eager
+                    && !unresolved.last().is_some_and(|(full, _, shortcut)| !shortcut && full.contains(&pos));
+            if recorded && !self.src[label.clone()].trim().is_empty() {
+                unresolved.push((pos..end, label, shortcut));
+            }
+            return None;
+        };
+        let link = Inline::Link {
+            range: pos..end,
+            url: definition.url.clone(),
+            title: definition.title.clone(),
+            label: Some(label),
+            children: self.scan_link_text(text),
+        };
+        Some((end, link))
+    }
+
+    /// Inlines of a link's text, without autolinks
+    fn scan_link_text(&self, range: Range<usize>) -> Vec<Inline> {
+        let outer = self.in_link.replace(true);
+        let children = self.scan(range);
+        self.in_link.set(outer);
+        children
+    }
+
+    /// Source range of the close tag ending the element `tag` opens, when
+    /// `inlineHtml` parses it into an `htmlElement`; `rest` follows the
+    /// open tag
+    fn element_close(&self, tag: &html::Tag<'_>, rest: Range<usize>) -> Option<(usize, usize)> {
+        if !self.options.inline_html
+            || tag.closing
+            || tag.self_closing
+            || !html::INLINE_TAGS.iter().any(|name| name.eq_ignore_ascii_case(tag.name))
+        {
+            return None;
+        }
+        let (start, end) = html::closing_tag(&self.src[rest.clone()], tag.name)?;
+        Some((rest.start + start, rest.start + end))
+    }
+
+    /// Append the text content of `inlines`, without markup (an image's
+    /// alt text)
+    fn plain_text(&self, inlines: &[Inline], out: &mut String) {
+        for inline in inlines {
+            match inline {
+                Inline::Text(range) => out.push_str(&self.src[range.clone()]),
+                Inline::Code { content, .. } => out.push_str(&code_span_value(&self.src[content.clone()])),
+                Inline::Image { alt, .. } => out.push_str(alt),
+                Inline::Link { children, .. }
+                | Inline::Emphasis { children, .. }
+                | Inline::Strong { children, .. }
+                | Inline::Delete { children, .. }
+                | Inline::Element { children, .. }
+                | Inline::Footnote { children, .. } => self.plain_text(children, out),
+                Inline::Custom { .. } | Inline::Html(_) => {}
+            }
+        }
+    }
+
+    /// Autolink starting at `pos` (an ASCII alphanumeric) under the
+    /// `autolink`/`gfmAutolink` options: its end and URL
+    ///
+    /// Never inside a link's text.
+    fn autolink_at(&self, pos: usize, range: &Range<usize>) -> Option<(usize, String)> {
+        if self.in_link.get() {
+            return None;
+        }
+        let before = (pos > range.start).then(|| self.src.as_bytes()[pos - 1]);
+        if self.options.autolink
+            && before.is_none_or(|b| !b.is_ascii_alphanumeric())
+            && let Some(end) = self.scan_autolink(pos, range.end)
+        {
+            return Some((end, self.src[pos..end].to_string()));
+        }
+        // GFM: only at the start, after whitespace or after `*`, `_`, `~`, `(`
+        if self.options.gfm_autolink
+            && before.is_none_or(|b| b.is_ascii_whitespace() || matches!(b, b'*' | b'_' | b'~' | b'('))
+        {
+            return self
+                .scan_www_autolink(pos, range.end)
+                .or_else(|| self.scan_url_autolink(pos, range.end))
+                .or_else(|| self.scan_email_autolink(pos, range.end));
+        }
+        None
+    }
+
+    /// GFM `www.` autolink at `pos`: its end and URL, with `http://` added
+    ///
+    /// Runs to whitespace or `<`, minus trailing punctuation, and needs a
+    /// valid domain (see `valid_domain`).
+    fn scan_www_autolink(&self, pos: usize, limit: usize) -> Option<(usize, String)> {
+        let bytes = &self.src.as_bytes()[..limit];
+        if !bytes[pos..].starts_with(b"www.") {
+            return None;
+        }
+        let mut end = pos;
+        while end < bytes.len() && !bytes[end].is_ascii_whitespace() && bytes[end] != b'<' {
+            end += 1;
+        }
+        let end = pos + trim_url_end(&self.src[pos..end]);
+
+        let domain = bytes[pos..end]
+            .iter()
+            .take_while(|&&b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+            .count();
+        let domain = self.src[pos..pos + domain].trim_end_matches('.');
+        valid_domain(domain).then(|| (end, format!("http://{}", &self.src[pos..end])))
+    }
+
+    /// GFM `http://` or `https://` autolink at `pos`: its end and URL
+    ///
+    /// Like `www.` links, but the valid domain follows the scheme.
+    fn scan_url_autolink(&self, pos: usize, limit: usize) -> Option<(usize, String)> {
+        let bytes = &self.src.as_bytes()[..limit];
+        let rest = &bytes[pos..];
+        let scheme = ["http://", "https://"]
+            .into_iter()
+            .find(|scheme| rest.len() >= scheme.len() && rest[..scheme.len()].eq_ignore_ascii_case(scheme.as_bytes()))?;
+        let host = pos + scheme.len();
+        let mut end = host;
+        while end < bytes.len() && !bytes[end].is_ascii_whitespace() && bytes[end] != b'<' {
+            end += 1;
+        }
+        let end = pos + trim_url_end(&self.src[pos..end]);
+
+        let domain = bytes[host..end.max(host)]
+            .iter()
+            .take_while(|&&b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+            .count();
+        let domain = self.src[host..host + domain].trim_end_matches('.');
+        valid_domain(domain).then(|| (end, self.src[pos..end].to_string()))
+    }
+
+    /// GFM email autolink at `pos`: its end and URL, with `mailto:` added
+    ///
+    /// The local part is alphanumerics and `.-_+`; the domain needs a `.`,
+    /// can't end in `-` or `_`, and a trailing `.` is left out.
+    fn scan_email_autolink(&self, pos: usize, limit: usize) -> Option<(usize, String)> {
+        let bytes = &self.src.as_bytes()[..limit];
+        let local = bytes[pos..]
+            .iter()
+            .take_while(|&&b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'_' | b'+'))
+            .count();
+        let at = pos + local;
+        if bytes.get(at) != Some(&b'@') {
+            return None;
+        }
+
+        let domain = bytes[at + 1..]
+            .iter()
+            .take_while(|&&b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+            .count();
+        let domain = self.src[at + 1..at + 1 + domain].trim_end_matches('.');
+        let valid = domain.contains('.')
+            && !domain.ends_with(['-', '_'])
+            && domain.split('.').all(|segment| !segment.is_empty());
+        let end = at + 1 + domain.len();
+        valid.then(|| (end, format!("mailto:{}", &self.src[pos..end])))
+    }
+
+    /// End of a bare `scheme:rest` URL at `pos` whose scheme is allowed
+    ///
+    /// Disallowed schemes (e.g. `javascript:`) are left as text.
+    fn scan_autolink(&self, pos: usize, limit: usize) -> Option<usize> {
+        let bytes = &self.src.as_bytes()[..limit];
+
+        // scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." ), then ':'
+        let mut i = pos + 1;
+        while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || matches!(bytes[i], b'+' | b'-' | b'.')) {
+            i += 1;
+        }
+        if i - pos > 32 || bytes.get(i) != Some(&b':') {
+            return None;
+        }
+        let scheme = &self.src[pos..i];
+        if !self
+            .options
+            .autolink_schemes
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+        {
+            return None;
+        }
+
+        // Destination runs to whitespace or `<`
+        let rest = i + 1;
+        let mut end = rest;
+        while end < bytes.len() && !bytes[end].is_ascii_whitespace() && bytes[end] != b'<' {
+            end += 1;
+        }
+
+        let end = trim_url_end(&self.src[pos..end]) + pos;
+        (end > rest).then_some(end)
+    }
+
+    fn emit(
+        &self,
+        tree: &mut Tree,
+        parent: NodeId,
+        inlines: &[Inline],
+        origin: (usize, u32),
+        footnotes: &mut Vec<NodeId>,
+    ) -> SynthResult<()> {
+        for inline in inlines {
+            match inline {
+                Inline::Text(range) => self.emit_text(tree, parent, range.clone(), origin)?,
+                Inline::Custom { range, node } => {
+                    let node = Node {
+                        parent: None,
+                        children: Vec::new(),
+                        span: Some(self.span(range.clone(), origin)),
+                        ..node.clone()
+                    };
+                    let id = tree.add_node(node);
+                    tree.add_child(parent, id)?;
+                }
+                Inline::Link { range, url, title, label, children } => {
+                    let node = Node::new(0, NodeType::Link)
+                        .with_span(self.span(range.clone(), origin))
+                        .with_data(self.link_data(url, title, label));
+                    let id = tree.add_node(node);
+                    tree.add_child(parent, id)?;
+                    self.emit(tree, id, children, origin, footnotes)?;
+                }
+                Inline::Image { range, url, title, label, alt } => {
+                    let mut data = self.link_data(url, title, label);
+                    data.insert("alt".to_string(), serde_json::json!(alt));
+                    let node = Node::new(0, NodeType::Image)
+                        .with_span(self.span(range.clone(), origin))
+                        .with_data(data);
+                    let id = tree.add_node(node);
+                    tree.add_child(parent, id)?;
+                }
+                Inline::Emphasis { range, children }
+                | Inline::Strong { range, children }
+                | Inline::Delete { range, children } => {
+                    let node_type = match inline {
+                        Inline::Strong { .. } => NodeType::Strong,
+                        Inline::Delete { .. } => NodeType::Delete,
+                        _ => NodeType::Emphasis,
+                    };
+                    let node = Node::new(0, node_type).with_span(self.span(range.clone(), origin));
+                    let id = tree.add_node(node);
+                    tree.add_child(parent, id)?;
+                    self.emit(tree, id, children, origin, footnotes)?;
+                }
+                Inline::Element { range, name, attributes, children } => {
+                    let mut data = HashMap::new();
+                    data.insert("tagName".to_string(), serde_json::json!(name));
+                    data.insert("attributes".to_string(), serde_json::json!(attributes));
+                    let node = Node::new(0, "htmlElement")
+                        .with_span(self.span(range.clone(), origin))
+                        .with_data(data);
+                    let id = tree.add_node(node);
+                    tree.add_child(parent, id)?;
+                    self.emit(tree, id, children, origin, footnotes)?;
+                }
+                Inline::Html(range) => {
+                    let mut data = self.value_data(&self.src[range.clone()]);
+                    if let Some((_, comment)) = html::comment(&self.src[range.clone()]) {
+                        data.insert("comment".to_string(), serde_json::json!(comment));
+                    }
+                    let node = Node::new(0, NodeType::Html)
+                        .with_span(self.span(range.clone(), origin))
+                        .with_data(data);
+                    let id = tree.add_node(node);
+                    tree.add_child(parent, id)?;
+                }
+                Inline::Code { range, content } => {
+                    let node = Node::new(0, NodeType::InlineCode)
+                        .with_span(self.span(range.clone(), origin))
+                        .with_data(self.value_data(&code_span_value(&self.src[content.clone()])));
+                    let id = tree.add_node(node);
+                    tree.add_child(parent, id)?;
+                }
+                Inline::Footnote { range, content, children } => {
+                    let label = (footnotes.len() + 1).to_string();
+                    let span = self.span(range.clone(), origin);
+                    let mut data = HashMap::new();
+                    data.insert("identifier".to_string(), serde_json::json!(label));
+                    data.insert("label".to_string(), serde_json::json!(label));
+
+                    let reference = Node::new(0, NodeType::FootnoteReference)
+                        .with_span(span)
+                        .with_data(data.clone());
+                    let id = tree.add_node(reference);
+                    tree.add_child(parent, id)?;
+
+                    // The definition holds the note's text as a paragraph;
+                    // the caller attaches it
+                    data.insert("inline".to_string(), serde_json::json!(true));
+                    let definition = Node::new(0, NodeType::FootnoteDefinition)
+                        .with_span(span)
+                        .with_data(data);
+                    let definition = tree.add_node(definition);
+                    footnotes.push(definition);
+                    let paragraph = Node::new(0, NodeType::Paragraph)
+                        .with_span(self.span(content.clone(), origin))
+                        .with_data(self.value_data(&self.src[content.clone()]));
+                    let paragraph = tree.add_node(paragraph);
+                    tree.add_child(definition, paragraph)?;
+                    self.emit(tree, paragraph, children, origin, footnotes)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Text node(s) for a source range, with line breaks represented as
+    /// the `softBreak` option asks
+    fn emit_text(
+        &self,
+        tree: &mut Tree,
+        parent: NodeId,
+        range: Range<usize>,
+        origin: (usize, u32),
+    ) -> SynthResult<()> {
+        let add = |tree: &mut Tree, node_type: NodeType, range: Range<usize>, data| {
+            let mut node = Node::new(0, node_type).with_span(self.span(range, origin));
+            node.data = data;
+            let id = tree.add_node(node);
+            tree.add_child(parent, id)
+        };
+
+        let text = &self.src[range.clone()];
+        if self.options.soft_break == SoftBreak::Newline || !text.contains('\n') {
+            return add(tree, NodeType::Text, range.clone(), Some(self.value_data(text)));
+        }
+
+        let lines = line_ranges(self.src, range.clone());
+        if self.options.soft_break == SoftBreak::Space {
+            let joined = lines.iter().map(|line| &self.src[line.clone()]).collect::<Vec<_>>().join(" ");
+            let mut data = self.value_data(&joined);
+            if self.options.escape_html {
+                data.insert("raw".to_string(), serde_json::json!(text));
+            }
+            return add(tree, NodeType::Text, range, Some(data));
+        }
+
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                add(tree, NodeType::Break, lines[i - 1].end..line.start, None)?;
+            }
+            if !line.is_empty() {
+                add(tree, NodeType::Text, line.clone(), Some(self.value_data(&self.src[line.clone()])))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `url`, `title`, and for references `identifier` and `label`, of a
+    /// link or image
+    fn link_data(
+        &self,
+        url: &str,
+        title: &Option<String>,
+        label: &Option<Range<usize>>,
+    ) -> HashMap<String, serde_json::Value> {
+        let mut data = HashMap::new();
+        data.insert("url".to_string(), serde_json::json!(url));
+        if let Some(title) = title {
+            data.insert("title".to_string(), serde_json::json!(title));
+        }
+        if let Some(label) = label {
+            let label = &self.src[label.clone()];
+            data.insert("identifier".to_string(), serde_json::json!(normalize_label(label)));
+            data.insert("label".to_string(), serde_json::json!(label));
+        }
+        data
+    }
+
+    /// `value` (and `raw` under `escapeHtml`) for some text
+    fn value_data(&self, text: &str) -> HashMap<String, serde_json::Value> {
+        let mut data = HashMap::new();
+        if self.options.escape_html {
+            data.insert("value".to_string(), serde_json::json!(escape_html(text)));
+            data.insert("raw".to_string(), serde_json::json!(text));
+        } else {
+            data.insert("value".to_string(), serde_json::json!(text));
+        }
+        data
+    }
+
+    /// Span of a source byte range; `origin` is a known (offset, line) pair
+    /// at or before it. Offsets are shifted like the block spans.
+    fn span(&self, range: Range<usize>, origin: (usize, u32)) -> Span {
+        let base = self.options.base_offset.unwrap_or(0);
+        if self.options.spans == SpanMode::Offset {
+            return Span::from_coords(0, 0, base + range.start as u32, 0, 0, base + range.end as u32);
+        }
+        let (start_line, start_column) = self.line_column(range.start, origin);
+        let (end_line, end_column) = self.line_column(range.end, origin);
+        Span::from_coords(
+            start_line,
+            start_column,
+            base + range.start as u32,
+            end_line,
+            end_column,
+            base + range.end as u32,
+        )
+    }
+
+    fn line_column(&self, offset: usize, (origin, origin_line): (usize, u32)) -> (u32, u32) {
+        let bytes = self.src.as_bytes();
+        let line = origin_line + memchr::memchr_iter(b'\n', &bytes[origin..offset]).count() as u32;
+        let line_start = memchr::memrchr(b'\n', &bytes[..offset]).map_or(0, |i| i + 1);
+        (line, self.options.column_unit.column(&bytes[line_start..offset]))
+    }
+}
+
+/// Match delimiter runs into emphasis/strong/delete (CommonMark "process
+/// emphasis")
+///
+/// Each closer pairs with the nearest compatible opener before it; two
+/// characters are used when both runs have them (strong), otherwise one.
+/// `~~` pairs always use both tildes (delete). Items between the pair
+/// become the new node's children.
+fn resolve_emphasis(items: &mut Vec<Item>) {
+    let mut closer = 0;
+    while closer < items.len() {
+        let Item::Delimiter(close) = &items[closer] else {
+            closer += 1;
+            continue;
+        };
+        if !close.can_close || close.remaining.is_empty() {
+            closer += 1;
+            continue;
+        }
+
+        let opener = (0..closer).rev().find(|&i| match &items[i] {
+            Item::Delimiter(open) => {
+                open.marker == close.marker
+                    && open.can_open
+                    && !open.remaining.is_empty()
+                    // Rule of 3 (not for `~~`): a run that can both open
+                    // and close only pairs when the lengths don't sum to a
+                    // multiple of 3
+                    && (open.marker == b'~'
+                        || !((open.can_close || close.can_open)
+                            && (open.original_len + close.original_len) % 3 == 0
+                            && !(open.original_len % 3 == 0 && close.original_len % 3 == 0)))
+            }
+            Item::Inline(_) => false,
+        });
+        let Some(opener) = opener else {
+            closer += 1;
+            continue;
+        };
+
+        let (Item::Delimiter(open), Item::Delimiter(close)) = (&items[opener], &items[closer]) else {
+            unreachable!("opener and closer are delimiters");
+        };
+        let used = if open.remaining.len() >= 2 && close.remaining.len() >= 2 { 2 } else { 1 };
+        let range = open.remaining.end - used..close.remaining.start + used;
+        let marker = open.marker;
+
+        let children = finish(items.drain(opener + 1..closer).collect());
+        let node = match (marker, used) {
+            (b'~', _) => Inline::Delete { range, children },
+            (_, 2) => Inline::Strong { range, children },
+            _ => Inline::Emphasis { range, children },
+        };
+        if let Item::Delimiter(open) = &mut items[opener] {
+            open.remaining.end -= used;
+        }
+        if let Item::Delimiter(close) = &mut items[opener + 1] {
+            close.remaining.start += used;
+        }
+        items.insert(opener + 1, Item::Inline(node));
+        // Re-examine the closer: it may still have characters left
+        closer = opener + 2;
+    }
+}
+
+/// The lines of `range`, without the whitespace around each line break
+fn line_ranges(src: &str, range: Range<usize>) -> Vec<Range<usize>> {
+    let bytes = src.as_bytes();
+    let mut lines = Vec::new();
+    let mut start = range.start;
+    for newline in memchr::memchr_iter(b'\n', &bytes[range.clone()]).map(|i| range.start + i) {
+        lines.push(start..newline);
+        start = newline + 1;
+    }
+    lines.push(start..range.end);
+
+    let last = lines.len() - 1;
+    for (i, line) in lines.iter_mut().enumerate() {
+        if i < last {
+            while line.end > line.start && matches!(bytes[line.end - 1], b' ' | b'\t' | b'\r') {
+                line.end -= 1;
+            }
+        }
+        if i > 0 {
+            while line.start < line.end && matches!(bytes[line.start], b' ' | b'\t') {
+                line.start += 1;
+            }
+        }
+    }
+    lines
+}
+
+/// Index of the `]` matching the `[` at `open`, before `limit`
+///
+/// Nested brackets must balance; backslash-escaped brackets don't count.
+fn closing_bracket(bytes: &[u8], open: usize, limit: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut i = open;
+    while i < limit {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'[' => depth += 1,
+            b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Start of the first run of exactly `len` backticks in `from..limit`
+fn closing_backticks(bytes: &[u8], from: usize, limit: usize, len: usize) -> Option<usize> {
+    let mut i = from;
+    while i < limit {
+        if bytes[i] != b'`' {
+            i += 1;
+            continue;
+        }
+        let run = bytes[i..limit].iter().take_while(|&&c| c == b'`').count();
+        if run == len {
+            return Some(i);
+        }
+        i += run;
+    }
+    None
+}
+
+/// Value of a code span from the text between its backtick runs
+///
+/// Line endings become spaces, then one space is stripped from each end
+/// when both ends have one and the content isn't all spaces, so
+/// `` `` `a` `` `` holds `` `a` ``.
+fn code_span_value(content: &str) -> String {
+    let value = content.replace("\r\n", " ").replace('\n', " ");
+    if value.len() >= 2 && value.starts_with(' ') && value.ends_with(' ') && value.bytes().any(|b| b != b' ') {
+        value[1..value.len() - 1].to_string()
+    } else {
+        value
+    }
+}
+
+/// Turn unmatched delimiters into text and merge adjacent text ranges
+fn finish(items: Vec<Item>) -> Vec<Inline> {
+    let mut out: Vec<Inline> = Vec::with_capacity(items.len());
+    for item in items {
+        let inline = match item {
+            Item::Inline(inline) => inline,
+            Item::Delimiter(d) if d.remaining.is_empty() => continue,
+            Item::Delimiter(d) => Inline::Text(d.remaining),
+        };
+        if let (Some(Inline::Text(prev)), Inline::Text(next)) = (out.last_mut(), &inline)
+            && prev.end == next.start
+        {
+            prev.end = next.end;
+            continue;
+        }
+        out.push(inline);
+    }
+    out
+}
+
+fn char_class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Whitespace
+    } else if is_punctuation(c) {
+        CharClass::Punctuation
+    } else {
+        CharClass::Other
+    }
+}
+
+/// Unicode punctuation or symbol (general categories P and S)
+///
+/// Covers ASCII and the non-ASCII blocks that are entirely or mostly
+/// punctuation/symbols (Latin-1, General Punctuation through Misc.
+/// Symbols, CJK Symbols and Punctuation, fullwidth forms); letters in
+/// those ranges are rare enough to be treated as punctuation.
+fn is_punctuation(c: char) -> bool {
+    if c.is_ascii() {
+        return c.is_ascii_punctuation();
+    }
+    matches!(c,
+        '\u{A1}'..='\u{BF}' | '\u{D7}' | '\u{F7}'
+        | '\u{2010}'..='\u{2027}' | '\u{2030}'..='\u{205E}'
+        | '\u{20A0}'..='\u{20C0}'
+        | '\u{2100}'..='\u{214F}' | '\u{2190}'..='\u{2BFF}'
+        | '\u{2E00}'..='\u{2E7F}'
+        | '\u{3001}'..='\u{3003}' | '\u{3008}'..='\u{3020}' | '\u{3030}' | '\u{303D}'
+        | '\u{30FB}'
+        | '\u{FE10}'..='\u{FE19}' | '\u{FE30}'..='\u{FE6B}'
+        | '\u{FF01}'..='\u{FF0F}' | '\u{FF1A}'..='\u{FF20}' | '\u{FF3B}'..='\u{FF40}'
+        | '\u{FF5B}'..='\u{FF65}'
+    )
+}
+
+/// GFM valid domain: at least two non-empty segments of alphanumerics,
+/// `-` and `_` separated by `.`, with no `_` in the last two
+fn valid_domain(domain: &str) -> bool {
+    let segments: Vec<&str> = domain.split('.').collect();
+    segments.len() >= 2
+        && segments.iter().all(|segment| !segment.is_empty())
+        && segments[segments.len() - 2..].iter().all(|segment| !segment.contains('_'))
+}
+
+/// Length of a URL candidate without trailing punctuation
+///
+/// Drops trailing `?!.,:;*_~'"` and a `)` that has no opening partner
+/// in the URL, so `(see https://x.com).` links just `https://x.com`.
+fn trim_url_end(url: &str) -> usize {
+    let bytes = url.as_bytes();
+    let mut end = bytes.len();
+    loop {
+        match bytes[..end].last() {
+            Some(b'?' | b'!' | b'.' | b',' | b':' | b';' | b'*' | b'_' | b'~' | b'\'' | b'"') => end -= 1,
+            Some(b')') => {
+                let opens = bytes[..end].iter().filter(|&&b| b == b'(').count();
+                let closes = bytes[..end].iter().filter(|&&b| b == b')').count();
+                if closes > opens {
+                    end -= 1;
+                } else {
+                    break;
+                }
+            }
+            _ => break,
+        }
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser_v2::MarkdownParserV2;
+
+    fn inline_types(src: &str, options: ParseOptions) -> Vec<(String, String)> {
+        let tree = MarkdownParserV2::new(src).with_options(options).parse().unwrap();
+        let para = tree.children_of(tree.root_id())[0];
+        tree.children_of(para)
+            .iter()
+            .map(|&id| {
+                let node = tree.get_node(id).unwrap();
+                let data = node.data.as_ref().unwrap();
+                let text = data
+                    .get("url")
+                    .or_else(|| data.get("value"))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default();
+                (node.node_type.to_string(), text.to_string())
+            })
+            .collect()
+    }
+
+    fn autolink() -> ParseOptions {
+        ParseOptions {
+            autolink: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_paragraph_has_text_child() {
+        assert_eq!(
+            inline_types("see https://x\n", ParseOptions::default()),
+            vec![("text".to_string(), "see https://x".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_autolink_default_allowlist() {
+        assert_eq!(
+            inline_types("see https://x now\n", autolink()),
+            vec![
+                ("text".to_string(), "see ".to_string()),
+                ("link".to_string(), "https://x".to_string()),
+                ("text".to_string(), " now".to_string()),
+            ]
+        );
+        assert_eq!(
+            inline_types("javascript:alert(1)\n", autolink()),
+            vec![("text".to_string(), "javascript:alert(1)".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_autolink_custom_schemes_and_trailing_punctuation() {
+        let options = ParseOptions {
+            autolink: true,
+            autolink_schemes: vec!["ftp".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            inline_types("(ftp://a.b/c). https://x\n", options),
+            vec![
+                ("text".to_string(), "(".to_string()),
+                ("link".to_string(), "ftp://a.b/c".to_string()),
+                ("text".to_string(), "). https://x".to_string()),
+            ]
+        );
+    }
+
+    /// First paragraph's inlines as HTML-like markup
+    fn render(src: &str) -> String {
+        fn walk(tree: &Tree, id: NodeId, out: &mut String) {
+            for &child in tree.children_of(id) {
+                let node = tree.get_node(child).unwrap();
+                let tag = match node.node_type {
+                    NodeType::Emphasis => "em",
+                    NodeType::Strong => "strong",
+                    NodeType::Link => "a",
+                    NodeType::Delete => "del",
+                    _ => {
+                        out.push_str(node.data.as_ref().unwrap()["value"].as_str().unwrap());
+                        continue;
+                    }
+                };
+                out.push_str(&format!("<{tag}>"));
+                walk(tree, child, out);
+                out.push_str(&format!("</{tag}>"));
+            }
+        }
+
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let mut out = String::new();
+        walk(&tree, tree.children_of(tree.root_id())[0], &mut out);
+        out
+    }
+
+    #[test]
+    fn test_gfm_autolink_literals() {
+        let options = || ParseOptions { gfm_autolink: true, ..Default::default() };
+        let link = |url: &str| ("link".to_string(), url.to_string());
+        let text = |value: &str| ("text".to_string(), value.to_string());
+
+        assert_eq!(inline_types("visit www.x.com.\n", options()), [text("visit "), link("http://www.x.com"), text(".")]);
+        assert_eq!(
+            inline_types("mail me@x.com here\n", options()),
+            [text("mail "), link("mailto:me@x.com"), text(" here")]
+        );
+        assert_eq!(
+            inline_types("(www.a.org/p?q=1) a.b-c+d@e.co.\n", options()),
+            [text("("), link("http://www.a.org/p?q=1"), text(") "), link("mailto:a.b-c+d@e.co"), text(".")]
+        );
+
+        // Boundaries, invalid domains, and the option being off
+        for src in ["xwww.x.com", "a@b", "a@b.c-", "www.x_y.com", "www.", "a@@b.com", "=me@x.com"] {
+            assert_eq!(inline_types(src, options()), [text(src)], "{src}");
+        }
+        assert_eq!(inline_types("www.x.com me@x.com", autolink()), [text("www.x.com me@x.com")]);
+
+        // `http(s)://` literals need a valid domain too
+        assert_eq!(
+            inline_types("(see https://x.com/a_(b)).\n", options()),
+            [text("(see "), link("https://x.com/a_(b)"), text(").")]
+        );
+        assert_eq!(
+            inline_types("(see https://x.com).\n", options()),
+            [text("(see "), link("https://x.com"), text(").")]
+        );
+        assert_eq!(inline_types("HTTP://www.x.org, then\n", options()), [link("HTTP://www.x.org"), text(", then")]);
+        for src in ["https://localhost/", "http://x", "https://.com", "xhttps://x.com", "ftp://x.com"] {
+            assert_eq!(inline_types(src, options()), [text(src)], "{src}");
+        }
+
+        // Explicit links aren't wrapped again
+        for src in ["[see https://x.com](/u)", "[mail me@x.com](/u)", "[www.x.com]: /u\n\n[www.x.com]"] {
+            let tree = MarkdownParserV2::new(src).with_options(options()).parse().unwrap();
+            let links: Vec<_> = tree.nodes().iter().filter(|n| n.node_type == NodeType::Link).collect();
+            assert_eq!(links.len(), 1, "{src}");
+            assert!(links[0].children.iter().all(|&c| tree.get_node(c).unwrap().node_type == NodeType::Text), "{src}");
+        }
+        let tree = MarkdownParserV2::new("*www.x.com*").with_options(options()).parse().unwrap();
+        let link = tree.nodes().iter().find(|n| n.node_type == NodeType::Link).unwrap();
+        assert_eq!(tree.get_node(link.parent.unwrap()).unwrap().node_type, NodeType::Emphasis);
+    }
+
+    #[test]
+    fn test_emphasis_basic() {
+        assert_eq!(render("x *a* and _b_"), "x <em>a</em> and <em>b</em>");
+        assert_eq!(render("x **a** __b__"), "x <strong>a</strong> <strong>b</strong>");
+        assert_eq!(render("x ***a***"), "x <em><strong>a</strong></em>");
+        assert_eq!(render("x **foo \"*bar*\" foo**"), "x <strong>foo \"<em>bar</em>\" foo</strong>");
+        assert_eq!(render("a * b *"), "a * b *");
+        assert_eq!(render("x *unclosed"), "x *unclosed");
+        assert_eq!(render("*a* starts the line"), "<em>a</em> starts the line");
+        assert_eq!(render("\\*not\\*"), "\\*not\\*");
+    }
+
+    #[test]
+    fn test_emphasis_flanking_with_punctuation() {
+        // CommonMark flanking examples
+        assert_eq!(render("x *\"foo\"*"), "x <em>\"foo\"</em>");
+        assert_eq!(render("a*\"foo\"*"), "a*\"foo\"*");
+        assert_eq!(render("foo-_(bar)_"), "foo-<em>(bar)</em>");
+        assert_eq!(render("_foo_bar"), "_foo_bar");
+        assert_eq!(render("foo*bar*"), "foo<em>bar</em>");
+        assert_eq!(render("x *(*foo*)*"), "x <em>(<em>foo</em>)</em>");
+        assert_eq!(render("x *foo**bar**baz*"), "x <em>foo<strong>bar</strong>baz</em>");
+        // Rule of 3
+        assert_eq!(render("x *foo**bar*"), "x <em>foo**bar</em>");
+    }
+
+    #[test]
+    fn test_emphasis_around_cjk() {
+        assert_eq!(render("中**文**字"), "中<strong>文</strong>字");
+        assert_eq!(render("日本*語*です"), "日本<em>語</em>です");
+        // `_` is intraword between CJK letters too
+        assert_eq!(render("中_文_字"), "中_文_字");
+        // Fullwidth punctuation is punctuation: `**` after `「` opens
+        assert_eq!(render("「**強調**」"), "「<strong>強調</strong>」");
+        // ...but a closer after punctuation followed by a letter does not close
+        assert_eq!(render("x **中文。**字"), "x **中文。**字");
+    }
+
+    #[test]
+    fn test_emphasis_spans() {
+        let src = "x **bold** y\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let strong = tree.nodes().iter().find(|n| n.node_type == NodeType::Strong).unwrap();
+        let span = strong.span.as_ref().unwrap();
+        assert_eq!(&src[span.start.offset as usize..span.end.offset as usize], "**bold**");
+    }
+
+    #[test]
+    fn test_inline_spans_are_source_offsets() {
+        let src = "# T\n\nline one\nand https://x\n";
+        let tree = MarkdownParserV2::new(src).with_options(autolink()).parse().unwrap();
+        let link = tree
+            .nodes()
+            .iter()
+            .find(|n| n.node_type == NodeType::Link)
+            .unwrap();
+        let span = link.span.as_ref().unwrap();
+        assert_eq!(&src[span.start.offset as usize..span.end.offset as usize], "https://x");
+        assert_eq!((span.start.line, span.start.column), (4, 4));
+    }
+
+    #[test]
+    fn test_inline_footnotes() {
+        let src = "Claim^[See *Smith*, p. [4]] and more^[Second].\n";
+        let options = ParseOptions { footnotes: true, inline_footnotes: true, ..Default::default() };
+        let tree = MarkdownParserV2::new(src).with_options(options).parse().unwrap();
+        let node = |id: NodeId| tree.get_node(id).unwrap();
+        let field = |id: NodeId, key: &str| node(id).data.as_ref().unwrap()[key].clone();
+        let text = |id: NodeId| {
+            let span = node(id).span.unwrap();
+            &src[span.start.offset as usize..span.end.offset as usize]
+        };
+
+        let root = tree.children_of(tree.root_id());
+        let types: Vec<_> = root.iter().map(|&id| node(id).node_type.to_string()).collect();
+        assert_eq!(types, ["paragraph", "footnoteDefinition", "footnoteDefinition"]);
+
+        // References at the sites
+        let references: Vec<_> = tree.children_of(root[0])
+            .iter()
+            .copied()
+            .filter(|&id| node(id).node_type == NodeType::FootnoteReference)
+            .collect();
+        assert_eq!(references.len(), 2);
+        assert_eq!(text(references[0]), "^[See *Smith*, p. [4]]");
+        assert_eq!(field(references[0], "identifier"), "1");
+        assert_eq!(field(references[1], "identifier"), "2");
+
+        // Collected definitions, their content inline-parsed
+        let definition = root[1];
+        assert_eq!(field(definition, "identifier"), "1");
+        let paragraph = tree.children_of(definition)[0];
+        assert_eq!(text(paragraph), "See *Smith*, p. [4]");
+        let inlines: Vec<_> = tree.children_of(paragraph).iter().map(|&id| node(id).node_type.to_string()).collect();
+        assert_eq!(inlines, ["text", "emphasis", "text"]);
+        assert_eq!(field(tree.children_of(root[2])[0], "value"), "Second");
+
+        // Off by default, and needs both flags
+        assert_eq!(render("x ^[note]"), "x ^[note]");
+        let options = ParseOptions { footnotes: true, ..Default::default() };
+        let tree = MarkdownParserV2::new("x ^[note]").with_options(options).parse().unwrap();
+        assert_eq!(tree.node_count(), 3);
+    }
+
+    #[test]
+    fn test_soft_break_modes() {
+        let inlines = |soft_break: SoftBreak| {
+            let options = ParseOptions { soft_break, ..Default::default() };
+            let tree = MarkdownParserV2::new("first line \n second *line*\n").with_options(options).parse().unwrap();
+            let paragraph = tree.children_of(tree.root_id())[0];
+            tree.children_of(paragraph)
+                .iter()
+                .map(|&id| {
+                    let node = tree.get_node(id).unwrap();
+                    let value = node.data.as_ref().and_then(|d| d["value"].as_str()).unwrap_or("");
+                    format!("{}:{value}", node.node_type)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(inlines(SoftBreak::Newline), ["text:first line \n second ", "emphasis:"]);
+        assert_eq!(inlines(SoftBreak::Space), ["text:first line second ", "emphasis:"]);
+        assert_eq!(
+            inlines(SoftBreak::Break),
+            ["text:first line", "break:", "text:second ", "emphasis:"]
+        );
+        assert_eq!(inlines(SoftBreak::default()), inlines(SoftBreak::Newline));
+    }
+
+    #[test]
+    fn test_inline_link_destinations() {
+        let link = |url: &str| ("link".to_string(), url.to_string());
+        let text = |value: &str| ("text".to_string(), value.to_string());
+        let parse = |src| inline_types(src, ParseOptions::default());
+
+        assert_eq!(parse("[a](/foo(bar)baz)"), [link("/foo(bar)baz")]);
+        assert_eq!(parse("[b](<with space>) after"), [link("with space"), text(" after")]);
+        assert_eq!(parse("[c](un\\)balanced)"), [link("un)balanced")]);
+        assert_eq!(parse("[empty]( )"), [link("")]);
+
+        // Malformed destinations stay text
+        for src in ["[d](/foo(bar)", "[e](a b)", "[f](<a<)", "[g] (/url)"] {
+            assert_eq!(parse(src), [text(src)], "{src}");
+        }
+
+        let tree = MarkdownParserV2::new("x [*em* text](/u) y").parse().unwrap();
+        let paragraph = tree.children_of(tree.root_id())[0];
+        let link_id = tree.children_of(paragraph)[1];
+        let span = tree.get_node(link_id).unwrap().span.unwrap();
+        assert_eq!((span.start.offset, span.end.offset), (2, 17));
+        let children: Vec<_> = tree.children_of(link_id)
+            .iter()
+            .map(|&id| tree.get_node(id).unwrap().node_type.to_string())
+            .collect();
+        assert_eq!(children, ["emphasis", "text"]);
+    }
+
+    #[test]
+    fn test_strikethrough() {
+        assert_eq!(render("a ~~struck~~ b"), "a <del>struck</del> b");
+        assert_eq!(render("~~*em* and **strong**~~"), "<del><em>em</em> and <strong>strong</strong></del>");
+        assert_eq!(render("*a ~~b~~*"), "<em>a <del>b</del></em>");
+        assert_eq!(render("x~~y~~z"), "x<del>y</del>z");
+
+        // One or three tildes, and runs that don't flank, stay literal
+        for src in ["a ~b~ c", "x ~~~a~~~", "~~a~", "a ~~ b ~~", "~~a ~~b"] {
+            assert_eq!(render(src), src, "{src}");
+        }
+
+        let tree = MarkdownParserV2::new("x ~~y~~").parse().unwrap();
+        let delete = tree.nodes().iter().find(|n| n.node_type == NodeType::Delete).unwrap();
+        let span = delete.span.unwrap();
+        assert_eq!((span.start.offset, span.end.offset), (2, 7));
+    }
+
+    #[test]
+    fn test_inline_link_titles() {
+        let links = |src: &str| {
+            let tree = MarkdownParserV2::new(src).parse().unwrap();
+            tree.nodes()
+                .iter()
+                .filter(|n| n.node_type == NodeType::Link)
+                .map(|n| {
+                    let data = n.data.as_ref().unwrap();
+                    (data["url"].as_str().unwrap().to_string(), data.get("title").map(|t| t.to_string()))
+                })
+                .collect::<Vec<_>>()
+        };
+        let link = |url: &str, title: Option<&str>| (url.to_string(), title.map(|t| format!("{t:?}")));
+
+        assert_eq!(
+            links("[label](https://example.com \"title\")"),
+            [link("https://example.com", Some("title"))]
+        );
+        assert_eq!(links("[a](/u 'single' ) [b](/v (paren))"), [link("/u", Some("single")), link("/v", Some("paren"))]);
+        assert_eq!(links("[a](<my (url)> \"say \\\"hi\\\"\")"), [link("my (url)", Some("say \"hi\""))]);
+        assert_eq!(links("[a [nested] \\] b](/u)"), [link("/u", None)]);
+
+        // A missing `)` or an unclosed or malformed title leaves the text alone
+        for src in ["[a](/u \"title\"", "[a](/u \"title)", "[a](/u \"title\" x)", "[a](/u (a(b)))"] {
+            assert_eq!(links(src), [], "{src}");
+            assert_eq!(inline_types(src, ParseOptions::default()), [("text".to_string(), src.to_string())]);
+        }
+
+        let tree = MarkdownParserV2::new("[*a* b](/u \"t\")").parse().unwrap();
+        let link = tree.nodes().iter().find(|n| n.node_type == NodeType::Link).unwrap();
+        let span = link.span.unwrap();
+        assert_eq!((span.start.offset, span.end.offset), (0, 15));
+        assert_eq!(link.children.len(), 2);
+    }
+
+    #[test]
+    fn test_images() {
+        let src = "See ![a *b* `c`](path.png \"T\") and ![]() or ![ref][logo] [x ![i](/i)](/l)\n\n[logo]: /logo.svg\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let images: Vec<_> = tree.nodes().iter().filter(|n| n.node_type == NodeType::Image).collect();
+        let data = |i: usize| serde_json::to_value(images[i].data.as_ref().unwrap()).unwrap();
+
+        assert_eq!(images.len(), 4);
+        assert_eq!(data(0), serde_json::json!({ "url": "path.png", "title": "T", "alt": "a b c" }));
+        assert_eq!(data(1), serde_json::json!({ "url": "", "alt": "" }));
+        assert_eq!(
+            data(2),
+            serde_json::json!({ "url": "/logo.svg", "alt": "ref", "identifier": "logo", "label": "logo" })
+        );
+        assert!(images.iter().all(|image| image.children.is_empty()));
+        let span = images[0].span.unwrap();
+        assert_eq!(&src[span.start.offset as usize..span.end.offset as usize], "![a *b* `c`](path.png \"T\")");
+
+        // An image inside a link's text
+        let parent = tree.get_node(images[3].parent.unwrap()).unwrap();
+        assert_eq!(parent.node_type, NodeType::Link);
+        assert_eq!(parent.data.as_ref().unwrap()["url"], "/l");
+
+        // `!` without a link after it is text
+        assert_eq!(inline_types("wow! [x] !(y)", ParseOptions::default())[0].1, "wow! [x] !(y)");
+    }
+
+    #[test]
+    fn test_inline_html_elements() {
+        let options = || ParseOptions { inline_html: true, ..Default::default() };
+        let tree = MarkdownParserV2::new("Press <kbd>Ctrl</kbd> + <KBD>*C*</KBD>, H<sub>2</sub>O, <b>bold</b>")
+            .with_options(options())
+            .parse()
+            .unwrap();
+        let paragraph = tree.children_of(tree.root_id())[0];
+        let children: Vec<_> = tree.children_of(paragraph)
+            .iter()
+            .map(|&id| {
+                let node = tree.get_node(id).unwrap();
+                let data = node.data.as_ref().unwrap();
+                let text = data.get("tagName").or_else(|| data.get("value")).and_then(|v| v.as_str());
+                format!("{}:{}", node.node_type, text.unwrap_or_default())
+            })
+            .collect();
+        assert_eq!(children, [
+            "text:Press ",
+            "htmlElement:kbd",
+            "text: + ",
+            "htmlElement:kbd",
+            "text:, H",
+            "htmlElement:sub",
+            "text:O, ",
+            "html:<b>",
+            "text:bold",
+            "html:</b>",
+        ]);
+
+        let kbd = tree.get_node(tree.children_of(paragraph)[1]).unwrap();
+        assert_eq!(kbd.data.as_ref().unwrap()["attributes"], serde_json::json!({}));
+        let text = tree.get_node(kbd.children[0]).unwrap();
+        assert_eq!(text.node_type, NodeType::Text);
+        assert_eq!(text.data.as_ref().unwrap()["value"], "Ctrl");
+        let span = kbd.span.unwrap();
+        assert_eq!((span.start.offset, span.end.offset), (6, 21));
+        let second = tree.get_node(tree.children_of(paragraph)[3]).unwrap();
+        assert_eq!(tree.get_node(second.children[0]).unwrap().node_type, NodeType::Emphasis);
+
+        let tree = MarkdownParserV2::new("<abbr title=\"HyperText\">HTML</abbr> and <mark>open")
+            .with_options(options())
+            .parse()
+            .unwrap();
+        let abbr = tree.nodes().iter().find(|n| n.node_type == "htmlElement").unwrap();
+        assert_eq!(abbr.data.as_ref().unwrap()["attributes"], serde_json::json!({ "title": "HyperText" }));
+        assert!(tree.nodes().iter().any(|n| n.node_type == NodeType::Html));
+
+        // Off by default: every tag is raw html
+        let types: Vec<_> = inline_types("<kbd>C</kbd>", ParseOptions::default())
+            .into_iter()
+            .map(|(node_type, text)| format!("{node_type}:{text}"))
+            .collect();
+        assert_eq!(types, ["html:<kbd>", "text:C", "html:</kbd>"]);
+    }
+
+    #[test]
+    fn test_reference_links_match_normalized_labels() {
+        let src = "[FOO] and [see][ ÉLAN\n  Vital ] and [bar][]\n\n[foo]: /foo \"Foo\"\n[élan vital]: /elan\n";
+        let link = |url: &str| ("link".to_string(), url.to_string());
+        let text = |value: &str| ("text".to_string(), value.to_string());
+        assert_eq!(
+            inline_types(src, ParseOptions::default()),
+            [link("/foo"), text(" and "), link("/elan"), text(" and [bar][]")]
+        );
+
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let root = tree.children_of(tree.root_id());
+        let data = |id: NodeId| tree.get_node(id).unwrap().data.clone().unwrap();
+        let links: Vec<_> = tree.children_of(root[0])
+            .iter()
+            .copied()
+            .filter(|&id| tree.get_node(id).unwrap().node_type == NodeType::Link)
+            .collect();
+        assert_eq!(data(links[0])["title"], "Foo");
+        assert_eq!(data(links[0])["identifier"], "foo");
+        assert_eq!(data(links[1])["identifier"], "élan vital");
+        assert_eq!(data(links[1])["label"], " ÉLAN\n  Vital ");
+        let link_text = tree.children_of(links[1])[0];
+        assert_eq!(data(link_text)["value"], "see");
+
+        let definitions: Vec<_> = root[1..]
+            .iter()
+            .map(|&id| (tree.get_node(id).unwrap().node_type.clone(), data(id)["identifier"].clone()))
+            .collect();
+        assert_eq!(definitions, [
+            (NodeType::Definition, serde_json::json!("foo")),
+            (NodeType::Definition, serde_json::json!("élan vital")),
+        ]);
+    }
+
+    #[test]
+    fn test_unresolved_references() {
+        let src = "# Refs\n\nText [see][missing], [ok][], [Missing][] and [shortcut].\n\n[ok]: /ok\n";
+        let mut parser = MarkdownParserV2::new(src);
+        let tree = parser.parse().unwrap();
+
+        let unresolved: Vec<_> = parser
+            .unresolved_references()
+            .iter()
+            .map(|(label, span)| (label.as_str(), &src[span.start.offset as usize..span.end.offset as usize]))
+            .collect();
+        assert_eq!(unresolved, [("missing", "[see][missing]"), ("Missing", "[Missing][]")]);
+        let span = parser.unresolved_references()[0].1;
+        assert_eq!((span.start.line, span.start.column, span.end.column), (3, 5, 19));
+
+        let listed = &tree.root().data.as_ref().unwrap()["unresolvedReferences"];
+        assert_eq!(listed[0]["label"], "missing");
+        assert_eq!(listed[0]["span"]["start"]["offset"], 13);
+
+        let mut parser = MarkdownParserV2::new("[ok][x]\n\n[x]: /x\n");
+        assert!(parser.parse().unwrap().root().data.is_none());
+        assert!(parser.unresolved_references().is_empty());
+    }
+
+    struct Mention;
+
+    impl InlineMatcher for Mention {
+        fn triggers(&self) -> &[u8] {
+            b"@"
+        }
+
+        fn match_inline(&self, rest: &str, before: Option<char>) -> Option<(usize, Node)> {
+            if before.is_some_and(char::is_alphanumeric) {
+                return None;
+            }
+            let name = &rest[1..];
+            let len = name.find(|c: char| !c.is_alphanumeric() && c != '-').unwrap_or(name.len());
+            if len == 0 {
+                return None;
+            }
+            let mut data = HashMap::new();
+            data.insert("user".to_string(), serde_json::json!(&name[..len]));
+            Some((len + 1, Node::new(0, "mention").with_data(data)))
+        }
+    }
+
+    #[test]
+    fn test_custom_inline_matcher() {
+        let src = "# Hi
+
+Thanks *@ana-b*, mail x@y.z or \\@skip
+and @bo.
+";
+        let tree = MarkdownParserV2::new(src).with_inline_matcher(Mention).parse().unwrap();
+        let mentions: Vec<_> = tree.nodes().iter().filter(|n| n.node_type == "mention").collect();
+        assert_eq!(mentions.len(), 2);
+
+        let users: Vec<_> = mentions.iter().map(|n| n.data.as_ref().unwrap()["user"].clone()).collect();
+        assert_eq!(users, [serde_json::json!("ana-b"), serde_json::json!("bo")]);
+        let span = mentions[1].span.unwrap();
+        assert_eq!(&src[span.start.offset as usize..span.end.offset as usize], "@bo");
+        assert_eq!((span.start.line, span.start.column), (4, 4));
+        let parent = tree.get_node(mentions[0].parent.unwrap()).unwrap();
+        assert_eq!(parent.node_type, NodeType::Emphasis);
+
+        // Claims are dropped past the container or inside a character
+        struct Greedy(usize);
+        impl InlineMatcher for Greedy {
+            fn triggers(&self) -> &[u8] {
+                b"@"
+            }
+            fn match_inline(&self, _: &str, _: Option<char>) -> Option<(usize, Node)> {
+                Some((self.0, Node::new(0, "mention")))
+            }
+        }
+        for len in [100, 2] {
+            let tree = MarkdownParserV2::new("@é\n").with_inline_matcher(Greedy(len)).parse().unwrap();
+            assert!(tree.nodes().iter().all(|n| n.node_type != "mention"), "{len}");
+        }
+    }
+
+    #[test]
+    fn test_code_spans() {
+        let code = |value: &str| ("inlineCode".to_string(), value.to_string());
+        let text = |value: &str| ("text".to_string(), value.to_string());
+
+        let src = "a ``x`y`` and `*b*` then `` `c` `` ```d`` z\n";
+        assert_eq!(
+            inline_types(src, ParseOptions::default()),
+            [text("a "), code("x`y"), text(" and "), code("*b*"), text(" then "), code("`c`"), text(" ```d`` z")]
+        );
+
+        // Spans cover the backticks; line endings become spaces
+        let tree = MarkdownParserV2::new("x `a\nb` y\n").parse().unwrap();
+        let node = tree.nodes().iter().find(|n| n.node_type == NodeType::InlineCode).unwrap();
+        assert_eq!(node.data.as_ref().unwrap()["value"], "a b");
+        let span = node.span.unwrap();
+        assert_eq!((span.start.offset, span.end.offset), (2, 7));
+        assert_eq!((span.end.line, span.end.column), (2, 2));
+
+        // Only one space is stripped, and only from both ends
+        assert_eq!(inline_types("`  a  ` ` b` ` `", ParseOptions::default())[..1], [code(" a ")]);
+        assert_eq!(inline_types("` b`", ParseOptions::default()), [code(" b")]);
+        assert_eq!(inline_types("`  `", ParseOptions::default()), [code("  ")]);
+        assert_eq!(inline_types("\\`a`", ParseOptions::default()), [text("\\`a`")]);
+    }
+}
@@ -6,16 +6,69 @@
 //! ## API
 //!
 //! - `parse(markdown)` → Returns Tree object (compatible with JS API)
+//! - `parseWithOptions(markdown, options, cancel)` → Tree object, with parser options
+//!   (`escapeHtml`, `autolink`, `autolinkSchemes`, `gfmAutolink`, `inlineHtml`, `attributes`, `headingShift`,
+//!   `spans`, `columnUnit`, `softBreak`, `lazyBlockquotes`, `itemParagraphs`, `references`, `maxNodes`,
+//!   `footnotes`, `inlineFootnotes`, `contentIds`, `strict`), stopped early
+//!   through an optional shared-memory `cancel` flag
+//! - `parseFragment(markdown, baseOffset, options)` → Tree object for a
+//!   snippet of a larger document, with offsets based at `baseOffset`
+//! - `parseUntil(markdown, sentinel, options)` → Tree object for the blocks
+//!   before the first `sentinel` line, with the bytes consumed
+//! - `parseWithCodeParsers(markdown, parsers, options)` → Tree object, with
+//!   fenced code parsed by per-language callbacks
+//! - `parseWithInlineMatchers(markdown, matchers, options)` → Tree object,
+//!   with custom inline syntax claimed by callbacks
+//! - `parseEvents(markdown, callback, options, cancel)` → Calls `callback`
+//!   per node event (SAX-style) without building a tree
 //! - `parseBinary(markdown)` → Returns Uint8Array (maximum performance)
+//! - `validateBinary(buffer)` → Throws if a `parseBinary` buffer is
+//!   truncated or corrupt
 //! - `parseToJson(markdown)` → Returns JSON string
+//! - `parseToJsonPretty(markdown, options)` → Indented JSON with sorted
+//!   keys, stable across runs (for snapshots)
 //! - `parseCount(markdown)` → Returns node count (for benchmarking)
+//! - `parseCountExcludingRoot(markdown)` → Node count without the root
+//! - `headings(markdown)` → `[depth, text, offset][]` without building a tree
+//! - `readingStats(markdown, options)` → `{ words, characters,
+//!   readingTimeMinutes }` of the text content
+//! - `renderText(markdown, options)` → Plain text without markup, for
+//!   search indexes and previews
+//! - `benchmark(markdown)` → Phase timings (tokenize, block scan, tree build)
+//! - `stringify(tree, options)` → Markdown text from a Tree
+//!
+//! Rust hosts can use `MarkdownParserV2` directly, adding custom inline
+//! syntax with `with_inline_matcher` and an `InlineMatcher`.
+
+#![forbid(unsafe_code)]
 
+mod attributes;
+mod bench;
+mod events;
+mod frontmatter;
+mod html;
+mod inline;
 mod parser_v2;
+mod reference;
+mod stats;
+mod stringify;
+mod text;
+
+pub use inline::InlineMatcher;
+pub use parser_v2::{ItemParagraphs, MarkdownParserV2, ParseOptions, References, SoftBreak, SpanMode};
 
-use parser_v2::MarkdownParserV2;
-use synth_wasm_core::Tree;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use synth_wasm_core::{Node, SynthError, SynthResult, Tree};
 use wasm_bindgen::prelude::*;
 
+use stats::ReadingOptions;
+use stringify::StringifyOptions;
+use text::TextOptions;
+
 /// Parse Markdown text into an AST Tree
 ///
 /// # Example (JavaScript)
@@ -30,7 +83,326 @@ pub fn parse(markdown: &str) -> Result<Tree, JsValue> {
     let mut parser = MarkdownParserV2::new(markdown);
     parser
         .parse()
-        .map_err(|e| JsValue::from_str(&e.to_string()))
+        .map_err(JsValue::from)
+}
+
+/// Parse Markdown text into an AST Tree with options
+///
+/// Options (all optional):
+/// - `escapeHtml`: store HTML-escaped text in `value`, source text in `raw`
+/// - `autolink`: turn bare URLs into `link` nodes
+/// - `autolinkSchemes`: schemes `autolink` may linkify
+///   (default `["http", "https", "mailto"]`)
+/// - `gfmAutolink`: link GFM literals: `http(s)://` URLs, `www.` domains and
+///   email addresses
+/// - `inlineHtml`: parse `<kbd>`, `<sub>`, `<sup>`, `<mark>` and `<abbr>`
+///   into `htmlElement` nodes with `tagName`, `attributes` and children
+/// - `attributes`: parse a trailing `{#id .class key=value}` on ATX
+///   headings into `data.id`, `data.classes` and `data.attributes`
+/// - `columnUnit`: unit of span columns, `"utf16"` (default, as LSP),
+///   `"char"` or `"byte"`
+/// - `lazyBlockquotes`: let a line without `>` continue a quoted
+///   paragraph (default `true`, as CommonMark)
+/// - `itemParagraphs`: which list items hold their text in a `paragraph`:
+///   `"loose"` (default, items of spread lists), `"always"` or `"never"`
+/// - `references`: when link reference definitions are found: `"prescan"`
+///   (default) scans the whole source for them first, so every reference
+///   resolves; `"eager"` learns them as parsing reaches them, leaving a
+///   reference before its definition as text and listing it, with the
+///   definition's `url` and `title`, in the root's `data.lateReferences`
+///
+/// References to undefined labels (`[text][label]`, `[label][]`) are
+/// listed as `{ label, span }` in the root's `data.unresolvedReferences`.
+///
+/// `cancel` is an optional `Int32Array` over a `SharedArrayBuffer`,
+/// checked every few blocks: once another thread stores a non-zero first
+/// element, parsing stops and the partial tree's root gets
+/// `data.cancelled`.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { parseWithOptions } from '@sylphx/synth-wasm-md';
+///
+/// const tree = parseWithOptions('<b>hi</b>', { escapeHtml: true });
+///
+/// // In a worker; the page calls `Atomics.store(cancel, 0, 1)` to stop it
+/// const cancel = new Int32Array(sharedBuffer);
+/// const partial = parseWithOptions(hugeDocument, {}, cancel);
+/// ```
+#[wasm_bindgen(js_name = parseWithOptions)]
+pub fn parse_with_options(markdown: &str, options: JsValue, cancel: Option<js_sys::Int32Array>) -> Result<Tree, JsValue> {
+    let options: ParseOptions = options_from_js(options)?;
+    let mut parser = MarkdownParserV2::new(markdown).with_options(options);
+    if let Some(cancel) = &cancel {
+        parser = parser.with_cancel_flag(cancel);
+    }
+    parser
+        .parse()
+        .map_err(JsValue::from)
+}
+
+/// Parse a Markdown snippet taken from a larger document at `baseOffset`
+///
+/// Span offsets count from the start of the larger document; lines and
+/// columns stay relative to the snippet. Takes the same options as
+/// `parseWithOptions`.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { parseFragment } from '@sylphx/synth-wasm-md';
+///
+/// const cell = parseFragment(doc.slice(start, end), start);
+/// ```
+#[wasm_bindgen(js_name = parseFragment)]
+pub fn parse_fragment(markdown: &str, base_offset: u32, options: JsValue) -> Result<Tree, JsValue> {
+    let options: ParseOptions = options_from_js(options)?;
+    let mut parser = MarkdownParserV2::new(markdown).with_options(options);
+    parser
+        .parse_fragment(base_offset)
+        .map_err(JsValue::from)
+}
+
+/// Parse Markdown up to the first line that is `sentinel`
+///
+/// The sentinel line and everything after it are left unparsed, even
+/// inside a paragraph or code block; trailing whitespace on a line is
+/// ignored. The root's `data.consumed` is the byte offset where parsing
+/// stopped (the source length when no line matched), so a host can carry
+/// on from there. Takes the same options as `parseWithOptions`.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { parseUntil } from '@sylphx/synth-wasm-md';
+///
+/// const { nodes } = parseUntil(doc, ':::').toJSON();
+/// const rest = bytes.subarray(nodes[0].data.consumed);
+/// ```
+#[wasm_bindgen(js_name = parseUntil)]
+pub fn parse_until(markdown: &str, sentinel: &str, options: JsValue) -> Result<Tree, JsValue> {
+    let options: ParseOptions = options_from_js(options)?;
+    Ok(parse_until_sentinel(markdown, sentinel, options)?)
+}
+
+/// Parse Markdown up to the first `sentinel` line (see `parseUntil`)
+fn parse_until_sentinel(markdown: &str, sentinel: &str, options: ParseOptions) -> SynthResult<Tree> {
+    let mut parser = MarkdownParserV2::new(markdown).with_options(options);
+    let mut tree = parser.parse_until(|line| line.trim_end() == sentinel)?;
+    let root = tree.root_id();
+    tree.get_node_mut(root)?
+        .data
+        .get_or_insert_with(HashMap::new)
+        .insert("consumed".to_string(), serde_json::json!(parser.consumed_len()));
+    Ok(tree)
+}
+
+/// Parse Markdown text, parsing fenced code with the callback registered
+/// for its language
+///
+/// `parsers` maps a language (the first word of the info string) to a
+/// function taking the code and returning a tree as JSON (`tree.toJSON()`).
+/// That tree, root included, becomes the child of the `code` node; its
+/// spans are relative to the code. A callback that throws leaves the
+/// block without children, or fails the parse with `strict`. Takes the same options as
+/// `parseWithOptions`.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { parseWithCodeParsers } from '@sylphx/synth-wasm-md';
+/// import { parseTree } from '@sylphx/synth-wasm-js';
+///
+/// const tree = parseWithCodeParsers(markdown, {
+///   js: (code) => parseTree(code).toJSON(),
+/// });
+/// ```
+#[wasm_bindgen(js_name = parseWithCodeParsers)]
+pub fn parse_with_code_parsers(markdown: &str, parsers: &js_sys::Object, options: JsValue) -> Result<Tree, JsValue> {
+    let options: ParseOptions = options_from_js(options)?;
+    let mut parser = MarkdownParserV2::new(markdown).with_options(options);
+    for entry in js_sys::Object::entries(parsers).iter() {
+        let entry: js_sys::Array = entry.unchecked_into();
+        let (Some(lang), Ok(callback)) = (entry.get(0).as_string(), entry.get(1).dyn_into::<js_sys::Function>()) else {
+            return Err(JsValue::from_str("parsers must map languages to functions"));
+        };
+        parser = parser.with_code_parser(&lang, move |code| {
+            let json = callback
+                .call1(&JsValue::NULL, &JsValue::from_str(code))
+                .map_err(|e| SynthError::ParseError(e.as_string().unwrap_or_else(|| format!("{e:?}"))))?;
+            serde_wasm_bindgen::from_value(json).map_err(|e| SynthError::ParseError(e.to_string()))
+        });
+    }
+    parser
+        .parse()
+        .map_err(JsValue::from)
+}
+
+/// Parse Markdown text, letting callbacks claim custom inline syntax
+///
+/// `matchers` is an array of `{ triggers, match }`: `triggers` is a string
+/// of the ASCII characters a match can start with, and `match(rest,
+/// before)` gets the container's text from such a character on and the
+/// character before it (`undefined` at the start). It returns `null` to
+/// pass, or `{ length, type, data }` to claim the first `length` UTF-16
+/// units of `rest` as a node of `type` with optional `data`. Matchers are
+/// tried in order, before the built-in inline syntax; a claim past the
+/// container's text is dropped. An exception thrown by a callback fails
+/// the parse. Takes the same options as `parseWithOptions`.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { parseWithInlineMatchers } from '@sylphx/synth-wasm-md';
+///
+/// const tree = parseWithInlineMatchers(markdown, [{
+///   triggers: '@',
+///   match: (rest, before) => {
+///     const name = /^@([\w-]+)/.exec(rest);
+///     if (!name || /\w/.test(before ?? '')) return null;
+///     return { length: name[0].length, type: 'mention', data: { user: name[1] } };
+///   },
+/// }]);
+/// ```
+#[wasm_bindgen(js_name = parseWithInlineMatchers)]
+pub fn parse_with_inline_matchers(markdown: &str, matchers: &js_sys::Array, options: JsValue) -> Result<Tree, JsValue> {
+    let options: ParseOptions = options_from_js(options)?;
+    let error = RefCell::new(None);
+    let mut parser = MarkdownParserV2::new(markdown).with_options(options);
+    for matcher in matchers.iter() {
+        let field = |name: &str| js_sys::Reflect::get(&matcher, &JsValue::from_str(name)).ok();
+        let (Some(triggers), Some(callback)) = (
+            field("triggers").and_then(|t| t.as_string()),
+            field("match").and_then(|m| m.dyn_into::<js_sys::Function>().ok()),
+        ) else {
+            return Err(JsValue::from_str("matchers must be { triggers, match } objects"));
+        };
+        parser = parser.with_inline_matcher(JsInlineMatcher { triggers: triggers.into_bytes(), callback, error: &error });
+    }
+    let tree = parser.parse().map_err(JsValue::from)?;
+    error.take().map_or(Ok(tree), Err)
+}
+
+/// An inline matcher calling a JS function (see `parseWithInlineMatchers`)
+struct JsInlineMatcher<'e> {
+    triggers: Vec<u8>,
+    callback: js_sys::Function,
+    /// First exception thrown by a callback, rethrown after the parse
+    error: &'e RefCell<Option<JsValue>>,
+}
+
+/// What a JS matcher claims
+#[derive(Deserialize)]
+struct InlineClaim {
+    /// UTF-16 code units, as JS counts string length
+    length: usize,
+    #[serde(rename = "type")]
+    node_type: String,
+    #[serde(default)]
+    data: Option<HashMap<String, serde_json::Value>>,
+}
+
+impl InlineMatcher for JsInlineMatcher<'_> {
+    fn triggers(&self) -> &[u8] {
+        &self.triggers
+    }
+
+    fn match_inline(&self, rest: &str, before: Option<char>) -> Option<(usize, Node)> {
+        if self.error.borrow().is_some() {
+            return None;
+        }
+        let before = before.map_or(JsValue::UNDEFINED, |c| JsValue::from_str(c.encode_utf8(&mut [0; 4])));
+        let claim = self
+            .callback
+            .call2(&JsValue::NULL, &JsValue::from_str(rest), &before)
+            .and_then(|claim| {
+                if claim.is_null() || claim.is_undefined() {
+                    return Ok(None);
+                }
+                serde_wasm_bindgen::from_value::<InlineClaim>(claim)
+                    .map(Some)
+                    .map_err(|e| JsValue::from_str(&e.to_string()))
+            });
+        let claim = match claim {
+            Ok(claim) => claim?,
+            Err(e) => {
+                *self.error.borrow_mut() = Some(e);
+                return None;
+            }
+        };
+        let node = Node::new(0, claim.node_type.as_str());
+        let node = match claim.data {
+            Some(data) => node.with_data(data),
+            None => node,
+        };
+        Some((utf16_byte_len(rest, claim.length)?, node))
+    }
+}
+
+/// Bytes in the first `units` UTF-16 code units of `text`, unless that
+/// ends inside a character or past the end
+fn utf16_byte_len(text: &str, units: usize) -> Option<usize> {
+    let mut counted = 0;
+    for (offset, c) in text.char_indices() {
+        if counted >= units {
+            return (counted == units).then_some(offset);
+        }
+        counted += c.len_utf16();
+    }
+    (counted == units).then_some(text.len())
+}
+
+/// Parse Markdown text, calling `callback` with each node event instead of
+/// building a tree
+///
+/// Events come in document order: `{ event: "enterBlock", type, span, data }`
+/// and `{ event: "exitBlock", type }` around every node except text, and
+/// `{ event: "text", value, span }` for text. Only the block being parsed
+/// is kept in memory. Takes the same options as `parseWithOptions`; an
+/// exception thrown by `callback` stops parsing and is rethrown.
+///
+/// With the default `references: "prescan"`, the first event waits for a
+/// scan of the whole source for link definitions. With `"eager"` events
+/// start at once; references met before their definition come out as
+/// text, and `{ event: "lateReferences", references }` before the root's
+/// `exitBlock` lists them (`{ label, span, url, title }`) for fixing up.
+///
+/// `cancel` works as in `parseWithOptions`; a cancelled parse ends the
+/// stream early.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { parseEvents } from '@sylphx/synth-wasm-md';
+///
+/// parseEvents(hugeDocument, (e) => {
+///   if (e.event === 'enterBlock' && e.type === 'heading') count++;
+/// });
+/// ```
+#[wasm_bindgen(js_name = parseEvents)]
+pub fn parse_events(
+    markdown: &str,
+    callback: &js_sys::Function,
+    options: JsValue,
+    cancel: Option<js_sys::Int32Array>,
+) -> Result<(), JsValue> {
+    let options: ParseOptions = options_from_js(options)?;
+    let mut error = None;
+    let mut parser = MarkdownParserV2::new(markdown).with_options(options);
+    if let Some(cancel) = &cancel {
+        parser = parser.with_cancel_flag(cancel);
+    }
+    parser
+        .parse_events(|event| {
+            if error.is_some() {
+                return;
+            }
+            // Plain objects for `data`, not `Map`s
+            let result = event
+                .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+                .map_err(|e| JsValue::from_str(&e.to_string()))
+                .and_then(|event| callback.call1(&JsValue::NULL, &event));
+            if let Err(e) = result {
+                error = Some(e);
+            }
+        })
+        .map_err(JsValue::from)?;
+    error.map_or(Ok(()), Err)
 }
 
 /// Parse Markdown text directly to JSON string
@@ -50,10 +422,32 @@ pub fn parse_to_json(markdown: &str) -> Result<String, JsValue> {
     let mut parser = MarkdownParserV2::new(markdown);
     let tree = parser
         .parse()
-        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        .map_err(JsValue::from)?;
     serde_json::to_string(&tree).map_err(|e| JsValue::from_str(&e.to_string()))
 }
 
+/// Parse Markdown text to indented JSON with sorted keys and without
+/// timestamps, so the same input always gives the same text
+///
+/// Meant for debugging and fixtures; takes the same options as
+/// `parseWithOptions`.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { parseToJsonPretty } from '@sylphx/synth-wasm-md';
+///
+/// writeFileSync('fixture.json', parseToJsonPretty(markdown));
+/// ```
+#[wasm_bindgen(js_name = parseToJsonPretty)]
+pub fn parse_to_json_pretty(markdown: &str, options: JsValue) -> Result<String, JsValue> {
+    let options: ParseOptions = options_from_js(options)?;
+    let tree = MarkdownParserV2::new(markdown)
+        .with_options(options)
+        .parse()
+        .map_err(JsValue::from)?;
+    Ok(tree.to_json_pretty()?)
+}
+
 /// Parse Markdown to compact binary format (maximum performance)
 ///
 /// Returns a Uint8Array containing the binary tree structure.
@@ -62,14 +456,18 @@ pub fn parse_to_json(markdown: &str) -> Result<String, JsValue> {
 /// Binary format:
 /// - Header: [node_count: u32, source_len: u32]
 /// - Nodes: 24 bytes each
-///   - node_type: u8 (1=heading, 2=para, 3=code, 4=hr, 5=quote, 6=list)
-///   - flags: u8 (depth for heading, ordered/checked for list)
+///   - node_type: u8 (1=heading, 2=para, 3=code, 4=hr, 5=quote, 6=list, 7=table, 8=html,
+///     9=frontmatter)
+///   - flags: u8 (depth for heading, ordered/checked for list, columns for table,
+///     block kind 1-7 for html, 0=yaml/1=toml/2=json for frontmatter)
 ///   - _pad: [u8; 2]
 ///   - parent: u32
 ///   - text_start: u32
 ///   - text_len: u32
-///   - span_start: u32
-///   - span_end: u32
+///   - span_start: u32 (byte offset, as in the tree's spans)
+///   - span_end: u32 (byte offset, exclusive)
+///
+/// The layout's version is `binaryFormatVersion()`.
 ///
 /// # Example (JavaScript)
 /// ```javascript
@@ -85,17 +483,177 @@ pub fn parse_binary(markdown: &str) -> Vec<u8> {
     parser.parse_binary()
 }
 
+/// Check a `parseBinary` buffer before decoding it
+///
+/// Throws a `SerializationError` naming the first problem: a buffer
+/// shorter than its header declares, a parent pointer that isn't an
+/// earlier node, or a text range past the end of the source.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { validateBinary } from '@sylphx/synth-wasm-md';
+///
+/// validateBinary(buffer); // throws on a truncated or corrupt buffer
+/// const view = new DataView(buffer.buffer);
+/// ```
+#[wasm_bindgen(js_name = validateBinary)]
+pub fn validate_binary(buffer: &[u8]) -> Result<(), JsValue> {
+    parser_v2::validate_binary(buffer).map_err(JsValue::from)
+}
+
 /// Count nodes in parsed markdown (for benchmarking)
 ///
 /// This measures pure parsing performance without any serialization overhead.
+/// The count is the size of the Tree `parse` returns, root included, like
+/// the JS `parseCount` includes its `Program` node.
 #[wasm_bindgen(js_name = parseCount)]
 pub fn parse_count(markdown: &str) -> usize {
     let mut parser = MarkdownParserV2::new(markdown);
     parser.parse_count()
 }
 
+/// Count nodes in parsed markdown, not counting the root
+#[wasm_bindgen(js_name = parseCountExcludingRoot)]
+pub fn parse_count_excluding_root(markdown: &str) -> usize {
+    let mut parser = MarkdownParserV2::new(markdown);
+    parser.parse_count_excluding_root()
+}
+
+/// Collect headings without building a tree (e.g. for a table of contents)
+///
+/// Returns an array of `[depth, text, offset]` entries in document order.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { headings } from '@sylphx/synth-wasm-md';
+///
+/// for (const [depth, text, offset] of headings(source)) { ... }
+/// ```
+#[wasm_bindgen]
+pub fn headings(markdown: &str) -> Result<JsValue, JsValue> {
+    let mut parser = MarkdownParserV2::new(markdown);
+    serde_wasm_bindgen::to_value(&parser.headings()).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Word and character counts, and reading time, of the text content
+///
+/// Code blocks and markup aren't counted. Options (all optional):
+/// - `wordsPerMinute`: reading speed (default 200)
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { readingStats } from '@sylphx/synth-wasm-md';
+///
+/// const { words, readingTimeMinutes } = readingStats(post);
+/// ```
+#[wasm_bindgen(js_name = readingStats)]
+pub fn reading_stats(markdown: &str, options: JsValue) -> Result<JsValue, JsValue> {
+    let options: ReadingOptions = options_from_js(options)?;
+    let stats = stats::reading_stats(markdown, &options).map_err(JsValue::from)?;
+    serde_wasm_bindgen::to_value(&stats).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Plain text content, with all markup removed
+///
+/// Blocks are separated by a blank line, list items and table rows by a
+/// line break. Options (all optional):
+/// - `includeCode`: keep the content of code blocks (default false)
+/// - `linkUrls`: render links as `text (url)` (default false)
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { renderText } from '@sylphx/synth-wasm-md';
+///
+/// const snippet = renderText(post, { linkUrls: true }).slice(0, 200);
+/// ```
+#[wasm_bindgen(js_name = renderText)]
+pub fn render_text(markdown: &str, options: JsValue) -> Result<String, JsValue> {
+    let options: TextOptions = options_from_js(options)?;
+    text::render_text(markdown, &options).map_err(JsValue::from)
+}
+
+/// Time the parser's phases on `markdown` (for maintainers and profiling)
+///
+/// Returns `{ tokenizeMs, blockScanMs, buildMs, totalMs, nodeCount }`.
+/// Each phase time is the extra cost over the phase before it, so the
+/// three add up to roughly `totalMs`, the time of a full `parse`.
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { benchmark } from '@sylphx/synth-wasm-md';
+///
+/// const { blockScanMs, buildMs } = benchmark(largeDocument);
+/// ```
+#[wasm_bindgen]
+pub fn benchmark(markdown: &str) -> Result<JsValue, JsValue> {
+    serde_wasm_bindgen::to_value(&bench::benchmark(markdown))
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Stringify a Tree back to Markdown
+///
+/// Options (all optional):
+/// - `minifyWhitespace`: canonical output (collapsed blank lines, no
+///   trailing spaces except hard breaks, `-` bullets, `N.` ordered markers)
+///
+/// # Example (JavaScript)
+/// ```javascript
+/// import { parse, stringify } from '@sylphx/synth-wasm-md';
+///
+/// const formatted = stringify(parse(source), { minifyWhitespace: true });
+/// ```
+#[wasm_bindgen]
+pub fn stringify(tree: &Tree, options: JsValue) -> Result<String, JsValue> {
+    let options: StringifyOptions = options_from_js(options)?;
+    Ok(stringify::stringify(tree, &options))
+}
+
+/// Deserialize an optional JS options object
+fn options_from_js<T: DeserializeOwned + Default>(options: JsValue) -> Result<T, JsValue> {
+    if options.is_undefined() || options.is_null() {
+        return Ok(T::default());
+    }
+    serde_wasm_bindgen::from_value(options).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Version of the `parseBinary` layout, bumped on incompatible changes
+///
+/// 2: `span_start`/`span_end` are byte offsets (they were line numbers).
+#[wasm_bindgen(js_name = binaryFormatVersion)]
+pub fn binary_format_version() -> u32 {
+    parser_v2::BINARY_FORMAT_VERSION
+}
+
 /// Get the version of the Markdown parser
 #[wasm_bindgen]
 pub fn version() -> String {
     env!("CARGO_PKG_VERSION").to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_until_records_consumed() {
+        let src = "# Doc\n\ntext\n:::  \nafter\n";
+        let tree = parse_until_sentinel(src, ":::", ParseOptions::default()).unwrap();
+        assert_eq!(tree.root().data.as_ref().unwrap()["consumed"], src.find(":::").unwrap());
+        assert_eq!(tree.children_of(tree.root_id()).len(), 2);
+
+        let tree = parse_until_sentinel(src, "missing", ParseOptions::default()).unwrap();
+        assert_eq!(tree.root().data.as_ref().unwrap()["consumed"], src.len());
+    }
+
+    #[test]
+    fn test_utf16_byte_len() {
+        let text = "a😀é";
+        assert_eq!(utf16_byte_len(text, 0), Some(0));
+        assert_eq!(utf16_byte_len(text, 1), Some(1));
+        assert_eq!(utf16_byte_len(text, 3), Some(5));
+        assert_eq!(utf16_byte_len(text, 4), Some(7));
+        // Inside the surrogate pair, past the end
+        assert_eq!(utf16_byte_len(text, 2), None);
+        assert_eq!(utf16_byte_len(text, 5), None);
+    }
+}
@@ -8,9 +8,18 @@
 //!
 //! Performance: ~10-15x faster than pure JS
 
+use crate::attributes::{self, Attributes};
+use crate::events::{self, Event};
+use crate::frontmatter::{self, Frontmatter};
+use crate::html;
+use crate::inline::{InlineMatcher, InlineScanner};
+use crate::reference::{self, Definition, LateReference, UndefinedReferences};
 use memchr::memchr;
+use serde::Deserialize;
+use std::borrow::Cow;
 use std::collections::HashMap;
-use synth_wasm_core::{Node, Span, SynthResult, Tree};
+use std::ops::Range;
+use synth_wasm_core::{CancelSignal, ColumnUnit, Node, NodeId, NodeType, Span, SynthError, SynthResult, Tree};
 
 /// Node type constants
 pub mod node_type {
@@ -21,31 +30,431 @@ pub mod node_type {
     pub const THEMATIC_BREAK: u8 = 4;
     pub const BLOCKQUOTE: u8 = 5;
     pub const LIST_ITEM: u8 = 6;
+    pub const TABLE: u8 = 7;
+    pub const HTML: u8 = 8;
+    pub const FRONTMATTER: u8 = 9;
+}
+
+/// Parser options (camelCase when passed from JavaScript)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ParseOptions {
+    /// Store HTML-escaped text in `value`, keeping the source text in `raw`
+    pub escape_html: bool,
+    /// Turn bare `scheme:...` URLs into `link` nodes
+    pub autolink: bool,
+    /// URL schemes `autolink` may linkify; others stay text
+    pub autolink_schemes: Vec<String>,
+    /// GFM autolink literals: `http://` and `https://` URLs, `www.`
+    /// domains (linked with `http://`) and email addresses (linked with
+    /// `mailto:`), each needing a domain with a `.`
+    pub gfm_autolink: bool,
+    /// Parse `<kbd>`, `<sub>`, `<sup>`, `<mark>` and `<abbr>` with a
+    /// matching close tag into `htmlElement` nodes (`tagName`,
+    /// `attributes`, inline children) instead of raw inline `html`
+    pub inline_html: bool,
+    /// Split a trailing `{#id .class key=value}` off ATX heading text into
+    /// `id`, `classes` and `attributes` in the heading's `data`
+    pub attributes: bool,
+    /// Added to every heading depth (clamped to 1–6); a heading whose
+    /// depth changes keeps its source depth in `originalDepth`
+    pub heading_shift: i8,
+    /// Which `Position` fields spans fill in
+    pub spans: SpanMode,
+    /// Unit of span columns (`"byte"`, `"utf16"` or `"char"`)
+    pub column_unit: ColumnUnit,
+    /// How line breaks inside a paragraph appear in its inline children
+    pub soft_break: SoftBreak,
+    /// Let a line without `>` continue a blockquote's paragraph (CommonMark
+    /// lazy continuation); off, every quoted line needs its `>`
+    pub lazy_blockquotes: bool,
+    /// Which list items hold their text in a `paragraph` child
+    pub item_paragraphs: ItemParagraphs,
+    /// When link reference definitions are known to the references using
+    /// them
+    pub references: References,
+    /// Stop building the tree once it has more nodes than this
+    ///
+    /// Checked after each top-level block; the partial tree gets
+    /// `truncated: true` on the root and a diagnostic is recorded.
+    pub max_nodes: Option<usize>,
+    /// Enable footnote syntax
+    pub footnotes: bool,
+    /// With `footnotes`, parse pandoc-style inline notes `^[text]` into a
+    /// `footnoteReference` in place plus a `footnoteDefinition` (its text
+    /// in a paragraph) appended to the root
+    pub inline_footnotes: bool,
+    /// Put a content-addressed `contentId` (see `Tree::content_ids`) in
+    /// each node's `data`, stable across parses of the same content
+    pub content_ids: bool,
+    /// Fail instead of recovering from malformed input (e.g. a code fence
+    /// that is never closed, or a reference link whose label has no
+    /// definition); `parse` returns the first problem as an error
+    pub strict: bool,
+    /// Offset of the source within a larger document, set by
+    /// `parse_fragment`; `None` when the source is a whole document
+    #[serde(skip)]
+    pub(crate) base_offset: Option<u32>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            escape_html: false,
+            autolink: false,
+            autolink_schemes: vec!["http".into(), "https".into(), "mailto".into()],
+            gfm_autolink: false,
+            inline_html: false,
+            attributes: false,
+            heading_shift: 0,
+            spans: SpanMode::default(),
+            column_unit: ColumnUnit::default(),
+            soft_break: SoftBreak::default(),
+            lazy_blockquotes: true,
+            item_paragraphs: ItemParagraphs::default(),
+            references: References::default(),
+            max_nodes: None,
+            footnotes: false,
+            inline_footnotes: false,
+            content_ids: false,
+            strict: false,
+            base_offset: None,
+        }
+    }
+}
+
+/// A problem found while parsing (byte offsets into the source)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub start: u32,
+    pub end: u32,
+}
+
+/// Span model of tree nodes
+///
+/// Every span is a half-open byte range `start.offset..end.offset` into
+/// the source; the end excludes the block's trailing newline. In
+/// `LineColumn` mode each position also has its 1-indexed `line` and
+/// 0-indexed `column` (in `ParseOptions::column_unit`, UTF-16 code units
+/// by default), both describing that same offset. Binary output's
+/// `span_start`/`span_end` are the same byte offsets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SpanMode {
+    /// Line, column and offset
+    #[default]
+    LineColumn,
+    /// Byte offsets only; lines and columns are 0
+    Offset,
+}
+
+/// Representation of a line break inside a paragraph's inline content
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SoftBreak {
+    /// Keep the `\n` in the text node's value
+    #[default]
+    Newline,
+    /// Join the lines with a single space
+    Space,
+    /// Split the text, with a `break` node between the lines
+    Break,
+}
+
+/// Which list items wrap their inline content in a `paragraph`
+///
+/// Without a paragraph the inline nodes are the item's first children,
+/// before any nested list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ItemParagraphs {
+    /// Items of loose (`spread`) lists, as they render with `<p>`
+    #[default]
+    Loose,
+    /// Every item, for a uniform structure
+    Always,
+    /// No item
+    Never,
+}
+
+/// When link reference definitions are found, which decides what a
+/// reference before its definition becomes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum References {
+    /// Collect every definition in one scan of the source before parsing,
+    /// so all references resolve; `parse_events` reports nothing until
+    /// that scan is done
+    #[default]
+    Prescan,
+    /// Learn definitions as parsing reaches them, in a single pass: a
+    /// reference before its definition stays text and is listed as a
+    /// `LateReference` once parsing ends (`lateReferences` on the root,
+    /// or a `lateReferences` event after the last block)
+    Eager,
+}
+
+/// Remove up to `indent` leading spaces (the opening fence's indentation)
+/// from each code line, keeping any further indentation as is
+fn strip_fence_indent(code: &str, indent: usize) -> Cow<'_, str> {
+    if indent == 0 {
+        return Cow::Borrowed(code);
+    }
+    let mut out = String::with_capacity(code.len());
+    for line in code.split_inclusive('\n') {
+        let spaces = line.bytes().take(indent).take_while(|&b| b == b' ').count();
+        out.push_str(&line[spaces..]);
+    }
+    Cow::Owned(out)
+}
+
+/// Copy the node `id` of `sub` and its descendants into `tree`,
+/// returning the copy (not yet attached)
+fn graft(tree: &mut Tree, sub: &Tree, id: NodeId) -> SynthResult<NodeId> {
+    let node = sub.get_node(id)?;
+    let copy = tree.add_node(Node {
+        parent: None,
+        children: Vec::new(),
+        ..node.clone()
+    });
+    let mut stack: Vec<_> = node.children.iter().rev().map(|&child| (child, copy)).collect();
+    while let Some((old_id, new_parent)) = stack.pop() {
+        let node = sub.get_node(old_id)?;
+        let id = tree.add_node(Node {
+            parent: None,
+            children: Vec::new(),
+            ..node.clone()
+        });
+        tree.add_child(new_parent, id)?;
+        stack.extend(node.children.iter().rev().map(|&child| (child, id)));
+    }
+    Ok(copy)
+}
+
+/// Set a boolean `data` flag on a node
+fn set_flag(tree: &mut Tree, id: NodeId, key: &str) -> SynthResult<()> {
+    tree.get_node_mut(id)?
+        .data
+        .get_or_insert_with(HashMap::new)
+        .insert(key.to_string(), serde_json::json!(true));
+    Ok(())
+}
+
+/// Escape `&`, `<`, `>` and `"` for embedding in HTML
+pub fn escape_html(text: &str) -> Cow<'_, str> {
+    if !text.bytes().any(|b| matches!(b, b'&' | b'<' | b'>' | b'"')) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut out = String::with_capacity(text.len() + 16);
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// GFM table column alignment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    None,
+    Left,
+    Right,
+    Center,
+}
+
+impl Align {
+    /// Parse a delimiter row cell (`---`, `:--`, `--:`, `:-:`)
+    fn from_delimiter(cell: &str) -> Option<Self> {
+        let left = cell.starts_with(':');
+        let right = cell.len() > 1 && cell.ends_with(':');
+        let dashes = &cell[left as usize..cell.len() - right as usize];
+        if dashes.is_empty() || !dashes.bytes().all(|b| b == b'-') {
+            return None;
+        }
+        Some(match (left, right) {
+            (true, true) => Align::Center,
+            (true, false) => Align::Left,
+            (false, true) => Align::Right,
+            (false, false) => Align::None,
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Align::None => "none",
+            Align::Left => "left",
+            Align::Right => "right",
+            Align::Center => "center",
+        }
+    }
+}
+
+/// Whether `line` has a `|` that isn't backslash-escaped, as a table
+/// header row needs
+fn has_unescaped_pipe(line: &str) -> bool {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'|' => return true,
+            _ => {}
+        }
+        i += 1;
+    }
+    false
+}
+
+/// Split a table row into trimmed cells, honoring optional outer pipes
+/// and escaped `\|`.
+fn split_table_row(line: &str) -> Vec<&str> {
+    let mut row = line.trim();
+    if let Some(rest) = row.strip_prefix('|') {
+        row = rest;
+    }
+    if row.ends_with('|') && !row.ends_with("\\|") {
+        row = &row[..row.len() - 1];
+    }
+
+    let bytes = row.as_bytes();
+    let mut cells = Vec::new();
+    let mut cell_start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'|' => {
+                cells.push(row[cell_start..i].trim());
+                cell_start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    cells.push(row[cell_start..].trim());
+    cells
 }
 
 /// Compact binary node - 24 bytes, safe field sizes
 #[repr(C)]
 #[derive(Clone, Copy, Default)]
 pub struct BinaryNode {
-    pub node_type: u8,      // 1: heading, 2: para, 3: code, 4: hr, 5: quote, 6: list
-    pub flags: u8,          // depth for heading, ordered/checked for list
+    pub node_type: u8,      // 1: heading, 2: para, 3: code, 4: hr, 5: quote, 6: list, 7: table
+    pub flags: u8,          // depth for heading, ordered/checked for list, columns for table
     pub _pad: [u8; 2],      // alignment padding
     pub parent: u32,        // parent node index
     pub text_start: u32,    // offset into source
     pub text_len: u32,      // text length
-    pub span_start: u32,    // start line
-    pub span_end: u32,      // end line
+    pub span_start: u32,    // start offset
+    pub span_end: u32,      // end offset (exclusive)
 }
 
+/// Blocks parsed between checks of the cancel flag
+const CANCEL_CHECK_INTERVAL: usize = 64;
+
 const BINARY_NODE_SIZE: usize = std::mem::size_of::<BinaryNode>();
+
+/// Version of the `parse_binary` layout: 1 had line-number spans, 2 has
+/// byte-offset spans
+pub const BINARY_FORMAT_VERSION: u32 = 2;
 const _: () = assert!(BINARY_NODE_SIZE == 24);
 
+/// Check that `buf` is a well-formed `parse_binary` buffer before it is
+/// decoded
+///
+/// The header's node count must match the buffer length, node 0 must be
+/// the root covering the whole source, every other node must have a known
+/// type and a parent before it, and text ranges and spans must be in
+/// range. The error names the first problem found.
+pub fn validate_binary(buf: &[u8]) -> SynthResult<()> {
+    let invalid = |message: String| Err(SynthError::SerializationError(message));
+    let u32_at = |offset: usize| u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap());
+
+    if buf.len() < 8 {
+        return invalid(format!("binary buffer has {} bytes, too short for the 8-byte header", buf.len()));
+    }
+    let node_count = u32_at(0) as usize;
+    let source_len = u32_at(4);
+    let expected = node_count.checked_mul(BINARY_NODE_SIZE).and_then(|len| len.checked_add(8));
+    if expected != Some(buf.len()) {
+        return invalid(format!(
+            "binary buffer has {} bytes, but its header declares {node_count} nodes ({} bytes)",
+            buf.len(),
+            8 + node_count as u64 * BINARY_NODE_SIZE as u64,
+        ));
+    }
+    if node_count == 0 || buf[8] != node_type::ROOT || u32_at(8 + 12) != source_len {
+        return invalid("node 0 is not a root covering the source".to_string());
+    }
+
+    for i in 1..node_count {
+        let offset = 8 + i * BINARY_NODE_SIZE;
+        let kind = buf[offset];
+        if !(node_type::HEADING..=node_type::FRONTMATTER).contains(&kind) {
+            return invalid(format!("node {i} has unknown type {kind}"));
+        }
+        let parent = u32_at(offset + 4);
+        if parent as usize >= i {
+            return invalid(format!("node {i} has parent {parent}, which is not an earlier node"));
+        }
+        let (text_start, text_len) = (u32_at(offset + 8), u32_at(offset + 12));
+        if text_start.checked_add(text_len).is_none_or(|end| end > source_len) {
+            return invalid(format!(
+                "node {i} text {text_start}+{text_len} is past the end of the {source_len}-byte source"
+            ));
+        }
+        let (span_start, span_end) = (u32_at(offset + 16), u32_at(offset + 20));
+        if span_start > span_end || span_end > source_len {
+            return invalid(format!("node {i} has invalid span {span_start}..{span_end}"));
+        }
+    }
+    Ok(())
+}
+
+/// Parses the content of a fenced code block into a Tree, which is
+/// attached (root included) under the block's `code` node
+pub type CodeParser<'a> = Box<dyn Fn(&str) -> SynthResult<Tree> + 'a>;
+
 /// High-performance unified Markdown parser
 pub struct MarkdownParserV2<'a> {
     src: &'a str,
     bytes: &'a [u8],
     pos: usize,
     line: u32,
+    options: ParseOptions,
+    /// Inline text (source range, start line) of the block just scanned
+    pending_inline: Vec<(Range<usize>, u32)>,
+    /// Block opened on the marker line of the list item just scanned,
+    /// attached as the item's child
+    pending_block: Option<Node>,
+    /// Source region (from the start of its first line) and first line
+    /// of the blockquote just scanned, parsed into its children
+    pending_quote: Option<(Range<usize>, u32)>,
+    /// Parsing a blockquote's content, without its `>` markers
+    in_blockquote: bool,
+    /// Footnote definitions collected from inline notes, not yet attached
+    footnotes: Vec<NodeId>,
+    /// Link reference definitions by normalized label (first one wins)
+    definitions: HashMap<String, Definition>,
+    /// Start offsets of the lines holding those definitions
+    definition_lines: Vec<usize>,
+    /// Sub-parsers for fenced code, by language
+    code_parsers: HashMap<String, CodeParser<'a>>,
+    /// Custom inline syntax, in the order added
+    inline_matchers: Vec<Box<dyn InlineMatcher + 'a>>,
+    /// References to labels not defined when they were scanned
+    unresolved_references: UndefinedReferences,
+    /// References defined only after them (`References::Eager`)
+    late_references: Vec<LateReference>,
+    diagnostics: Vec<Diagnostic>,
+    cancel: Option<&'a dyn CancelSignal>,
 }
 
 impl<'a> MarkdownParserV2<'a> {
@@ -57,23 +466,379 @@ impl<'a> MarkdownParserV2<'a> {
             bytes: source.as_bytes(),
             pos: 0,
             line: 1,
+            options: ParseOptions::default(),
+            pending_inline: Vec::new(),
+            pending_block: None,
+            pending_quote: None,
+            in_blockquote: false,
+            footnotes: Vec::new(),
+            definitions: HashMap::new(),
+            definition_lines: Vec::new(),
+            code_parsers: HashMap::new(),
+            inline_matchers: Vec::new(),
+            unresolved_references: UndefinedReferences::default(),
+            late_references: Vec::new(),
+            diagnostics: Vec::new(),
+            cancel: None,
         }
     }
 
+    /// Set parser options
+    pub fn with_options(mut self, options: ParseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Parse fenced code in `lang` (the first word of the info string)
+    /// with `parser`, attaching its tree under the `code` node
+    ///
+    /// The sub-tree's spans are relative to the code (the `code` node's
+    /// `value`, unescaped). When `parser` fails the block keeps no
+    /// children; in strict mode the parse fails.
+    pub fn with_code_parser(mut self, lang: &str, parser: impl Fn(&str) -> SynthResult<Tree> + 'a) -> Self {
+        self.code_parsers.insert(lang.to_string(), Box::new(parser));
+        self
+    }
+
+    /// Add custom inline syntax, tried before the built-in inline syntax
+    /// and before matchers added later
+    pub fn with_inline_matcher(mut self, matcher: impl InlineMatcher + 'a) -> Self {
+        self.inline_matchers.push(Box::new(matcher));
+        self
+    }
+
     /// Parse and return a Tree object (compatible with JS API)
     pub fn parse(&mut self) -> SynthResult<Tree> {
+        if self.consume_whitespace_only() {
+            return Ok(Tree::new("markdown", self.src));
+        }
+        self.parse_blocks()
+    }
+
+    /// Parse a snippet from inside a larger document (a table cell, a list
+    /// item's content) whose source starts at `base_offset`
+    ///
+    /// Span and diagnostic offsets count from the start of the larger
+    /// document; lines and columns stay relative to the snippet.
+    /// Behavior tied to the start of a document doesn't apply.
+    pub fn parse_fragment(&mut self, base_offset: u32) -> SynthResult<Tree> {
+        self.options.base_offset = Some(base_offset);
+        let tree = self.parse()?;
+        for diagnostic in &mut self.diagnostics {
+            diagnostic.start += base_offset;
+            diagnostic.end += base_offset;
+        }
+        Ok(tree)
+    }
+
+    /// Parse blocks until `stop` matches a line
+    ///
+    /// `stop` sees each line (without its newline) before any block
+    /// parsing, so a matching line ends the region even inside a paragraph
+    /// or code block. The matching line is not consumed: `consumed_len()`
+    /// reports its offset, or the source length when nothing matched.
+    /// Lets a host parse a sub-region of a larger document.
+    pub fn parse_until(&mut self, stop: impl Fn(&str) -> bool) -> SynthResult<Tree> {
+        let mut line_start = self.pos;
+        while line_start < self.bytes.len() {
+            let line_end = memchr(b'\n', &self.bytes[line_start..])
+                .map_or(self.bytes.len(), |i| line_start + i);
+            if stop(&self.src[line_start..line_end]) {
+                self.src = &self.src[..line_start];
+                self.bytes = &self.bytes[..line_start];
+                break;
+            }
+            line_start = line_end + 1;
+        }
+
+        self.parse_blocks()
+    }
+
+    /// Parse without keeping a tree, reporting each node to `on_event` in
+    /// document order (see `events`)
+    ///
+    /// Only the block being parsed is held in memory: a top-level block is
+    /// reported and dropped once the next one starts, since only the last
+    /// one can still grow (a list gaining items). Inline footnote
+    /// definitions are kept until the end, where `parse` puts them.
+    /// `maxNodes` doesn't apply; a cancelled parse ends the stream early,
+    /// with a diagnostic.
+    pub fn parse_events(&mut self, mut on_event: impl FnMut(Event<'_>)) -> SynthResult<()> {
         let mut tree = Tree::new("markdown", self.src);
+        let root = tree.root_id();
+        let root_node = tree.root().clone();
+        on_event(Event::EnterBlock {
+            node_type: &root_node.node_type,
+            span: root_node.span,
+            data: root_node.data.as_ref(),
+        });
 
-        while self.pos < self.bytes.len() {
-            if let Some(node) = self.scan_block_to_node()? {
-                let node_id = tree.add_node(node);
-                tree.add_child(tree.root_id(), node_id)?;
+        let mut footnotes = Vec::new();
+        if !self.consume_whitespace_only() {
+            self.collect_definitions();
+            let mut blocks = 0usize;
+            while self.pos < self.bytes.len() {
+                self.scan_block_to_node(&mut tree, root)?;
+                blocks += 1;
+
+                for id in std::mem::take(&mut self.footnotes) {
+                    footnotes.push(tree.clone_subtree(id)?);
+                }
+                let done = tree.children_of(root).len().saturating_sub(1);
+                if done > 0 {
+                    let children: Vec<NodeId> = tree.get_node_mut(root)?.children.drain(..done).collect();
+                    for id in children {
+                        self.wrap_item_paragraphs(&mut tree, id)?;
+                        events::walk(&tree, id, &mut on_event);
+                    }
+                    tree.compact();
+                }
+
+                if self.pos < self.bytes.len()
+                    && blocks.is_multiple_of(CANCEL_CHECK_INTERVAL)
+                    && self.cancel.is_some_and(|flag| flag.is_cancelled())
+                {
+                    self.diagnostics.push(Diagnostic {
+                        message: "Parsing cancelled".to_string(),
+                        start: self.pos as u32,
+                        end: self.bytes.len() as u32,
+                    });
+                    break;
+                }
             }
         }
 
+        self.wrap_item_paragraphs(&mut tree, root)?;
+        for &id in tree.children_of(root) {
+            events::walk(&tree, id, &mut on_event);
+        }
+        for footnote in &footnotes {
+            events::walk(footnote, footnote.root_id(), &mut on_event);
+        }
+        self.settle_references();
+        self.check_references()?;
+        if !self.late_references.is_empty() {
+            on_event(Event::LateReferences { references: &self.late_references });
+        }
+        on_event(Event::ExitBlock { node_type: &root_node.node_type });
+        Ok(())
+    }
+
+    fn parse_blocks(&mut self) -> SynthResult<Tree> {
+        self.collect_definitions();
+        let mut tree = Tree::new("markdown", self.src);
+        let root = tree.root_id();
+        let mut blocks = 0usize;
+        while self.pos < self.bytes.len() {
+            self.scan_block_to_node(&mut tree, root)?;
+            blocks += 1;
+
+            if self.pos >= self.bytes.len() {
+                break;
+            }
+            if let Some(max) = self.options.max_nodes
+                && tree.node_count() > max
+            {
+                self.stop_early(&mut tree, format!("Document truncated: more than {max} nodes"), "truncated")?;
+                break;
+            }
+            if blocks.is_multiple_of(CANCEL_CHECK_INTERVAL)
+                && self.cancel.is_some_and(|flag| flag.is_cancelled())
+            {
+                self.stop_early(&mut tree, "Parsing cancelled".to_string(), "cancelled")?;
+                break;
+            }
+        }
+        for definition in std::mem::take(&mut self.footnotes) {
+            tree.add_child(root, definition)?;
+        }
+        self.wrap_item_paragraphs(&mut tree, root)?;
+        self.settle_references();
+        self.check_references()?;
+        if !self.unresolved_references.full.is_empty() {
+            let references: Vec<_> = self
+                .unresolved_references
+                .full
+                .iter()
+                .map(|(label, span)| serde_json::json!({ "label": label, "span": span }))
+                .collect();
+            tree.get_node_mut(root)?
+                .data
+                .get_or_insert_with(HashMap::new)
+                .insert("unresolvedReferences".to_string(), serde_json::json!(references));
+        }
+        if !self.late_references.is_empty() {
+            tree.get_node_mut(root)?
+                .data
+                .get_or_insert_with(HashMap::new)
+                .insert("lateReferences".to_string(), serde_json::json!(self.late_references));
+        }
+        if self.options.content_ids {
+            for (id, content_id) in tree.content_ids().into_iter().enumerate() {
+                if let Some(content_id) = content_id {
+                    tree.get_node_mut(id as NodeId)?
+                        .data
+                        .get_or_insert_with(HashMap::new)
+                        .insert("contentId".to_string(), serde_json::json!(content_id));
+                }
+            }
+        }
         Ok(tree)
     }
 
+    /// Move list items' inline content into a `paragraph` as
+    /// `itemParagraphs` asks, in the lists at or under `id`
+    ///
+    /// Runs once the lists are complete, since a later item can still
+    /// make a list loose.
+    fn wrap_item_paragraphs(&self, tree: &mut Tree, id: NodeId) -> SynthResult<()> {
+        let node = tree.get_node(id)?;
+        let wrap = node.node_type == NodeType::List
+            && match self.options.item_paragraphs {
+                ItemParagraphs::Loose => {
+                    node.data.as_ref().and_then(|d| d.get("spread")) == Some(&serde_json::json!(true))
+                }
+                ItemParagraphs::Always => true,
+                ItemParagraphs::Never => return Ok(()),
+            };
+
+        for child in tree.children_of(id).to_vec() {
+            let child_type = tree.get_node(child)?.node_type.clone();
+            if wrap && child_type == NodeType::ListItem {
+                self.wrap_item_content(tree, child)?;
+            }
+            if matches!(child_type, NodeType::List | NodeType::ListItem | NodeType::FootnoteDefinition) {
+                self.wrap_item_paragraphs(tree, child)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Move an item's inline children (those before a nested list) into a
+    /// new first-child `paragraph` carrying the item's text
+    fn wrap_item_content(&self, tree: &mut Tree, item: NodeId) -> SynthResult<()> {
+        let inline: Vec<NodeId> = tree
+            .children_of(item)
+            .iter()
+            .copied()
+            .take_while(|&c| {
+                tree.get_node(c).is_ok_and(|n| {
+                    !matches!(
+                        n.node_type,
+                        NodeType::List | NodeType::Code | NodeType::Heading | NodeType::Paragraph
+                    )
+                })
+            })
+            .collect();
+        let (Some(&first), Some(&last)) = (inline.first(), inline.last()) else {
+            return Ok(());
+        };
+
+        let mut paragraph = Node::new(0, NodeType::Paragraph);
+        if let (Some(start), Some(end)) = (tree.get_node(first)?.span, tree.get_node(last)?.span) {
+            paragraph.span = Some(Span::new(start.start, end.end));
+        }
+        if let Some(data) = &tree.get_node(item)?.data {
+            let text: HashMap<_, _> = data
+                .iter()
+                .filter(|(key, _)| matches!(key.as_str(), "value" | "raw"))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            paragraph.data = Some(text);
+        }
+
+        let paragraph = tree.add_node(paragraph);
+        tree.insert_child(item, 0, paragraph)?;
+        for (index, child) in inline.into_iter().enumerate() {
+            tree.insert_child(paragraph, index, child)?;
+        }
+        Ok(())
+    }
+
+    /// Find the link reference definitions up front, so references
+    /// before their definition resolve too (not under `References::Eager`,
+    /// where `scan_definition_node` adds them)
+    fn collect_definitions(&mut self) {
+        if self.options.references == References::Eager {
+            return;
+        }
+        let body_start = self.frontmatter().map_or(0, |frontmatter| frontmatter.end);
+        for range in reference::definition_lines(self.src) {
+            if range.start < body_start {
+                continue;
+            }
+            if let Some(line) = reference::parse_definition(&self.src[range.clone()]) {
+                self.definitions
+                    .entry(reference::normalize_label(line.label))
+                    .or_insert(line.definition);
+                self.definition_lines.push(range.start);
+            }
+        }
+    }
+
+    /// Record why parsing stopped before the end, flagging `key` on the root
+    fn stop_early(&mut self, tree: &mut Tree, message: String, key: &str) -> SynthResult<()> {
+        self.diagnostics.push(Diagnostic {
+            message,
+            start: self.pos as u32,
+            end: self.bytes.len() as u32,
+        });
+        let root = tree.root_id();
+        tree.get_node_mut(root)?
+            .data
+            .get_or_insert_with(HashMap::new)
+            .insert(key.to_string(), serde_json::json!(true));
+        Ok(())
+    }
+
+    /// Recover from malformed input, or fail in strict mode
+    fn recover(&mut self, message: &str, start: usize, end: usize) -> SynthResult<()> {
+        if !self.options.strict {
+            return Ok(());
+        }
+        self.diagnostics.push(Diagnostic {
+            message: message.to_string(),
+            start: start as u32,
+            end: end as u32,
+        });
+        Err(SynthError::ParseError(format!("{message} at offset {start}")))
+    }
+
+    /// Stop parsing once `flag` is set (checked every few blocks)
+    ///
+    /// The partial tree gets `cancelled: true` on the root and a
+    /// diagnostic is recorded. The flag can be set from another thread.
+    pub fn with_cancel_flag(mut self, flag: &'a dyn CancelSignal) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    /// Problems found by the last parse
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// References (`[text][label]`, `[label][]`) whose label has no
+    /// definition, as (label as written, span of the reference)
+    ///
+    /// `parse` also lists them on the root as `unresolvedReferences`.
+    pub fn unresolved_references(&self) -> &[(String, Span)] {
+        &self.unresolved_references.full
+    }
+
+    /// References found before their definition under
+    /// `References::Eager`, in document order
+    ///
+    /// `parse` also lists them on the root as `lateReferences`.
+    pub fn late_references(&self) -> &[LateReference] {
+        &self.late_references
+    }
+
+    /// Bytes consumed by the last parse
+    pub fn consumed_len(&self) -> usize {
+        self.pos
+    }
+
     /// Parse and return binary buffer (maximum performance)
     ///
     /// Format:
@@ -86,21 +851,18 @@ impl<'a> MarkdownParserV2<'a> {
         nodes.push(BinaryNode {
             node_type: node_type::ROOT,
             text_len: self.bytes.len() as u32,
-            span_start: 1,
+            span_end: self.bytes.len() as u32,
             ..Default::default()
         });
 
-        while self.pos < self.bytes.len() {
-            if let Some(node) = self.scan_block_to_binary() {
-                nodes.push(node);
+        if !self.consume_whitespace_only() {
+            while self.pos < self.bytes.len() {
+                if let Some(node) = self.scan_block_to_binary() {
+                    nodes.push(node);
+                }
             }
         }
 
-        // Update root span_end
-        if let Some(root) = nodes.first_mut() {
-            root.span_end = self.line;
-        }
-
         // Serialize to binary
         let node_count = nodes.len();
         let header_size = 8;
@@ -127,10 +889,27 @@ impl<'a> MarkdownParserV2<'a> {
         buf
     }
 
-    /// Parse and return node count (for benchmarking)
+    /// Parse and return the node count of the Tree `parse` builds
+    ///
+    /// Counting convention (shared with the JS parser): the count includes
+    /// the root. Use `parse_count_excluding_root` to leave the root out.
+    /// A parse that fails counts 0.
     pub fn parse_count(&mut self) -> usize {
+        self.parse().map_or(0, |tree| tree.node_count())
+    }
+
+    /// Count the root plus the block nodes of `parse_binary`, without
+    /// building a tree (for benchmarking the block scan)
+    ///
+    /// Lists, table rows and blocks inside quotes aren't nodes there, so
+    /// this is less than `parse_count`.
+    pub fn block_count(&mut self) -> usize {
         let mut count = 1; // root
 
+        if self.consume_whitespace_only() {
+            return count;
+        }
+
         while self.pos < self.bytes.len() {
             if self.scan_block_to_binary().is_some() {
                 count += 1;
@@ -140,10 +919,120 @@ impl<'a> MarkdownParserV2<'a> {
         count
     }
 
+    /// Collect ATX headings as `(depth, text, offset)` without building a tree
+    ///
+    /// A single block scan that only skips over other blocks (fenced code
+    /// is skipped so `#` lines inside it don't count). Returns the same
+    /// headings, in order, as filtering the output of `parse`.
+    pub fn headings(&mut self) -> Vec<(u8, String, u32)> {
+        let mut headings = Vec::new();
+
+        if self.consume_whitespace_only() {
+            return headings;
+        }
+
+        while self.pos < self.bytes.len() {
+            self.skip_horizontal_space();
+            let Some(b) = self.current() else {
+                break;
+            };
+            let start_pos = self.pos;
+
+            match b {
+                b'\n' => {
+                    self.pos += 1;
+                    self.line += 1;
+                }
+                b'#' => match self.atx_heading_depth() {
+                    Some(depth) => {
+                        let text_start = self.pos;
+                        let text_end = self.trimmed_line_end(text_start);
+                        let (text, _) = self.heading_text(self.text_slice(text_start, text_end));
+                        headings.push((self.shift_depth(depth), text.to_string(), start_pos as u32));
+                        self.skip_to_newline();
+                    }
+                    None => {
+                        self.pos = start_pos;
+                        self.skip_setext_paragraph(start_pos, &mut headings);
+                    }
+                },
+                b'`' | b'~' if self.is_code_fence() => self.skip_code_block(0),
+                b'-' | b'+' | b';' if let Some(frontmatter) = self.frontmatter() => {
+                    self.skip_frontmatter(&frontmatter);
+                }
+                b'-' | b'*' | b'_' if self.is_thematic_break() => self.skip_to_newline(),
+                b'-' | b'*' | b'+' if self.is_bullet_list() => self.skip_list_item_line(),
+                b'>' => {
+                    self.scan_blockquote_lines();
+                }
+                b'0'..=b'9' if self.is_ordered_list() => self.skip_list_item_line(),
+                b'<' if let Some(kind) = self.html_block_kind() => self.skip_html_block(kind),
+                _ if self.table_alignments().is_some() => {
+                    self.skip_to_newline(); // header
+                    self.skip_to_newline(); // delimiter
+                    while self.pos < self.bytes.len() && !self.ends_table(self.pos) {
+                        self.skip_to_newline();
+                    }
+                }
+                _ => self.skip_setext_paragraph(start_pos, &mut headings),
+            }
+        }
+
+        headings
+    }
+
+    /// Skip a list item's text, or the code fence opened on its first line
+    fn skip_list_item_line(&mut self) {
+        self.skip_list_marker();
+        if self.is_code_fence() {
+            self.skip_code_block(self.line_indent(self.pos));
+        } else {
+            self.scan_item_lines();
+        }
+    }
+
+    /// Skip a fenced code block starting at the current position
+    fn skip_code_block(&mut self, container_indent: usize) {
+        let marker = self.bytes[self.pos];
+        let fence_len = self.fence_len();
+        self.skip_to_newline();
+        self.skip_code_block_body(marker, fence_len, container_indent);
+    }
+
+    /// Skip a paragraph, recording it if a setext underline makes it a heading
+    fn skip_setext_paragraph(&mut self, start_pos: usize, headings: &mut Vec<(u8, String, u32)>) {
+        if let (text_end, Some(depth)) = self.skip_paragraph_lines() {
+            let text = self.text_slice(start_pos, text_end).trim();
+            headings.push((self.shift_depth(depth), text.to_string(), start_pos as u32));
+        }
+    }
+
+    /// Parse and return node count, not counting the root
+    pub fn parse_count_excluding_root(&mut self) -> usize {
+        self.parse_count().saturating_sub(1)
+    }
+
+    /// Check whether the source is empty or contains only whitespace
+    #[inline]
+    pub fn is_whitespace_only(&self) -> bool {
+        self.bytes.iter().all(u8::is_ascii_whitespace)
+    }
+
     // ============================================================
     // Helper methods
     // ============================================================
 
+    /// Short-circuit empty/whitespace-only sources: jump to the end,
+    /// keeping the line counter in sync. Returns true if consumed.
+    fn consume_whitespace_only(&mut self) -> bool {
+        if !self.is_whitespace_only() {
+            return false;
+        }
+        self.line += memchr::memchr_iter(b'\n', &self.bytes[self.pos..]).count() as u32;
+        self.pos = self.bytes.len();
+        true
+    }
+
     #[inline]
     fn byte(&self, pos: usize) -> Option<u8> {
         self.bytes.get(pos).copied()
@@ -160,6 +1049,62 @@ impl<'a> MarkdownParserV2<'a> {
     }
 
     #[inline]
+    /// Store a node's text as `value`, escaped (with `raw`) under `escapeHtml`
+    fn insert_value(&self, data: &mut HashMap<String, serde_json::Value>, text: &str) {
+        if self.options.escape_html {
+            data.insert("value".to_string(), serde_json::json!(escape_html(text)));
+            data.insert("raw".to_string(), serde_json::json!(text));
+        } else {
+            data.insert("value".to_string(), serde_json::json!(text));
+        }
+    }
+
+    /// `insert_value` for inline containers: also queues the text for the
+    /// inline scanner once the node is in the tree
+    fn insert_inline_value(
+        &mut self,
+        data: &mut HashMap<String, serde_json::Value>,
+        text: &'a str,
+        line: u32,
+    ) {
+        self.insert_value(data, text);
+        let start = text.as_ptr() as usize - self.src.as_ptr() as usize;
+        self.pending_inline = vec![(start..start + text.len(), line)];
+    }
+
+    /// Scan queued inline text into children of `id`
+    ///
+    /// Text queued as separate lines (a blockquote's, without their `>`
+    /// markers) is scanned line by line, with the line breaks between
+    /// them shown as `softBreak` asks.
+    fn attach_inlines(&mut self, tree: &mut Tree, id: NodeId) -> SynthResult<()> {
+        let lines = std::mem::take(&mut self.pending_inline);
+        let scanner = InlineScanner::new(self.src, &self.options, &self.definitions, &self.inline_matchers);
+        let mut prev: Option<(usize, u32)> = None;
+        for (range, line) in lines {
+            if let Some((prev_end, prev_line)) = prev {
+                let span = self.span(prev_end, prev_line, range.start, line);
+                let text = |value| {
+                    let mut data = HashMap::new();
+                    self.insert_value(&mut data, value);
+                    Node::new(0, NodeType::Text).with_data(data)
+                };
+                let separator = match self.options.soft_break {
+                    SoftBreak::Newline => text("\n"),
+                    SoftBreak::Space => text(" "),
+                    SoftBreak::Break => Node::new(0, NodeType::Break),
+                };
+                let separator = tree.add_node(separator.with_span(span));
+                tree.add_child(id, separator)?;
+            }
+            prev = Some((range.end, line));
+            if !range.is_empty() {
+                scanner.attach(tree, id, range, line, &mut self.footnotes, &mut self.unresolved_references)?;
+            }
+        }
+        Ok(())
+    }
+
     fn skip_horizontal_space(&mut self) {
         while let Some(b) = self.current() {
             if b == b' ' || b == b'\t' {
@@ -187,28 +1132,298 @@ impl<'a> MarkdownParserV2<'a> {
         }
     }
 
-    #[inline]
-    fn is_code_fence(&self) -> bool {
-        self.byte(self.pos + 1) == Some(b'`') && self.byte(self.pos + 2) == Some(b'`')
+    /// Heading depth after `heading_shift`
+    fn shift_depth(&self, depth: u8) -> u8 {
+        (i16::from(depth) + i16::from(self.options.heading_shift)).clamp(1, 6) as u8
     }
 
-    #[inline]
-    fn is_thematic_break(&self) -> bool {
-        let marker = self.bytes[self.pos];
-        if !matches!(marker, b'-' | b'*' | b'_') {
-            return false;
+    fn insert_depth(&self, data: &mut HashMap<String, serde_json::Value>, depth: u8) {
+        let shifted = self.shift_depth(depth);
+        data.insert("depth".to_string(), serde_json::json!(shifted));
+        if shifted != depth {
+            data.insert("originalDepth".to_string(), serde_json::json!(depth));
         }
+    }
 
-        let mut count = 0;
-        let mut i = self.pos;
-        while i < self.bytes.len() {
-            let b = self.bytes[i];
-            if b == marker {
-                count += 1;
-            } else if b == b' ' || b == b'\t' {
-                // ok
-            } else if b == b'\n' {
-                break;
+    /// End offset of a block starting at `start`, excluding a consumed
+    /// trailing newline
+    fn block_end(&self, start: usize) -> usize {
+        if self.pos > start && self.byte(self.pos - 1) == Some(b'\n') {
+            self.pos - 1
+        } else {
+            self.pos
+        }
+    }
+
+    /// Span of `start..end` (byte offsets) starting and ending on the given lines
+    ///
+    /// Offsets are shifted by the fragment's `base_offset`, if any.
+    ///
+    /// Columns count `column_unit`s from the start of their line. With
+    /// `SpanMode::Offset` only the offsets are filled in (lines and
+    /// columns are 0).
+    fn span(&self, start: usize, start_line: u32, end: usize, end_line: u32) -> Span {
+        let base = self.options.base_offset.unwrap_or(0);
+        if self.options.spans == SpanMode::Offset {
+            return Span::from_coords(0, 0, base + start as u32, 0, 0, base + end as u32);
+        }
+        Span::from_coords(
+            start_line,
+            self.column(start),
+            base + start as u32,
+            end_line,
+            self.column(end),
+            base + end as u32,
+        )
+    }
+
+    /// Position in the source of a span offset (undoing `base_offset`)
+    fn source_offset(&self, offset: u32) -> usize {
+        (offset - self.options.base_offset.unwrap_or(0)) as usize
+    }
+
+    /// Column of `pos` within its line
+    fn column(&self, pos: usize) -> u32 {
+        let line_start = memchr::memrchr(b'\n', &self.bytes[..pos]).map_or(0, |i| i + 1);
+        self.options.column_unit.column(&self.bytes[line_start..pos])
+    }
+
+    /// Line of the last consumed byte for a block starting on `start_line`
+    ///
+    /// `self.line` has already moved past a consumed trailing newline, but
+    /// not when the block ended at EOF without one.
+    fn end_line(&self, start_line: u32) -> u32 {
+        let past_newline = self.pos > 0 && self.byte(self.pos - 1) == Some(b'\n');
+        if past_newline { self.line - 1 } else { self.line }.max(start_line)
+    }
+
+    /// Consume paragraph lines until a blank line or another block starts
+    ///
+    /// Returns where the paragraph text ends, plus the heading depth when
+    /// a setext underline (`===` → 1, `---` → 2) closes it; the underline
+    /// is consumed. It is checked before thematic breaks and list items,
+    /// so `text\n---` is a heading while `text\n- - -` is not.
+    fn skip_paragraph_lines(&mut self) -> (usize, Option<u8>) {
+        loop {
+            self.skip_to_newline();
+
+            if self.pos >= self.bytes.len() {
+                break;
+            }
+
+            if let Some(depth) = self.setext_underline(self.pos) {
+                let text_end = self.pos - 1;
+                self.skip_to_newline();
+                return (text_end, Some(depth));
+            }
+
+            if self.interrupts_paragraph() {
+                break;
+            }
+        }
+
+        // Exclude the trailing newline
+        let text_end = if self.pos > 0 && self.byte(self.pos - 1) == Some(b'\n') {
+            self.pos - 1
+        } else {
+            self.pos
+        };
+        (text_end, None)
+    }
+
+    /// Whether the line at the current position (not at EOF) is blank or
+    /// starts a block that can interrupt a paragraph
+    fn interrupts_paragraph(&self) -> bool {
+        match self.bytes[self.pos] {
+            b'\n' | b'#' | b'>' | b'`' => true,
+            b'~' if self.is_code_fence() => true,
+            b'-' | b'*' | b'_' if self.is_thematic_break() => true,
+            b'-' | b'*' | b'+' if self.is_bullet_list() => true,
+            b'0'..=b'9' if self.is_ordered_list() => true,
+            b'<' => self.html_block_kind().is_some_and(html::interrupts_paragraph),
+            _ => false,
+        }
+    }
+
+    /// Consume a blockquote's lines, returning the content of each (after
+    /// its `>` and following spaces) and its line number
+    ///
+    /// Consecutive `>` lines belong to the quote. Under `lazyBlockquotes`
+    /// so does a line without `>` that continues quoted paragraph text:
+    /// one following a non-blank quoted line that isn't a heading or
+    /// fence, and not itself blank or the start of another block.
+    fn scan_blockquote_lines(&mut self) -> Vec<(Range<usize>, u32)> {
+        let mut lines = Vec::new();
+        loop {
+            if self.current() == Some(b'>') {
+                self.pos += 1;
+                self.skip_horizontal_space();
+            }
+            let content = self.pos..self.find_newline();
+            lines.push((content.clone(), self.line));
+            self.skip_to_newline();
+
+            let line_start = self.pos;
+            self.skip_horizontal_space();
+            if self.pos >= self.bytes.len() {
+                self.pos = line_start;
+                break;
+            }
+            if self.current() == Some(b'>') {
+                continue;
+            }
+            let text = self.text_slice(content.start, content.end).trim();
+            let in_paragraph = !text.is_empty() && !text.starts_with(['#', '`']) && !text.starts_with("~~~");
+            if !(self.options.lazy_blockquotes && in_paragraph && !self.interrupts_paragraph()) {
+                self.pos = line_start;
+                break;
+            }
+        }
+        lines
+    }
+
+    /// Frontmatter starting at the current position: only at the start
+    /// of a whole document, never in a fragment
+    fn frontmatter(&self) -> Option<Frontmatter<'a>> {
+        if self.pos != 0 || self.options.base_offset.is_some() {
+            return None;
+        }
+        frontmatter::detect(self.src)
+    }
+
+    /// Consume a frontmatter block through its closing fence line
+    fn skip_frontmatter(&mut self, frontmatter: &Frontmatter<'_>) {
+        while self.pos <= frontmatter.end && self.pos < self.bytes.len() {
+            self.skip_to_newline();
+        }
+    }
+
+    /// Heading depth if the line at `pos` is a setext underline
+    fn setext_underline(&self, pos: usize) -> Option<u8> {
+        let end = memchr(b'\n', &self.bytes[pos..]).map_or(self.bytes.len(), |i| pos + i);
+        let line = self.text_slice(pos, end);
+        let marker = line.trim_start_matches(' ');
+        if line.len() - marker.len() > 3 {
+            return None;
+        }
+
+        let marker = marker.trim_end().as_bytes();
+        let depth = match marker.first()? {
+            b'=' => 1,
+            b'-' => 2,
+            _ => return None,
+        };
+        marker.iter().all(|&b| b == marker[0]).then_some(depth)
+    }
+
+    /// Count the `#` run of an ATX heading and skip to its text
+    ///
+    /// Returns `None` (position unspecified) if the run isn't followed by
+    /// a space or line end.
+    fn atx_heading_depth(&mut self) -> Option<u8> {
+        let mut depth = 0u8;
+        while self.current() == Some(b'#') && depth < 6 {
+            self.pos += 1;
+            depth += 1;
+        }
+
+        match self.current() {
+            Some(b' ') | Some(b'\n') | None => {
+                self.skip_horizontal_space();
+                Some(depth)
+            }
+            _ => None,
+        }
+    }
+
+    /// ATX heading text without its attribute list, which is parsed under
+    /// the `attributes` option
+    fn heading_text(&self, text: &'a str) -> (&'a str, Option<Attributes>) {
+        if !self.options.attributes {
+            return (text, None);
+        }
+        match attributes::trailing(text) {
+            Some((end, attributes)) => (&text[..end], Some(attributes)),
+            None => (text, None),
+        }
+    }
+
+    /// End of the current line with trailing spaces and tabs removed
+    fn trimmed_line_end(&self, text_start: usize) -> usize {
+        let mut end = self.find_newline();
+        while end > text_start && matches!(self.byte(end - 1), Some(b' ' | b'\t')) {
+            end -= 1;
+        }
+        end
+    }
+
+    /// Skip a fenced code body and its closing fence, returning where the
+    /// code ends
+    ///
+    /// Only a line of at least `fence_len` of the opening `marker` (`` ` ``
+    /// or `~`, indented at most 3 spaces past `container_indent`, followed
+    /// by nothing but whitespace) closes the block, so content lines like
+    /// `` ```js `` don't, and neither does a fence of the other marker.
+    /// `container_indent` is the content column of the list item the fence
+    /// opened in, 0 outside items.
+    fn skip_code_block_body(&mut self, marker: u8, fence_len: usize, container_indent: usize) -> usize {
+        while self.pos < self.bytes.len() {
+            let line_start = self.pos;
+            let is_close = self.is_closing_fence(line_start, marker, fence_len, container_indent);
+            self.skip_to_newline();
+            if is_close {
+                return line_start;
+            }
+        }
+        self.bytes.len()
+    }
+
+    fn is_closing_fence(&self, pos: usize, marker: u8, fence_len: usize, container_indent: usize) -> bool {
+        let line_end = memchr(b'\n', &self.bytes[pos..]).map_or(self.bytes.len(), |i| pos + i);
+        let line = &self.bytes[pos..line_end];
+
+        let indent = line.iter().take_while(|&&b| b == b' ').count();
+        if indent > container_indent + 3 {
+            return false;
+        }
+        let run = line[indent..].iter().take_while(|&&b| b == marker).count();
+        run >= fence_len && line[indent + run..].iter().all(|b| b.is_ascii_whitespace())
+    }
+
+    /// Length of the backtick or tilde run opening a fence at the current
+    /// position
+    fn fence_len(&self) -> usize {
+        let marker = self.bytes[self.pos];
+        self.bytes[self.pos..].iter().take_while(|&&b| b == marker).count()
+    }
+
+    /// Whether a fence (three or more `` ` `` or `~`) starts at the
+    /// current position
+    #[inline]
+    fn is_code_fence(&self) -> bool {
+        let marker = self.current();
+        matches!(marker, Some(b'`' | b'~'))
+            && self.byte(self.pos + 1) == marker
+            && self.byte(self.pos + 2) == marker
+    }
+
+    #[inline]
+    fn is_thematic_break(&self) -> bool {
+        let marker = self.bytes[self.pos];
+        if !matches!(marker, b'-' | b'*' | b'_') {
+            return false;
+        }
+
+        let mut count = 0;
+        let mut i = self.pos;
+        while i < self.bytes.len() {
+            let b = self.bytes[i];
+            if b == marker {
+                count += 1;
+            } else if b == b' ' || b == b'\t' {
+                // ok
+            } else if b == b'\n' {
+                break;
             } else {
                 return false;
             }
@@ -218,6 +1433,12 @@ impl<'a> MarkdownParserV2<'a> {
     }
 
     #[inline]
+    /// Whether the bullet at `pos` starts a list item: it must be followed
+    /// by a space or tab, or end the line (an empty item)
+    fn is_bullet_list(&self) -> bool {
+        matches!(self.byte(self.pos + 1), Some(b' ' | b'\t' | b'\n') | None)
+    }
+
     fn is_ordered_list(&self) -> bool {
         let mut i = self.pos;
         while i < self.bytes.len() {
@@ -225,7 +1446,8 @@ impl<'a> MarkdownParserV2<'a> {
             if b.is_ascii_digit() {
                 i += 1;
             } else if b == b'.' || b == b')' {
-                return self.byte(i + 1) == Some(b' ');
+                // `1.` alone (at a line end or EOF) is an empty item
+                return matches!(self.byte(i + 1), Some(b' ' | b'\t' | b'\n') | None);
             } else {
                 return false;
             }
@@ -233,11 +1455,68 @@ impl<'a> MarkdownParserV2<'a> {
         false
     }
 
+    /// Type of the HTML block starting at the current position, if any
+    fn html_block_kind(&self) -> Option<u8> {
+        html::block_start(self.text_slice(self.pos, self.find_newline()))
+    }
+
+    /// Indentation width (tabs = 4) between the start of the line and `pos`
+    fn line_indent(&self, pos: usize) -> usize {
+        let line_start = memchr::memrchr(b'\n', &self.bytes[..pos]).map_or(0, |i| i + 1);
+        self.bytes[line_start..pos]
+            .iter()
+            .map(|&b| if b == b'\t' { 4 } else { 1 })
+            .sum()
+    }
+
+    /// Column alignments if a GFM table starts at the current line
+    ///
+    /// Requires a header row containing `|`, followed by a delimiter row
+    /// with the same number of columns, both indented at most 3 spaces.
+    fn table_alignments(&self) -> Option<Vec<Align>> {
+        if self.line_indent(self.pos) > 3 {
+            return None;
+        }
+
+        let header_end = self.find_newline();
+        let header = self.text_slice(self.pos, header_end);
+        if !has_unescaped_pipe(header) || header_end >= self.bytes.len() {
+            return None;
+        }
+
+        let delim_start = header_end + 1;
+        let delim_end = memchr(b'\n', &self.bytes[delim_start..])
+            .map_or(self.bytes.len(), |i| delim_start + i);
+        let delim = self.text_slice(delim_start, delim_end);
+        let indent = delim.len() - delim.trim_start_matches([' ', '\t']).len();
+        if indent > 3 || !delim.contains('-') {
+            return None;
+        }
+
+        let aligns = split_table_row(delim)
+            .into_iter()
+            .map(Align::from_delimiter)
+            .collect::<Option<Vec<_>>>()?;
+        (split_table_row(header).len() == aligns.len()).then_some(aligns)
+    }
+
+    /// Whether the line at `pos` ends a table body
+    fn ends_table(&self, pos: usize) -> bool {
+        let line = &self.bytes[pos..];
+        let trimmed = line
+            .iter()
+            .position(|&b| b != b' ' && b != b'\t')
+            .map_or(&line[line.len()..], |i| &line[i..]);
+        matches!(trimmed.first(), None | Some(b'\n' | b'#' | b'>'))
+            || trimmed.starts_with(b"```")
+            || trimmed.starts_with(b"~~~")
+    }
+
     // ============================================================
     // Block scanning → Node (for Tree output)
     // ============================================================
 
-    fn scan_block_to_node(&mut self) -> SynthResult<Option<Node>> {
+    fn scan_block_to_node(&mut self, tree: &mut Tree, parent: NodeId) -> SynthResult<Option<NodeId>> {
         self.skip_horizontal_space();
 
         if self.pos >= self.bytes.len() {
@@ -248,199 +1527,656 @@ impl<'a> MarkdownParserV2<'a> {
         let start_line = self.line;
         let start_pos = self.pos;
 
-        match b {
+        let node = match b {
             b'\n' => {
                 self.pos += 1;
                 self.line += 1;
-                Ok(None)
+                return Ok(None);
+            }
+            b'#' => self.scan_heading_node(start_pos, start_line)?,
+            b'`' | b'~' if self.is_code_fence() => self.scan_code_block_node(start_pos, start_line, 0)?,
+            b'-' | b'+' | b';' if let Some(frontmatter) = self.frontmatter() => {
+                self.scan_frontmatter_node(&frontmatter, start_line)?
             }
-            b'#' => self.scan_heading_node(start_pos, start_line),
-            b'`' if self.is_code_fence() => self.scan_code_block_node(start_pos, start_line),
             b'-' | b'*' | b'_' if self.is_thematic_break() => {
-                self.scan_thematic_break_node(start_line)
+                self.scan_thematic_break_node(start_line)?
             }
-            b'-' | b'*' | b'+' => self.scan_list_item_node(start_pos, start_line),
-            b'>' => self.scan_blockquote_node(start_pos, start_line),
-            b'0'..=b'9' if self.is_ordered_list() => self.scan_list_item_node(start_pos, start_line),
-            _ => self.scan_paragraph_node(start_pos, start_line),
-        }
-    }
+            b'-' | b'*' | b'+' if self.is_bullet_list() => {
+                self.scan_list_item_node(start_pos, start_line)?
+            }
+            b'>' => self.scan_blockquote_node(start_pos, start_line)?,
+            b'0'..=b'9' if self.is_ordered_list() => {
+                self.scan_list_item_node(start_pos, start_line)?
+            }
+            b'[' if self.is_definition_line(start_pos) => self.scan_definition_node(start_pos, start_line)?,
+            b'<' => match self.html_block_kind() {
+                Some(kind) => self.scan_html_node(kind, start_pos, start_line)?,
+                None => self.scan_paragraph_node(start_pos, start_line)?,
+            },
+            _ => match self.table_alignments() {
+                Some(aligns) => {
+                    return self
+                        .scan_table_node(tree, parent, &aligns, start_pos, start_line)
+                        .map(Some);
+                }
+                None => self.scan_paragraph_node(start_pos, start_line)?,
+            },
+        };
 
-    fn scan_heading_node(&mut self, start_pos: usize, start_line: u32) -> SynthResult<Option<Node>> {
-        let mut depth = 0u8;
-        while self.current() == Some(b'#') && depth < 6 {
-            self.pos += 1;
-            depth += 1;
+        let Some(mut node) = node else {
+            return Ok(None);
+        };
+        let is_item = node.node_type == NodeType::ListItem;
+        let open_items = if node.node_type == NodeType::Paragraph {
+            self.open_items(tree, parent, self.line_indent(start_pos))?
+        } else {
+            Vec::new()
+        };
+        if !open_items.is_empty() || (self.in_blockquote && node.node_type == NodeType::Paragraph) {
+            self.dedent_lines(&mut node);
+        }
+        let id = tree.add_node(node);
+        if is_item {
+            self.attach_list_item(tree, parent, id, start_pos)?;
+        } else if !open_items.is_empty() {
+            self.attach_item_paragraph(tree, open_items, id, start_pos)?;
+        } else {
+            tree.add_child(parent, id)?;
+        }
+        if let Some(block) = self.pending_block.take() {
+            let block = tree.add_node(block);
+            tree.add_child(id, block)?;
+            self.attach_inlines(tree, block)?;
+            self.attach_code_tree(tree, block)?;
+            return Ok(Some(id));
         }
+        self.attach_inlines(tree, id)?;
+        self.attach_code_tree(tree, id)?;
+        self.attach_quote_content(tree, id)?;
+        Ok(Some(id))
+    }
 
-        // Must have space or newline after #
-        match self.current() {
-            Some(b' ') | Some(b'\n') | None => {}
-            _ => {
-                self.pos = start_pos;
-                return self.scan_paragraph_node(start_pos, start_line);
+    /// Parse the blockquote just scanned into block children of `id`
+    ///
+    /// Its lines are parsed on their own without their `>` marker (and
+    /// one space after it), so a marker left (`> >`) opens a nested
+    /// quote; spans are then mapped back to the source. The content sees
+    /// the definitions known so far and may add to them
+    /// (`References::Eager`).
+    fn attach_quote_content(&mut self, tree: &mut Tree, id: NodeId) -> SynthResult<()> {
+        let Some((region, line)) = self.pending_quote.take() else {
+            return Ok(());
+        };
+        // Per content line: its start, the distance to its source
+        // position, and the bytes stripped from its start
+        let mut content = String::with_capacity(region.len());
+        let mut lines: Vec<(usize, usize, u32)> = Vec::new();
+        let mut line_start = region.start;
+        for text in self.text_slice(region.start, region.end).split('\n') {
+            let indent = text.len() - text.trim_start_matches([' ', '\t']).len();
+            let strip = match text[indent..].strip_prefix('>') {
+                Some(rest) if rest.starts_with([' ', '\t']) => indent + 2,
+                Some(_) => indent + 1,
+                None => 0,
+            };
+            if !lines.is_empty() {
+                content.push('\n');
             }
+            lines.push((content.len(), line_start + strip - content.len(), strip as u32));
+            content.push_str(&text[strip..]);
+            line_start += text.len() + 1;
         }
+        let local = |offset: u32| {
+            let offset = offset as usize;
+            let (_, shift, strip) = lines[lines.partition_point(|&(start, ..)| start <= offset) - 1];
+            (offset + shift, strip)
+        };
+        let base = self.options.base_offset.unwrap_or(0);
+        let line_columns = self.options.spans != SpanMode::Offset;
+        let map_span = |span: &mut Span| {
+            for position in [&mut span.start, &mut span.end] {
+                let (offset, strip) = local(position.offset);
+                position.offset = base + offset as u32;
+                if line_columns {
+                    position.column += strip;
+                }
+            }
+        };
 
-        self.skip_horizontal_space();
-        let text_start = self.pos;
-        let text_end = self.find_newline();
-
-        // Trim trailing whitespace
-        let mut len = text_end - text_start;
-        while len > 0 && matches!(self.byte(text_start + len - 1), Some(b' ' | b'\t')) {
-            len -= 1;
+        let mut sub = MarkdownParserV2::new(&content).with_options(ParseOptions {
+            base_offset: Some(0),
+            ..self.options.clone()
+        });
+        sub.line = line;
+        sub.in_blockquote = true;
+        sub.cancel = self.cancel;
+        sub.definitions = std::mem::take(&mut self.definitions);
+        sub.unresolved_references = std::mem::take(&mut self.unresolved_references);
+        // Stand-ins, so inline notes in the quote are numbered after the
+        // ones before it
+        sub.footnotes = self.footnotes.clone();
+        for (lang, parser) in &self.code_parsers {
+            sub.code_parsers.insert(lang.clone(), Box::new(|code: &str| parser(code)));
+        }
+        for matcher in &self.inline_matchers {
+            sub.inline_matchers.push(Box::new(&**matcher));
         }
 
-        let text = self.text_slice(text_start, text_start + len);
-        self.skip_to_newline();
+        let mut quote = Tree::new("markdown", &content);
+        let root = quote.root_id();
+        sub.collect_definitions();
+        while sub.pos < sub.bytes.len() {
+            sub.scan_block_to_node(&mut quote, root)?;
+        }
+        sub.wrap_item_paragraphs(&mut quote, root)?;
+
+        let footnotes = sub.footnotes.split_off(self.footnotes.len());
+        self.definitions = std::mem::take(&mut sub.definitions);
+        let mut unresolved = std::mem::take(&mut sub.unresolved_references);
+        for (_, span) in unresolved.full[self.unresolved_references.full.len()..]
+            .iter_mut()
+            .chain(&mut unresolved.shortcut[self.unresolved_references.shortcut.len()..])
+        {
+            map_span(span);
+        }
+        self.unresolved_references = unresolved;
+        for mut diagnostic in std::mem::take(&mut sub.diagnostics) {
+            diagnostic.start = local(diagnostic.start).0 as u32;
+            diagnostic.end = local(diagnostic.end).0 as u32;
+            self.diagnostics.push(diagnostic);
+        }
+        quote.map_spans(map_span);
 
-        let mut data = HashMap::new();
-        data.insert("depth".to_string(), serde_json::json!(depth));
-        data.insert("value".to_string(), serde_json::json!(text));
+        for &child in quote.children_of(root) {
+            let child = graft(tree, &quote, child)?;
+            tree.add_child(id, child)?;
+        }
+        for footnote in footnotes {
+            let footnote = graft(tree, &quote, footnote)?;
+            self.footnotes.push(footnote);
+        }
+        Ok(())
+    }
 
-        let span = Span::from_coords(
-            start_line,
-            1,
-            start_pos as u32,
-            self.line.saturating_sub(1).max(start_line),
-            (text_end - start_pos) as u32,
-            text_end as u32,
-        );
+    /// Run the code parser registered for a `code` node's language, if any
+    fn attach_code_tree(&mut self, tree: &mut Tree, id: NodeId) -> SynthResult<()> {
+        if self.code_parsers.is_empty() {
+            return Ok(());
+        }
+        let node = tree.get_node(id)?;
+        let Some(data) = node.data.as_ref().filter(|_| node.node_type == NodeType::Code) else {
+            return Ok(());
+        };
+        let field = |key: &str| data.get(key).and_then(|v| v.as_str());
+        let Some(lang) = field("lang").and_then(|lang| lang.split_whitespace().next()) else {
+            return Ok(());
+        };
+        let Some(parser) = self.code_parsers.get(lang) else {
+            return Ok(());
+        };
 
-        Ok(Some(
-            Node::new(0, "heading").with_span(span).with_data(data),
-        ))
+        let code = field("raw").or_else(|| field("value")).unwrap_or_default();
+        match parser(code) {
+            Ok(sub) => {
+                let root = graft(tree, &sub, sub.root_id())?;
+                tree.add_child(id, root)
+            }
+            Err(err) => {
+                let message = format!("Could not parse {lang} code: {err}");
+                let (start, end) = node.span.map_or((0, 0), |s| (s.start.offset, s.end.offset));
+                self.recover(&message, self.source_offset(start), self.source_offset(end))
+            }
+        }
     }
 
-    fn scan_paragraph_node(
+    /// Add a list item to the list it continues, or start a new list
+    ///
+    /// An item indented at least to the content column of the previous
+    /// item (past its marker and following spaces) nests inside it, at any
+    /// depth. At its level, an item continues the previous sibling list
+    /// when it uses the same bullet character or ordered delimiter
+    /// (CommonMark list grouping). A blank line between two items makes
+    /// the list loose (`spread`); an item is `spread` when a blank line
+    /// separates its own children (e.g. its text and a nested list), which
+    /// also makes its list loose. A list with any task item (`[ ]`/`[x]`)
+    /// gets `taskList: true`.
+    fn attach_list_item(
         &mut self,
+        tree: &mut Tree,
+        parent: NodeId,
+        item: NodeId,
         start_pos: usize,
-        start_line: u32,
-    ) -> SynthResult<Option<Node>> {
-        loop {
-            self.skip_to_newline();
-
-            if self.pos >= self.bytes.len() {
-                break;
+    ) -> SynthResult<()> {
+        let marker = self.list_marker(start_pos);
+        let item_span = tree.get_node(item)?.span;
+
+        // Descend into the deepest open item this one is indented under
+        let mut ancestors = self.open_items(tree, parent, self.line_indent(start_pos))?;
+        let container = ancestors.last().map_or(parent, |&(_, open_item)| open_item);
+
+        let continued = self
+            .last_list(tree, container)
+            .filter(|&list| self.first_marker(tree, list) == Some(marker));
+
+        let list = match continued {
+            Some(list) => {
+                let prev_end = self.last_end(tree, list).unwrap_or(start_pos);
+                if self.blank_line_between(prev_end, start_pos) {
+                    set_flag(tree, list, "spread")?;
+                }
+                list
             }
+            None => {
+                // A nested list after a blank line spreads its parent item
+                if let Some(&(parent_list, parent_item)) = ancestors.last()
+                    && tree.children_of(parent_item).iter().all(|&c| {
+                        tree.get_node(c).is_ok_and(|n| n.node_type != NodeType::List)
+                    })
+                    && let Some(item_end) = self.last_end(tree, parent_item)
+                    && self.blank_line_between(item_end, start_pos)
+                {
+                    set_flag(tree, parent_item, "spread")?;
+                    set_flag(tree, parent_list, "spread")?;
+                }
 
-            let b = self.bytes[self.pos];
-            match b {
-                b'\n' | b'#' | b'>' | b'-' | b'*' | b'+' | b'`' => break,
-                b'0'..=b'9' if self.is_ordered_list() => break,
-                _ => {}
-            }
-        }
+                let ordered = marker == b'.' || marker == b')';
+                let mut data = HashMap::new();
+                data.insert("ordered".to_string(), serde_json::json!(ordered));
+                if ordered {
+                    let digits = self.bytes[start_pos..]
+                        .iter()
+                        .take_while(|b| b.is_ascii_digit())
+                        .count();
+                    let start: u64 = self.text_slice(start_pos, start_pos + digits).parse().unwrap_or(1);
+                    data.insert("start".to_string(), serde_json::json!(start));
+                }
+                data.insert("spread".to_string(), serde_json::json!(false));
 
-        // Calculate text end (exclude trailing newline)
-        let text_end = if self.pos > 0 && self.byte(self.pos - 1) == Some(b'\n') {
-            self.pos - 1
-        } else {
-            self.pos
+                let mut node = Node::new(0, NodeType::List).with_data(data);
+                node.span = item_span;
+                let list = tree.add_node(node);
+                tree.add_child(container, list)?;
+                list
+            }
         };
 
-        let text = self.text_slice(start_pos, text_end);
-
-        let mut data = HashMap::new();
-        data.insert("value".to_string(), serde_json::json!(text));
-
-        let span = Span::from_coords(
-            start_line,
-            1,
-            start_pos as u32,
-            self.line.saturating_sub(1).max(start_line),
-            (text_end - start_pos) as u32,
-            text_end as u32,
-        );
+        let node = tree.get_node_mut(item)?;
+        let is_task = node.data.as_ref().is_some_and(|d| d.contains_key("checked"));
+        if let Some(data) = node.data.as_mut() {
+            data.insert("spread".to_string(), serde_json::json!(false));
+        }
+        if is_task {
+            set_flag(tree, list, "taskList")?;
+        }
+        tree.add_child(list, item)?;
 
-        Ok(Some(
-            Node::new(0, "paragraph").with_span(span).with_data(data),
-        ))
+        ancestors.push((list, item));
+        self.extend_open_items(tree, &ancestors, item)
     }
 
-    fn scan_code_block_node(
+    /// Add a paragraph indented under the open items `ancestors` (see
+    /// `open_items`) to the innermost one
+    ///
+    /// It follows a blank line or a block in the item (a continuation
+    /// line would have joined the item's text); after a blank line, the
+    /// item and its list are `spread`.
+    fn attach_item_paragraph(
         &mut self,
+        tree: &mut Tree,
+        ancestors: Vec<(NodeId, NodeId)>,
+        paragraph: NodeId,
         start_pos: usize,
-        start_line: u32,
-    ) -> SynthResult<Option<Node>> {
-        // Skip ```
-        self.pos += 3;
+    ) -> SynthResult<()> {
+        let Some(&(list, item)) = ancestors.last() else {
+            return Ok(());
+        };
+        if let Some(item_end) = self.last_end(tree, item)
+            && self.blank_line_between(item_end, start_pos)
+        {
+            set_flag(tree, item, "spread")?;
+            set_flag(tree, list, "spread")?;
+        }
+        tree.add_child(item, paragraph)?;
+        self.extend_open_items(tree, &ancestors, paragraph)
+    }
 
-        // Read info line (language)
-        let info_start = self.pos;
-        let info_end = self.find_newline();
-        let lang = self.text_slice(info_start, info_end).trim();
+    /// Open list items, with their lists, that a block starting at
+    /// `indent` is indented under, outermost first
+    ///
+    /// An item is open while it is the last in a list that is the last
+    /// child of `parent` or of the enclosing open item, and a block
+    /// belongs in it when indented at least to its content column.
+    fn open_items(&self, tree: &Tree, parent: NodeId, indent: usize) -> SynthResult<Vec<(NodeId, NodeId)>> {
+        let mut container = parent;
+        let mut ancestors = Vec::new();
+        while let Some(list) = self.last_list(tree, container)
+            && let Some(&open_item) = tree.children_of(list).last()
+            && let Some(open_start) = tree.get_node(open_item)?.span.map(|s| self.source_offset(s.start.offset))
+            && indent >= self.content_indent(open_start)
+        {
+            ancestors.push((list, open_item));
+            container = open_item;
+        }
+        Ok(ancestors)
+    }
+
+    /// Make the lists and items of `ancestors` end where `last` does
+    fn extend_open_items(&self, tree: &mut Tree, ancestors: &[(NodeId, NodeId)], last: NodeId) -> SynthResult<()> {
+        let Some(end) = tree.get_node(last)?.span.map(|s| s.end) else {
+            return Ok(());
+        };
+        for &id in ancestors.iter().flat_map(|(list, item)| [list, item]) {
+            if let Some(span) = tree.get_node_mut(id)?.span.as_mut() {
+                span.end = end;
+            }
+        }
+        Ok(())
+    }
+
+    /// Requeue a paragraph's text line by line without its indentation,
+    /// as for list item text, updating its value to match
+    fn dedent_lines(&mut self, node: &mut Node) {
+        let Some((range, mut line)) = self.pending_inline.pop() else {
+            return;
+        };
+        let mut start = range.start;
+        for text in self.text_slice(range.start, range.end).split('\n') {
+            let indent = text.len() - text.trim_start_matches([' ', '\t']).len();
+            self.pending_inline.push((start + indent..start + text.len(), line));
+            start += text.len() + 1;
+            line += 1;
+        }
+        let text = self
+            .pending_inline
+            .iter()
+            .map(|(range, _)| self.text_slice(range.start, range.end))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if let Some(data) = node.data.as_mut() {
+            self.insert_value(data, &text);
+        }
+    }
+
+    /// Last child of `parent` if it is a list
+    fn last_list(&self, tree: &Tree, parent: NodeId) -> Option<NodeId> {
+        let &last = tree.children_of(parent).last()?;
+        (tree.get_node(last).ok()?.node_type == NodeType::List).then_some(last)
+    }
+
+    /// Marker of a list's first item
+    fn first_marker(&self, tree: &Tree, list: NodeId) -> Option<u8> {
+        let span = tree.get_node(list).ok()?.span?;
+        Some(self.list_marker(self.source_offset(span.start.offset)))
+    }
+
+    /// End offset of the last child of `id`, or of `id` itself when childless
+    fn last_end(&self, tree: &Tree, id: NodeId) -> Option<usize> {
+        let node = tree.get_node(id).ok()?;
+        let last = node.children.last().and_then(|&c| tree.get_node(c).ok()).unwrap_or(node);
+        last.span.map(|s| self.source_offset(s.end.offset))
+    }
+
+    fn blank_line_between(&self, from: usize, to: usize) -> bool {
+        from <= to && memchr::memchr_iter(b'\n', &self.bytes[from..to]).count() > 1
+    }
+
+    /// Indentation of a list item's content: the marker's column plus the
+    /// marker and the 1–4 spaces after it (1 when more follow or none do)
+    fn content_indent(&self, item_start: usize) -> usize {
+        let digits = self.bytes[item_start..].iter().take_while(|b| b.is_ascii_digit()).count();
+        let after_marker = item_start + digits + 1;
+        let spaces = self.bytes[after_marker.min(self.bytes.len())..]
+            .iter()
+            .take_while(|&&b| b == b' ')
+            .count();
+        let at_line_end = matches!(self.byte(after_marker + spaces), Some(b'\n') | None);
+        let padding = if (1..=4).contains(&spaces) && !at_line_end { spaces } else { 1 };
+        self.line_indent(item_start) + digits + 1 + padding
+    }
+
+    /// Bullet character, or delimiter (`.`/`)`) of an ordered marker, at `pos`
+    fn list_marker(&self, pos: usize) -> u8 {
+        let digits = self.bytes[pos..].iter().take_while(|b| b.is_ascii_digit()).count();
+        self.byte(pos + digits).unwrap_or(0)
+    }
+
+    fn scan_heading_node(&mut self, start_pos: usize, start_line: u32) -> SynthResult<Option<Node>> {
+        let Some(depth) = self.atx_heading_depth() else {
+            self.pos = start_pos;
+            return self.scan_paragraph_node(start_pos, start_line);
+        };
+
+        let text_start = self.pos;
+        let text_end = self.find_newline();
+        let (text, attributes) = self.heading_text(self.text_slice(text_start, self.trimmed_line_end(text_start)));
+        self.skip_to_newline();
+
+        let mut data = HashMap::new();
+        self.insert_depth(&mut data, depth);
+        self.insert_inline_value(&mut data, text, start_line);
+        if let Some(attributes) = attributes {
+            if let Some(id) = attributes.id {
+                data.insert("id".to_string(), serde_json::json!(id));
+            }
+            if !attributes.classes.is_empty() {
+                data.insert("classes".to_string(), serde_json::json!(attributes.classes));
+            }
+            if !attributes.pairs.is_empty() {
+                data.insert("attributes".to_string(), serde_json::json!(attributes.pairs));
+            }
+        }
+
+        let span = self.span(start_pos, start_line, text_end, self.end_line(start_line));
+
+        Ok(Some(
+            Node::new(0, NodeType::Heading).with_span(span).with_data(data),
+        ))
+    }
+
+    fn scan_paragraph_node(
+        &mut self,
+        start_pos: usize,
+        start_line: u32,
+    ) -> SynthResult<Option<Node>> {
+        let (text_end, setext) = self.skip_paragraph_lines();
+        let text = self.text_slice(start_pos, text_end);
+        let end_line = self.end_line(start_line);
+
+        if let Some(depth) = setext {
+            // Span covers the text and its underline
+            let end = if self.byte(self.pos - 1) == Some(b'\n') {
+                self.pos - 1
+            } else {
+                self.pos
+            };
+
+            let mut data = HashMap::new();
+            self.insert_depth(&mut data, depth);
+            self.insert_inline_value(&mut data, text.trim(), start_line);
+
+            let span = self.span(start_pos, start_line, end, end_line);
+
+            return Ok(Some(
+                Node::new(0, NodeType::Heading).with_span(span).with_data(data),
+            ));
+        }
+
+        let mut data = HashMap::new();
+        self.insert_inline_value(&mut data, text, start_line);
+
+        let span = self.span(start_pos, start_line, text_end, end_line);
+
+        Ok(Some(
+            Node::new(0, NodeType::Paragraph).with_span(span).with_data(data),
+        ))
+    }
+
+    fn scan_code_block_node(
+        &mut self,
+        start_pos: usize,
+        start_line: u32,
+        container_indent: usize,
+    ) -> SynthResult<Option<Node>> {
+        // Skip the opening fence
+        let marker = self.bytes[self.pos];
+        let fence_len = self.fence_len();
+        self.pos += fence_len;
+
+        // Read info line (language)
+        let info_start = self.pos;
+        let info_end = self.find_newline();
+        let lang = self.text_slice(info_start, info_end).trim();
         self.skip_to_newline();
 
         let code_start = self.pos;
+        let code_end = self.skip_code_block_body(marker, fence_len, container_indent);
+        if code_end == self.bytes.len() {
+            self.recover("Unclosed code fence", start_pos, code_end)?;
+        }
 
-        // Find closing ```
-        let code_end = loop {
-            if self.pos >= self.bytes.len() {
-                break self.bytes.len();
-            }
+        let code = self.text_slice(code_start, code_end);
+        let code = strip_fence_indent(code, self.line_indent(start_pos));
 
-            match memchr(b'`', &self.bytes[self.pos..]) {
-                Some(i) => {
-                    let tick_pos = self.pos + i;
-                    if self.byte(tick_pos + 1) == Some(b'`') && self.byte(tick_pos + 2) == Some(b'`')
-                    {
-                        // Found closing - count newlines up to here
-                        let newlines =
-                            memchr::memchr_iter(b'\n', &self.bytes[self.pos..tick_pos]).count();
-                        self.line += newlines as u32;
+        let mut data = HashMap::new();
+        if !lang.is_empty() {
+            data.insert("lang".to_string(), serde_json::json!(lang));
+        }
+        self.insert_value(&mut data, &code);
 
-                        self.pos = tick_pos + 3;
-                        self.skip_to_newline();
-                        break tick_pos;
-                    }
-                    self.pos = tick_pos + 1;
-                }
-                None => {
-                    // No closing, consume rest
-                    let newlines = memchr::memchr_iter(b'\n', &self.bytes[self.pos..]).count();
-                    self.line += newlines as u32;
-                    self.pos = self.bytes.len();
-                    break self.bytes.len();
+        let span = self.span(start_pos, start_line, self.block_end(start_pos), self.end_line(start_line));
+
+        Ok(Some(Node::new(0, NodeType::Code).with_span(span).with_data(data)))
+    }
+
+    /// Whether `pos` is on a line `collect_definitions` found a definition
+    /// on, or under `References::Eager`, a line holding one (`pos` starts
+    /// a block, so the definition doesn't interrupt a paragraph)
+    fn is_definition_line(&self, pos: usize) -> bool {
+        let line_start = memchr::memrchr(b'\n', &self.bytes[..pos]).map_or(0, |i| i + 1);
+        if self.options.references == References::Eager {
+            return reference::parse_definition(self.text_slice(line_start, self.find_newline())).is_some();
+        }
+        self.definition_lines.binary_search(&line_start).is_ok()
+    }
+
+    /// Under `References::Eager`, move references whose definition came
+    /// after them to `late_references`, and drop shortcuts still undefined
+    fn settle_references(&mut self) {
+        if self.options.references != References::Eager {
+            return;
+        }
+        let undefined = std::mem::take(&mut self.unresolved_references);
+        for (references, shortcut) in [(undefined.full, false), (undefined.shortcut, true)] {
+            for (label, span) in references {
+                match self.definitions.get(&reference::normalize_label(&label)) {
+                    Some(definition) => self.late_references.push(LateReference {
+                        label,
+                        span,
+                        url: definition.url.clone(),
+                        title: definition.title.clone(),
+                    }),
+                    None if !shortcut => self.unresolved_references.full.push((label, span)),
+                    None => {}
                 }
             }
+        }
+        self.late_references.sort_by_key(|reference| reference.span.start.offset);
+    }
+
+    /// Fail a strict parse on the first reference link with no definition
+    fn check_references(&mut self) -> SynthResult<()> {
+        let Some((label, span)) = self.unresolved_references.full.first() else {
+            return Ok(());
         };
+        let message = format!("Unresolved reference [{label}]");
+        let (start, end) = (span.start.offset as usize, span.end.offset as usize);
+        self.recover(&message, start, end)
+    }
 
-        let code = self.text_slice(code_start, code_end);
+    /// `definition` node with `identifier` (normalized label), `label`,
+    /// `url` and optional `title`
+    fn scan_definition_node(&mut self, start_pos: usize, start_line: u32) -> SynthResult<Option<Node>> {
+        let line = self.text_slice(start_pos, self.find_newline());
+        self.skip_to_newline();
+        let Some(line) = reference::parse_definition(line) else {
+            return Ok(None);
+        };
+
+        if self.options.references == References::Eager {
+            self.definitions
+                .entry(reference::normalize_label(line.label))
+                .or_insert_with(|| line.definition.clone());
+        }
 
         let mut data = HashMap::new();
-        if !lang.is_empty() {
-            data.insert("lang".to_string(), serde_json::json!(lang));
+        data.insert("identifier".to_string(), serde_json::json!(reference::normalize_label(line.label)));
+        data.insert("label".to_string(), serde_json::json!(line.label));
+        data.insert("url".to_string(), serde_json::json!(line.definition.url));
+        if let Some(title) = line.definition.title {
+            data.insert("title".to_string(), serde_json::json!(title));
         }
-        data.insert("value".to_string(), serde_json::json!(code));
 
-        let span = Span::from_coords(
-            start_line,
-            1,
-            start_pos as u32,
-            self.line,
-            3, // ```
-            self.pos as u32,
-        );
+        let span = self.span(start_pos, start_line, self.block_end(start_pos), start_line);
+        Ok(Some(Node::new(0, NodeType::Definition).with_span(span).with_data(data)))
+    }
+
+    /// Raw `html` node; see `html` for where each kind of block ends
+    fn scan_html_node(&mut self, kind: u8, start_pos: usize, start_line: u32) -> SynthResult<Option<Node>> {
+        self.skip_html_block(kind);
+        let end = self.block_end(start_pos);
+
+        let value = self.text_slice(start_pos, end);
+        let mut data = HashMap::new();
+        self.insert_value(&mut data, value);
+        if let Some((len, comment)) = html::comment(value)
+            && len == value.trim_end().len()
+        {
+            data.insert("comment".to_string(), serde_json::json!(comment));
+        }
+
+        let span = self.span(start_pos, start_line, end, self.end_line(start_line));
+        Ok(Some(Node::new(0, NodeType::Html).with_span(span).with_data(data)))
+    }
+
+    /// Consume an HTML block of `kind` starting at the current line
+    ///
+    /// Types 1–5 end with the line holding their terminator (or EOF);
+    /// types 6 and 7 before the next blank line.
+    fn skip_html_block(&mut self, kind: u8) {
+        while self.pos < self.bytes.len() {
+            let line = self.text_slice(self.pos, self.find_newline());
+            if kind >= 6 && line.trim().is_empty() {
+                break;
+            }
+            self.skip_to_newline();
+            if html::block_end(kind, line) {
+                break;
+            }
+        }
+    }
+
+    fn scan_frontmatter_node(&mut self, frontmatter: &Frontmatter<'_>, start_line: u32) -> SynthResult<Option<Node>> {
+        self.skip_frontmatter(frontmatter);
+
+        let mut data = HashMap::new();
+        data.insert("value".to_string(), serde_json::json!(frontmatter.value));
+        if frontmatter.format == frontmatter::Format::Json
+            && let Ok(parsed) = serde_json::from_str::<serde_json::Value>(frontmatter.value)
+        {
+            data.insert("parsed".to_string(), parsed);
+        }
 
-        Ok(Some(Node::new(0, "code").with_span(span).with_data(data)))
+        let span = self.span(0, start_line, frontmatter.end, self.end_line(start_line));
+        Ok(Some(Node::new(0, frontmatter.format.node_type()).with_span(span).with_data(data)))
     }
 
     fn scan_thematic_break_node(&mut self, start_line: u32) -> SynthResult<Option<Node>> {
         let start_pos = self.pos;
         self.skip_to_newline();
 
-        let span = Span::from_coords(
-            start_line,
-            1,
-            start_pos as u32,
-            start_line,
-            3,
-            self.pos as u32,
-        );
+        let span = self.span(start_pos, start_line, self.block_end(start_pos), start_line);
 
-        Ok(Some(Node::new(0, "thematicBreak").with_span(span)))
+        Ok(Some(Node::new(0, NodeType::ThematicBreak).with_span(span)))
     }
 
     fn scan_blockquote_node(
@@ -448,30 +2184,14 @@ impl<'a> MarkdownParserV2<'a> {
         start_pos: usize,
         start_line: u32,
     ) -> SynthResult<Option<Node>> {
-        self.pos += 1; // skip >
-        self.skip_horizontal_space();
-
-        let text_start = self.pos;
-        let text_end = self.find_newline();
-        let text = self.text_slice(text_start, text_end);
-
-        self.skip_to_newline();
-
-        let mut data = HashMap::new();
-        data.insert("value".to_string(), serde_json::json!(text));
+        self.scan_blockquote_lines();
+        let line_start = memchr::memrchr(b'\n', &self.bytes[..start_pos]).map_or(0, |i| i + 1);
+        let end = self.block_end(start_pos);
+        self.pending_quote = Some((line_start..end, start_line));
 
-        let span = Span::from_coords(
-            start_line,
-            1,
-            start_pos as u32,
-            self.line.saturating_sub(1).max(start_line),
-            (text_end - start_pos) as u32,
-            text_end as u32,
-        );
+        let span = self.span(start_pos, start_line, end, self.end_line(start_line));
 
-        Ok(Some(
-            Node::new(0, "blockquote").with_span(span).with_data(data),
-        ))
+        Ok(Some(Node::new(0, NodeType::Blockquote).with_span(span)))
     }
 
     fn scan_list_item_node(
@@ -479,23 +2199,19 @@ impl<'a> MarkdownParserV2<'a> {
         start_pos: usize,
         start_line: u32,
     ) -> SynthResult<Option<Node>> {
-        let first = self.bytes[self.pos];
-        let ordered = first.is_ascii_digit();
-
-        if ordered {
-            while self
-                .current()
-                .map(|b| b.is_ascii_digit())
-                .unwrap_or(false)
-            {
-                self.pos += 1;
-            }
-            self.pos += 1; // skip . or )
-        } else {
-            self.pos += 1; // skip - or * or +
-        }
+        let ordered = self.bytes[self.pos].is_ascii_digit();
+        self.skip_list_marker();
 
-        self.skip_horizontal_space();
+        let mut data = HashMap::new();
+        data.insert("ordered".to_string(), serde_json::json!(ordered));
+
+        // A fence or heading right after the marker is the item's content
+        if let Some(block) = self.scan_item_block(start_line)? {
+            self.pending_block = Some(block);
+            let end = self.block_end(start_pos);
+            let span = self.span(start_pos, start_line, end, self.end_line(start_line));
+            return Ok(Some(Node::new(0, NodeType::ListItem).with_span(span).with_data(data)));
+        }
 
         // Check for task list [ ] or [x]
         let mut checked: Option<bool> = None;
@@ -517,33 +2233,146 @@ impl<'a> MarkdownParserV2<'a> {
             }
         }
 
-        let text_start = self.pos;
-        let text_end = self.find_newline();
-        let text = self.text_slice(text_start, text_end);
-
-        self.skip_to_newline();
+        let lines = self.scan_item_lines();
+        let text_end = lines.last().map_or(self.pos, |(range, _)| range.end);
+        let text = lines
+            .iter()
+            .map(|(range, _)| self.text_slice(range.start, range.end))
+            .collect::<Vec<_>>()
+            .join("\n");
 
-        let mut data = HashMap::new();
-        data.insert("ordered".to_string(), serde_json::json!(ordered));
-        data.insert("value".to_string(), serde_json::json!(text));
+        self.insert_value(&mut data, &text);
+        self.pending_inline = lines;
         if let Some(c) = checked {
             data.insert("checked".to_string(), serde_json::json!(c));
         }
 
-        let span = Span::from_coords(
-            start_line,
-            1,
-            start_pos as u32,
-            self.line.saturating_sub(1).max(start_line),
-            (text_end - start_pos) as u32,
-            text_end as u32,
-        );
+        let span = self.span(start_pos, start_line, text_end, self.end_line(start_line));
 
         Ok(Some(
-            Node::new(0, "listItem").with_span(span).with_data(data),
+            Node::new(0, NodeType::ListItem).with_span(span).with_data(data),
         ))
     }
 
+    /// Consume a list item's text: the rest of its first line and the
+    /// continuation lines after it, returning the content of each (after
+    /// its indentation) and its line
+    ///
+    /// A continuation line is any line that doesn't start another block:
+    /// `- a\n  b` is one item with the text `a\nb`, and so is the lazy
+    /// `- a\nb`, while `- a\n  - b` nests a list.
+    fn scan_item_lines(&mut self) -> Vec<(Range<usize>, u32)> {
+        let mut lines = vec![(self.pos..self.find_newline(), self.line)];
+        loop {
+            self.skip_to_newline();
+            let line_start = self.pos;
+            self.skip_horizontal_space();
+            if self.pos >= self.bytes.len() || self.interrupts_paragraph() {
+                self.pos = line_start;
+                return lines;
+            }
+            lines.push((self.pos..self.find_newline(), self.line));
+        }
+    }
+
+    /// Move past a list marker (bullet, or digits and `.`/`)`) and the
+    /// spaces after it
+    fn skip_list_marker(&mut self) {
+        while self.current().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.pos += 1;
+        self.skip_horizontal_space();
+    }
+
+    /// Fenced code or an ATX heading opened right after a list marker
+    fn scan_item_block(&mut self, start_line: u32) -> SynthResult<Option<Node>> {
+        let start_pos = self.pos;
+        match self.current() {
+            Some(b'`' | b'~') if self.is_code_fence() => {
+                self.scan_code_block_node(start_pos, start_line, self.line_indent(start_pos))
+            }
+            Some(b'#') if self.atx_heading_depth().is_some() => {
+                self.pos = start_pos;
+                self.scan_heading_node(start_pos, start_line)
+            }
+            _ => {
+                self.pos = start_pos;
+                Ok(None)
+            }
+        }
+    }
+
+    fn scan_table_node(
+        &mut self,
+        tree: &mut Tree,
+        parent: NodeId,
+        aligns: &[Align],
+        start_pos: usize,
+        start_line: u32,
+    ) -> SynthResult<NodeId> {
+        let mut data = HashMap::new();
+        let align: Vec<&str> = aligns.iter().map(|a| a.as_str()).collect();
+        data.insert("align".to_string(), serde_json::json!(align));
+
+        let table_id = tree.add_node(Node::new(0, NodeType::Table).with_data(data));
+        tree.add_child(parent, table_id)?;
+
+        // Header row
+        self.scan_table_row_node(tree, table_id, aligns.len())?;
+        // Delimiter row
+        self.skip_to_newline();
+
+        // Body rows until a blank line or another block starts
+        while self.pos < self.bytes.len() && !self.ends_table(self.pos) {
+            self.scan_table_row_node(tree, table_id, aligns.len())?;
+        }
+
+        let end_line = self.end_line(start_line);
+        let span = self.span(start_pos, start_line, self.block_end(start_pos), end_line);
+        tree.get_node_mut(table_id)?.span = Some(span);
+
+        Ok(table_id)
+    }
+
+    /// Scan one table row, padding or truncating to `columns` cells
+    ///
+    /// Cells span their trimmed text; padding cells have no span.
+    fn scan_table_row_node(
+        &mut self,
+        tree: &mut Tree,
+        table_id: NodeId,
+        columns: usize,
+    ) -> SynthResult<()> {
+        let row_start = self.pos;
+        let row_end = self.find_newline();
+        let row_line = self.line;
+        let cells = split_table_row(self.text_slice(row_start, row_end));
+
+        let span = self.span(row_start, row_line, row_end, row_line);
+        let row_id = tree.add_node(Node::new(0, NodeType::TableRow).with_span(span));
+        tree.add_child(table_id, row_id)?;
+
+        for i in 0..columns {
+            let mut data = HashMap::new();
+            let mut cell_node = Node::new(0, NodeType::TableCell);
+            match cells.get(i) {
+                Some(&cell) => {
+                    self.insert_inline_value(&mut data, cell, row_line);
+                    let start = cell.as_ptr() as usize - self.src.as_ptr() as usize;
+                    cell_node.span = Some(self.span(start, row_line, start + cell.len(), row_line));
+                }
+                None => self.insert_value(&mut data, ""),
+            }
+            let cell_id = tree.add_node(cell_node.with_data(data));
+            tree.add_child(row_id, cell_id)?;
+            self.attach_inlines(tree, cell_id)?;
+        }
+
+        self.skip_to_newline();
+        Ok(())
+    }
+
     // ============================================================
     // Block scanning → BinaryNode (for binary output)
     // ============================================================
@@ -556,30 +2385,75 @@ impl<'a> MarkdownParserV2<'a> {
         }
 
         let b = self.bytes[self.pos];
-        let start_line = self.line;
         let start_pos = self.pos;
 
-        match b {
+        let node = match b {
             b'\n' => {
                 self.pos += 1;
                 self.line += 1;
                 None
             }
-            b'#' => self.scan_heading_binary(start_pos, start_line),
-            b'`' if self.is_code_fence() => self.scan_code_block_binary(start_pos, start_line),
+            b'#' => self.scan_heading_binary(start_pos),
+            b'`' | b'~' if self.is_code_fence() => self.scan_code_block_binary(start_pos),
+            b'-' | b'+' | b';' if let Some(frontmatter) = self.frontmatter() => {
+                self.scan_frontmatter_binary(&frontmatter)
+            }
             b'-' | b'*' | b'_' if self.is_thematic_break() => {
-                self.scan_thematic_break_binary(start_line)
+                self.scan_thematic_break_binary()
+            }
+            b'-' | b'*' | b'+' if self.is_bullet_list() => {
+                self.scan_list_item_binary(start_pos)
             }
-            b'-' | b'*' | b'+' => self.scan_list_item_binary(start_pos, start_line),
-            b'>' => self.scan_blockquote_binary(start_pos, start_line),
+            b'>' => self.scan_blockquote_binary(start_pos),
             b'0'..=b'9' if self.is_ordered_list() => {
-                self.scan_list_item_binary(start_pos, start_line)
+                self.scan_list_item_binary(start_pos)
             }
-            _ => self.scan_paragraph_binary(start_pos, start_line),
+            b'<' if let Some(kind) = self.html_block_kind() => {
+                self.scan_html_binary(kind, start_pos)
+            }
+            _ => match self.table_alignments() {
+                Some(aligns) => self.scan_table_binary(aligns.len(), start_pos),
+                None => self.scan_paragraph_binary(start_pos),
+            },
+        };
+        // The same byte range as the block's span in `parse`
+        node.map(|node| BinaryNode {
+            span_start: start_pos as u32,
+            span_end: self.block_end(start_pos) as u32,
+            ..node
+        })
+    }
+
+    fn scan_table_binary(
+        &mut self,
+        columns: usize,
+        start_pos: usize,
+    ) -> Option<BinaryNode> {
+        // Header and delimiter rows
+        self.skip_to_newline();
+        self.skip_to_newline();
+
+        while self.pos < self.bytes.len() && !self.ends_table(self.pos) {
+            self.skip_to_newline();
         }
+
+        let text_end = if self.pos > start_pos && self.byte(self.pos - 1) == Some(b'\n') {
+            self.pos - 1
+        } else {
+            self.pos
+        };
+
+        Some(BinaryNode {
+            node_type: node_type::TABLE,
+            flags: columns.min(255) as u8,
+            parent: 0,
+            text_start: start_pos as u32,
+            text_len: (text_end - start_pos) as u32,
+            ..Default::default()
+        })
     }
 
-    fn scan_heading_binary(&mut self, start_pos: usize, start_line: u32) -> Option<BinaryNode> {
+    fn scan_heading_binary(&mut self, start_pos: usize) -> Option<BinaryNode> {
         let mut depth = 0u8;
         while self.current() == Some(b'#') && depth < 6 {
             self.pos += 1;
@@ -590,7 +2464,7 @@ impl<'a> MarkdownParserV2<'a> {
             Some(b' ') | Some(b'\n') | None => {}
             _ => {
                 self.pos = start_pos;
-                return self.scan_paragraph_binary(start_pos, start_line);
+                return self.scan_paragraph_binary(start_pos);
             }
         }
 
@@ -602,331 +2476,1636 @@ impl<'a> MarkdownParserV2<'a> {
         while len > 0 && matches!(self.byte(text_start + len - 1), Some(b' ' | b'\t')) {
             len -= 1;
         }
+        let len = self.heading_text(self.text_slice(text_start, text_start + len)).0.len();
 
         self.skip_to_newline();
 
         Some(BinaryNode {
             node_type: node_type::HEADING,
-            flags: depth,
+            flags: self.shift_depth(depth),
             parent: 0,
             text_start: text_start as u32,
             text_len: len as u32,
-            span_start: start_line,
-            span_end: self.line,
             ..Default::default()
         })
     }
 
-    fn scan_paragraph_binary(&mut self, start_pos: usize, start_line: u32) -> Option<BinaryNode> {
-        loop {
-            self.skip_to_newline();
+    fn scan_paragraph_binary(&mut self, start_pos: usize) -> Option<BinaryNode> {
+        let (text_end, setext) = self.skip_paragraph_lines();
+
+        if let Some(depth) = setext {
+            let text = self.text_slice(start_pos, text_end);
+            let text_start = start_pos + (text.len() - text.trim_start().len());
+            return Some(BinaryNode {
+                node_type: node_type::HEADING,
+                flags: self.shift_depth(depth),
+                parent: 0,
+                text_start: text_start as u32,
+                text_len: text.trim().len() as u32,
+                ..Default::default()
+            });
+        }
 
-            if self.pos >= self.bytes.len() {
-                break;
-            }
+        Some(BinaryNode {
+            node_type: node_type::PARAGRAPH,
+            parent: 0,
+            text_start: start_pos as u32,
+            text_len: (text_end - start_pos) as u32,
+            ..Default::default()
+        })
+    }
+
+    fn scan_code_block_binary(&mut self, _start_pos: usize) -> Option<BinaryNode> {
+        let marker = self.bytes[self.pos];
+        let fence_len = self.fence_len();
+        self.pos += fence_len;
+
+        let info_start = self.pos;
+        let info_end = self.find_newline();
+        let lang_len = (info_end - info_start).min(255);
+        self.skip_to_newline();
+
+        let code_start = self.pos;
+        let code_end = self.skip_code_block_body(marker, fence_len, 0);
+
+        Some(BinaryNode {
+            node_type: node_type::CODE,
+            flags: lang_len as u8,
+            parent: 0,
+            text_start: code_start as u32,
+            text_len: (code_end - code_start) as u32,
+            ..Default::default()
+        })
+    }
+
+    fn scan_html_binary(&mut self, kind: u8, start_pos: usize) -> Option<BinaryNode> {
+        self.skip_html_block(kind);
+        let text_end = self.block_end(start_pos);
+
+        Some(BinaryNode {
+            node_type: node_type::HTML,
+            flags: kind,
+            parent: 0,
+            text_start: start_pos as u32,
+            text_len: (text_end - start_pos) as u32,
+            ..Default::default()
+        })
+    }
+
+    fn scan_frontmatter_binary(&mut self, frontmatter: &Frontmatter<'_>) -> Option<BinaryNode> {
+        self.skip_frontmatter(frontmatter);
+
+        Some(BinaryNode {
+            node_type: node_type::FRONTMATTER,
+            flags: frontmatter.format.code(),
+            parent: 0,
+            text_start: frontmatter.value_start as u32,
+            text_len: frontmatter.value.len() as u32,
+            ..Default::default()
+        })
+    }
+
+    fn scan_thematic_break_binary(&mut self) -> Option<BinaryNode> {
+        let start_pos = self.pos;
+        self.skip_to_newline();
+
+        Some(BinaryNode {
+            node_type: node_type::THEMATIC_BREAK,
+            parent: 0,
+            text_start: start_pos as u32,
+            ..Default::default()
+        })
+    }
+
+    fn scan_blockquote_binary(&mut self, _start_pos: usize) -> Option<BinaryNode> {
+        let lines = self.scan_blockquote_lines();
+        let text_start = lines.first().map_or(self.pos, |(range, _)| range.start);
+        let text_end = lines.last().map_or(self.pos, |(range, _)| range.end);
+
+        Some(BinaryNode {
+            node_type: node_type::BLOCKQUOTE,
+            parent: 0,
+            text_start: text_start as u32,
+            text_len: (text_end - text_start) as u32,
+            ..Default::default()
+        })
+    }
+
+    fn scan_list_item_binary(&mut self, _start_pos: usize) -> Option<BinaryNode> {
+        let ordered = self.bytes[self.pos].is_ascii_digit();
+        self.skip_list_marker();
+
+        let mut flags = if ordered { 1u8 } else { 0u8 };
+        if self.current() == Some(b'[')
+            && let (Some(mark), Some(b']')) = (self.byte(self.pos + 1), self.byte(self.pos + 2))
+        {
+            flags |= match mark {
+                b'x' | b'X' => 0b10,
+                b' ' => 0b100,
+                _ => 0,
+            };
+            if flags & 0b110 != 0 {
+                self.pos += 3;
+                self.skip_horizontal_space();
+            }
+        }
+
+        let text_start = self.pos;
+        let mut text_end = self.find_newline();
+
+        if flags & 0b110 == 0 && self.is_code_fence() {
+            // The fence's lines belong to the item
+            self.skip_code_block(self.line_indent(self.pos));
+        } else if let Some((last, _)) = self.scan_item_lines().pop() {
+            text_end = last.end;
+        }
+
+        Some(BinaryNode {
+            node_type: node_type::LIST_ITEM,
+            flags,
+            parent: 0,
+            text_start: text_start as u32,
+            text_len: (text_end - text_start) as u32,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[derive(Debug, serde::Deserialize, PartialEq, Eq, serde::Serialize)]
+    struct BlockSignature {
+        #[serde(rename = "type")]
+        node_type: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        depth: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        lang: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        checked: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        ordered: Option<bool>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct GoldenFixture {
+        source: String,
+        blocks: Vec<BlockSignature>,
+    }
+
+    /// Intended Rust output for a golden fixture the TS baseline disagrees with
+    #[derive(serde::Deserialize)]
+    struct Divergence {
+        blocks: Vec<BlockSignature>,
+    }
+
+    fn golden_fixture_path() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test/fixtures/markdown-parity/golden.json")
+    }
+
+    fn divergences_path() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test/fixtures/markdown-parity/rust-divergences.json")
+    }
+
+    fn normalize_blocks(tree: &Tree) -> Vec<BlockSignature> {
+        let mut blocks: Vec<BlockSignature> = tree
+            .nodes()
+            .iter()
+            .filter(|n| !matches!(n.node_type.as_str(), "root" | "list" | "text" | "inline"))
+            .map(|n| {
+                let data = n.data.as_ref();
+                BlockSignature {
+                    node_type: n.node_type.to_string(),
+                    depth: data.and_then(|d| d.get("depth")).and_then(|v| v.as_u64()),
+                    lang: data
+                        .and_then(|d| d.get("lang"))
+                        .and_then(|v| v.as_str())
+                        .map(str::to_string),
+                    checked: data
+                        .and_then(|d| d.get("checked"))
+                        .and_then(|v| v.as_bool()),
+                    ordered: data
+                        .and_then(|d| d.get("ordered"))
+                        .and_then(|v| v.as_bool())
+                        .filter(|&o| o),
+                }
+            })
+            .collect();
+
+        blocks.sort_by(|a, b| {
+            serde_json::to_string(a)
+                .unwrap_or_default()
+                .cmp(&serde_json::to_string(b).unwrap_or_default())
+        });
+        blocks
+    }
+
+    #[test]
+    fn golden_fixtures_match_ts_baseline() {
+        let raw = fs::read_to_string(golden_fixture_path()).expect("golden.json must exist");
+        let fixtures: HashMap<String, GoldenFixture> =
+            serde_json::from_str(&raw).expect("golden.json must parse");
+
+        assert!(!fixtures.is_empty(), "golden fixtures must not be empty");
+
+        let raw = fs::read_to_string(divergences_path()).expect("rust-divergences.json must exist");
+        let divergences: HashMap<String, Divergence> =
+            serde_json::from_str(&raw).expect("rust-divergences.json must parse");
+        for (id, divergence) in &divergences {
+            let fixture = fixtures.get(id).unwrap_or_else(|| panic!("{id}: no golden fixture"));
+            assert_ne!(divergence.blocks, fixture.blocks, "{id}: divergence matches the TS baseline");
+        }
+
+        for (id, fixture) in &fixtures {
+            let mut parser = MarkdownParserV2::new(&fixture.source);
+            let tree = parser
+                .parse()
+                .unwrap_or_else(|e| panic!("{id}: parse failed: {e}"));
+            let got = normalize_blocks(&tree);
+            let expected = divergences.get(id).map_or(&fixture.blocks, |d| &d.blocks);
+            assert_eq!(
+                &got, expected,
+                "fixture {id}: Rust markdown parser must match TS baseline"
+            );
+        }
+    }
+
+    #[test]
+    fn test_escape_html_option() {
+        let options = ParseOptions {
+            escape_html: true,
+            ..Default::default()
+        };
+        let mut p =
+            MarkdownParserV2::new("<b> & \"x\"\n\n```html\n<i>\n```\n").with_options(options);
+        let tree = p.parse().unwrap();
+        let root = tree.root_id();
+
+        let para = tree.get_node(tree.children_of(root)[0]).unwrap();
+        let data = para.data.as_ref().unwrap();
+        assert_eq!(data.get("value"), Some(&serde_json::json!("&lt;b&gt; &amp; &quot;x&quot;")));
+        assert_eq!(data.get("raw"), Some(&serde_json::json!("<b> & \"x\"")));
+
+        let code = tree.get_node(tree.children_of(root)[1]).unwrap();
+        let data = code.data.as_ref().unwrap();
+        assert_eq!(data.get("value"), Some(&serde_json::json!("&lt;i&gt;\n")));
+        assert_eq!(data.get("raw"), Some(&serde_json::json!("<i>\n")));
+    }
+
+    #[test]
+    fn test_escape_html_off_by_default() {
+        let mut p = MarkdownParserV2::new("<b>\n");
+        let tree = p.parse().unwrap();
+        let para = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        let data = para.data.as_ref().unwrap();
+        assert_eq!(data.get("value"), Some(&serde_json::json!("<b>")));
+        assert!(!data.contains_key("raw"));
+    }
+
+    #[test]
+    fn test_headings_match_full_parse() {
+        let src = "# One\n\ntext\n#not a heading\n\n```\n# in code\n```\n\n## Two  \n\n- item\n> # quoted\n\n| a |\n| - |\n| b |\n### Three\n#\n";
+        let headings = MarkdownParserV2::new(src).headings();
+
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let expected: Vec<(u8, String, u32)> = tree
+            .children_of(tree.root_id())
+            .iter()
+            .map(|&id| tree.get_node(id).unwrap())
+            .filter(|n| n.node_type == NodeType::Heading)
+            .map(|n| {
+                let data = n.data.as_ref().unwrap();
+                (
+                    data["depth"].as_u64().unwrap() as u8,
+                    data["value"].as_str().unwrap().to_string(),
+                    n.span.as_ref().unwrap().start.offset,
+                )
+            })
+            .collect();
+
+        assert_eq!(headings, expected);
+        let texts: Vec<&str> = headings.iter().map(|(_, t, _)| t.as_str()).collect();
+        assert_eq!(texts, vec!["One", "Two", "Three", ""]);
+        assert_eq!(headings[1], (2, "Two".to_string(), src.find("## Two").unwrap() as u32));
+    }
+
+    fn block_types(src: &str) -> Vec<String> {
+        block_types_of(&MarkdownParserV2::new(src).parse().unwrap())
+    }
+
+    fn block_types_of(tree: &Tree) -> Vec<String> {
+        tree.children_of(tree.root_id())
+            .iter()
+            .map(|&id| tree.get_node(id).unwrap().node_type.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_dash_line_after_text_is_setext_heading() {
+        let src = "Title\n---\n";
+        assert_eq!(block_types(src), vec!["heading"]);
+
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let heading = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        let data = heading.data.as_ref().unwrap();
+        assert_eq!(data.get("depth"), Some(&serde_json::json!(2)));
+        assert_eq!(data.get("value"), Some(&serde_json::json!("Title")));
+        let span = heading.span.as_ref().unwrap();
+        assert_eq!((span.start.offset, span.end.offset), (0, 9));
+
+        assert_eq!(block_types("Title\n===\n"), vec!["heading"]);
+        assert_eq!(MarkdownParserV2::new("Title\n===\n").headings()[0].0, 1);
+    }
+
+    #[test]
+    fn test_setext_needs_a_paragraph() {
+        assert_eq!(block_types("foo\n===\n"), vec!["heading"]);
+        assert_eq!(block_types("- a\n---\n"), vec!["list", "thematicBreak"]);
+        assert_eq!(block_types("> q\n---\n"), vec!["blockquote", "thematicBreak"]);
+        assert_eq!(block_types("# h\n---\n"), vec!["heading", "thematicBreak"]);
+        assert_eq!(block_types("```\nx\n```\n---\n"), vec!["code", "thematicBreak"]);
+        // `===` after a non-paragraph block is plain text, here a lazy
+        // continuation of the item's text
+        assert_eq!(block_types("> q\n\n===\n"), vec!["blockquote", "paragraph"]);
+        assert_eq!(block_types("- a\n===\n"), vec!["list"]);
+
+        let binary_types = |src: &str| -> Vec<u8> {
+            let buf = MarkdownParserV2::new(src).parse_binary();
+            buf[8..].chunks(24).skip(1).map(|node| node[0]).collect()
+        };
+        assert_eq!(binary_types("foo\n===\n"), [node_type::HEADING]);
+        assert_eq!(binary_types("- a\n---\n"), [node_type::LIST_ITEM, node_type::THEMATIC_BREAK]);
+        assert_eq!(binary_types("> q\n---\n"), [node_type::BLOCKQUOTE, node_type::THEMATIC_BREAK]);
+    }
+
+    #[test]
+    fn test_bullet_needs_following_space() {
+        assert_eq!(block_types("*not a list*\n"), vec!["paragraph"]);
+        assert_eq!(block_types("-5 degrees\n"), vec!["paragraph"]);
+        assert_eq!(block_types("+1\n"), vec!["paragraph"]);
+        assert_eq!(block_types("-\tx\n"), vec!["list"]);
+        assert_eq!(block_types("-\n"), vec!["list"]);
+        assert_eq!(block_types("-"), vec!["list"]);
+
+        // Such lines don't interrupt a paragraph either
+        assert_eq!(block_types("text\n-5 degrees\n*more*\n"), vec!["paragraph"]);
+        assert_eq!(block_types("text\n* item\n"), vec!["paragraph", "list"]);
+        assert_eq!(block_types("text\n***\n"), vec!["paragraph", "thematicBreak"]);
+
+        let tree = MarkdownParserV2::new("*not a list*\n").parse().unwrap();
+        let para = tree.children_of(tree.root_id())[0];
+        let emphasis = tree.get_node(tree.children_of(para)[0]).unwrap();
+        assert_eq!(emphasis.node_type, NodeType::Emphasis);
+
+        let buf = MarkdownParserV2::new("-5 degrees\n").parse_binary();
+        assert_eq!(buf[8 + 24], node_type::PARAGRAPH);
+        assert_eq!(MarkdownParserV2::new("*x*\n---\n").headings().len(), 1);
+    }
+
+    #[test]
+    fn test_dash_marker_precedence() {
+        assert_eq!(block_types("- - -\n"), vec!["thematicBreak"]);
+        assert_eq!(block_types("text\n- - -\n"), vec!["paragraph", "thematicBreak"]);
+        assert_eq!(block_types("- x\n"), vec!["list"]);
+        assert_eq!(block_types("text\n- x\n"), vec!["paragraph", "list"]);
+        assert_eq!(block_types("---\n"), vec!["thematicBreak"]);
+        // `_` is never a list marker
+        assert_eq!(block_types("_ x\n"), vec!["paragraph"]);
+        assert_eq!(block_types("___\n"), vec!["thematicBreak"]);
+    }
+
+    #[test]
+    fn test_setext_underline_forms() {
+        assert_eq!(block_types("Title\n   ---  \n"), vec!["heading"]);
+        assert_eq!(block_types("Title\n----------\n"), vec!["heading"]);
+        // Not underlines: inner spaces, four-space indent, a blank line between
+        assert_eq!(block_types("Title\n= =\n"), vec!["paragraph"]);
+        assert_eq!(block_types("Title\n    ---\n"), vec!["paragraph"]);
+        assert_eq!(block_types("Title\n\n---\n"), vec!["paragraph", "thematicBreak"]);
+
+        // Every paragraph line is heading text; the span ends on the underline
+        let tree = MarkdownParserV2::new("Multi\nline\n===\n").parse().unwrap();
+        let heading = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        assert_eq!(heading.data.as_ref().unwrap()["value"], "Multi\nline");
+        let span = heading.span.as_ref().unwrap();
+        assert_eq!((span.start.line, span.end.line, span.end.offset), (1, 3, 14));
+    }
+
+    #[test]
+    fn test_setext_heading_binary() {
+        let buf = MarkdownParserV2::new("Title\n---\n").parse_binary();
+        let node = &buf[8 + 24..8 + 48];
+        assert_eq!(node[0], node_type::HEADING);
+        assert_eq!(node[1], 2);
+        assert_eq!(u32::from_le_bytes(node[8..12].try_into().unwrap()), 0);
+        assert_eq!(u32::from_le_bytes(node[12..16].try_into().unwrap()), 5);
+    }
+
+    #[test]
+    fn test_parse_count_convention() {
+        let src = "# Hi\n\ntext\n\n- a\n- b\n\n> quote\n\n| a |\n|---|\n| 1 |\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        assert_eq!(MarkdownParserV2::new(src).parse_count(), tree.node_count());
+        assert_eq!(MarkdownParserV2::new(src).parse_count_excluding_root(), tree.node_count() - 1);
+        assert_eq!(MarkdownParserV2::new("").parse_count(), 1);
+        assert_eq!(MarkdownParserV2::new("").parse_count_excluding_root(), 0);
+
+        // root, heading, paragraph, two list items, blockquote, table
+        assert_eq!(MarkdownParserV2::new(src).block_count(), 7);
+        assert!(MarkdownParserV2::new(src).block_count() < tree.node_count());
+    }
+
+    fn first_code_value(src: &str) -> String {
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let code = tree
+            .nodes()
+            .iter()
+            .find(|n| n.node_type == NodeType::Code)
+            .unwrap();
+        code.data.as_ref().unwrap()["value"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_info_string_line_does_not_close_fence() {
+        let src = "```md\nbefore\n```js\nafter\n```\n";
+        assert_eq!(first_code_value(src), "before\n```js\nafter\n");
+        assert_eq!(block_types(src), vec!["code"]);
+    }
+
+    #[test]
+    fn test_closing_fence_rules() {
+        // Trailing whitespace is allowed on the closing fence
+        assert_eq!(first_code_value("```\nx\n```   \ny\n"), "x\n");
+        // Backticks mid-line don't close
+        assert_eq!(first_code_value("```\na ``` b\n```\n"), "a ``` b\n");
+        // The closing run must be at least as long as the opening one
+        assert_eq!(first_code_value("````\n```\n````\n"), "```\n");
+        // Indented up to three spaces closes; four doesn't
+        assert_eq!(first_code_value("```\nx\n   ```\n"), "x\n");
+        assert_eq!(first_code_value("```\nx\n    ```\n"), "x\n    ```\n");
+    }
+
+    #[test]
+    fn test_tilde_fences() {
+        // Only the opening marker closes the block
+        assert_eq!(first_code_value("~~~\n```\nx\n```\n~~~\n"), "```\nx\n```\n");
+        assert_eq!(first_code_value("```\n~~~\n```\n"), "~~~\n");
+        assert_eq!(first_code_value("~~~~\n~~~\n~~~~~\n"), "~~~\n");
+        assert_eq!(block_types("~~~\na\n~~~\ntext\n"), ["code", "paragraph"]);
+        assert_eq!(block_types("para\n~~~\ncode\n~~~\n"), ["paragraph", "code"]);
+        assert_eq!(block_types("~~ not a fence\n"), ["paragraph"]);
+
+        let tree = MarkdownParserV2::new("~~~ rust\nfn main() {}\n~~~\n").parse().unwrap();
+        let code = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        assert_eq!(code.data.as_ref().unwrap()["lang"], "rust");
+
+        // `#` and `[x]: y` lines inside are code
+        let src = "~~~\n# not a heading\n[x]: /y\n~~~\n[x]\n";
+        assert!(MarkdownParserV2::new(src).headings().is_empty());
+        assert_eq!(reference::definition_lines(src), []);
+
+        let buf = MarkdownParserV2::new("~~~js\nx\n~~~\n").parse_binary();
+        assert_eq!((buf[8 + 24], buf[8 + 25]), (node_type::CODE, 2));
+    }
+
+    #[test]
+    fn test_code_indentation_preserved() {
+        let python = "def f(x):\n    if x:\n        return 1\n\n    return 0\n";
+        assert_eq!(first_code_value(&format!("```python\n{python}```\n")), python);
+        // Trailing whitespace and tabs inside the code are kept too
+        assert_eq!(first_code_value("```\n\tx  \n  y\n```\n"), "\tx  \n  y\n");
+
+        // An indented fence strips up to its own indent from each line
+        assert_eq!(
+            first_code_value("  ```python\n  def f():\n      pass\n x\ny\n  ```\n"),
+            "def f():\n    pass\nx\ny\n"
+        );
+    }
+
+    #[test]
+    fn test_adjacent_fences() {
+        let src = "```js\nlet a;\n```\n```python\nb = 1\n```\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        assert_eq!(block_types_of(&tree), ["code", "code"]);
+        let codes: Vec<_> = tree
+            .children_of(tree.root_id())
+            .iter()
+            .map(|&id| {
+                let data = tree.get_node(id).unwrap().data.as_ref().unwrap();
+                (data["lang"].as_str().unwrap(), data["value"].as_str().unwrap())
+            })
+            .collect();
+        assert_eq!(codes, [("js", "let a;\n"), ("python", "b = 1\n")]);
+
+        let buf = MarkdownParserV2::new(src).parse_binary();
+        assert_eq!(u32::from_le_bytes(buf[0..4].try_into().unwrap()), 3);
+
+        // The second block's content isn't scanned as markdown either
+        let headings = MarkdownParserV2::new("```\n# a\n```\n```\n# b\n```\n# c\n").headings();
+        assert_eq!(headings.len(), 1);
+        assert_eq!(headings[0].1, "c");
+    }
+
+    #[test]
+    fn test_strict_unclosed_fence() {
+        let src = "# Doc\n\n```js\nlet a;\n";
+        // The default parse runs the code block to the end
+        assert_eq!(first_code_value(src), "let a;\n");
+
+        let options = ParseOptions { strict: true, ..Default::default() };
+        let mut parser = MarkdownParserV2::new(src).with_options(options.clone());
+        let err = parser.parse().unwrap_err().to_string();
+        assert!(err.contains("Unclosed code fence at offset 7"), "{err}");
+        assert_eq!(parser.diagnostics().len(), 1);
+
+        let closed = "```js\nlet a;\n```";
+        assert!(MarkdownParserV2::new(closed).with_options(options).parse().is_ok());
+    }
+
+    #[test]
+    fn test_strict_unresolved_reference() {
+        let src = "[see][missing] and [ok][]\n\n[ok]: /ok\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        assert!(tree.get_node(tree.root_id()).unwrap().data.as_ref().unwrap().contains_key("unresolvedReferences"));
+
+        let options = ParseOptions { strict: true, ..Default::default() };
+        let mut parser = MarkdownParserV2::new(src).with_options(options.clone());
+        let err = parser.parse().unwrap_err().to_string();
+        assert!(err.contains("Unresolved reference [missing] at offset 0"), "{err}");
+        assert_eq!(parser.diagnostics().len(), 1);
+
+        // Shortcuts without a definition are plain text, not failures
+        assert!(MarkdownParserV2::new("[ok][] [plain]\n\n[ok]: /ok\n").with_options(options.clone()).parse().is_ok());
+        let eager = ParseOptions { references: References::Eager, ..options };
+        assert!(MarkdownParserV2::new("[late][x]\n\n[x]: /x\n").with_options(eager.clone()).parse().is_ok());
+        assert!(MarkdownParserV2::new("[see][missing]\n").with_options(eager).parse().is_err());
+    }
+
+    #[test]
+    fn test_closing_fence_binary() {
+        let buf = MarkdownParserV2::new("```\n```js\n```\n").parse_binary();
+        assert_eq!(u32::from_le_bytes(buf[0..4].try_into().unwrap()), 2);
+        let node = &buf[8 + 24..8 + 48];
+        assert_eq!(node[0], node_type::CODE);
+        assert_eq!(u32::from_le_bytes(node[12..16].try_into().unwrap()), 6);
+    }
+
+    #[test]
+    fn test_type_histogram_of_document() {
+        let src = "# A\n\ntext\n\n```\nx\n```\n\n## B\n\nmore\n\n```rs\ny\n```\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let histogram = tree.type_histogram();
+        assert_eq!(histogram["heading"], 2);
+        assert_eq!(histogram["paragraph"], 2);
+        assert_eq!(histogram["code"], 2);
+    }
+
+    /// (type, value, start offset, end line, end offset) of the first block
+    fn first_block(src: &str) -> (String, Option<String>, u32, u32, u32) {
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let mut node = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        if node.node_type == NodeType::List {
+            node = tree.get_node(node.children[0]).unwrap();
+        }
+        let value = node
+            .data
+            .as_ref()
+            .and_then(|d| d.get("value"))
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        let span = node.span.as_ref().unwrap();
+        (node.node_type.to_string(), value, span.start.offset, span.end.line, span.end.offset)
+    }
+
+    #[test]
+    fn test_markers_at_eof_without_newline() {
+        let some = |v: &str| Some(v.to_string());
+        assert_eq!(first_block("# Title"), ("heading".into(), some("Title"), 0, 1, 7));
+        assert_eq!(first_block("#"), ("heading".into(), some(""), 0, 1, 1));
+        assert_eq!(first_block("- item"), ("listItem".into(), some("item"), 0, 1, 6));
+        assert_eq!(first_block("- "), ("listItem".into(), some(""), 0, 1, 2));
+        assert_eq!(first_block("1. x"), ("listItem".into(), some("x"), 0, 1, 4));
+        assert_eq!(first_block("1."), ("listItem".into(), some(""), 0, 1, 2));
+        assert_eq!(first_block("> q"), ("blockquote".into(), None, 0, 1, 3));
+        assert_eq!(first_block("a\nb"), ("paragraph".into(), some("a\nb"), 0, 2, 3));
+        assert_eq!(first_block("Title\n==="), ("heading".into(), some("Title"), 0, 2, 9));
+        assert_eq!(first_block("```js\ncode"), ("code".into(), some("code"), 0, 2, 10));
+        assert_eq!(first_block("---"), ("thematicBreak".into(), None, 0, 1, 3));
+    }
+
+    #[test]
+    fn test_end_line_with_trailing_newline() {
+        assert_eq!(first_block("a\nb\n").3, 2);
+        assert_eq!(first_block("```\nx\n```\n").3, 3);
+    }
+
+    #[test]
+    fn test_parse_tree() {
+        let mut p = MarkdownParserV2::new("# Hello\n\nWorld\n");
+        let tree = p.parse().unwrap();
+        assert!(tree.node_count() >= 3); // root + heading + paragraph
+    }
+
+    #[test]
+    fn test_root_children_are_top_level_blocks_in_order() {
+        let mut p = MarkdownParserV2::new("# Title\n\nText\n\n- item\n\n---\n");
+        let tree = p.parse().unwrap();
+
+        assert_eq!(tree.root_id(), 0);
+        assert_eq!(tree.nodes()[0].node_type, "root");
+        let types: Vec<&str> = tree
+            .children_of(tree.root_id())
+            .iter()
+            .map(|&id| tree.get_node(id).unwrap().node_type.as_str())
+            .collect();
+        assert_eq!(types, ["heading", "paragraph", "list", "thematicBreak"]);
+        assert!(tree
+            .children_of(tree.root_id())
+            .iter()
+            .all(|&id| tree.get_node(id).unwrap().parent == Some(tree.root_id())));
+    }
+
+    fn table_align(src: &str) -> Option<Vec<String>> {
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let table = tree.nodes().iter().find(|n| n.node_type == "table")?;
+        let align = table.data.as_ref().unwrap().get("align").unwrap();
+        Some(serde_json::from_value(align.clone()).unwrap())
+    }
+
+    #[test]
+    fn test_table_alignment() {
+        let align = table_align("| a | b | c | d |\n|:-:|:--|--:|---|\n| 1 | 2 | 3 | 4 |\n");
+        assert_eq!(align.unwrap(), ["center", "left", "right", "none"]);
+
+        // Outer pipes are optional
+        let align = table_align("a | b\n:-: | -\n");
+        assert_eq!(align.unwrap(), ["center", "none"]);
+    }
+
+    #[test]
+    fn test_table_structure() {
+        let tree = MarkdownParserV2::new("| a | b |\n|---|---|\n| 1 |\n| 2 | 3 | 4 |\n\ntext\n")
+            .parse()
+            .unwrap();
+        let root_types: Vec<&str> = tree
+            .children_of(tree.root_id())
+            .iter()
+            .map(|&id| tree.get_node(id).unwrap().node_type.as_str())
+            .collect();
+        assert_eq!(root_types, ["table", "paragraph"]);
+
+        let table = tree.children_of(tree.root_id())[0];
+        let rows = tree.children_of(table);
+        assert_eq!(rows.len(), 3);
+        for &row in rows {
+            // Short rows are padded and long rows truncated to the header width
+            assert_eq!(tree.children_of(row).len(), 2);
+        }
+        let cell = tree.get_node(tree.children_of(rows[2])[1]).unwrap();
+        assert_eq!(cell.data.as_ref().unwrap().get("value"), Some(&serde_json::json!("3")));
+    }
+
+    #[test]
+    fn test_table_cells() {
+        let src = "a | `b` |\n--|:-:\n  *1*  |2\\|3\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let table = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        assert_eq!(table.data.as_ref().unwrap()["align"], serde_json::json!(["none", "center"]));
+
+        let cells: Vec<_> = table
+            .children
+            .iter()
+            .flat_map(|&row| tree.children_of(row))
+            .map(|&id| {
+                let cell = tree.get_node(id).unwrap();
+                let span = cell.span.unwrap();
+                assert_eq!(span.start.line, span.end.line);
+                (cell.data.as_ref().unwrap()["value"].clone(), &src[span.start.offset as usize..span.end.offset as usize])
+            })
+            .collect();
+        assert_eq!(cells, [
+            (serde_json::json!("a"), "a"),
+            (serde_json::json!("`b`"), "`b`"),
+            (serde_json::json!("*1*"), "*1*"),
+            (serde_json::json!("2\\|3"), "2\\|3"),
+        ]);
+        let inline = tree.children_of(tree.children_of(table.children[0])[1])[0];
+        assert_eq!(tree.get_node(inline).unwrap().node_type, NodeType::InlineCode);
+
+        // Only escaped pipes in the header: not a table
+        assert!(table_align("a \\| b\n---\n").is_none());
+    }
+
+    #[test]
+    fn test_table_indentation() {
+        assert!(table_align("   | a | b |\n   | - | - |\n").is_some());
+        assert!(table_align("    | a | b |\n    | - | - |\n").is_none());
+    }
+
+    #[test]
+    fn test_table_column_mismatch_is_paragraph() {
+        let src = "| a | b |\n|---|---|---|\n";
+        assert!(table_align(src).is_none());
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        assert_eq!(tree.get_node(tree.children_of(0)[0]).unwrap().node_type, "paragraph");
+
+        let buf = MarkdownParserV2::new(src).parse_binary();
+        assert_eq!(buf[8 + BINARY_NODE_SIZE], node_type::PARAGRAPH);
+        let buf = MarkdownParserV2::new("| a | b |\n|---|:-:|\n").parse_binary();
+        assert_eq!(buf[8 + BINARY_NODE_SIZE], node_type::TABLE);
+        assert_eq!(buf[8 + BINARY_NODE_SIZE + 1], 2);
+    }
+
+    #[test]
+    fn test_parse_binary() {
+        let mut p = MarkdownParserV2::new("# Hello\n");
+        let buf = p.parse_binary();
+
+        // Check header
+        let node_count = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        assert_eq!(node_count, 2); // root + heading
+    }
+
+    #[test]
+    fn test_binary_spans_are_tree_offsets() {
+        let src = "# Hello\n\nSome *text*\nmore\n\n```js\nx\n```\n\n---\n> quote\n\n| a |\n|---|\n| 1 |\n";
+        let buf = MarkdownParserV2::new(src).parse_binary();
+        assert!(validate_binary(&buf).is_ok());
+        let spans: Vec<_> = buf[8..]
+            .chunks(BINARY_NODE_SIZE)
+            .map(|node| {
+                let u32_at = |at: usize| u32::from_le_bytes(node[at..at + 4].try_into().unwrap());
+                (u32_at(16), u32_at(20))
+            })
+            .collect();
+
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let mut expected = vec![(0, src.len() as u32)];
+        expected.extend(tree.children_of(tree.root_id()).iter().map(|&id| {
+            let span = tree.get_node(id).unwrap().span.unwrap();
+            (span.start.offset, span.end.offset)
+        }));
+        assert_eq!(spans, expected);
+    }
+
+    #[test]
+    fn test_frontmatter() {
+        let frontmatter = |src: &str| {
+            let tree = MarkdownParserV2::new(src).parse().unwrap();
+            let node = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap().clone();
+            (node.node_type.to_string(), node.data.unwrap()["value"].clone(), block_types_of(&tree))
+        };
+
+        let (kind, value, blocks) = frontmatter("+++\ntitle = \"Hi\"\ndraft = true\n+++\n# Body\n");
+        assert_eq!((kind.as_str(), value), ("toml", serde_json::json!("title = \"Hi\"\ndraft = true")));
+        assert_eq!(blocks, ["toml", "heading"]);
+
+        let src = "---\ntitle: Hi\nlist:\n  - a\n---\n\nText\n";
+        let (kind, value, blocks) = frontmatter(src);
+        assert_eq!((kind.as_str(), value), ("yaml", serde_json::json!("title: Hi\nlist:\n  - a")));
+        assert_eq!(blocks, ["yaml", "paragraph"]);
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let span = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap().span.unwrap();
+        assert_eq!((span.start.offset, span.end.offset, span.end.line), (0, 29, 5));
+
+        let tree = MarkdownParserV2::new(";;;\n{ \"title\": \"Hi\" }\n;;;\n").parse().unwrap();
+        let json = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        assert_eq!(json.node_type, NodeType::Json);
+        assert_eq!(json.data.as_ref().unwrap()["parsed"]["title"], "Hi");
+
+        // Unclosed, or not at the start: markdown as usual
+        assert_eq!(block_types("---\ntitle: Hi\n"), ["thematicBreak", "paragraph"]);
+        assert_eq!(block_types("Intro\n\n---\na\n---\n"), ["paragraph", "thematicBreak", "heading"]);
+        assert_eq!(block_types("\n---\na: 1\n---\n"), ["thematicBreak", "heading"]);
+        assert_eq!(block_types(" ---\na: 1\n---\n"), ["thematicBreak", "heading"]);
+
+        // Empty, and closed at the end of the source
+        let (kind, value, blocks) = frontmatter("---\n---\nText\n");
+        assert_eq!((kind.as_str(), value), ("yaml", serde_json::json!("")));
+        assert_eq!(blocks, ["yaml", "paragraph"]);
+        let (_, value, blocks) = frontmatter("---\nkey: value\n---");
+        assert_eq!((value, blocks), (serde_json::json!("key: value"), vec!["yaml".to_string()]));
+
+        // The `---`-underlined line inside isn't a heading
+        let mut parser = MarkdownParserV2::new("---\ntitle\n---\n# Real\n");
+        assert_eq!(parser.headings(), [(1, "Real".to_string(), 14)]);
+
+        let buf = MarkdownParserV2::new("+++\na = 1\n+++\n").parse_binary();
+        assert!(validate_binary(&buf).is_ok());
+        let node = &buf[8 + 24..8 + 48];
+        assert_eq!((node[0], node[1]), (node_type::FRONTMATTER, 1));
+        assert_eq!(u32::from_le_bytes(node[8..12].try_into().unwrap()), 4);
+        assert_eq!(u32::from_le_bytes(node[12..16].try_into().unwrap()), 5);
+    }
+
+    #[test]
+    fn test_validate_binary() {
+        let buf = MarkdownParserV2::new("# Hello\n\ntext\n\n- a\n- b\n").parse_binary();
+        assert!(validate_binary(&buf).is_ok());
+        assert!(validate_binary(&MarkdownParserV2::new("").parse_binary()).is_ok());
+
+        let message = |buf: &[u8]| validate_binary(buf).unwrap_err().to_string();
+        assert_eq!(
+            message(&buf[..buf.len() - 5]),
+            "Serialization error: binary buffer has 123 bytes, but its header declares 5 nodes (128 bytes)"
+        );
+        assert!(message(&buf[..3]).contains("too short"));
+
+        let mut corrupt = buf.clone();
+        corrupt[8 + 2 * BINARY_NODE_SIZE + 4..][..4].copy_from_slice(&7u32.to_le_bytes());
+        assert_eq!(
+            message(&corrupt),
+            "Serialization error: node 2 has parent 7, which is not an earlier node"
+        );
+
+        let mut corrupt = buf.clone();
+        corrupt[8 + BINARY_NODE_SIZE + 12..][..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(message(&corrupt).starts_with("Serialization error: node 1 text"));
+    }
+
+    #[test]
+    fn test_whitespace_only_produces_root_only() {
+        for src in ["", "   ", "\n\n\n"] {
+            let mut p = MarkdownParserV2::new(src);
+            assert!(p.is_whitespace_only());
+            let tree = p.parse().unwrap();
+            assert_eq!(tree.node_count(), 1, "tree for {src:?}");
+
+            let mut p = MarkdownParserV2::new(src);
+            let buf = p.parse_binary();
+            let node_count = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+            assert_eq!(node_count, 1, "binary for {src:?}");
+            assert_eq!(buf.len(), 8 + BINARY_NODE_SIZE);
+
+            let mut p = MarkdownParserV2::new(src);
+            assert_eq!(p.parse_count(), 1, "count for {src:?}");
+        }
+    }
+
+    #[test]
+    fn test_heading() {
+        let mut p = MarkdownParserV2::new("# Hello\n");
+        let tree = p.parse().unwrap();
+
+        let heading = tree.nodes().iter().find(|n| n.node_type == "heading");
+        assert!(heading.is_some());
+
+        let data = heading.unwrap().data.as_ref().unwrap();
+        assert_eq!(data.get("depth"), Some(&serde_json::json!(1)));
+        assert_eq!(data.get("value"), Some(&serde_json::json!("Hello")));
+    }
+
+    #[test]
+    fn test_code_block() {
+        let mut p = MarkdownParserV2::new("```rust\ncode\n```\n");
+        let tree = p.parse().unwrap();
+
+        let code = tree.nodes().iter().find(|n| n.node_type == "code");
+        assert!(code.is_some());
+
+        let data = code.unwrap().data.as_ref().unwrap();
+        assert_eq!(data.get("lang"), Some(&serde_json::json!("rust")));
+        assert_eq!(data.get("value"), Some(&serde_json::json!("code\n")));
+    }
+
+    /// (spread, item count) of each top-level list
+    fn lists(src: &str) -> Vec<(bool, usize)> {
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        tree.children_of(tree.root_id())
+            .iter()
+            .map(|&id| tree.get_node(id).unwrap())
+            .filter(|n| n.node_type == NodeType::List)
+            .map(|n| {
+                let spread = n.data.as_ref().unwrap()["spread"].as_bool().unwrap();
+                (spread, n.children.len())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_list_item_opening_a_block() {
+        let src = "- ```js\n  let a = 1;\n  # not a heading\n  ```\n- # Title\n- plain\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let list = tree.children_of(tree.root_id())[0];
+        let items = tree.children_of(list);
+        assert_eq!(items.len(), 3);
+
+        let code = tree.get_node(tree.children_of(items[0])[0]).unwrap();
+        assert_eq!(code.node_type, NodeType::Code);
+        let data = code.data.as_ref().unwrap();
+        assert_eq!((&data["lang"], &data["value"]), (&serde_json::json!("js"), &serde_json::json!("let a = 1;\n# not a heading\n")));
+        let item = tree.get_node(items[0]).unwrap();
+        assert!(!item.data.as_ref().unwrap().contains_key("value"));
+        assert_eq!(item.span.unwrap().end.offset as usize, src.find("\n- #").unwrap());
+
+        let heading = tree.get_node(tree.children_of(items[1])[0]).unwrap();
+        assert_eq!(heading.node_type, NodeType::Heading);
+        assert_eq!(heading.data.as_ref().unwrap()["value"], "Title");
+        assert_eq!(tree.get_node(tree.children_of(heading.id)[0]).unwrap().node_type, NodeType::Text);
+
+        // Nested: the closing fence lines up with the item's content
+        let nested = "- a\n  - ~~~\n    x\n    ~~~\n- b\n";
+        let tree = MarkdownParserV2::new(nested).parse().unwrap();
+        assert_eq!(first_code_value(nested), "x\n");
+        assert_eq!(tree.children_of(tree.children_of(tree.root_id())[0]).len(), 2);
+
+        // Without a tree, the fence's lines still belong to the item
+        assert!(MarkdownParserV2::new(src).headings().is_empty());
+        let buf = MarkdownParserV2::new(src).parse_binary();
+        let types: Vec<u8> = buf[8..].chunks(24).skip(1).map(|node| node[0]).collect();
+        assert_eq!(types, [node_type::LIST_ITEM; 3]);
+    }
+
+    #[test]
+    fn test_list_grouping() {
+        assert_eq!(lists("- a\n- b\n- c\n"), vec![(false, 3)]);
+        // A new bullet character or ordered delimiter starts a new list
+        assert_eq!(lists("- a\n* b\n1. c\n2) d\n"), vec![(false, 1); 4]);
+        assert_eq!(lists("- a\n\ntext\n\n- b\n"), vec![(false, 1), (false, 1)]);
+
+        let tree = MarkdownParserV2::new("3. x\n4. y\n").parse().unwrap();
+        let list = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        let data = list.data.as_ref().unwrap();
+        assert_eq!(data["ordered"], serde_json::json!(true));
+        assert_eq!(data["start"], serde_json::json!(3));
+        let span = list.span.unwrap();
+        assert_eq!((span.start.offset, span.end.offset, span.end.line), (0, 9, 2));
+    }
+
+    #[test]
+    fn test_list_spread() {
+        // Tight: items on consecutive lines
+        assert_eq!(lists("- a\n- b\n"), vec![(false, 2)]);
+        // Loose: a blank line between any two items
+        assert_eq!(lists("- a\n- b\n\n- c\n"), vec![(true, 3)]);
+        assert_eq!(lists("1. a\n\n\n2. b"), vec![(true, 2)]);
+
+        let tree = MarkdownParserV2::new("- a\n\n- b\n").parse().unwrap();
+        let items: Vec<_> = tree.nodes().iter().filter(|n| n.node_type == NodeType::ListItem).collect();
+        assert_eq!(items.len(), 2);
+        for item in items {
+            assert_eq!(item.data.as_ref().unwrap()["spread"], serde_json::json!(false));
+        }
+    }
+
+    #[test]
+    fn test_list_spread_mixed_gaps() {
+        // One blank line makes the whole list loose, wherever it is
+        assert_eq!(lists("- a\n\n- b\n- c\n"), vec![(true, 3)]);
+        assert_eq!(lists("1. a\n\n2. b\n3. c\n4. d\n"), vec![(true, 4)]);
+        // Blank lines after the last item don't
+        assert_eq!(lists("- a\n- b\n\n\nText\n"), vec![(false, 2)]);
+        // A blank line between another list's items doesn't either
+        assert_eq!(lists("- a\n- b\n\n* c\n* d\n"), vec![(false, 2), (false, 2)]);
+
+        // Loose inner list, tight outer one
+        let src = "- a\n  - x\n\n  - y\n- b\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let spread = |id: NodeId| tree.get_node(id).unwrap().data.as_ref().unwrap()["spread"].clone();
+        let outer = tree.children_of(tree.root_id())[0];
+        let [a, _] = tree.children_of(outer)[..] else { panic!() };
+        let inner = *tree.children_of(a).last().unwrap();
+        assert_eq!((spread(outer), spread(a), spread(inner)), (false.into(), false.into(), true.into()));
+
+        // Every item of a loose list gets a paragraph, tight gaps included
+        let tree = MarkdownParserV2::new("- a\n\n- b\n- c\n").parse().unwrap();
+        let list = tree.children_of(tree.root_id())[0];
+        for &item in tree.children_of(list) {
+            let [paragraph] = tree.children_of(item)[..] else { panic!() };
+            assert_eq!(tree.get_node(paragraph).unwrap().node_type, NodeType::Paragraph);
+        }
+    }
+
+    #[test]
+    fn test_lazy_blockquote_continuation() {
+        let src = "> quoted *a*\n> b\nlazy\n\n> c\n# after\n";
+        // Blocks, with a quote's first child standing in for it
+        let quote_values = |lazy_blockquotes| {
+            let options = ParseOptions { lazy_blockquotes, ..Default::default() };
+            let tree = MarkdownParserV2::new(src).with_options(options).parse().unwrap();
+            let values: Vec<_> = tree
+                .children_of(tree.root_id())
+                .iter()
+                .map(|&id| {
+                    let node = tree.get_node(id).unwrap();
+                    let content = match node.node_type {
+                        NodeType::Blockquote => tree.get_node(node.children[0]).unwrap(),
+                        _ => node,
+                    };
+                    let value = content.data.as_ref().and_then(|d| d.get("value")).and_then(|v| v.as_str());
+                    (node.node_type.to_string(), value.unwrap_or_default().to_string())
+                })
+                .collect();
+            (tree, values)
+        };
 
-            let b = self.bytes[self.pos];
-            match b {
-                b'\n' | b'#' | b'>' | b'-' | b'*' | b'+' | b'`' => break,
-                b'0'..=b'9' if self.is_ordered_list() => break,
-                _ => {}
-            }
-        }
+        let (tree, lazy) = quote_values(true);
+        assert_eq!(lazy, [
+            ("blockquote".to_string(), "quoted *a*\nb\nlazy".to_string()),
+            ("blockquote".to_string(), "c".to_string()),
+            ("heading".to_string(), "after".to_string()),
+        ]);
+        let quote = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        let span = quote.span.unwrap();
+        assert_eq!((span.start.line, span.end.line, span.end.offset as usize), (1, 3, src.find("\n\n").unwrap()));
+        let paragraph = tree.get_node(quote.children[0]).unwrap();
+        assert_eq!(quote.children.len(), 1);
+        let span = paragraph.span.unwrap();
+        assert_eq!((span.start.offset, span.start.column, span.end.line), (2, 2, 3));
+        let inline: Vec<_> = paragraph
+            .children
+            .iter()
+            .map(|&id| tree.get_node(id).unwrap().node_type.to_string())
+            .collect();
+        assert_eq!(inline, ["text", "emphasis", "text", "text", "text", "text"]);
+
+        let (_, strict) = quote_values(false);
+        assert_eq!(strict[0], ("blockquote".to_string(), "quoted *a*\nb".to_string()));
+        assert_eq!(strict[1], ("paragraph".to_string(), "lazy".to_string()));
+        assert_eq!(strict.len(), 4);
+
+        // Headings don't take lazy lines, and other blocks end the quote
+        assert_eq!(block_types("> # h\ntext\n"), ["blockquote", "paragraph"]);
+        assert_eq!(block_types("> a\n- item\n"), ["blockquote", "list"]);
+        assert_eq!(MarkdownParserV2::new("> a\nb\n===\n").headings(), []);
+        assert_eq!(block_types("> a\nb\n===\n"), ["blockquote"]);
+    }
 
-        let text_end = if self.pos > 0 && self.byte(self.pos - 1) == Some(b'\n') {
-            self.pos - 1
-        } else {
-            self.pos
+    #[test]
+    fn test_blockquote_blocks() {
+        let src = "> # Title\n> - one\n>   - two\n>\n> > nested\n> > > deep\nlazy\n>\n> ```js\n> x\n> ```\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        assert_eq!(block_types(src), ["blockquote"]);
+        let quote = tree.children_of(tree.root_id())[0];
+        let types = |tree: &Tree, id| -> Vec<String> {
+            tree.children_of(id).iter().map(|&c| tree.get_node(c).unwrap().node_type.to_string()).collect()
         };
+        assert_eq!(types(&tree, quote), ["heading", "list", "blockquote", "code"]);
+        let [heading, list, nested, code] = tree.children_of(quote)[..] else { panic!() };
+        let value = |tree: &Tree, id| {
+            let node = tree.get_node(id).unwrap();
+            node.data.as_ref().unwrap()["value"].as_str().unwrap().to_string()
+        };
+        assert_eq!(value(&tree, heading), "Title");
+        assert_eq!(value(&tree, code), "x\n");
+        let item = tree.children_of(list)[0];
+        assert_eq!(value(&tree, item), "one");
+        assert_eq!(types(&tree, item), ["text", "list"]);
+
+        // `> >` nests, and the lazy line continues the innermost paragraph
+        assert_eq!(types(&tree, nested), ["paragraph", "blockquote"]);
+        let deep = tree.children_of(nested)[1];
+        let paragraph = tree.children_of(deep)[0];
+        assert_eq!(value(&tree, paragraph), "deep\nlazy");
+        let span = tree.get_node(paragraph).unwrap().span.unwrap();
+        let offset = src.find("deep").unwrap() as u32;
+        assert_eq!((span.start.line, span.start.column, span.start.offset), (6, 6, offset));
+        assert_eq!(span.end.line, 7);
+
+        // Blocks interrupt quoted paragraphs, and nesting doesn't indent
+        let tree = MarkdownParserV2::new("> a\n> - b\n\n> > ```\n> > y\n> > ```\n").parse().unwrap();
+        let [first, second] = tree.children_of(tree.root_id())[..] else { panic!() };
+        assert_eq!(types(&tree, first), ["paragraph", "list"]);
+        let inner = tree.children_of(second)[0];
+        let code = tree.children_of(inner)[0];
+        assert_eq!(value(&tree, code), "y\n");
+
+        // Fragment offsets carry through
+        let tree = MarkdownParserV2::new("> > x").parse_fragment(10).unwrap();
+        let paragraph = tree.nodes().iter().find(|n| n.node_type == NodeType::Paragraph).unwrap();
+        assert_eq!(paragraph.span.unwrap().start.offset, 14);
+    }
 
-        Some(BinaryNode {
-            node_type: node_type::PARAGRAPH,
-            parent: 0,
-            text_start: start_pos as u32,
-            text_len: (text_end - start_pos) as u32,
-            span_start: start_line,
-            span_end: self.line,
+    #[test]
+    fn test_blockquote_shares_document_state() {
+        let src = "Before^[one]\n\n> [x]: /x\n> [a][x] and^[two]\n\n[x]\n";
+        let options = ParseOptions {
+            footnotes: true,
+            inline_footnotes: true,
+            references: References::Eager,
             ..Default::default()
-        })
+        };
+        let mut parser = MarkdownParserV2::new(src).with_options(options);
+        let tree = parser.parse().unwrap();
+        let links: Vec<_> = tree.nodes().iter().filter(|n| n.node_type == NodeType::Link).collect();
+        assert_eq!(links.len(), 2);
+        let labels: Vec<_> = tree
+            .children_of(tree.root_id())
+            .iter()
+            .map(|&id| tree.get_node(id).unwrap())
+            .filter(|n| n.node_type == NodeType::FootnoteDefinition)
+            .map(|n| n.data.as_ref().unwrap()["label"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(labels, ["1", "2"]);
+        assert!(parser.unresolved_references().is_empty());
     }
 
-    fn scan_code_block_binary(&mut self, _start_pos: usize, start_line: u32) -> Option<BinaryNode> {
-        self.pos += 3;
+    #[test]
+    fn test_item_paragraphs() {
+        let item_children = |src: &str, item_paragraphs| {
+            let options = ParseOptions { item_paragraphs, ..Default::default() };
+            let tree = MarkdownParserV2::new(src).with_options(options).parse().unwrap();
+            let type_of = |id| tree.get_node(id).unwrap().node_type.to_string();
+            tree.nodes()
+                .iter()
+                .filter(|n| n.node_type == NodeType::ListItem)
+                .map(|item| item.children.iter().map(|&c| type_of(c)).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
 
-        let info_start = self.pos;
-        let info_end = self.find_newline();
-        let lang_len = (info_end - info_start).min(255);
-        self.skip_to_newline();
+        // Loose items hold a paragraph, tight ones their inline nodes
+        let src = "- *a*\n\n- b\n  - c\n";
+        assert_eq!(item_children(src, ItemParagraphs::Loose), [
+            vec!["paragraph"],
+            vec!["paragraph", "list"],
+            vec!["text"],
+        ]);
+        assert_eq!(item_children(src, ItemParagraphs::Always), [
+            vec!["paragraph"],
+            vec!["paragraph", "list"],
+            vec!["paragraph"],
+        ]);
+        assert_eq!(item_children(src, ItemParagraphs::Never), [
+            vec!["emphasis"],
+            vec!["text", "list"],
+            vec!["text"],
+        ]);
+
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let paragraph = tree.nodes().iter().find(|n| n.node_type == NodeType::Paragraph).unwrap();
+        assert_eq!(paragraph.data.as_ref().unwrap()["value"], serde_json::json!("*a*"));
+        let span = paragraph.span.unwrap();
+        assert_eq!((span.start.offset, span.end.offset), (2, 5));
+        assert_eq!(tree.get_node(paragraph.children[0]).unwrap().node_type, NodeType::Emphasis);
+    }
 
-        let code_start = self.pos;
+    #[test]
+    fn test_nested_task_list() {
+        let src = "- [x] parent\n  - [ ] child\n  - plain\n- [ ] sibling\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let data = |id: NodeId| tree.get_node(id).unwrap().data.clone().unwrap();
+
+        let outer = tree.children_of(tree.root_id())[0];
+        assert_eq!(data(outer)["taskList"], serde_json::json!(true));
+        let [parent, sibling] = tree.children_of(outer) else { panic!() };
+        assert_eq!(data(*parent)["checked"], serde_json::json!(true));
+        assert_eq!(data(*sibling)["checked"], serde_json::json!(false));
+
+        let inner = *tree.children_of(*parent).last().unwrap();
+        assert_eq!(tree.get_node(inner).unwrap().node_type, NodeType::List);
+        assert_eq!(data(inner)["taskList"], serde_json::json!(true));
+        let [child, plain] = tree.children_of(inner) else { panic!() };
+        assert_eq!(data(*child)["checked"], serde_json::json!(false));
+        assert!(!data(*plain).contains_key("checked"));
+
+        // The parent item and its list span the nested list
+        let parent_span = tree.get_node(*parent).unwrap().span.unwrap();
+        assert_eq!(parent_span.end.offset as usize, src.find("\n- [ ]").unwrap());
+
+        // Not indented past the marker: a sibling, not a child
+        assert_eq!(lists("- a\n - b\n"), vec![(false, 2)]);
+        // No task items: no flag
+        let tree = MarkdownParserV2::new("- a\n  - b\n").parse().unwrap();
+        let list = tree.children_of(tree.root_id())[0];
+        assert!(!tree.get_node(list).unwrap().data.as_ref().unwrap().contains_key("taskList"));
+    }
 
-        loop {
-            if self.pos >= self.bytes.len() {
-                break;
-            }
+    #[test]
+    fn test_list_item_continuation_lines() {
+        let src = "- Intro\n  continues here\n  - Setup\n    and *more*\n    - Deep\n- Usage\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let value = |id: NodeId| tree.get_node(id).unwrap().data.as_ref().unwrap()["value"].clone();
+        let kids = |id: NodeId| tree.children_of(id).to_vec();
+
+        assert_eq!(block_types(src), ["list"]);
+        let outer = kids(tree.root_id())[0];
+        let [intro, usage] = kids(outer)[..] else { panic!() };
+        assert_eq!(value(intro), "Intro\ncontinues here");
+        assert_eq!(value(usage), "Usage");
+
+        let intro_list = *kids(intro).last().unwrap();
+        let [setup] = kids(intro_list)[..] else { panic!() };
+        assert_eq!(value(setup), "Setup\nand *more*");
+        assert!(kids(setup).iter().any(|&c| tree.get_node(c).unwrap().node_type == NodeType::Emphasis));
+        let setup_list = *kids(setup).last().unwrap();
+        assert_eq!(value(kids(setup_list)[0]), "Deep");
+
+        // Text ends on the continuation line
+        let text = kids(intro)[2];
+        let span = tree.get_node(text).unwrap().span.unwrap();
+        assert_eq!(&src[span.start.offset as usize..span.end.offset as usize], "continues here");
+
+        // Starting a block: not a continuation
+        assert_eq!(block_types("- a\n  # b\n"), ["list", "heading"]);
+        // Headings and the binary format see the same item
+        assert_eq!(MarkdownParserV2::new("- a\n  # b\n").headings().len(), 1);
+        assert_eq!(MarkdownParserV2::new(src).headings().len(), 0);
+    }
 
-            match memchr(b'`', &self.bytes[self.pos..]) {
-                Some(i) => {
-                    let tick_pos = self.pos + i;
-                    if self.byte(tick_pos + 1) == Some(b'`') && self.byte(tick_pos + 2) == Some(b'`')
-                    {
-                        let code_end = tick_pos;
-                        let newlines =
-                            memchr::memchr_iter(b'\n', &self.bytes[self.pos..tick_pos]).count();
-                        self.line += newlines as u32;
+    #[test]
+    fn test_list_item_body() {
+        let src = "- first *line*\nlazy line\n\n  Second paragraph\n  continues\n\n  - nested\n\n    Nested paragraph\n\nAfter\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let node = |id: NodeId| tree.get_node(id).unwrap();
+        let value = |id: NodeId| node(id).data.as_ref().unwrap()["value"].clone();
+        let types = |id: NodeId| -> Vec<String> {
+            tree.children_of(id).iter().map(|&c| node(c).node_type.to_string()).collect()
+        };
 
-                        self.pos = tick_pos + 3;
-                        self.skip_to_newline();
+        assert_eq!(block_types_of(&tree), ["list", "paragraph"]);
+        let [list, after] = tree.children_of(tree.root_id())[..] else { panic!() };
+        assert_eq!(value(after), "After");
+        let [item] = tree.children_of(list)[..] else { panic!() };
+        assert_eq!(value(item), "first *line*\nlazy line");
+        assert_eq!(types(item), ["paragraph", "paragraph", "list"]);
+
+        // A loose list, so the item's own text is a paragraph too
+        assert_eq!(node(list).data.as_ref().unwrap()["spread"], serde_json::json!(true));
+        let [first, second, nested] = tree.children_of(item)[..] else { panic!() };
+        assert_eq!(value(first), "first *line*\nlazy line");
+        assert_eq!(value(second), "Second paragraph\ncontinues");
+        let span = node(second).span.unwrap();
+        assert_eq!(&src[span.start.offset as usize..span.end.offset as usize], "Second paragraph\n  continues");
+
+        let [nested_item] = tree.children_of(nested)[..] else { panic!() };
+        assert_eq!(types(nested_item), ["paragraph", "paragraph"]);
+        assert_eq!(value(tree.children_of(nested_item)[1]), "Nested paragraph");
+
+        // The item and its list end with the nested paragraph
+        let end = src.find("\n\nAfter").unwrap() as u32;
+        assert_eq!(node(item).span.unwrap().end.offset, end);
+        assert_eq!(node(list).span.unwrap().end.offset, end);
+
+        // Without a blank line, indented text continues the item's text
+        let tight = MarkdownParserV2::new("- a\n  b\n- c\n").parse().unwrap();
+        let list = tight.children_of(tight.root_id())[0];
+        assert_eq!(tight.get_node(list).unwrap().data.as_ref().unwrap()["spread"], serde_json::json!(false));
+        // Unindented text after a blank line ends the list
+        assert_eq!(block_types("- a\n\nb\n"), ["list", "paragraph"]);
+    }
 
-                        return Some(BinaryNode {
-                            node_type: node_type::CODE,
-                            flags: lang_len as u8,
-                            parent: 0,
-                            text_start: code_start as u32,
-                            text_len: (code_end.saturating_sub(code_start)) as u32,
-                            span_start: start_line,
-                            span_end: self.line,
-                            ..Default::default()
-                        });
-                    }
-                    self.pos = tick_pos + 1;
-                }
-                None => {
-                    let code_end = self.bytes.len();
-                    let newlines = memchr::memchr_iter(b'\n', &self.bytes[self.pos..]).count();
-                    self.line += newlines as u32;
-                    self.pos = self.bytes.len();
-
-                    return Some(BinaryNode {
-                        node_type: node_type::CODE,
-                        flags: lang_len as u8,
-                        parent: 0,
-                        text_start: code_start as u32,
-                        text_len: (code_end - code_start) as u32,
-                        span_start: start_line,
-                        span_end: self.line,
-                        ..Default::default()
-                    });
-                }
-            }
+    #[test]
+    fn test_nested_list_after_blank_line_is_spread() {
+        let src = "- a\n\n  - b\n- c\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let outer = tree.children_of(tree.root_id())[0];
+        let item = tree.children_of(outer)[0];
+        for id in [outer, item] {
+            let data = tree.get_node(id).unwrap().data.as_ref().unwrap();
+            assert_eq!(data["spread"], serde_json::json!(true));
         }
-
-        None
     }
 
-    fn scan_thematic_break_binary(&mut self, start_line: u32) -> Option<BinaryNode> {
-        let start_pos = self.pos;
-        self.skip_to_newline();
-
-        Some(BinaryNode {
-            node_type: node_type::THEMATIC_BREAK,
-            parent: 0,
-            text_start: start_pos as u32,
-            span_start: start_line,
-            span_end: start_line,
-            ..Default::default()
-        })
+    #[test]
+    fn test_html_block_ends_at_blank_line() {
+        let src = "<div>\n\n# Heading\n\n</div>\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        assert_eq!(block_types_of(&tree), ["html", "heading", "html"]);
+        let values: Vec<_> = tree
+            .children_of(tree.root_id())
+            .iter()
+            .map(|&id| tree.get_node(id).unwrap().data.as_ref().unwrap().get("value").cloned())
+            .collect();
+        assert_eq!(values[0], Some(serde_json::json!("<div>")));
+        assert_eq!(values[2], Some(serde_json::json!("</div>")));
+
+        // Without blank lines the whole block is raw
+        assert_eq!(block_types("<div>\n# Not a heading\n</div>\n"), ["html"]);
+        // Type 6 interrupts a paragraph, type 7 doesn't
+        assert_eq!(block_types("text\n<div>\n"), ["paragraph", "html"]);
+        assert_eq!(block_types("text\n<span>\n"), ["paragraph"]);
     }
 
-    fn scan_blockquote_binary(&mut self, _start_pos: usize, start_line: u32) -> Option<BinaryNode> {
-        self.pos += 1;
-        self.skip_horizontal_space();
-
-        let text_start = self.pos;
-        let text_end = self.find_newline();
-
-        self.skip_to_newline();
+    #[test]
+    fn test_raw_html_block_keeps_blank_lines() {
+        let src = "<script>\nlet a = 1;\n\n# not a heading\n</script>\nafter\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        assert_eq!(block_types_of(&tree), ["html", "paragraph"]);
+        let html = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        assert_eq!(
+            html.data.as_ref().unwrap()["value"],
+            serde_json::json!("<script>\nlet a = 1;\n\n# not a heading\n</script>")
+        );
+        assert_eq!(html.span.unwrap().end.line, 5);
 
-        Some(BinaryNode {
-            node_type: node_type::BLOCKQUOTE,
-            parent: 0,
-            text_start: text_start as u32,
-            text_len: (text_end - text_start) as u32,
-            span_start: start_line,
-            span_end: self.line,
-            ..Default::default()
-        })
+        assert_eq!(MarkdownParserV2::new(src).block_count(), 3);
+        assert!(MarkdownParserV2::new(src).headings().is_empty());
     }
 
-    fn scan_list_item_binary(&mut self, _start_pos: usize, start_line: u32) -> Option<BinaryNode> {
-        let first = self.bytes[self.pos];
-        let ordered = first.is_ascii_digit();
-
-        if ordered {
-            while self
-                .current()
-                .map(|b| b.is_ascii_digit())
-                .unwrap_or(false)
-            {
-                self.pos += 1;
-            }
-            self.pos += 1;
-        } else {
-            self.pos += 1;
-        }
+    #[test]
+    fn test_comments() {
+        let src = "<!-- prettier-ignore\n\n# not a heading\n-->\n\nSee <!-- TODO:\nlink --> *here*.\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        assert_eq!(block_types_of(&tree), ["html", "paragraph"]);
+
+        let data = |id| tree.get_node(id).unwrap().data.clone().unwrap();
+        let block = data(tree.children_of(tree.root_id())[0]);
+        assert_eq!(block["value"], serde_json::json!("<!-- prettier-ignore\n\n# not a heading\n-->"));
+        assert_eq!(block["comment"], serde_json::json!("prettier-ignore\n\n# not a heading"));
+
+        let paragraph = tree.children_of(tree.root_id())[1];
+        let inline: Vec<_> = tree.children_of(paragraph).to_vec();
+        let types: Vec<_> = inline.iter().map(|&id| tree.get_node(id).unwrap().node_type.to_string()).collect();
+        assert_eq!(types, ["text", "html", "text", "emphasis", "text"]);
+        assert_eq!(data(inline[1])["comment"], serde_json::json!("TODO:\nlink"));
+        let span = tree.get_node(inline[1]).unwrap().span.unwrap();
+        assert_eq!(&src[span.start.offset as usize..span.end.offset as usize], "<!-- TODO:\nlink -->");
+
+        // Only a block that is exactly one comment gets `comment`
+        let tree = MarkdownParserV2::new("<!-- a --> trailing\n").parse().unwrap();
+        let block = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        assert!(!block.data.as_ref().unwrap().contains_key("comment"));
+    }
 
-        self.skip_horizontal_space();
+    #[test]
+    fn test_parse_until_sentinel() {
+        let src = "# Doc\n\ntext\n:::end\n\nafter\n";
+        let mut parser = MarkdownParserV2::new(src);
+        let tree = parser.parse_until(|line| line == ":::end").unwrap();
+        assert_eq!(parser.consumed_len(), src.find(":::end").unwrap());
+        assert_eq!(block_types_of(&tree), ["heading", "paragraph"]);
+
+        // Nothing matches: runs to EOF
+        let mut parser = MarkdownParserV2::new(src);
+        parser.parse_until(|line| line == "missing").unwrap();
+        assert_eq!(parser.consumed_len(), src.len());
+    }
 
-        let mut flags = if ordered { 1u8 } else { 0u8 };
-        if self.current() == Some(b'[')
-            && let (Some(mark), Some(b']')) = (self.byte(self.pos + 1), self.byte(self.pos + 2))
-        {
-            flags |= match mark {
-                b'x' | b'X' => 0b10,
-                b' ' => 0b100,
-                _ => 0,
+    #[test]
+    fn test_parse_fragment_offsets() {
+        let src = "---\ntitle: x\n---\n\n- a\n- *b* [c][missing]\n\n```\ncode\n```";
+        let doc = MarkdownParserV2::new(src).parse_fragment(0).unwrap();
+        let mut parser = MarkdownParserV2::new(src);
+        let fragment = parser.parse_fragment(100).unwrap();
+
+        // Same structure and lines, offsets shifted by the base
+        assert_eq!(fragment.node_count(), doc.node_count());
+        for (a, b) in doc.nodes().iter().zip(fragment.nodes()) {
+            assert_eq!(a.node_type, b.node_type);
+            assert_eq!(a.children, b.children);
+            let (Some(a), Some(b)) = (a.span, b.span) else {
+                assert_eq!(a.span, b.span);
+                continue;
             };
-            if flags & 0b110 != 0 {
-                self.pos += 3;
-                self.skip_horizontal_space();
-            }
+            assert_eq!((b.start.offset, b.end.offset), (a.start.offset + 100, a.end.offset + 100));
+            assert_eq!((b.start.line, b.start.column), (a.start.line, a.start.column));
         }
+        let emphasis = fragment.nodes().iter().find(|n| n.node_type == NodeType::Emphasis).unwrap();
+        assert_eq!(emphasis.span.unwrap().start.offset as usize, 100 + src.find("*b*").unwrap());
+        assert_eq!(parser.unresolved_references()[0].1.start.offset as usize, 100 + src.find("[c]").unwrap());
 
-        let text_start = self.pos;
-        let text_end = self.find_newline();
-
-        self.skip_to_newline();
-
-        Some(BinaryNode {
-            node_type: node_type::LIST_ITEM,
-            flags,
-            parent: 0,
-            text_start: text_start as u32,
-            text_len: (text_end - text_start) as u32,
-            span_start: start_line,
-            span_end: self.line,
-            ..Default::default()
-        })
+        // A leading `---` block is markdown, not frontmatter
+        assert_eq!(block_types_of(&fragment), ["thematicBreak", "heading", "list", "code"]);
+        assert_eq!(block_types(src), ["yaml", "list", "code"]);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::HashMap;
-    use std::fs;
-    use std::path::PathBuf;
+    #[test]
+    fn test_shifted_fragment_matches_in_place_parse() {
+        let intro = "Intro\n\n";
+        let fragment = "# Title\n\n- a\n- *b* `c`\n\n> quoted\n> > nested\n";
+        let doc = MarkdownParserV2::new(&format!("{intro}{fragment}")).parse().unwrap();
+        let mut shifted = MarkdownParserV2::new(fragment).parse().unwrap();
+        shifted.shift_spans(intro.len() as i64, intro.matches('\n').count() as i64);
+
+        // After the root, the intro paragraph and its text; the fragment's
+        // nodes follow in order
+        let in_place = &doc.nodes()[3..];
+        assert_eq!(in_place.len(), shifted.node_count() - 1);
+        for (a, b) in in_place.iter().zip(&shifted.nodes()[1..]) {
+            assert_eq!(a.node_type, b.node_type);
+            assert_eq!(a.span, b.span, "{}", a.node_type);
+        }
+    }
 
-    #[derive(Debug, serde::Deserialize, PartialEq, Eq, serde::Serialize)]
-    struct BlockSignature {
-        #[serde(rename = "type")]
-        node_type: String,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        depth: Option<u64>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        lang: Option<String>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        checked: Option<bool>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        ordered: Option<bool>,
+    #[test]
+    fn test_max_nodes_truncates() {
+        let src = "para\n\n".repeat(100);
+        let options = ParseOptions {
+            max_nodes: Some(20),
+            ..Default::default()
+        };
+        let mut parser = MarkdownParserV2::new(&src).with_options(options);
+        let tree = parser.parse().unwrap();
+
+        // Each paragraph adds two nodes (paragraph + text)
+        assert!(tree.node_count() > 20 && tree.node_count() <= 22);
+        assert_eq!(tree.root().data.as_ref().unwrap()["truncated"], serde_json::json!(true));
+        let diagnostics = parser.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("truncated"));
+        assert!(src[diagnostics[0].start as usize..].trim_start().starts_with("para"));
+
+        // Under the cap: no truncation
+        let options = ParseOptions {
+            max_nodes: Some(1000),
+            ..Default::default()
+        };
+        let mut parser = MarkdownParserV2::new(&src).with_options(options);
+        let tree = parser.parse().unwrap();
+        assert_eq!(tree.node_count(), 201);
+        assert!(tree.root().data.is_none());
+        assert!(parser.diagnostics().is_empty());
     }
 
-    #[derive(serde::Deserialize)]
-    struct GoldenFixture {
-        source: String,
-        blocks: Vec<BlockSignature>,
+    fn corners(span: &Span) -> [(u32, u32, u32); 2] {
+        [
+            (span.start.line, span.start.column, span.start.offset),
+            (span.end.line, span.end.column, span.end.offset),
+        ]
     }
 
-    fn golden_fixture_path() -> PathBuf {
-        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("../../test/fixtures/markdown-parity/golden.json")
+    #[test]
+    fn test_heading_span_corners() {
+        let src = "intro\n\n  ## Title\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let heading = tree.nodes().iter().find(|n| n.node_type == NodeType::Heading).unwrap();
+        let span = heading.span.unwrap();
+        // `## Title` starts at line 3, column 2 and ends before the newline
+        assert_eq!(corners(&span), [(3, 2, 9), (3, 10, 17)]);
+        assert_eq!(&src[9..17], "## Title");
+
+        // Setext heading ends on its underline line
+        let src = "Title\n=====\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let span = tree.get_node(tree.children_of(0)[0]).unwrap().span.unwrap();
+        assert_eq!(corners(&span), [(1, 0, 0), (2, 5, 11)]);
     }
 
-    fn normalize_blocks(tree: &Tree) -> Vec<BlockSignature> {
-        let mut blocks: Vec<BlockSignature> = tree
-            .nodes()
+    #[test]
+    fn test_block_spans_exclude_trailing_newline() {
+        let src = "```\nx\n```\n***\n";
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let spans: Vec<_> = tree
+            .children_of(0)
             .iter()
-            .filter(|n| !matches!(n.node_type.as_str(), "root" | "text" | "inline"))
-            .map(|n| {
-                let data = n.data.as_ref();
-                BlockSignature {
-                    node_type: n.node_type.clone(),
-                    depth: data.and_then(|d| d.get("depth")).and_then(|v| v.as_u64()),
-                    lang: data
-                        .and_then(|d| d.get("lang"))
-                        .and_then(|v| v.as_str())
-                        .map(str::to_string),
-                    checked: data
-                        .and_then(|d| d.get("checked"))
-                        .and_then(|v| v.as_bool()),
-                    ordered: data
-                        .and_then(|d| d.get("ordered"))
-                        .and_then(|v| v.as_bool())
-                        .filter(|&o| o),
-                }
-            })
+            .map(|&id| corners(&tree.get_node(id).unwrap().span.unwrap()))
             .collect();
-
-        blocks.sort_by(|a, b| {
-            serde_json::to_string(a)
-                .unwrap_or_default()
-                .cmp(&serde_json::to_string(b).unwrap_or_default())
-        });
-        blocks
+        assert_eq!(spans, [[(1, 0, 0), (3, 3, 9)], [(4, 0, 10), (4, 3, 13)]]);
     }
 
     #[test]
-    fn golden_fixtures_match_ts_baseline() {
-        let raw = fs::read_to_string(golden_fixture_path()).expect("golden.json must exist");
-        let fixtures: HashMap<String, GoldenFixture> =
-            serde_json::from_str(&raw).expect("golden.json must parse");
-
-        assert!(!fixtures.is_empty(), "golden fixtures must not be empty");
-
-        for (id, fixture) in &fixtures {
-            let mut parser = MarkdownParserV2::new(&fixture.source);
-            let tree = parser
-                .parse()
-                .unwrap_or_else(|e| panic!("{id}: parse failed: {e}"));
-            let got = normalize_blocks(&tree);
-            assert_eq!(
-                got, fixture.blocks,
-                "fixture {id}: Rust markdown parser must match TS baseline"
-            );
-        }
+    fn test_pretty_json_is_deterministic() {
+        let src = "# T\n\n- [x] *a* [l][r]\n\n| a | b |\n|:-|-:|\n| 1 | 2 |\n\n[r]: /u \"t\"\n";
+        let pretty = || MarkdownParserV2::new(src).parse().unwrap().to_json_pretty().unwrap();
+        let json = pretty();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert_eq!(pretty(), json);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["meta"]["source"], src);
     }
 
     #[test]
-    fn test_parse_tree() {
-        let mut p = MarkdownParserV2::new("# Hello\n\nWorld\n");
-        let tree = p.parse().unwrap();
-        assert!(tree.node_count() >= 3); // root + heading + paragraph
+    fn test_content_ids_survive_reparse() {
+        let options = || ParseOptions { content_ids: true, ..Default::default() };
+        let ids = |src: &str| {
+            let tree = MarkdownParserV2::new(src).with_options(options()).parse().unwrap();
+            tree.nodes()
+                .iter()
+                .map(|n| (n.node_type.to_string(), n.data.as_ref().unwrap()["contentId"].as_str().unwrap().to_string()))
+                .collect::<Vec<_>>()
+        };
+        let src = "# Title\n\nFirst *para*.\n\n- item\n\nLast para.\n";
+        let first = ids(src);
+        assert_eq!(first, ids(src));
+
+        // Editing one block keeps the other blocks' IDs, though offsets moved
+        let edited = ids("# Title!\n\nFirst *para*.\n\n- item\n\nLast para.\n");
+        let blocks = |ids: &[(String, String)]| -> Vec<String> {
+            ids.iter().filter(|(t, _)| t != "root").map(|(_, id)| id.clone()).collect()
+        };
+        let (before, after) = (blocks(&first), blocks(&edited));
+        assert_eq!(before.len(), after.len());
+        assert_ne!(before[0], after[0]);
+        assert_eq!(before[2..], after[2..]);
+        assert_ne!(first[0], edited[0]);
     }
 
     #[test]
-    fn test_parse_binary() {
-        let mut p = MarkdownParserV2::new("# Hello\n");
-        let buf = p.parse_binary();
+    fn test_column_units() {
+        let src = "# 😀 *hi*\n";
+        let columns = |unit| {
+            let options = ParseOptions { column_unit: unit, ..Default::default() };
+            let tree = MarkdownParserV2::new(src).with_options(options).parse().unwrap();
+            let heading = tree.nodes().iter().find(|n| n.node_type == NodeType::Heading).unwrap();
+            let emphasis = tree.nodes().iter().find(|n| n.node_type == NodeType::Emphasis).unwrap();
+            let (heading, emphasis) = (heading.span.unwrap(), emphasis.span.unwrap());
+            assert_eq!((emphasis.start.offset, emphasis.end.offset), (7, 11));
+            (emphasis.start.column, emphasis.end.column, heading.end.column)
+        };
+        assert_eq!(columns(ColumnUnit::Byte), (7, 11, 11));
+        assert_eq!(columns(ColumnUnit::Utf16), (5, 9, 9));
+        assert_eq!(columns(ColumnUnit::Char), (4, 8, 8));
+        assert_eq!(ParseOptions::default().column_unit, ColumnUnit::Utf16);
+    }
 
-        // Check header
-        let node_count = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
-        assert_eq!(node_count, 2); // root + heading
+    #[test]
+    fn test_offset_span_mode() {
+        let options = ParseOptions {
+            spans: SpanMode::Offset,
+            ..Default::default()
+        };
+        let tree = MarkdownParserV2::new("a\n\n# *b*\n").with_options(options).parse().unwrap();
+        for node in &tree.nodes()[1..] {
+            let span = node.span.unwrap();
+            assert_eq!((span.start.line, span.start.column, span.end.line, span.end.column), (0, 0, 0, 0));
+        }
+        let emphasis = tree.nodes().iter().find(|n| n.node_type == NodeType::Emphasis).unwrap();
+        assert_eq!(corners(&emphasis.span.unwrap()), [(0, 0, 5), (0, 0, 8)]);
     }
 
     #[test]
-    fn test_heading() {
-        let mut p = MarkdownParserV2::new("# Hello\n");
-        let tree = p.parse().unwrap();
+    fn test_heading_shift() {
+        let src = "# One\n\n## Two\n\n### Three\n\n###### Six\n\nSetext\n---\n";
+        let options = ParseOptions {
+            heading_shift: 1,
+            ..Default::default()
+        };
+        let tree = MarkdownParserV2::new(src).with_options(options.clone()).parse().unwrap();
+        let depths: Vec<_> = tree
+            .nodes()
+            .iter()
+            .filter(|n| n.node_type == NodeType::Heading)
+            .map(|n| {
+                let data = n.data.as_ref().unwrap();
+                (data["depth"].as_u64().unwrap(), data.get("originalDepth").and_then(|v| v.as_u64()))
+            })
+            .collect();
+        assert_eq!(
+            depths,
+            [(2, Some(1)), (3, Some(2)), (4, Some(3)), (6, None), (3, Some(2))]
+        );
 
-        let heading = tree.nodes().iter().find(|n| n.node_type == "heading");
-        assert!(heading.is_some());
+        let headings = MarkdownParserV2::new(src).with_options(options.clone()).headings();
+        let depths: Vec<u8> = headings.iter().map(|h| h.0).collect();
+        assert_eq!(depths, [2, 3, 4, 6, 3]);
 
-        let data = heading.unwrap().data.as_ref().unwrap();
-        assert_eq!(data.get("depth"), Some(&serde_json::json!(1)));
-        assert_eq!(data.get("value"), Some(&serde_json::json!("Hello")));
+        let buf = MarkdownParserV2::new("# One\n").with_options(options).parse_binary();
+        assert_eq!(buf[8 + 24 + 1], 2);
+
+        // Negative shifts clamp at 1
+        let options = ParseOptions {
+            heading_shift: -2,
+            ..Default::default()
+        };
+        let headings = MarkdownParserV2::new(src).with_options(options).headings();
+        let depths: Vec<u8> = headings.iter().map(|h| h.0).collect();
+        assert_eq!(depths, [1, 1, 1, 4, 1]);
     }
 
-    #[test]
-    fn test_code_block() {
-        let mut p = MarkdownParserV2::new("```rust\ncode\n```\n");
-        let tree = p.parse().unwrap();
+    /// A flag that becomes set on its `n`th check, as if a host set it
+    /// partway through the parse
+    struct SetOnCheck(Cell<usize>);
 
-        let code = tree.nodes().iter().find(|n| n.node_type == "code");
-        assert!(code.is_some());
+    impl CancelSignal for SetOnCheck {
+        fn is_cancelled(&self) -> bool {
+            self.0.set(self.0.get().saturating_sub(1));
+            self.0.get() == 0
+        }
+    }
 
-        let data = code.unwrap().data.as_ref().unwrap();
-        assert_eq!(data.get("lang"), Some(&serde_json::json!("rust")));
-        assert_eq!(data.get("value"), Some(&serde_json::json!("code\n")));
+    #[test]
+    fn test_cancel_flag_stops_parse() {
+        let src = "para\n\n".repeat(1000);
+        let cancel = AtomicBool::new(false);
+        let mut parser = MarkdownParserV2::new(&src).with_cancel_flag(&cancel);
+        let tree = parser.parse().unwrap();
+        assert_eq!(tree.children_of(0).len(), 1000);
+        assert!(parser.diagnostics().is_empty());
+
+        // Set partway: stops at the next check with the blocks parsed so
+        // far (each blank line is scanned as a block too)
+        let cancel = SetOnCheck(Cell::new(3));
+        let mut parser = MarkdownParserV2::new(&src).with_cancel_flag(&cancel);
+        let tree = parser.parse().unwrap();
+        assert_eq!(tree.children_of(0).len(), 3 * CANCEL_CHECK_INTERVAL / 2);
+        assert_eq!(tree.root().data.as_ref().unwrap()["cancelled"], serde_json::json!(true));
+        assert_eq!(parser.diagnostics()[0].message, "Parsing cancelled");
+        assert_eq!(parser.consumed_len(), parser.diagnostics()[0].start as usize);
+
+        // Already set: stops at the first check
+        let cancel = AtomicBool::new(true);
+        let mut parser = MarkdownParserV2::new(&src).with_cancel_flag(&cancel);
+        assert_eq!(parser.parse().unwrap().children_of(0).len(), CANCEL_CHECK_INTERVAL / 2);
+
+        // From another thread
+        let cancel = AtomicBool::new(false);
+        let src = "para\n\n".repeat(200_000);
+        std::thread::scope(|scope| {
+            scope.spawn(|| cancel.store(true, Ordering::Relaxed));
+            let mut parser = MarkdownParserV2::new(&src).with_cancel_flag(&cancel);
+            let tree = parser.parse().unwrap();
+            let cancelled = tree.root().data.is_some();
+            assert_eq!(cancelled, tree.children_of(0).len() < 200_000);
+        });
     }
 
     #[test]
@@ -941,4 +4120,69 @@ mod tests {
         assert_eq!(data.get("ordered"), Some(&serde_json::json!(false)));
         assert_eq!(data.get("checked"), Some(&serde_json::json!(true)));
     }
+
+    #[test]
+    fn test_code_parser_attaches_sub_tree() {
+        let src = "```js
+let x = 1;
+x;
+```
+
+```py
+x = 1
+```
+";
+        let tree = MarkdownParserV2::new(src)
+            .with_code_parser("js", |code| synth_wasm_js::parse_tree(code, false))
+            .parse()
+            .unwrap();
+
+        let code: Vec<_> = tree.nodes().iter().filter(|n| n.node_type == NodeType::Code).collect();
+        assert_eq!(code.len(), 2);
+        let program = tree.get_node(code[0].children[0]).unwrap();
+        assert_eq!(program.node_type, "Program");
+        assert_eq!(program.parent, Some(code[0].id));
+        let statements: Vec<_> = program
+            .children
+            .iter()
+            .map(|&id| tree.get_node(id).unwrap().node_type.to_string())
+            .collect();
+        assert_eq!(statements, ["VariableDeclaration", "ExpressionStatement"]);
+        assert!(code[1].children.is_empty());
+
+        // A failing sub-parser leaves the block alone, or fails a strict parse
+        let failing = |_: &str| Err(SynthError::ParseError("bad".into()));
+        let tree = MarkdownParserV2::new(src).with_code_parser("py", failing).parse().unwrap();
+        assert!(tree.nodes().iter().all(|n| n.node_type != NodeType::Code || n.children.is_empty()));
+        let strict = ParseOptions { strict: true, ..Default::default() };
+        let mut parser = MarkdownParserV2::new(src).with_options(strict).with_code_parser("py", failing);
+        assert!(parser.parse().is_err());
+        assert_eq!(parser.diagnostics()[0].message, "Could not parse py code: Parse error: bad");
+    }
+
+    #[test]
+    fn test_heading_attributes() {
+        let src = "## T {#x .c key=val}\n\n# Plain {#y}\n";
+        let options = ParseOptions { attributes: true, ..Default::default() };
+        let tree = MarkdownParserV2::new(src).with_options(options.clone()).parse().unwrap();
+        let heading = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        let data = heading.data.as_ref().unwrap();
+        assert_eq!(data["value"], "T");
+        assert_eq!(data["id"], "x");
+        assert_eq!(data["classes"], serde_json::json!(["c"]));
+        assert_eq!(data["attributes"], serde_json::json!({ "key": "val" }));
+        let text = tree.get_node(heading.children[0]).unwrap();
+        assert_eq!(text.data.as_ref().unwrap()["value"], "T");
+        assert_eq!(heading.span.unwrap().end.offset, 20);
+
+        let headings = MarkdownParserV2::new(src).with_options(options).headings();
+        assert_eq!(headings, [(2, "T".to_string(), 0), (1, "Plain".to_string(), 22)]);
+
+        // Off by default: the list stays heading text
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        let heading = tree.get_node(tree.children_of(tree.root_id())[0]).unwrap();
+        let data = heading.data.as_ref().unwrap();
+        assert_eq!(data["value"], "T {#x .c key=val}");
+        assert!(!data.contains_key("id"));
+    }
 }
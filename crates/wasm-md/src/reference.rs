@@ -0,0 +1,312 @@
+//! Link destinations and reference definitions (`[label]: /url "title"`)
+//!
+//! Definitions are collected before block parsing, so a reference resolves
+//! whether its definition comes before or after it. Under
+//! `references: "eager"` they are learned as block parsing reaches them
+//! instead, and a reference seen before its definition is reported
+//! afterwards as a `LateReference`. Labels match after `normalize_label`.
+
+use serde::Serialize;
+use std::ops::Range;
+use synth_wasm_core::Span;
+
+/// Destination and optional title of a reference definition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Definition {
+    pub url: String,
+    pub title: Option<String>,
+}
+
+/// References whose label had no definition when they were scanned, as
+/// (label as written, span of the reference)
+#[derive(Debug, Default)]
+pub(crate) struct UndefinedReferences {
+    /// Full and collapsed references (`[text][label]`, `[label][]`)
+    pub full: Vec<(String, Span)>,
+    /// Shortcut references (`[label]`), kept only under `references:
+    /// "eager"`: without a definition they are usually bracketed text
+    pub shortcut: Vec<(String, Span)>,
+}
+
+/// A reference scanned before its definition under `references:
+/// "eager"`, so left as text; the fix-up a consumer applies to link it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LateReference {
+    /// Label as written
+    pub label: String,
+    /// Span of the whole reference (`[text][label]`, `[label]`)
+    pub span: Span,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// A single-line definition found in the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DefinitionLine<'a> {
+    /// Label as written, without the brackets
+    pub label: &'a str,
+    pub definition: Definition,
+}
+
+/// CommonMark label normalization: Unicode case fold, with runs of
+/// whitespace collapsed to one space and the ends trimmed
+///
+/// Case folding is approximated by upper- then lowercasing (as commonmark.js
+/// does), which also folds `ß`/`SS` and final `ς`/`σ` that lowercasing alone
+/// keeps apart. The result is the `identifier` mdast expects.
+pub(crate) fn normalize_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_uppercase()
+        .to_lowercase()
+}
+
+/// Parse a definition that makes up all of `line` (up to 3 spaces of
+/// indentation allowed)
+///
+/// Takes any destination `parse_destination` does and a `"..."`, `'...'`
+/// or `(...)` title. Footnote definitions (`[^label]:`) are not link
+/// definitions.
+pub(crate) fn parse_definition(line: &str) -> Option<DefinitionLine<'_>> {
+    let indent = line.bytes().take_while(|&b| b == b' ').count();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    if !rest.starts_with('[') || rest.starts_with("[^") {
+        return None;
+    }
+
+    let close = label_end(rest)?;
+    let label = &rest[1..close];
+    if label.trim().is_empty() || label.len() > 999 {
+        return None;
+    }
+    let rest = rest[close + 1..].strip_prefix(':')?.trim_start();
+
+    let (url, len) = parse_destination(rest)?;
+    let rest = &rest[len..];
+
+    let after_url = rest.trim_start();
+    let title = if after_url.is_empty() {
+        None
+    } else if after_url.len() == rest.len() {
+        // The title must be separated from the destination
+        return None;
+    } else {
+        Some(parse_title(after_url.trim_end())?)
+    };
+
+    Some(DefinitionLine {
+        label,
+        definition: Definition { url, title },
+    })
+}
+
+/// Link destination at the start of `text`: the URL with backslash
+/// escapes removed, and the length it takes up
+///
+/// Either `<...>` (may contain spaces, not `<`, `>` or a line break) or
+/// a non-empty bare destination without spaces or control characters,
+/// whose unescaped parentheses must balance.
+pub(crate) fn parse_destination(text: &str) -> Option<(String, usize)> {
+    let bytes = text.as_bytes();
+    let escaped = |i: usize| bytes[i] == b'\\' && bytes.get(i + 1).is_some_and(u8::is_ascii_punctuation);
+
+    if bytes.first() == Some(&b'<') {
+        let mut i = 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                _ if escaped(i) => i += 1,
+                b'>' => return Some((unescape(&text[1..i]), i + 1)),
+                b'<' | b'\n' => return None,
+                _ => {}
+            }
+            i += 1;
+        }
+        return None;
+    }
+
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            _ if escaped(i) => i += 1,
+            b'(' => {
+                depth += 1;
+                if depth > 32 {
+                    return None;
+                }
+            }
+            b')' if depth == 0 => break,
+            b')' => depth -= 1,
+            b if b <= b' ' || b == 0x7f => break,
+            _ => {}
+        }
+        i += 1;
+    }
+    (i > 0 && depth == 0).then(|| (unescape(&text[..i]), i))
+}
+
+/// Remove the backslashes of backslash-escaped ASCII punctuation
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek().is_some_and(char::is_ascii_punctuation) {
+            continue;
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Index of the `]` closing the label that starts `text`
+///
+/// Labels can't contain unescaped brackets.
+fn label_end(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'[' => return None,
+            b']' => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Link title (`"..."`, `'...'` or `(...)`) at the start of `text`: its
+/// contents with backslash escapes removed, and the length it takes up
+///
+/// The closing delimiter is the first unescaped one; a `(...)` title
+/// can't contain an unescaped `(`.
+pub(crate) fn parse_link_title(text: &str) -> Option<(String, usize)> {
+    let bytes = text.as_bytes();
+    let close = match bytes.first()? {
+        b'"' => b'"',
+        b'\'' => b'\'',
+        b'(' => b')',
+        _ => return None,
+    };
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b if b == close => return Some((unescape(&text[1..i]), i + 1)),
+            b'(' if close == b')' => return None,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Contents of a title that makes up all of `text`
+fn parse_title(text: &str) -> Option<String> {
+    let (title, len) = parse_link_title(text)?;
+    (len == text.len()).then_some(title)
+}
+
+/// Source ranges of the lines holding definitions, in order
+///
+/// A definition can't interrupt a paragraph, so it must start the
+/// document or follow a blank line, a heading or another definition.
+/// Fenced code is skipped.
+pub(crate) fn definition_lines(src: &str) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut fence: Option<(u8, usize)> = None;
+    let mut block_start = true;
+    let mut offset = 0;
+
+    for line in src.split('\n') {
+        let range = offset..offset + line.len();
+        offset = range.end + 1;
+
+        let trimmed = line.trim_start_matches(' ');
+        let marker = trimmed.bytes().next().filter(|&b| b == b'`' || b == b'~');
+        let run = marker.map_or(0, |marker| trimmed.bytes().take_while(|&b| b == marker).count());
+        if let Some((open_marker, open_len)) = fence {
+            if marker == Some(open_marker) && run >= open_len && trimmed[run..].trim().is_empty() {
+                fence = None;
+                block_start = true;
+            }
+            continue;
+        }
+        if let Some(marker) = marker
+            && run >= 3
+            && line.len() - trimmed.len() <= 3
+        {
+            fence = Some((marker, run));
+            continue;
+        }
+
+        if block_start && parse_definition(line).is_some() {
+            lines.push(range);
+            continue;
+        }
+        block_start = trimmed.trim().is_empty() || trimmed.starts_with('#');
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_label() {
+        assert_eq!(normalize_label("FOO"), normalize_label("foo"));
+        assert_eq!(normalize_label(" a \t b\n c "), "a b c");
+        assert_eq!(normalize_label("ÉCOLE"), normalize_label("école"));
+        assert_eq!(normalize_label("Straße"), normalize_label("STRASSE"));
+        assert_eq!(normalize_label("ΣΟΦΟΣ"), normalize_label("σοφος"));
+        assert_ne!(normalize_label("a b"), normalize_label("ab"));
+    }
+
+    #[test]
+    fn test_parse_definition() {
+        let def = parse_definition("[Foo Bar]: /url \"The title\"").unwrap();
+        assert_eq!(def.label, "Foo Bar");
+        assert_eq!(def.definition.url, "/url");
+        assert_eq!(def.definition.title.as_deref(), Some("The title"));
+
+        let def = parse_definition("   [x]: <with space> (paren title)").unwrap();
+        assert_eq!(def.definition.url, "with space");
+        assert_eq!(def.definition.title.as_deref(), Some("paren title"));
+
+        for line in ["[x]:", "[x] /url", "[^x]: note", "    [x]: /url", "[x]: /url title", "[a[b]]: /url"] {
+            assert!(parse_definition(line).is_none(), "{line}");
+        }
+    }
+
+    #[test]
+    fn test_parse_destination() {
+        let dest = |text| parse_destination(text).map(|(url, len)| (url, &text[len..]));
+        assert_eq!(dest("/foo(bar)baz) x"), Some(("/foo(bar)baz".to_string(), ") x")));
+        assert_eq!(dest("<with space>)"), Some(("with space".to_string(), ")")));
+        assert_eq!(dest("un\\)balanced)"), Some(("un)balanced".to_string(), ")")));
+        assert_eq!(dest("<a\\>b>"), Some(("a>b".to_string(), "")));
+        assert_eq!(dest("a\\b"), Some(("a\\b".to_string(), "")));
+        assert_eq!(dest("/foo(bar"), None);
+        assert_eq!(dest("<a<b>"), None);
+        assert_eq!(dest("<a\nb>"), None);
+        assert_eq!(dest(")"), None);
+    }
+
+    #[test]
+    fn test_definition_lines() {
+        let src = "[a]: /a\n[b]: /b\n\npara\n[c]: /c\n\n```\n[d]: /d\n```\n# H\n[e]: /e";
+        let labels: Vec<_> = definition_lines(src)
+            .into_iter()
+            .map(|range| parse_definition(&src[range]).unwrap().label)
+            .collect();
+        assert_eq!(labels, ["a", "b", "e"]);
+    }
+}
@@ -0,0 +1,122 @@
+//! Word count and reading time for content tooling
+//!
+//! Only prose counts: text in paragraphs, headings, lists, quotes, tables
+//! and footnotes. Code blocks, HTML and definitions are skipped, and
+//! markup (`*`, `[...](url)`, list markers) never reaches the text nodes.
+//! Inline code reads as part of its sentence and counts.
+
+use serde::{Deserialize, Serialize};
+use synth_wasm_core::{NodeId, NodeType, SynthResult, Tree};
+
+use crate::parser_v2::MarkdownParserV2;
+
+/// Options for `reading_stats`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ReadingOptions {
+    /// Reading speed used for `reading_time_minutes` (default 200)
+    pub words_per_minute: u32,
+}
+
+impl Default for ReadingOptions {
+    fn default() -> Self {
+        Self { words_per_minute: 200 }
+    }
+}
+
+/// Size of a document's text content
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingStats {
+    /// Whitespace-separated runs containing a letter or digit
+    pub words: usize,
+    /// Characters of the text content, whitespace excluded
+    pub characters: usize,
+    /// `words / wordsPerMinute`, unrounded
+    pub reading_time_minutes: f64,
+}
+
+/// Count the words and characters of `source`'s text content
+pub fn reading_stats(source: &str, options: &ReadingOptions) -> SynthResult<ReadingStats> {
+    let tree = MarkdownParserV2::new(source).parse()?;
+    let mut text = String::new();
+    collect_text(&tree, tree.root_id(), &mut text);
+
+    let words = text
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count();
+    Ok(ReadingStats {
+        words,
+        characters: text.chars().filter(|c| !c.is_whitespace()).count(),
+        reading_time_minutes: words as f64 / options.words_per_minute.max(1) as f64,
+    })
+}
+
+/// Append the text under `id`, with blocks separated by line breaks so
+/// words never run together across them
+fn collect_text(tree: &Tree, id: NodeId, out: &mut String) {
+    let Ok(node) = tree.get_node(id) else {
+        return;
+    };
+    match node.node_type {
+        NodeType::Code
+        | NodeType::Html
+        | NodeType::Definition
+        | NodeType::Yaml
+        | NodeType::Toml
+        | NodeType::Json => {}
+        NodeType::Text | NodeType::InlineCode => {
+            let value = node.data.as_ref().and_then(|d| d.get("value")).and_then(|v| v.as_str());
+            out.push_str(value.unwrap_or_default());
+        }
+        NodeType::Break => out.push('\n'),
+        NodeType::Emphasis | NodeType::Strong | NodeType::Delete | NodeType::Link => {
+            for &child in &node.children {
+                collect_text(tree, child, out);
+            }
+        }
+        _ if node.node_type == "htmlElement" => {
+            for &child in &node.children {
+                collect_text(tree, child, out);
+            }
+        }
+        _ => {
+            for &child in &node.children {
+                collect_text(tree, child, out);
+            }
+            out.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reading_stats_counts_prose_only() {
+        let src = "# Getting *started*\n\nRead the [full guide](https://example.com/guide) \u{2014} it's **short**.\n\n\
+                   - one item\n- two\n\n> quoted words\n\n```rust\nfn main() { println!(\"not counted\"); }\n```\n\n\
+                   [ref]: /not-counted\n";
+        let stats = reading_stats(src, &ReadingOptions::default()).unwrap();
+
+        // getting started / read the full guide it's short / one item two / quoted words
+        assert_eq!(stats.words, 13);
+        let prose = "Gettingstarted Readthefullguide\u{2014}it'sshort. oneitemtwo quotedwords";
+        assert_eq!(stats.characters, prose.chars().filter(|c| !c.is_whitespace()).count());
+        assert_eq!(stats.reading_time_minutes, 13.0 / 200.0);
+
+        let fast = ReadingOptions { words_per_minute: 13 };
+        assert_eq!(reading_stats(src, &fast).unwrap().reading_time_minutes, 1.0);
+    }
+
+    #[test]
+    fn test_reading_stats_excludes_code_blocks() {
+        let code = "```js\nconst a = 1;\nconst b = 2;\n```\n";
+        assert_eq!(reading_stats(code, &ReadingOptions::default()).unwrap(), ReadingStats::default());
+
+        let mixed = format!("Two words\n\n{code}");
+        assert_eq!(reading_stats(&mixed, &ReadingOptions::default()).unwrap().words, 2);
+    }
+}
@@ -0,0 +1,588 @@
+//! Markdown stringify
+//!
+//! Turns a parsed markdown Tree back into markdown text. By default the
+//! source's list markers and blank-line gaps are kept; with
+//! `minifyWhitespace` the output is canonical (one blank line at most,
+//! no trailing spaces except hard breaks, `-` bullets, `N.` ordered markers), which makes
+//! it usable as a formatter.
+
+use serde::Deserialize;
+use synth_wasm_core::{Node, NodeId, NodeType, Tree};
+
+/// Stringify options (camelCase when passed from JavaScript)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StringifyOptions {
+    /// Collapse blank lines, trim trailing spaces (keeping hard breaks)
+    /// and normalize markers
+    pub minify_whitespace: bool,
+}
+
+/// Stringify a markdown tree
+pub fn stringify(tree: &Tree, options: &StringifyOptions) -> String {
+    let source = tree.source();
+    let mut out = String::with_capacity(source.len());
+    write_blocks(tree, tree.root_id(), &source, options, &mut out);
+    out
+}
+
+/// Write the block children of `parent`, each followed by a newline and
+/// separated by the source's blank lines
+fn write_blocks(tree: &Tree, parent: NodeId, source: &str, options: &StringifyOptions, out: &mut String) {
+    let mut prev: Option<&Node> = None;
+    // Adjacent lists of the same kind need different markers to stay apart
+    let mut alternate = false;
+
+    for &id in tree.children_of(parent) {
+        let Ok(node) = tree.get_node(id) else {
+            continue;
+        };
+        // Inline footnotes are already written where they occur
+        if node.node_type == NodeType::FootnoteDefinition
+            && node.data.as_ref().is_some_and(|d| d.get("inline") == Some(&serde_json::json!(true)))
+        {
+            continue;
+        }
+
+        if let Some(prev) = prev {
+            push_gap(out, blank_lines_between(source, prev, node), options);
+            let adjacent_list = prev.node_type == NodeType::List
+                && node.node_type == NodeType::List
+                && is_ordered(prev) == is_ordered(node);
+            alternate = adjacent_list && !alternate;
+        } else {
+            alternate = false;
+        }
+
+        if node.node_type == NodeType::List {
+            write_list(tree, id, source, options, alternate, out);
+        } else {
+            write_block(tree, id, node, source, options, out);
+        }
+        out.push('\n');
+        prev = Some(node);
+    }
+}
+
+/// Write `blank_lines` empty lines (at most one when minifying)
+fn push_gap(out: &mut String, blank_lines: usize, options: &StringifyOptions) {
+    let blank_lines = if options.minify_whitespace {
+        blank_lines.min(1)
+    } else {
+        blank_lines
+    };
+    for _ in 0..blank_lines {
+        out.push('\n');
+    }
+}
+
+fn is_ordered(node: &Node) -> bool {
+    node.data
+        .as_ref()
+        .and_then(|d| d.get("ordered"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// Write a list's items, keeping blank lines between them (loose lists)
+fn write_list(
+    tree: &Tree,
+    id: NodeId,
+    source: &str,
+    options: &StringifyOptions,
+    alternate: bool,
+    out: &mut String,
+) {
+    let mut prev: Option<&Node> = None;
+    for &item_id in tree.children_of(id) {
+        let Ok(item) = tree.get_node(item_id) else {
+            continue;
+        };
+        if let Some(prev) = prev {
+            out.push('\n');
+            push_gap(out, blank_lines_between(source, prev, item), options);
+        }
+        write_list_item(item, source, options.minify_whitespace, alternate, out);
+        write_item_block(tree, item_id, item, source, options, alternate, out);
+        write_item_children(tree, item_id, item, source, options, out);
+        prev = Some(item);
+    }
+}
+
+/// Write the code block or heading opened on a list item's marker line,
+/// its later lines indented to the item's content
+fn write_item_block(
+    tree: &Tree,
+    item_id: NodeId,
+    item: &Node,
+    source: &str,
+    options: &StringifyOptions,
+    alternate: bool,
+    out: &mut String,
+) {
+    let Some((id, block)) = tree
+        .children_of(item_id)
+        .first()
+        .and_then(|&id| Some((id, tree.get_node(id).ok()?)))
+        .filter(|(_, block)| matches!(block.node_type, NodeType::Code | NodeType::Heading))
+    else {
+        return;
+    };
+    let pad = " ".repeat(list_marker(source, item, options.minify_whitespace, alternate).len() + 1);
+    let mut text = String::new();
+    write_block(tree, id, block, source, options, &mut text);
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+            if !line.is_empty() {
+                out.push_str(&pad);
+            }
+        }
+        out.push_str(line);
+    }
+}
+
+/// Write the lists and paragraphs nested in a list item on the
+/// following lines
+///
+/// Nested lines keep their source indentation, or line up with the
+/// item's text when minifying. A `paragraph` wrapping the item's own
+/// text (`itemParagraphs`) is already written with the marker.
+fn write_item_children(
+    tree: &Tree,
+    item_id: NodeId,
+    item: &Node,
+    source: &str,
+    options: &StringifyOptions,
+    out: &mut String,
+) {
+    let item_start = item.span.as_ref().map_or(0, |s| (s.start.offset as usize).min(source.len()));
+    let item_indent = item_start - line_start(source, item_start);
+    // The item's text may continue on later lines
+    let text_lines = text(item, "value").split('\n').count();
+    let text_end = item_start
+        + source[item_start..].split('\n').take(text_lines).map(|line| line.len() + 1).sum::<usize>()
+        - 1;
+    let mut prev_end = text_end;
+    let mut alternate = false;
+
+    for &child_id in tree.children_of(item_id) {
+        let Ok(child) = tree.get_node(child_id) else {
+            continue;
+        };
+        let start = child.span.as_ref().map_or(prev_end, |s| s.start.offset as usize);
+        let is_list = child.node_type == NodeType::List;
+        if !is_list && (child.node_type != NodeType::Paragraph || start < text_end) {
+            continue;
+        }
+        let gap = source
+            .get(prev_end..start)
+            .map_or(0, |between| between.matches('\n').count().saturating_sub(1));
+        out.push('\n');
+        push_gap(out, gap, options);
+
+        let pad = if options.minify_whitespace {
+            " ".repeat(list_marker(source, item, true, false).len() + 1)
+        } else {
+            // Relative to the item, which is itself already indented
+            let start = start.min(source.len());
+            let line_start = line_start(source, start);
+            source.get(line_start + item_indent..start).unwrap_or("").to_string()
+        };
+        let mut nested = String::new();
+        if is_list {
+            write_list(tree, child_id, source, options, alternate, &mut nested);
+            alternate = !alternate;
+        } else {
+            write_block(tree, child_id, child, source, options, &mut nested);
+            alternate = false;
+        }
+        for (i, line) in nested.split('\n').enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            if !line.is_empty() {
+                out.push_str(&pad);
+            }
+            out.push_str(line);
+        }
+
+        prev_end = child.span.as_ref().map_or(start, |s| s.end.offset as usize);
+    }
+}
+
+/// Blank lines separating two sibling blocks in the source
+fn blank_lines_between(source: &str, prev: &Node, next: &Node) -> usize {
+    let (Some(prev_span), Some(next_span)) = (prev.span.as_ref(), next.span.as_ref()) else {
+        return 1;
+    };
+
+    let mut from = (prev_span.end.offset as usize).min(source.len());
+    let to = (next_span.start.offset as usize).min(source.len());
+    // Some blocks end after their trailing newline; count from the newline itself
+    if from > 0 && source.as_bytes()[from - 1] == b'\n' {
+        from -= 1;
+    }
+    if from >= to {
+        return 0;
+    }
+
+    source[from..to].bytes().filter(|&b| b == b'\n').count().saturating_sub(1)
+}
+
+/// Source text of a node (`raw` wins over an escaped `value`)
+fn text<'n>(node: &'n Node, key: &str) -> &'n str {
+    let data = node.data.as_ref();
+    data.and_then(|d| d.get("raw"))
+        .filter(|_| key == "value")
+        .or_else(|| data.and_then(|d| d.get(key)))
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+}
+
+/// Trim trailing whitespace from each line, except a line whose two or
+/// more trailing spaces make a hard break before the next one
+fn trim_lines(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let last = lines.len().saturating_sub(1);
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, &line)| {
+            let hard_break = i < last && line.ends_with("  ") && !line.trim_end().is_empty();
+            if hard_break { line } else { line.trim_end() }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn write_block(
+    tree: &Tree,
+    id: NodeId,
+    node: &Node,
+    source: &str,
+    options: &StringifyOptions,
+    out: &mut String,
+) {
+    let minify = options.minify_whitespace;
+    let inline = |text: &str| {
+        if minify {
+            trim_lines(text)
+        } else {
+            text.to_string()
+        }
+    };
+
+    match &node.node_type {
+        NodeType::Heading => {
+            let depth = node
+                .data
+                .as_ref()
+                .and_then(|d| d.get("depth"))
+                .and_then(|v| v.as_u64())
+                .unwrap_or(1) as usize;
+            out.push_str(&"#".repeat(depth));
+            let value = inline(text(node, "value"));
+            if !value.is_empty() {
+                out.push(' ');
+                out.push_str(&value);
+            }
+        }
+        NodeType::Paragraph => out.push_str(&inline(text(node, "value"))),
+        NodeType::Code => {
+            out.push_str("```");
+            out.push_str(text(node, "lang"));
+            out.push('\n');
+            let code = text(node, "value");
+            out.push_str(code);
+            if !code.is_empty() && !code.ends_with('\n') {
+                out.push('\n');
+            }
+            out.push_str("```");
+        }
+        NodeType::ThematicBreak => {
+            if minify {
+                out.push_str("---");
+            } else {
+                out.push_str(source_line(source, node).trim());
+            }
+        }
+        NodeType::Blockquote => {
+            // Every line gets its `>`, lazy continuation lines included
+            let mut content = String::new();
+            write_blocks(tree, id, source, options, &mut content);
+            for (i, line) in content.trim_end_matches('\n').split('\n').enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                }
+                out.push('>');
+                if !line.is_empty() {
+                    out.push(' ');
+                    out.push_str(line);
+                }
+            }
+        }
+        NodeType::ListItem => write_list_item(node, source, minify, false, out),
+        NodeType::Table => write_table(tree, id, node, out),
+        NodeType::Html => out.push_str(text(node, "value")),
+        NodeType::Yaml | NodeType::Toml | NodeType::Json => {
+            let fence = match node.node_type {
+                NodeType::Yaml => "---",
+                NodeType::Toml => "+++",
+                _ => ";;;",
+            };
+            let value = text(node, "value");
+            out.push_str(fence);
+            out.push('\n');
+            out.push_str(value);
+            if !value.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(fence);
+        }
+        _ => out.push_str(source_line(source, node)),
+    }
+}
+
+/// Offset of the start of the line containing `pos`
+fn line_start(source: &str, pos: usize) -> usize {
+    source[..pos].rfind('\n').map_or(0, |i| i + 1)
+}
+
+/// First source line of a node
+fn source_line<'s>(source: &'s str, node: &Node) -> &'s str {
+    let start = node
+        .span
+        .as_ref()
+        .map_or(0, |s| (s.start.offset as usize).min(source.len()));
+    let rest = &source[start..];
+    rest.split('\n').next().unwrap_or("")
+}
+
+/// Write a list item's marker and text, continuation lines indented to
+/// its content
+fn write_list_item(node: &Node, source: &str, minify: bool, alternate: bool, out: &mut String) {
+    let marker = list_marker(source, node, minify, alternate);
+    out.push_str(&marker);
+    out.push(' ');
+    if let Some(checked) = node
+        .data
+        .as_ref()
+        .and_then(|d| d.get("checked"))
+        .and_then(|v| v.as_bool())
+    {
+        out.push_str(if checked { "[x] " } else { "[ ] " });
+    }
+    let value = text(node, "value");
+    let value = if minify { trim_lines(value) } else { value.to_string() };
+    for (i, line) in value.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+            out.push_str(&" ".repeat(marker.len() + 1));
+        }
+        out.push_str(line);
+    }
+}
+
+/// List item marker, normalized to `-` / `N.` when minifying
+///
+/// `alternate` picks `*` / `N)` instead, for a list that directly follows
+/// another list of the same kind and would otherwise merge into it.
+fn list_marker(source: &str, node: &Node, minify: bool, alternate: bool) -> String {
+    let line = source_line(source, node).trim_start();
+    let digits = line.bytes().take_while(u8::is_ascii_digit).count();
+
+    match (is_ordered(node), minify) {
+        (true, true) => format!(
+            "{}{}",
+            if digits > 0 { &line[..digits] } else { "1" },
+            if alternate { ')' } else { '.' }
+        ),
+        (true, false) => line.get(..digits + 1).unwrap_or("1.").to_string(),
+        (false, true) => if alternate { "*" } else { "-" }.to_string(),
+        (false, false) => line.get(..1).unwrap_or("-").to_string(),
+    }
+}
+
+fn write_table(tree: &Tree, id: NodeId, node: &Node, out: &mut String) {
+    let aligns: Vec<&str> = node
+        .data
+        .as_ref()
+        .and_then(|d| d.get("align"))
+        .and_then(|v| v.as_array())
+        .map(|a| a.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
+
+    let write_row = |row: NodeId, out: &mut String| {
+        out.push('|');
+        for &cell in tree.children_of(row) {
+            let value = tree.get_node(cell).map_or("", |c| text(c, "value"));
+            out.push(' ');
+            out.push_str(value);
+            out.push_str(" |");
+        }
+    };
+
+    for (i, &row) in tree.children_of(id).iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        write_row(row, out);
+        if i == 0 {
+            out.push_str("\n|");
+            for align in &aligns {
+                out.push_str(match *align {
+                    "left" => " :-- |",
+                    "right" => " --: |",
+                    "center" => " :-: |",
+                    _ => " --- |",
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser_v2::MarkdownParserV2;
+
+    /// Block types plus the structural data (depth, ordered, checked, lang),
+    /// with list items following their list
+    fn structure(tree: &Tree) -> Vec<String> {
+        tree.children_of(tree.root_id())
+            .iter()
+            .flat_map(|&id| std::iter::once(id).chain(tree.children_of(id).iter().copied()))
+            .filter(|&id| tree.get_node(id).unwrap().node_type != NodeType::Text)
+            .map(|id| {
+                let node = tree.get_node(id).unwrap();
+                let data = node.data.as_ref();
+                let field = |k: &str| data.and_then(|d| d.get(k)).map(|v| v.to_string());
+                format!(
+                    "{} {:?} {:?} {:?} {:?}",
+                    node.node_type,
+                    field("depth"),
+                    field("ordered"),
+                    field("checked"),
+                    field("lang"),
+                )
+            })
+            .collect()
+    }
+
+    fn format(src: &str, minify: bool) -> String {
+        let tree = MarkdownParserV2::new(src).parse().unwrap();
+        stringify(&tree, &StringifyOptions { minify_whitespace: minify })
+    }
+
+    const MESSY: &str = "# Title   \n\n\n\nSome text  \nmore\n\n* one\n+ two\n- [X] three\n\n\n1) first\n2) second\n\n\n\n```rust\nfn x() {}\n```\n\n\n> quote  \n\n***\n\n| a | b |\n|:--|--:|\n| 1 | 2 |\n";
+
+    #[test]
+    fn test_minify_whitespace_canonical_form() {
+        assert_eq!(
+            format(MESSY, true),
+            "# Title\n\nSome text  \nmore\n\n- one\n* two\n- [x] three\n\n1. first\n2. second\n\n```rust\nfn x() {}\n```\n\n> quote\n\n---\n\n| a | b |\n| :-- | --: |\n| 1 | 2 |\n"
+        );
+    }
+
+    #[test]
+    fn test_minified_output_round_trips() {
+        let once = format(MESSY, true);
+        let original = MarkdownParserV2::new(MESSY).parse().unwrap();
+        let reparsed = MarkdownParserV2::new(&once).parse().unwrap();
+        assert_eq!(structure(&original), structure(&reparsed));
+        // Hard breaks keep their trailing spaces, so paragraphs read the
+        // same up to the spaces ending their last line
+        let paragraphs = |tree: &Tree| -> Vec<String> {
+            tree.nodes()
+                .iter()
+                .filter(|n| n.node_type == NodeType::Paragraph)
+                .map(|n| text(n, "value").trim_end().to_string())
+                .collect()
+        };
+        assert_eq!(paragraphs(&original), paragraphs(&reparsed));
+        assert!(paragraphs(&reparsed).contains(&"Some text  \nmore".to_string()));
+        assert_eq!(format("a \t\nb    \nc  \n\nd\n", true), "a\nb    \nc\n\nd\n");
+        // Formatting is idempotent
+        assert_eq!(format(&once, true), once);
+    }
+
+    #[test]
+    fn test_loose_list_keeps_one_blank_line() {
+        assert_eq!(format("- a\n\n\n- b\n- c\n", true), "- a\n\n- b\n- c\n");
+        assert_eq!(format("1. a\n2. b\n", true), "1. a\n2. b\n");
+    }
+
+    #[test]
+    fn test_nested_lists() {
+        let src = "* [x] parent\n    + [ ] child\n      1) deep\n\n\n* next\n";
+        assert_eq!(format(src, false), src);
+        assert_eq!(
+            format(src, true),
+            "- [x] parent\n  - [ ] child\n    1. deep\n\n- next\n"
+        );
+        let once = format(src, true);
+        assert_eq!(format(&once, true), once);
+    }
+
+    #[test]
+    fn test_list_item_code_block() {
+        let src = "- ```js\n  let a;\n\n  a = 1;\n  ```\n- # Title\n";
+        assert_eq!(format(src, false), src);
+        assert_eq!(format(src, true), src);
+    }
+
+    #[test]
+    fn test_list_item_continuation_lines() {
+        let src = "1. one\n   more\n   - two\n     more\n\n   - three\n2. four\n";
+        assert_eq!(format(src, false), src);
+        assert_eq!(format(src, true), src);
+    }
+
+    #[test]
+    fn test_list_item_paragraphs() {
+        let src = "- one\n  more\n\n  second\n  paragraph\n\n  - nested\n\n    deeper\n- two\n";
+        assert_eq!(format(src, false), src);
+        assert_eq!(format(src, true), src);
+        // Lazy lines are indented to the item's content
+        assert_eq!(format("1. a\nlazy\n", true), "1. a\n   lazy\n");
+    }
+
+    #[test]
+    fn test_inline_footnotes_written_once() {
+        let src = "Claim^[a note].\n\nNext\n";
+        let options = crate::parser_v2::ParseOptions {
+            footnotes: true,
+            inline_footnotes: true,
+            ..Default::default()
+        };
+        let tree = MarkdownParserV2::new(src).with_options(options).parse().unwrap();
+        assert_eq!(stringify(&tree, &StringifyOptions::default()), src);
+    }
+
+    #[test]
+    fn test_blockquote_lines_all_quoted() {
+        assert_eq!(format("> a\nlazy\n>\n> b\n", true), "> a\n> lazy\n>\n> b\n");
+    }
+
+    #[test]
+    fn test_blockquote_blocks() {
+        let src = "> # Title\n> - one\n>   - two\n>\n> > nested\n> > > deep\n";
+        assert_eq!(format(src, false), src);
+        assert_eq!(format(src, true), src);
+        assert_eq!(format("> > a\nlazy\n", true), "> > a\n> > lazy\n");
+    }
+
+    #[test]
+    fn test_html_blocks_written_whole() {
+        let src = "<!-- toc\n\nkeep -->\n\n<div>\n*raw*\n</div>\n";
+        assert_eq!(format(src, false), src);
+        assert_eq!(format(src, true), src);
+    }
+
+    #[test]
+    fn test_default_keeps_markers_and_gaps() {
+        let src = "* a\n+ b\n\n\n3) c\n";
+        assert_eq!(format(src, false), src);
+    }
+}
@@ -0,0 +1,187 @@
+//! Plain text rendering for search indexes and previews
+//!
+//! Drops all markup: headings, paragraphs and quotes become their text,
+//! list items one per line, table rows one per line with cells separated
+//! by spaces, and emphasis and links their text. Blocks are separated by
+//! a blank line. HTML, definitions, thematic breaks and frontmatter are
+//! left out; fenced code only with `includeCode`.
+
+use serde::Deserialize;
+use synth_wasm_core::{NodeId, NodeType, SynthResult, Tree};
+
+use crate::parser_v2::MarkdownParserV2;
+
+/// Options for `render_text`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TextOptions {
+    /// Keep the content of code blocks
+    pub include_code: bool,
+    /// Follow a link's text with its URL, as `text (url)`, unless the
+    /// text is the URL
+    pub link_urls: bool,
+}
+
+/// Plain text content of `source`
+pub fn render_text(source: &str, options: &TextOptions) -> SynthResult<String> {
+    let tree = MarkdownParserV2::new(source).parse()?;
+    let mut blocks = Vec::new();
+    for &id in tree.children_of(tree.root_id()) {
+        block_text(&tree, id, options, &mut blocks);
+    }
+    Ok(blocks.join("\n\n"))
+}
+
+/// Append the text of the block `id` to `blocks`, if it has any
+fn block_text(tree: &Tree, id: NodeId, options: &TextOptions, blocks: &mut Vec<String>) {
+    let Ok(node) = tree.get_node(id) else {
+        return;
+    };
+    let text = match node.node_type {
+        NodeType::Code if options.include_code => value(tree, id).trim_end_matches('\n').to_string(),
+        NodeType::Code
+        | NodeType::Html
+        | NodeType::Definition
+        | NodeType::ThematicBreak
+        | NodeType::Yaml
+        | NodeType::Toml
+        | NodeType::Json => return,
+        NodeType::List => {
+            let items: Vec<String> = node
+                .children
+                .iter()
+                .map(|&item| container_text(tree, item, options))
+                .filter(|text| !text.is_empty())
+                .collect();
+            items.join("\n")
+        }
+        NodeType::Table => {
+            let rows: Vec<String> = node
+                .children
+                .iter()
+                .map(|&row| {
+                    let cells: Vec<String> = tree
+                        .children_of(row)
+                        .iter()
+                        .map(|&cell| container_text(tree, cell, options))
+                        .filter(|text| !text.is_empty())
+                        .collect();
+                    cells.join(" ")
+                })
+                .collect();
+            rows.join("\n")
+        }
+        _ => container_text(tree, id, options),
+    };
+    if !text.is_empty() {
+        blocks.push(text);
+    }
+}
+
+/// Text of a node holding inline content, block children (a list item's
+/// nested list or code), or both, one line per block
+fn container_text(tree: &Tree, id: NodeId, options: &TextOptions) -> String {
+    let mut lines = Vec::new();
+    let mut inline = String::new();
+    for &child in tree.children_of(id) {
+        let Ok(node) = tree.get_node(child) else {
+            continue;
+        };
+        let is_block = matches!(
+            node.node_type,
+            NodeType::Paragraph
+                | NodeType::Heading
+                | NodeType::Code
+                | NodeType::List
+                | NodeType::Table
+                | NodeType::Blockquote
+        );
+        if is_block {
+            lines.push(std::mem::take(&mut inline));
+            block_text(tree, child, options, &mut lines);
+        } else {
+            inline_text(tree, child, options, &mut inline);
+        }
+    }
+    lines.push(inline);
+    lines.retain(|line| !line.is_empty());
+    lines.join("\n")
+}
+
+/// Append the text of the inline node `id`
+fn inline_text(tree: &Tree, id: NodeId, options: &TextOptions, out: &mut String) {
+    let Ok(node) = tree.get_node(id) else {
+        return;
+    };
+    match node.node_type {
+        NodeType::Text | NodeType::InlineCode => out.push_str(value(tree, id)),
+        NodeType::Break => out.push('\n'),
+        NodeType::Html | NodeType::FootnoteReference => {}
+        NodeType::Image => out.push_str(field(tree, id, "alt")),
+        NodeType::Link => {
+            let start = out.len();
+            for &child in &node.children {
+                inline_text(tree, child, options, out);
+            }
+            let url = field(tree, id, "url");
+            if options.link_urls && !url.is_empty() && out[start..] != *url {
+                out.push_str(" (");
+                out.push_str(url);
+                out.push(')');
+            }
+        }
+        _ => {
+            for &child in &node.children {
+                inline_text(tree, child, options, out);
+            }
+        }
+    }
+}
+
+fn value(tree: &Tree, id: NodeId) -> &str {
+    field(tree, id, "value")
+}
+
+fn field<'t>(tree: &'t Tree, id: NodeId, key: &str) -> &'t str {
+    tree.get_node(id)
+        .ok()
+        .and_then(|node| node.data.as_ref())
+        .and_then(|data| data.get(key))
+        .and_then(|value| value.as_str())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "# Release *notes*\n\nThe **new** parser is [fast](https://example.com) and \
+                       [https://synth.dev](https://synth.dev) works too.\n\n```rust\nfn main() {}\n```\n\n\
+                       - one\n- two with `code`\n  - nested\n\n---\n\n> quoted ![logo](/l.png)\n";
+
+    #[test]
+    fn test_render_text() {
+        assert_eq!(
+            render_text(DOC, &TextOptions::default()).unwrap(),
+            "Release notes\n\nThe new parser is fast and https://synth.dev works too.\n\n\
+             one\ntwo with code\nnested\n\nquoted logo"
+        );
+
+        let options = TextOptions { include_code: true, link_urls: true };
+        assert_eq!(
+            render_text(DOC, &options).unwrap(),
+            "Release notes\n\nThe new parser is fast (https://example.com) and https://synth.dev works too.\n\n\
+             fn main() {}\n\none\ntwo with code\nnested\n\nquoted logo"
+        );
+    }
+
+    #[test]
+    fn test_render_text_tables_and_empty() {
+        assert_eq!(
+            render_text("| a | *b* |\n|---|---|\n| 1 | 2 |\n", &TextOptions::default()).unwrap(),
+            "a b\n1 2"
+        );
+        assert_eq!(render_text("", &TextOptions::default()).unwrap(), "");
+        assert_eq!(render_text("<div>\nhtml\n</div>\n", &TextOptions::default()).unwrap(), "");
+    }
+}
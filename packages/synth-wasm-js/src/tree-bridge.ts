@@ -24,7 +24,7 @@ export function decodeBinaryAst(binary: Uint8Array): ASTNode[] {
 		const offset = 4 + i * 16
 		nodes.push({
 			kind: view.getUint8(offset),
-			flags: view.getUint8(offset + 1),
+			flags: view.getUint16(offset + 1, true),
 			start: view.getUint32(offset + 4, true),
 			end: view.getUint32(offset + 8, true),
 			extra: view.getUint32(offset + 12, true),
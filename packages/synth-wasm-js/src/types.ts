@@ -97,6 +97,7 @@ export const NodeFlags: {
   readonly SHORTHAND: number
   readonly STATIC: number
   readonly EXPORT_DEFAULT: number
+  readonly DECLARE: number
 } = {
   CONST: 1 << 0,
   LET: 1 << 1,
@@ -106,6 +107,7 @@ export const NodeFlags: {
   SHORTHAND: 1 << 5,
   STATIC: 1 << 6,
   EXPORT_DEFAULT: 1 << 7,
+  DECLARE: 1 << 8,
 }
 
 /**
@@ -40,7 +40,7 @@ export async function parseCount(source: string): Promise<number> {
  *
  * Binary format:
  * - Header: [node_count: u32]
- * - Nodes: 16 bytes each (kind: u8, flags: u8, pad: u16, start: u32, end: u32, extra: u32)
+ * - Nodes: 16 bytes each (kind: u8, flags: u16, pad: u8, start: u32, end: u32, extra: u32)
  *
  * @param source - JavaScript source code
  * @returns Binary buffer containing AST
@@ -84,7 +84,7 @@ export async function parseFlat(source: string): Promise<ParseResult> {
     const offset = 4 + i * 16
     nodes.push({
       kind: view.getUint8(offset) as NodeKind,
-      flags: view.getUint8(offset + 1),
+      flags: view.getUint16(offset + 1, true),
       start: view.getUint32(offset + 4, true),
       end: view.getUint32(offset + 8, true),
       extra: view.getUint32(offset + 12, true),
@@ -43,6 +43,7 @@ export async function parse(markdown: string): Promise<WasmTree> {
  * Binary format:
  * - Header: [node_count: u32, source_len: u32]
  * - Nodes: 24 bytes each (node_type, flags, parent, text_start, text_len, span_start, span_end)
+ * - span_start/span_end are byte offsets, as in tree spans (binary format version 2)
  *
  * @example
  * ```typescript